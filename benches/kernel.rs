@@ -0,0 +1,127 @@
+// Timings of the iteration kernel for every number type, fractal and the custom formula
+// evaluator, so optimizations of the inner loop can be checked kernel by kernel instead of
+// through whole renders. Run with
+//     cargo bench --bench kernel [-- <filter>]
+// Every benchmark escapes the same grid of points around Seahorse Valley, a mix of escaping
+// and interior points, and prints the time per point and per iteration.
+
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use mandelbrot_set::{Real, Complex, Vec2, BigFloat, Float, RenderSettings, Precision, Fractal, Formula, Polynomial, LyapunovSequence, HybridPattern, EscapeResult, escape_time_at, escape_time_x4, simd_available};
+
+const GRID: usize = 16;
+// Time spent on each benchmark after the warm up pass
+const MEASURE_TIME: Duration = Duration::from_millis(500);
+
+fn settings(fractal: Fractal) -> RenderSettings {
+    let mut settings = RenderSettings::new();
+    settings.fractal = fractal;
+    settings.center = Vec2::<BigFloat>{x: BigFloat::from_real(-0.7453), y: BigFloat::from_real(0.1127)};
+    settings.view_size = Vec2::<Real>{x: 6.5e-3, y: 6.5e-3};
+    settings.max_iterations = 1000;
+    settings
+}
+
+// Offsets from the view center of a GRID x GRID grid over the view
+fn offsets(settings: &RenderSettings) -> Vec<Vec2<Real>> {
+    let mut offsets = Vec::with_capacity(GRID * GRID);
+    for y in 0..GRID {
+        for x in 0..GRID {
+            offsets.push(Vec2::<Real>{
+                x: ((x as Real + 0.5) / GRID as Real - 0.5) * settings.view_size.x,
+                y: ((y as Real + 0.5) / GRID as Real - 0.5) * settings.view_size.y,
+            });
+        }
+    }
+    offsets
+}
+
+// Runs pass, which escapes every point and returns the iteration total, until MEASURE_TIME
+// has passed and prints the mean cost
+fn bench<F: FnMut() -> u64>(name: &str, points: usize, mut pass: F) {
+    let iterations = pass();
+    let start = Instant::now();
+    let mut passes = 0;
+    while start.elapsed() < MEASURE_TIME {
+        black_box(pass());
+        passes += 1;
+    }
+    let seconds = start.elapsed().as_secs_f64() / passes as f64;
+    println!("{:<28}{:>12.1} ns/point{:>10.2} ns/iteration{:>10.1} Miterations/s", name,
+        seconds * 1e9 / points as f64, seconds * 1e9 / iterations as f64, iterations as f64 / seconds / 1e6);
+}
+
+fn iteration_total(results: &[EscapeResult]) -> u64 {
+    results.iter().map(|result| result.iterations as u64).sum()
+}
+
+fn bench_precision(name: &str, settings: &RenderSettings, precision: Precision) {
+    let offsets = offsets(settings);
+    let limbs = settings.arbitrary_limbs();
+    bench(name, offsets.len(), || {
+        let results: Vec<EscapeResult> = offsets.iter().map(|&offset| escape_time_at(settings, precision, limbs, offset)).collect();
+        iteration_total(&results)
+    });
+}
+
+fn bench_simd(name: &str, settings: &RenderSettings) {
+    let center = Complex::new(settings.center.x.to_real(), settings.center.y.to_real());
+    let positions: Vec<Complex<Real>> = offsets(settings).iter().map(|offset| Complex::new(center.r + offset.x, center.i + offset.y)).collect();
+    bench(name, positions.len(), || {
+        positions.chunks(4).map(|chunk| {
+            let lanes = [chunk[0], chunk[1], chunk[2], chunk[3]];
+            iteration_total(&escape_time_x4(settings, &lanes))
+        }).sum()
+    });
+}
+
+fn main() {
+    // cargo bench passes --bench, everything else filters the benchmarks by name
+    let filter: Vec<String> = env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+    let selected = |name: &str| filter.is_empty() || filter.iter().any(|part| name.contains(part.as_str()));
+
+    let mandelbrot = settings(Fractal::Mandelbrot);
+    for (name, precision) in [("precision/single", Precision::Single), ("precision/double", Precision::Double),
+        ("precision/double-double", Precision::DoubleDouble), ("precision/arbitrary", Precision::Arbitrary)] {
+        if selected(name) {
+            bench_precision(name, &mandelbrot, precision);
+        }
+    }
+    if selected("simd/double-x4") {
+        if simd_available() {
+            bench_simd("simd/double-x4", &mandelbrot);
+        }
+        else {
+            println!("{:<28}skipped, AVX isn't supported by this CPU", "simd/double-x4");
+        }
+    }
+    for (name, fractal) in [("fractal/julia", Fractal::Julia), ("fractal/burning-ship", Fractal::BurningShip),
+        ("fractal/tricorn", Fractal::Tricorn), ("fractal/celtic", Fractal::Celtic), ("fractal/multibrot", Fractal::Multibrot),
+        ("fractal/nova", Fractal::Nova), ("fractal/phoenix", Fractal::Phoenix), ("fractal/magnet1", Fractal::MagnetI),
+        ("fractal/magnet2", Fractal::MagnetII)] {
+        if selected(name) {
+            bench_precision(name, &settings(fractal), Precision::Double);
+        }
+    }
+    if selected("formula/quadratic") {
+        let mut formula = settings(Fractal::Formula);
+        formula.formula = Some("z^2 + c".parse::<Formula>().unwrap());
+        bench_precision("formula/quadratic", &formula, Precision::Double);
+    }
+    if selected("fractal/newton") {
+        let mut newton = settings(Fractal::Newton);
+        newton.polynomial = Some("1/0/0/-1".parse::<Polynomial>().unwrap());
+        bench_precision("fractal/newton", &newton, Precision::Double);
+    }
+    if selected("fractal/hybrid") {
+        let mut hybrid = settings(Fractal::Hybrid);
+        hybrid.hybrid = Some("MMMB".parse::<HybridPattern>().unwrap());
+        bench_precision("fractal/hybrid", &hybrid, Precision::Double);
+    }
+    if selected("fractal/lyapunov") {
+        let mut lyapunov = settings(Fractal::Lyapunov);
+        lyapunov.lyapunov_sequence = Some("AB".parse::<LyapunovSequence>().unwrap());
+        bench_precision("fractal/lyapunov", &lyapunov, Precision::Double);
+    }
+}
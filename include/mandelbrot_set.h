@@ -0,0 +1,53 @@
+/* C API of the mandelbrot_set library, implemented in src/ffi.rs.
+ * Link with libmandelbrot_set.so (or .dylib/.dll) from `cargo build --release --lib`.
+ * Functions returning int return 0 on success and -1 on failure, the message of the
+ * failure is available from mandelbrot_last_error. */
+
+#ifndef MANDELBROT_SET_H
+#define MANDELBROT_SET_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque render settings */
+typedef struct MandelbrotSettings MandelbrotSettings;
+
+/* Message of the last failed call on this thread, valid until the next failing call */
+const char *mandelbrot_last_error(void);
+
+/* Default settings, released with mandelbrot_settings_free */
+MandelbrotSettings *mandelbrot_settings_new(void);
+void mandelbrot_settings_free(MandelbrotSettings *settings);
+
+/* Image size in pixels, the view keeps its width and follows the aspect ratio */
+int mandelbrot_settings_set_size(MandelbrotSettings *settings, size_t width, size_t height);
+/* View center as decimal text so deep zooms keep every digit */
+int mandelbrot_settings_set_center(MandelbrotSettings *settings, const char *x, const char *y);
+/* Width of the view in the complex plane, the height follows the aspect ratio */
+int mandelbrot_settings_set_view_width(MandelbrotSettings *settings, double view_width);
+/* Maximum iteration count, must be positive */
+int mandelbrot_settings_set_iterations(MandelbrotSettings *settings, uint32_t iterations);
+/* Anti-aliasing samples per pixel */
+int mandelbrot_settings_set_samples(MandelbrotSettings *settings, size_t samples);
+/* Worker threads, 0 for one per core */
+int mandelbrot_settings_set_threads(MandelbrotSettings *settings, size_t threads);
+/* Fixes the sample positions so renders are reproducible */
+int mandelbrot_settings_set_seed(MandelbrotSettings *settings, uint64_t seed);
+/* Fractal, coloring and generated palette by name, like the command line options. The formula,
+   newton, lyapunov and hybrid fractals need options of their own and are rejected. */
+int mandelbrot_settings_set_fractal(MandelbrotSettings *settings, const char *fractal);
+int mandelbrot_settings_set_coloring(MandelbrotSettings *settings, const char *coloring);
+int mandelbrot_settings_set_palette(MandelbrotSettings *settings, const char *spec);
+
+/* Renders into buffer, which has to hold width * height * 4 bytes of RGBA */
+int mandelbrot_render(const MandelbrotSettings *settings, uint8_t *buffer, size_t length);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
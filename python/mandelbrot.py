@@ -0,0 +1,86 @@
+"""Python bindings of the mandelbrot_set renderer through its C API (include/mandelbrot_set.h).
+
+Build the library with `cargo build --release --lib`, it is looked up in target/release of the
+repository or at the path in the MANDELBROT_SET_LIB environment variable. ctypes releases the
+GIL during the calls, so renders run alongside other Python threads.
+"""
+
+import ctypes
+import os
+import sys
+
+DEFAULT_VIEW_WIDTH = 4.0
+
+_library = None
+
+
+def _library_path():
+    path = os.environ.get('MANDELBROT_SET_LIB')
+    if path:
+        return path
+    name = {'win32': 'mandelbrot_set.dll', 'darwin': 'libmandelbrot_set.dylib'}.get(sys.platform, 'libmandelbrot_set.so')
+    root = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+    return os.path.join(root, 'target', 'release', name)
+
+
+def _load():
+    global _library
+    if _library is None:
+        library = ctypes.CDLL(_library_path())
+        library.mandelbrot_last_error.restype = ctypes.c_char_p
+        library.mandelbrot_settings_new.restype = ctypes.c_void_p
+        library.mandelbrot_settings_free.argtypes = [ctypes.c_void_p]
+        library.mandelbrot_settings_set_size.argtypes = [ctypes.c_void_p, ctypes.c_size_t, ctypes.c_size_t]
+        library.mandelbrot_settings_set_center.argtypes = [ctypes.c_void_p, ctypes.c_char_p, ctypes.c_char_p]
+        library.mandelbrot_settings_set_view_width.argtypes = [ctypes.c_void_p, ctypes.c_double]
+        library.mandelbrot_settings_set_iterations.argtypes = [ctypes.c_void_p, ctypes.c_uint32]
+        library.mandelbrot_settings_set_samples.argtypes = [ctypes.c_void_p, ctypes.c_size_t]
+        library.mandelbrot_settings_set_seed.argtypes = [ctypes.c_void_p, ctypes.c_uint64]
+        library.mandelbrot_settings_set_palette.argtypes = [ctypes.c_void_p, ctypes.c_char_p]
+        library.mandelbrot_settings_set_coloring.argtypes = [ctypes.c_void_p, ctypes.c_char_p]
+        library.mandelbrot_render.argtypes = [ctypes.c_void_p, ctypes.c_void_p, ctypes.c_size_t]
+        _library = library
+    return _library
+
+
+def _check(library, status):
+    if status != 0:
+        raise ValueError(library.mandelbrot_last_error().decode())
+
+
+def render_rgba(center=(-0.5, 0.0), zoom=1.0, size=(800, 600), iterations=250, palette=None,
+                coloring=None, samples=None, seed=None):
+    """Renders the view and returns its RGBA pixels as bytes, rows from the top.
+
+    center takes numbers or decimal strings, strings keep every digit for deep zooms. zoom 1
+    shows a view 4 wide, palette is a generated gradient like --palette-gen.
+    """
+    library = _load()
+    width, height = size
+    settings = library.mandelbrot_settings_new()
+    try:
+        _check(library, library.mandelbrot_settings_set_size(settings, width, height))
+        _check(library, library.mandelbrot_settings_set_center(settings, str(center[0]).encode(), str(center[1]).encode()))
+        _check(library, library.mandelbrot_settings_set_view_width(settings, DEFAULT_VIEW_WIDTH / zoom))
+        _check(library, library.mandelbrot_settings_set_iterations(settings, iterations))
+        if palette is not None:
+            _check(library, library.mandelbrot_settings_set_palette(settings, palette.encode()))
+        if coloring is not None:
+            _check(library, library.mandelbrot_settings_set_coloring(settings, coloring.encode()))
+        if samples is not None:
+            _check(library, library.mandelbrot_settings_set_samples(settings, samples))
+        if seed is not None:
+            _check(library, library.mandelbrot_settings_set_seed(settings, seed))
+        buffer = ctypes.create_string_buffer(width * height * 4)
+        _check(library, library.mandelbrot_render(settings, buffer, len(buffer)))
+        return buffer.raw
+    finally:
+        library.mandelbrot_settings_free(settings)
+
+
+def render(center=(-0.5, 0.0), zoom=1.0, size=(800, 600), iterations=250, palette=None, **options):
+    """Like render_rgba, returning a numpy array of shape (height, width, 4) and type uint8."""
+    import numpy
+    width, height = size
+    pixels = render_rgba(center, zoom, size, iterations, palette, **options)
+    return numpy.frombuffer(pixels, dtype=numpy.uint8).reshape(height, width, 4)
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use deflate::write::ZlibEncoder;
+use crate::image::{ImageBuffer, create_file, write_png_chunk, filter_row};
+use crate::video::VideoSettings;
+
+// Colors of a GIF frame
+const GIF_COLORS: usize = 256;
+// Largest LZW code of GIF image data
+const MAX_CODE: u16 = 4095;
+
+// Animated image formats written without ffmpeg
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AnimatedFormat {
+    // 256 colors per frame, delays in hundredths of a second
+    Gif,
+    // Animated PNG, full color with transparency
+    Apng,
+}
+
+impl AnimatedFormat {
+    pub fn from_path(path: &str) -> Option<AnimatedFormat> {
+        let extension = Path::new(path).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        match extension.as_str() {
+            "gif" => Some(AnimatedFormat::Gif),
+            "apng" => Some(AnimatedFormat::Apng),
+            _ => None,
+        }
+    }
+}
+
+// Writes the frames of an animation into one GIF or APNG file as they are rendered
+pub struct AnimatedEncoder {
+    file: BufWriter<File>,
+    format: AnimatedFormat,
+    path: String,
+    width: usize,
+    height: usize,
+    frames_left: usize,
+    // Milliseconds each frame is shown
+    delay: u32,
+    // Sequence number of the next APNG chunk
+    sequence: u32,
+}
+
+impl AnimatedEncoder {
+    pub fn new(path: &str, width: usize, height: usize, frame_count: usize, settings: &VideoSettings) -> Result<AnimatedEncoder, String> {
+        let format = AnimatedFormat::from_path(path).ok_or_else(|| format!("{} isn't a .gif or .apng path", path))?;
+        if format == AnimatedFormat::Gif && (width > 0xffff || height > 0xffff) {
+            return Err(String::from("GIF images are at most 65535 pixels wide and high"));
+        }
+        let error = |e: std::io::Error| format!("cannot write {}: {}", path, e);
+        let mut file = BufWriter::new(create_file(path)?);
+        let loop_count = settings.loop_count;
+        match format {
+            AnimatedFormat::Gif => {
+                file.write_all(b"GIF89a").map_err(error)?;
+                // Logical screen without a global color table
+                file.write_all(&(width as u16).to_le_bytes()).map_err(error)?;
+                file.write_all(&(height as u16).to_le_bytes()).map_err(error)?;
+                file.write_all(&[0, 0, 0]).map_err(error)?;
+                // The Netscape extension counts the repetitions after the first play, 0 is forever
+                if loop_count != 1 {
+                    let repetitions = loop_count.saturating_sub(1).min(0xffff) as u16;
+                    file.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01").map_err(error)?;
+                    file.write_all(&repetitions.to_le_bytes()).map_err(error)?;
+                    file.write_all(&[0]).map_err(error)?;
+                }
+            }
+            AnimatedFormat::Apng => {
+                file.write_all(b"\x89PNG\r\n\x1a\n").map_err(error)?;
+                let mut header = Vec::new();
+                header.extend_from_slice(&(width as u32).to_be_bytes());
+                header.extend_from_slice(&(height as u32).to_be_bytes());
+                // 8 bit RGBA, compression, filter and interlace methods
+                header.extend_from_slice(&[8, 6, 0, 0, 0]);
+                write_png_chunk(&mut file, b"IHDR", &header).map_err(error)?;
+                let mut control = Vec::new();
+                control.extend_from_slice(&(frame_count as u32).to_be_bytes());
+                control.extend_from_slice(&loop_count.to_be_bytes());
+                write_png_chunk(&mut file, b"acTL", &control).map_err(error)?;
+            }
+        }
+        Ok(AnimatedEncoder {
+            file,
+            format,
+            path: String::from(path),
+            width,
+            height,
+            frames_left: frame_count,
+            delay: settings.frame_delay(),
+            sequence: 0,
+        })
+    }
+
+    pub fn write_frame(&mut self, image: &ImageBuffer) -> Result<(), String> {
+        if image.width != self.width || image.height != self.height {
+            return Err(String::from("frame size doesn't match the animation size"));
+        }
+        if self.frames_left == 0 {
+            return Err(String::from("the animation has all its frames already"));
+        }
+        let written = match self.format {
+            AnimatedFormat::Gif => self.write_gif_frame(image),
+            AnimatedFormat::Apng => self.write_apng_frame(image),
+        };
+        written.map_err(|e| format!("cannot write {}: {}", self.path, e))?;
+        self.frames_left -= 1;
+        Ok(())
+    }
+
+    fn write_gif_frame(&mut self, image: &ImageBuffer) -> std::io::Result<()> {
+        let rgba = image.to_rgba8();
+        let (colors, indices) = quantize(&rgba);
+        // Graphic control extension with the delay in hundredths of a second
+        let delay = ((self.delay + 5) / 10).min(0xffff) as u16;
+        self.file.write_all(&[0x21, 0xf9, 0x04, 0])?;
+        self.file.write_all(&delay.to_le_bytes())?;
+        self.file.write_all(&[0, 0])?;
+        // Image descriptor covering the screen with a local color table of 256 entries
+        self.file.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.file.write_all(&(self.width as u16).to_le_bytes())?;
+        self.file.write_all(&(self.height as u16).to_le_bytes())?;
+        self.file.write_all(&[0x87])?;
+        let mut table = vec![0u8; 3 * GIF_COLORS];
+        for (entry, color) in table.chunks_mut(3).zip(&colors) {
+            entry.copy_from_slice(color);
+        }
+        self.file.write_all(&table)?;
+        self.file.write_all(&[8])?;
+        for block in lzw_encode(&indices).chunks(255) {
+            self.file.write_all(&[block.len() as u8])?;
+            self.file.write_all(block)?;
+        }
+        self.file.write_all(&[0])
+    }
+
+    fn write_apng_frame(&mut self, image: &ImageBuffer) -> std::io::Result<()> {
+        let mut control = Vec::new();
+        control.extend_from_slice(&self.sequence.to_be_bytes());
+        control.extend_from_slice(&(self.width as u32).to_be_bytes());
+        control.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // Offset, delay as a fraction of seconds, no disposal and source blending
+        control.extend_from_slice(&[0; 8]);
+        control.extend_from_slice(&(self.delay.min(0xffff) as u16).to_be_bytes());
+        control.extend_from_slice(&1000u16.to_be_bytes());
+        control.extend_from_slice(&[0, 0]);
+        write_png_chunk(&mut self.file, b"fcTL", &control)?;
+        self.sequence += 1;
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), deflate::Compression::Default);
+        let mut filtered = Vec::new();
+        for row in image.to_rgba8().chunks(4 * self.width) {
+            filtered.clear();
+            filter_row(row, 4, &mut filtered);
+            encoder.write_all(&filtered)?;
+        }
+        let data = encoder.finish()?;
+        // The first frame is the default image other programs show, the others are frame data
+        if self.sequence == 1 {
+            write_png_chunk(&mut self.file, b"IDAT", &data)
+        }
+        else {
+            let mut chunk = self.sequence.to_be_bytes().to_vec();
+            chunk.extend_from_slice(&data);
+            self.sequence += 1;
+            write_png_chunk(&mut self.file, b"fdAT", &chunk)
+        }
+    }
+
+    pub fn finish(mut self) -> Result<(), String> {
+        let path = self.path.clone();
+        let error = |e: std::io::Error| format!("cannot write {}: {}", path, e);
+        if self.frames_left != 0 {
+            return Err(format!("cannot write {}: {} frames are missing", path, self.frames_left));
+        }
+        match self.format {
+            AnimatedFormat::Gif => self.file.write_all(&[0x3b]).map_err(error)?,
+            AnimatedFormat::Apng => write_png_chunk(&mut self.file, b"IEND", &[]).map_err(error)?,
+        }
+        self.file.flush().map_err(error)
+    }
+}
+
+// Histogram bucket of a color, 5 bits per channel
+fn bucket(r: u8, g: u8, b: u8) -> usize {
+    ((r as usize >> 3) << 10) | ((g as usize >> 3) << 5) | (b as usize >> 3)
+}
+
+// Median cut of the colors of RGBA pixels to at most 256, returns the colors and the index of
+// every pixel. Alpha is ignored.
+fn quantize(rgba: &[u8]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let mut counts = vec![0u64; 1 << 15];
+    let mut sums = vec![[0u64; 3]; 1 << 15];
+    for pixel in rgba.chunks(4) {
+        let key = bucket(pixel[0], pixel[1], pixel[2]);
+        counts[key] += 1;
+        for channel in 0..3 {
+            sums[key][channel] += pixel[channel] as u64;
+        }
+    }
+    let used: Vec<usize> = (0..counts.len()).filter(|&key| counts[key] > 0).collect();
+    let channel = |key: usize, channel: usize| (key >> (10 - 5 * channel)) & 31;
+
+    // Splits the box with the widest channel range at its median pixel until there are enough
+    let mut boxes = vec![used];
+    while boxes.len() < GIF_COLORS {
+        let range = |keys: &Vec<usize>, c: usize| {
+            let values = keys.iter().map(|&key| channel(key, c));
+            values.clone().max().unwrap_or(0) - values.min().unwrap_or(0)
+        };
+        let widest = boxes.iter().enumerate()
+            .filter(|(_, keys)| keys.len() > 1)
+            .map(|(index, keys)| (index, (0..3).max_by_key(|&c| range(keys, c)).unwrap_or(0), keys))
+            .max_by_key(|(_, c, keys)| range(keys, *c));
+        let (index, split_channel) = match widest {
+            Some((index, c, _)) => (index, c),
+            None => break,
+        };
+        let mut keys = boxes.swap_remove(index);
+        keys.sort_by_key(|&key| channel(key, split_channel));
+        let total: u64 = keys.iter().map(|&key| counts[key]).sum();
+        let mut seen = 0;
+        let median = keys.iter().position(|&key| {
+            seen += counts[key];
+            seen * 2 >= total
+        }).unwrap_or(0);
+        let upper = keys.split_off((median + 1).min(keys.len() - 1));
+        boxes.push(keys);
+        boxes.push(upper);
+    }
+
+    // Every box is the mean color of its pixels, every bucket maps to its box
+    let mut colors = Vec::with_capacity(boxes.len());
+    let mut table = vec![0u8; 1 << 15];
+    for (index, keys) in boxes.iter().enumerate() {
+        let total: u64 = keys.iter().map(|&key| counts[key]).sum::<u64>().max(1);
+        let mut color = [0u8; 3];
+        for (c, value) in color.iter_mut().enumerate() {
+            *value = (keys.iter().map(|&key| sums[key][c]).sum::<u64>() / total) as u8;
+        }
+        colors.push(color);
+        for &key in keys {
+            table[key] = index as u8;
+        }
+    }
+    let indices = rgba.chunks(4).map(|pixel| table[bucket(pixel[0], pixel[1], pixel[2])]).collect();
+    (colors, indices)
+}
+
+// Variable length LZW compression of 8 bit GIF image data, codes packed from the low bits
+fn lzw_encode(indices: &[u8]) -> Vec<u8> {
+    let clear: u16 = 1 << 8;
+    let end = clear + 1;
+    let mut output = Vec::new();
+    let (mut bits, mut bit_count) = (0u32, 0u32);
+    let mut code_size = 9;
+    let mut emit = |code: u16, code_size: u32, output: &mut Vec<u8>| {
+        bits |= (code as u32) << bit_count;
+        bit_count += code_size;
+        while bit_count >= 8 {
+            output.push(bits as u8);
+            bits >>= 8;
+            bit_count -= 8;
+        }
+    };
+
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end + 1;
+    emit(clear, code_size, &mut output);
+    let mut prefix = match indices.first() {
+        Some(&index) => index as u16,
+        None => {
+            emit(end, code_size, &mut output);
+            emit(0, 7, &mut output);
+            return output;
+        }
+    };
+    for &index in &indices[1..] {
+        if let Some(&code) = dictionary.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        emit(prefix, code_size, &mut output);
+        if next_code <= MAX_CODE {
+            dictionary.insert((prefix, index), next_code);
+            // The decoder widens its codes once the next code needs another bit
+            if next_code == 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+            next_code += 1;
+        }
+        else {
+            emit(clear, code_size, &mut output);
+            dictionary.clear();
+            next_code = end + 1;
+            code_size = 9;
+        }
+        prefix = index as u16;
+    }
+    emit(prefix, code_size, &mut output);
+    emit(end, code_size, &mut output);
+    // Flush the last partial byte
+    emit(0, 7, &mut output);
+    output
+}
@@ -0,0 +1,190 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, Float, Complex, Vec2};
+use crate::big_float::BigFloat;
+use crate::render::RenderSettings;
+
+// Maps the linear animation time [0:1] to the eased time [0:1]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl FromStr for Easing {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Easing, String> {
+        match text {
+            "linear" => Ok(Easing::Linear),
+            "ease-in" => Ok(Easing::EaseIn),
+            "ease-out" => Ok(Easing::EaseOut),
+            "ease-in-out" | "smoothstep" => Ok(Easing::EaseInOut),
+            _ => Err(format!("unknown easing '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for Easing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Easing::Linear => "linear",
+            Easing::EaseIn => "ease-in",
+            Easing::EaseOut => "ease-out",
+            Easing::EaseInOut => "ease-in-out",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Easing {
+    pub fn apply(&self, t: Real) -> Real {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+// View the animation passes through. Parameters that are None keep the value of the previous
+// keyframe, or of the base settings before the first one that sets them.
+#[derive(Copy, Clone)]
+pub struct Keyframe {
+    pub center: Vec2<BigFloat>,
+    pub view_width: Real,
+    // Degrees, see RenderSettings::rotation
+    pub rotation: Option<Real>,
+    pub iterations: Option<u32>,
+    // See Palette::offset
+    pub palette_offset: Option<Real>,
+    pub julia_c: Option<Vec2<Real>>,
+    // Easing of the segment to the next keyframe
+    pub easing: Easing,
+}
+
+impl Keyframe {
+    pub fn new(center: Vec2<BigFloat>, view_width: Real) -> Keyframe {
+        Keyframe {
+            center,
+            view_width,
+            rotation: None,
+            iterations: None,
+            palette_offset: None,
+            julia_c: None,
+            easing: Easing::Linear,
+        }
+    }
+}
+
+// Path of the Julia constant c of a morph animation
+#[derive(Copy, Clone)]
+pub enum JuliaPath {
+    Circle {center: Vec2<Real>, radius: Real},
+    Line {from: Vec2<Real>, to: Vec2<Real>},
+    // Main cardioid of the Mandelbrot set, c = m/2 - m^2/4 for multipliers m of the attracting
+    // fixed point with |m| = radius: inside the set below 1, on its boundary at 1
+    Boundary {radius: Real},
+}
+
+impl JuliaPath {
+    // Constant at time t in [0:1], starting at angle 0 for closed paths
+    pub fn point(&self, t: Real) -> Vec2<Real> {
+        let angle = 2.0 * std::f64::consts::PI * t;
+        match *self {
+            JuliaPath::Circle {center, radius} => Vec2::<Real>{x: center.x + radius * angle.cos(), y: center.y + radius * angle.sin()},
+            JuliaPath::Line {from, to} => Vec2::<Real>{x: from.x + (to.x - from.x) * t, y: from.y + (to.y - from.y) * t},
+            JuliaPath::Boundary {radius} => {
+                let m = Complex::new(radius * angle.cos(), radius * angle.sin());
+                let c = m * 0.5 - m.squared() * 0.25;
+                Vec2::<Real>{x: c.r, y: c.i}
+            }
+        }
+    }
+
+    // Whether the path ends where it starts, so the animation loops
+    pub fn is_closed(&self) -> bool {
+        !matches!(self, JuliaPath::Line {..})
+    }
+}
+
+// Zoom through at least two keyframes, every segment between two of them takes the same time
+pub struct Animation {
+    pub keyframes: Vec<Keyframe>,
+    pub frame_count: usize,
+    pub easing: Easing,
+    // Moves the Julia constant along the path over the whole animation instead of the keyframes
+    pub julia_path: Option<JuliaPath>,
+}
+
+impl Animation {
+    // Eased time of a frame, the first frame is at 0 and the last one at 1
+    pub fn frame_time(&self, frame: usize) -> Real {
+        if self.frame_count <= 1 {
+            return 0.0;
+        }
+        self.easing.apply(frame as Real / (self.frame_count - 1) as Real)
+    }
+
+    // Last value of a keyframe parameter set at or before the given keyframe
+    fn held<T: Copy>(&self, keyframe: usize, parameter: impl Fn(&Keyframe) -> Option<T>) -> Option<T> {
+        self.keyframes[..=keyframe].iter().rev().find_map(parameter)
+    }
+
+    // Settings of a frame, everything the keyframes don't set is taken from base
+    pub fn frame_settings(&self, base: &RenderSettings, frame: usize) -> RenderSettings {
+        let segments = self.keyframes.len().saturating_sub(1).max(1);
+        let position = self.frame_time(frame) * segments as Real;
+        let segment = (position.floor() as usize).min(segments - 1);
+        let next = (segment + 1).min(self.keyframes.len() - 1);
+        let start = &self.keyframes[segment];
+        let end = &self.keyframes[next];
+        let t = start.easing.apply(position - segment as Real);
+        // Zoom at a constant rate so every frame magnifies by the same factor
+        let view_width = start.view_width * (end.view_width / start.view_width).powf(t);
+        // Move the center in step with the zoom so the target doesn't leave the view
+        let weight = if (end.view_width - start.view_width).abs() > start.view_width * 1e-9 {
+            (view_width - start.view_width) / (end.view_width - start.view_width)
+        }
+        else {
+            t
+        };
+        let weight = BigFloat::from_real(weight);
+        let lerp = |a: Real, b: Real| a + (b - a) * t;
+        let mut settings = base.clone();
+        settings.center = Vec2::<BigFloat>{
+            x: start.center.x + (end.center.x - start.center.x) * weight,
+            y: start.center.y + (end.center.y - start.center.y) * weight,
+        };
+        settings.view_size = Vec2::<Real>{x: view_width, y: view_width * base.height as Real / base.width as Real};
+        let rotation = |keyframe| self.held(keyframe, |k| k.rotation).unwrap_or(base.rotation);
+        settings.rotation = lerp(rotation(segment), rotation(next));
+        settings.update_auto_iterations();
+        // Iteration counts grow with the zoom depth, so they change at a constant rate too
+        let iterations = |keyframe| self.held(keyframe, |k| k.iterations).map(|iterations| iterations as Real).unwrap_or(base.max_iterations as Real);
+        if self.held(next, |k| k.iterations).is_some() {
+            let (from, to) = (iterations(segment).max(1.0), iterations(next).max(1.0));
+            settings.max_iterations = (from * (to / from).powf(t)).round() as u32;
+        }
+        let palette_offset = |keyframe| self.held(keyframe, |k| k.palette_offset).unwrap_or(base.palette.offset);
+        settings.palette.offset = lerp(palette_offset(segment), palette_offset(next));
+        let julia_c = |keyframe| self.held(keyframe, |k| k.julia_c).unwrap_or(base.julia_c);
+        let (from, to) = (julia_c(segment), julia_c(next));
+        settings.julia_c = Vec2::<Real>{x: lerp(from.x, to.x), y: lerp(from.y, to.y)};
+        if let Some(path) = &self.julia_path {
+            // The frame after the last one of a closed path would repeat the first
+            let time = if path.is_closed() {
+                self.easing.apply(frame as Real / self.frame_count as Real)
+            }
+            else {
+                self.frame_time(frame)
+            };
+            settings.julia_c = path.point(time);
+        }
+        settings
+    }
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, Float, Vec2};
+use crate::color::Color;
+use crate::image::ImageBuffer;
+use crate::font::{GLYPH_HEIGHT, draw_text, text_width};
+use crate::overlay::{draw_segment, clip};
+use crate::render::RenderSettings;
+
+// Opacity of the gridlines, the axes are opaque
+const GRID_OPACITY: Real = 0.35;
+// Smallest view width relative to the center magnitude whose coordinates f64 labels tell apart
+const MIN_RELATIVE_VIEW: Real = 1e-12;
+
+// Coordinate annotations drawn over the image of a 2D fractal
+#[derive(Copy, Clone, PartialEq)]
+pub struct Annotations {
+    // Real and imaginary axes
+    pub axes: bool,
+    // Lines at round coordinates
+    pub grid: bool,
+    // Coordinates of the lines at round coordinates along the bottom and left edges, with ticks
+    pub labels: bool,
+    // Bar of a round length in the bottom right corner
+    pub scale_bar: bool,
+    pub color: Color,
+}
+
+impl Annotations {
+    pub fn new() -> Annotations {
+        Annotations {axes: false, grid: false, labels: false, scale_bar: false, color: Color {r: 1.0, g: 1.0, b: 1.0, a: 1.0}}
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !(self.axes || self.grid || self.labels || self.scale_bar)
+    }
+
+    // Fails for views without a complex plane or too deep for the labels
+    pub fn check(&self, settings: &RenderSettings) -> Result<(), String> {
+        if settings.fractal.is_3d() {
+            return Err(format!("{} fractals have no complex plane to annotate", settings.fractal));
+        }
+        let magnitude = settings.center.x.to_real().abs().max(settings.center.y.to_real().abs()).max(1.0);
+        if settings.view_size.x.min(settings.view_size.y) < magnitude * MIN_RELATIVE_VIEW {
+            return Err(String::from("the view is too deep to annotate its coordinates"));
+        }
+        Ok(())
+    }
+
+    pub fn draw(&self, image: &mut ImageBuffer, settings: &RenderSettings) {
+        // Larger text on larger images
+        let scale = (image.width.min(image.height) / 500).max(1);
+        let center = Vec2::<Real>{x: settings.center.x.to_real(), y: settings.center.y.to_real()};
+        let pixel = |x: Real, y: Real| settings.offset_to_pixel(Vec2::<Real>{x: x - center.x, y: y - center.y});
+        // Coordinates covered by the image, wider than the image when it's rotated or skewed
+        let corners = [(0.0, 0.0), (image.width as Real, 0.0), (0.0, image.height as Real), (image.width as Real, image.height as Real)]
+            .map(|(x, y)| settings.pixel_to_offset(x, y));
+        let low = Vec2::<Real>{x: center.x + corners.iter().map(|c| c.x).fold(Real::INFINITY, Real::min), y: center.y + corners.iter().map(|c| c.y).fold(Real::INFINITY, Real::min)};
+        let high = Vec2::<Real>{x: center.x + corners.iter().map(|c| c.x).fold(Real::NEG_INFINITY, Real::max), y: center.y + corners.iter().map(|c| c.y).fold(Real::NEG_INFINITY, Real::max)};
+        let units_per_pixel = settings.view_size.x / settings.width as Real;
+        let step = grid_step(low, high, units_per_pixel, scale);
+        let grid_color = Color {a: self.color.a * GRID_OPACITY, ..self.color};
+        let (text_height, gap) = (GLYPH_HEIGHT * scale, 3 * scale);
+
+        // Lines of constant real part, labeled where they meet the bottom edge
+        for k in (low.x / step).ceil() as i64..=(high.x / step).floor() as i64 {
+            let x = k as Real * step;
+            let (a, b) = (pixel(x, low.y), pixel(x, high.y));
+            if let Some(color) = self.line_color(k, grid_color) {
+                draw_segment(image, a, b, color);
+            }
+            if let (true, Some((a, b))) = (self.labels, clip(a, b, image.width as Real, image.height as Real)) {
+                let (bottom, top) = if a.y > b.y { (a, b) } else { (b, a) };
+                if bottom.y < image.height as Real - 1.0 {
+                    continue;
+                }
+                tick(image, bottom, top, 6.0 * scale as Real, self.color);
+                let text = label(k, step, "");
+                let left = (bottom.x as usize + gap).min(image.width.saturating_sub(text_width(&text, scale)));
+                shadowed_text(image, left, (bottom.y as usize).saturating_sub(text_height + gap), &text, scale, self.color);
+            }
+        }
+        // Lines of constant imaginary part, labeled where they meet the left edge
+        for k in (low.y / step).ceil() as i64..=(high.y / step).floor() as i64 {
+            let y = k as Real * step;
+            let (a, b) = (pixel(low.x, y), pixel(high.x, y));
+            if let Some(color) = self.line_color(k, grid_color) {
+                draw_segment(image, a, b, color);
+            }
+            if let (true, Some((a, b))) = (self.labels, clip(a, b, image.width as Real, image.height as Real)) {
+                let (left, right) = if a.x < b.x { (a, b) } else { (b, a) };
+                if left.x > 1.0 {
+                    continue;
+                }
+                tick(image, left, right, 6.0 * scale as Real, self.color);
+                let text = label(k, step, "i");
+                shadowed_text(image, left.x as usize + gap, (left.y as usize).saturating_sub(text_height + gap), &text, scale, self.color);
+            }
+        }
+        if self.scale_bar {
+            self.draw_scale_bar(image, units_per_pixel, scale);
+        }
+    }
+
+    // Color of the line at k steps from 0, None when only its label is drawn
+    fn line_color(&self, k: i64, grid_color: Color) -> Option<Color> {
+        if k == 0 && self.axes {
+            Some(self.color)
+        }
+        else if self.grid {
+            Some(grid_color)
+        }
+        else {
+            None
+        }
+    }
+
+    // Bar of the longest round length up to a fifth of the image width, with its length above it
+    fn draw_scale_bar(&self, image: &mut ImageBuffer, units_per_pixel: Real, scale: usize) {
+        let length = round_below(image.width as Real * units_per_pixel / 5.0);
+        let pixels = length / units_per_pixel;
+        let margin = (10 * scale) as Real;
+        let (right, bottom) = (image.width as Real - margin, image.height as Real - margin);
+        let left = right - pixels;
+        for row in 0..2 * scale {
+            let y = bottom - row as Real;
+            draw_segment(image, Vec2::<Real>{x: left, y}, Vec2::<Real>{x: right, y}, self.color);
+        }
+        for x in [left, right - 1.0] {
+            draw_segment(image, Vec2::<Real>{x, y: bottom}, Vec2::<Real>{x, y: bottom - (6 * scale) as Real}, self.color);
+        }
+        let text = format_length(length);
+        let text_left = (left + (pixels - text_width(&text, scale) as Real) / 2.0).max(0.0) as usize;
+        let text_top = (bottom as usize).saturating_sub((GLYPH_HEIGHT + 4) * scale + 2 * scale);
+        shadowed_text(image, text_left, text_top, &text, scale, self.color);
+    }
+}
+
+impl Default for Annotations {
+    fn default() -> Annotations {
+        Annotations::new()
+    }
+}
+
+impl FromStr for Annotations {
+    type Err = String;
+
+    // Comma separated parts: axes, grid, labels, scale-bar or all of them
+    fn from_str(text: &str) -> Result<Annotations, String> {
+        let mut annotations = Annotations::new();
+        for part in text.split(',') {
+            match part.trim() {
+                "axes" => annotations.axes = true,
+                "grid" => annotations.grid = true,
+                "labels" => annotations.labels = true,
+                "scale-bar" => annotations.scale_bar = true,
+                "all" => {
+                    annotations = Annotations {axes: true, grid: true, labels: true, scale_bar: true, ..annotations};
+                }
+                other => return Err(format!("unknown annotation '{}', expected axes, grid, labels, scale-bar or all", other)),
+            }
+        }
+        Ok(annotations)
+    }
+}
+
+impl fmt::Display for Annotations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts: Vec<&str> = [(self.axes, "axes"), (self.grid, "grid"), (self.labels, "labels"), (self.scale_bar, "scale-bar")]
+            .iter().filter(|(shown, _)| *shown).map(|(_, name)| *name).collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+// Short mark from the edge point towards the other end of the line
+fn tick(image: &mut ImageBuffer, edge: Vec2<Real>, other: Vec2<Real>, length: Real, color: Color) {
+    let (dx, dy) = (other.x - edge.x, other.y - edge.y);
+    let distance = (dx * dx + dy * dy).sqrt();
+    if distance > 0.0 {
+        let t = (length / distance).min(1.0);
+        draw_segment(image, edge, Vec2::<Real>{x: edge.x + dx * t, y: edge.y + dy * t}, color);
+    }
+}
+
+// Text with a dark outline one glyph pixel to the bottom right, readable over any color
+fn shadowed_text(image: &mut ImageBuffer, x: usize, y: usize, text: &str, scale: usize, color: Color) {
+    draw_text(image, x + scale, y + scale, text, scale, Color {r: 0.0, g: 0.0, b: 0.0, a: color.a});
+    draw_text(image, x, y, text, scale, color);
+}
+
+// Smallest round spacing (1, 2 or 5 times a power of ten) of at least 80 pixels whose labels fit
+// between the lines
+fn grid_step(low: Vec2<Real>, high: Vec2<Real>, units_per_pixel: Real, scale: usize) -> Real {
+    let mut step = round_above(80.0 * units_per_pixel);
+    loop {
+        let widest = [low.x, high.x, low.y, high.y].iter()
+            .map(|value| text_width(&label((value / step).round() as i64, step, "i"), scale))
+            .max().unwrap_or(0);
+        if step / units_per_pixel >= (widest + 6 * scale) as Real {
+            return step;
+        }
+        step = round_above(step * 1.5);
+    }
+}
+
+fn round_above(value: Real) -> Real {
+    let power = (10.0 as Real).powf(value.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter().map(|m| m * power).find(|&step| step >= value * (1.0 - 1e-9)).unwrap_or(10.0 * power)
+}
+
+fn round_below(value: Real) -> Real {
+    let power = (10.0 as Real).powf(value.log10().floor());
+    [5.0, 2.0, 1.0].iter().map(|m| m * power).find(|&step| step <= value * (1.0 + 1e-9)).unwrap_or(power)
+}
+
+// Decimals that tell multiples of the step apart
+fn decimals(step: Real) -> usize {
+    (-step.log10() - 1e-9).ceil().max(0.0) as usize
+}
+
+fn label(k: i64, step: Real, suffix: &str) -> String {
+    if k == 0 {
+        return format!("0{}", suffix);
+    }
+    format!("{:.*}{}", decimals(step), k as Real * step, suffix)
+}
+
+// Length of the scale bar, in scientific notation when plain decimals get long
+fn format_length(length: Real) -> String {
+    if (1e-3..1e4).contains(&length) {
+        format!("{:.*}", decimals(length), length)
+    }
+    else {
+        format!("{:e}", length)
+    }
+}
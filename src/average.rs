@@ -0,0 +1,56 @@
+use crate::math::{Real, Complex, clamp};
+use crate::coloring::Coloring;
+use crate::render::{RenderSettings, EscapeResult};
+
+// Parameters of the colorings that average a quantity over the orbit (stripe and triangle
+// inequality average)
+#[derive(Copy, Clone, PartialEq)]
+pub struct OrbitAverage {
+    // Number of stripes around the origin, sin(frequency * arg z) is averaged
+    pub frequency: Real,
+    // Weight of the average against the smooth coloring, 1 colors by the average alone
+    pub mix: Real,
+}
+
+impl OrbitAverage {
+    pub fn new() -> OrbitAverage {
+        OrbitAverage {frequency: 5.0, mix: 1.0}
+    }
+
+    // Averaged quantity in [0:1] of the orbit value z = f(previous) + c
+    pub fn term(&self, coloring: Coloring, z: &Complex<Real>, c: &Complex<Real>) -> Real {
+        match coloring {
+            Coloring::Stripe => 0.5 * (self.frequency * z.i.atan2(z.r)).sin() + 0.5,
+            // Position of |z| between the bounds |f(previous)| +- |c| of the triangle inequality
+            _ => {
+                let step = (*z - *c).length();
+                let c_length = c.length();
+                let low = (step - c_length).abs();
+                let high = step + c_length;
+                if high > low { (z.length() - low) / (high - low) } else { 0.0 }
+            }
+        }
+    }
+
+    // Average of the orbit interpolated between the last two iterations with the fractional
+    // part of the smooth iteration count, which makes it continuous across iteration bands
+    pub fn value(&self, settings: &RenderSettings, result: &EscapeResult) -> Real {
+        // The first iteration isn't averaged
+        let count = result.iterations.saturating_sub(1) as Real;
+        if count < 2.0 {
+            return clamp(result.average_sum, 0.0, 1.0);
+        }
+        let average = result.average_sum / count;
+        let previous = (result.average_sum - result.average_last) / (count - 1.0);
+        let degree = settings.fractal.degree(settings.power);
+        let log_length = result.z.length().ln();
+        let fraction = clamp(1.0 + (settings.max_length().ln() / log_length).ln() / degree.ln(), 0.0, 1.0);
+        clamp(fraction * average + (1.0 - fraction) * previous, 0.0, 1.0)
+    }
+}
+
+impl Default for OrbitAverage {
+    fn default() -> OrbitAverage {
+        OrbitAverage::new()
+    }
+}
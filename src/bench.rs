@@ -0,0 +1,116 @@
+// Benchmark of the rendering backends on standard scenes. Every scene is rendered with the
+// scalar kernel and, where it applies, the AVX kernel on 1, 2, 4 ... threads up to one per
+// core, with fixed settings so the rates can be compared between versions and machines.
+
+use std::time::Instant;
+use mandelbrot_set::{Real, Vec2, BigFloat, RenderSettings, Precision, Coloring, render_with_progress, simd_available};
+
+pub struct BenchScene {
+    pub name: &'static str,
+    center: (&'static str, &'static str),
+    view_width: Real,
+    iterations: u32,
+    precision: Precision,
+}
+
+pub const BENCH_SCENES: [BenchScene; 3] = [
+    BenchScene {name: "wide", center: ("-0.75", "0.1"), view_width: 3.5, iterations: 500, precision: Precision::Double},
+    BenchScene {name: "boundary", center: ("-0.7453", "0.1127"), view_width: 6.5e-3, iterations: 2000, precision: Precision::Double},
+    BenchScene {name: "deep", center: ("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139"),
+        view_width: 4e-10, iterations: 10000, precision: Precision::Perturbation},
+];
+
+pub struct BenchOptions {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_threads: usize,
+    // Best of this many renders per configuration
+    pub repeat: usize,
+    // Names of the scenes to run, all when empty
+    pub scenes: Vec<String>,
+}
+
+impl BenchOptions {
+    pub fn new() -> BenchOptions {
+        BenchOptions {
+            width: 320,
+            height: 240,
+            samples: 4,
+            max_threads: RenderSettings::new().thread_count,
+            repeat: 1,
+            scenes: Vec::new(),
+        }
+    }
+}
+
+impl BenchScene {
+    fn settings(&self, options: &BenchOptions) -> RenderSettings {
+        let mut settings = RenderSettings::new();
+        settings.width = options.width;
+        settings.height = options.height;
+        settings.center = Vec2::<BigFloat>{x: self.center.0.parse().unwrap(), y: self.center.1.parse().unwrap()};
+        settings.view_size = Vec2::<Real>{x: self.view_width, y: self.view_width * options.height as Real / options.width as Real};
+        settings.max_iterations = self.iterations;
+        settings.precision = self.precision;
+        settings.coloring = Coloring::Smooth;
+        // Same work on every run: no early sample stop, no mirrored rows
+        settings.sample_count = options.samples;
+        settings.min_sample_count = options.samples;
+        settings.symmetry = false;
+        settings.seed = Some(1);
+        settings
+    }
+}
+
+// 1, 2, 4 ... and the maximum
+fn thread_counts(max_threads: usize) -> Vec<usize> {
+    let mut counts: Vec<usize> = (0..).map(|shift| 1 << shift).take_while(|&count| count < max_threads).collect();
+    counts.push(max_threads.max(1));
+    counts
+}
+
+// Seconds and samples of the fastest of repeat renders
+fn measure(settings: &RenderSettings, repeat: usize) -> (f64, usize) {
+    let mut best = (f64::INFINITY, 0);
+    for _ in 0..repeat.max(1) {
+        let mut samples = 0;
+        let start = Instant::now();
+        render_with_progress(settings, |progress| samples = progress.samples);
+        let seconds = start.elapsed().as_secs_f64();
+        if seconds < best.0 {
+            best = (seconds, samples);
+        }
+    }
+    best
+}
+
+pub fn run(options: &BenchOptions) -> Result<(), String> {
+    for name in &options.scenes {
+        if !BENCH_SCENES.iter().any(|scene| scene.name == name) {
+            return Err(format!("unknown bench scene '{}', expected wide, boundary or deep", name));
+        }
+    }
+    let simd = simd_available();
+    println!("{}x{} pixels, {} samples per pixel, best of {}{}", options.width, options.height, options.samples, options.repeat.max(1),
+        if simd { "" } else { ", AVX isn't supported by this CPU" });
+    println!("{:<10}{:<9}{:>8}{:>11}{:>12}{:>14}{:>9}", "scene", "backend", "threads", "time", "Mpx/s", "Msamples/s", "speedup");
+    let pixels = (options.width * options.height) as f64;
+    for scene in BENCH_SCENES.iter().filter(|scene| options.scenes.is_empty() || options.scenes.iter().any(|name| name == scene.name)) {
+        // The vectorized kernel only runs double precision
+        let backends: &[(&str, bool)] = if simd && scene.precision == Precision::Double { &[("scalar", false), ("simd", true)] } else { &[("scalar", false)] };
+        let mut baseline = None;
+        for &(backend, vectorize) in backends {
+            for threads in thread_counts(options.max_threads) {
+                let mut settings = scene.settings(options);
+                settings.simd = vectorize;
+                settings.thread_count = threads;
+                let (seconds, samples) = measure(&settings, options.repeat);
+                let baseline = *baseline.get_or_insert(seconds);
+                println!("{:<10}{:<9}{:>8}{:>9.0}ms{:>12.2}{:>14.2}{:>8.2}x", scene.name, backend, threads, seconds * 1e3,
+                    pixels / seconds / 1e6, samples as f64 / seconds / 1e6, baseline / seconds);
+            }
+        }
+    }
+    Ok(())
+}
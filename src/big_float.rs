@@ -0,0 +1,360 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Neg};
+use std::str::FromStr;
+use crate::math::{Real, Float};
+
+// Maximum number of 32 bit limbs, the first one holds the integer part
+pub const MAX_LIMBS: usize = 34;
+
+// Signed fixed point number with a runtime number of 32 bit limbs.
+// limbs[k] has the weight 2^(-32k), the integer part is limited to 32 bits.
+// Binary operations work with the larger precision of both operands.
+#[derive(Copy, Clone)]
+pub struct BigFloat {
+    negative: bool,
+    len: usize,
+    limbs: [u32; MAX_LIMBS],
+}
+
+impl BigFloat {
+    pub fn zero() -> BigFloat {
+        BigFloat {negative: false, len: 1, limbs: [0; MAX_LIMBS]}
+    }
+
+    // Number of limbs needed to hold the given number of fractional bits
+    pub fn limbs_for_bits(bits: usize) -> usize {
+        (1 + bits.div_ceil(32)).clamp(2, MAX_LIMBS)
+    }
+
+    pub fn precision(&self) -> usize {
+        self.len
+    }
+
+    // Truncates or extends the number to the given number of limbs
+    pub fn with_precision(&self, limbs: usize) -> BigFloat {
+        let limbs = limbs.clamp(1, MAX_LIMBS);
+        let mut result = *self;
+        for limb in result.limbs.iter_mut().skip(limbs) {
+            *limb = 0;
+        }
+        result.len = limbs;
+        result.normalize_sign();
+        result
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs[..self.len].iter().all(|&limb| limb == 0)
+    }
+
+    pub fn abs(&self) -> BigFloat {
+        BigFloat {negative: false, ..*self}
+    }
+
+    fn normalize_sign(&mut self) {
+        if self.is_zero() {
+            self.negative = false;
+        }
+    }
+
+    fn cmp_magnitude(&self, rhs: &BigFloat) -> Ordering {
+        let len = self.len.max(rhs.len);
+        self.limbs[..len].cmp(&rhs.limbs[..len])
+    }
+
+    // Adds the magnitudes ignoring the signs, overflow of the integer part wraps around
+    fn add_magnitude(&self, rhs: &BigFloat) -> BigFloat {
+        let len = self.len.max(rhs.len);
+        let mut result = BigFloat {negative: self.negative, len, limbs: [0; MAX_LIMBS]};
+        let mut carry = 0u64;
+        for k in (0..len).rev() {
+            let sum = self.limbs[k] as u64 + rhs.limbs[k] as u64 + carry;
+            result.limbs[k] = sum as u32;
+            carry = sum >> 32;
+        }
+        result
+    }
+
+    // Subtracts the magnitudes assuming |self| >= |rhs|
+    fn sub_magnitude(&self, rhs: &BigFloat) -> BigFloat {
+        let len = self.len.max(rhs.len);
+        let mut result = BigFloat {negative: self.negative, len, limbs: [0; MAX_LIMBS]};
+        let mut borrow = 0i64;
+        for k in (0..len).rev() {
+            let mut difference = self.limbs[k] as i64 - rhs.limbs[k] as i64 - borrow;
+            borrow = 0;
+            if difference < 0 {
+                difference += 1 << 32;
+                borrow = 1;
+            }
+            result.limbs[k] = difference as u32;
+        }
+        result.normalize_sign();
+        result
+    }
+
+    // Converts to a decimal string rounded to the given number of fractional digits
+    pub fn to_string_digits(&self, digits: usize) -> String {
+        // Exact digits of the fraction plus one for rounding
+        let mut fraction_digits = Vec::with_capacity(digits + 1);
+        let mut fraction = self.limbs;
+        for _ in 0..=digits {
+            let mut carry = 0u64;
+            for k in (1..self.len).rev() {
+                let product = fraction[k] as u64 * 10 + carry;
+                fraction[k] = product as u32;
+                carry = product >> 32;
+            }
+            fraction_digits.push(carry as u8);
+        }
+        let mut integer = self.limbs[0] as u64;
+        if fraction_digits.pop().unwrap_or(0) >= 5 {
+            let mut carry = true;
+            for d in fraction_digits.iter_mut().rev() {
+                if *d == 9 {
+                    *d = 0;
+                }
+                else {
+                    *d += 1;
+                    carry = false;
+                    break;
+                }
+            }
+            if carry {
+                integer += 1;
+            }
+        }
+        let mut text = String::new();
+        if self.negative && (integer != 0 || fraction_digits.iter().any(|&d| d != 0)) {
+            text.push('-');
+        }
+        text.push_str(&integer.to_string());
+        if digits > 0 {
+            text.push('.');
+            text.extend(fraction_digits.iter().map(|&d| (b'0' + d) as char));
+        }
+        text
+    }
+}
+
+impl Default for BigFloat {
+    fn default() -> BigFloat {
+        BigFloat::zero()
+    }
+}
+
+impl PartialEq for BigFloat {
+    fn eq(&self, rhs: &BigFloat) -> bool {
+        self.partial_cmp(rhs) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for BigFloat {
+    fn partial_cmp(&self, rhs: &BigFloat) -> Option<Ordering> {
+        Some(match (self.negative, rhs.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => self.cmp_magnitude(rhs),
+            (true, true) => rhs.cmp_magnitude(self),
+        })
+    }
+}
+
+impl Neg for BigFloat {
+    type Output = BigFloat;
+
+    fn neg(self) -> BigFloat {
+        let mut result = self;
+        result.negative = !self.negative;
+        result.normalize_sign();
+        result
+    }
+}
+
+impl Add for BigFloat {
+    type Output = BigFloat;
+
+    fn add(self, rhs: BigFloat) -> BigFloat {
+        if self.negative == rhs.negative {
+            return self.add_magnitude(&rhs);
+        }
+        if self.cmp_magnitude(&rhs) == Ordering::Less {
+            rhs.sub_magnitude(&self)
+        }
+        else {
+            self.sub_magnitude(&rhs)
+        }
+    }
+}
+
+impl Sub for BigFloat {
+    type Output = BigFloat;
+
+    fn sub(self, rhs: BigFloat) -> BigFloat {
+        self + (-rhs)
+    }
+}
+
+impl Mul for BigFloat {
+    type Output = BigFloat;
+
+    fn mul(self, rhs: BigFloat) -> BigFloat {
+        let len = self.len.max(rhs.len);
+        // columns[k+1] accumulates the weight 2^(-32k), columns[0] is the overflow
+        let mut columns = [0u64; MAX_LIMBS + 2];
+        for i in 0..len {
+            if self.limbs[i] == 0 {
+                continue;
+            }
+            for j in 0..(len + 1 - i).min(len) {
+                let product = self.limbs[i] as u64 * rhs.limbs[j] as u64;
+                columns[i + j + 1] += product & 0xFFFF_FFFF;
+                columns[i + j] += product >> 32;
+            }
+        }
+        for k in (1..=len + 1).rev() {
+            columns[k - 1] += columns[k] >> 32;
+            columns[k] &= 0xFFFF_FFFF;
+        }
+        let mut result = BigFloat {negative: self.negative != rhs.negative, len, limbs: [0; MAX_LIMBS]};
+        for k in 0..len {
+            result.limbs[k] = columns[k + 1] as u32;
+        }
+        result.normalize_sign();
+        result
+    }
+}
+
+impl Float for BigFloat {
+    fn from_real(value: Real) -> BigFloat {
+        let mut result = BigFloat::zero();
+        let mut magnitude = value.abs();
+        if !magnitude.is_finite() {
+            return result;
+        }
+        let integer = magnitude.floor().min(u32::MAX as Real);
+        result.limbs[0] = integer as u32;
+        magnitude -= integer;
+        // Scaling by 2^32 and removing the integer part is exact
+        while magnitude > 0.0 && result.len < MAX_LIMBS {
+            magnitude *= 4294967296.0;
+            let limb = magnitude.floor();
+            result.limbs[result.len] = limb as u32;
+            result.len += 1;
+            magnitude -= limb;
+        }
+        result.negative = value < 0.0;
+        result.normalize_sign();
+        result
+    }
+
+    fn to_real(self) -> Real {
+        let mut value = 0.0;
+        for k in (0..self.len).rev() {
+            value = value / 4294967296.0 + self.limbs[k] as Real;
+        }
+        if self.negative {
+            -value
+        }
+        else {
+            value
+        }
+    }
+
+    fn sqrt(self) -> BigFloat {
+        // Only used for bailout tests, so f64 accuracy is enough
+        BigFloat::from_real(self.to_real().sqrt())
+    }
+
+    fn abs(self) -> BigFloat {
+        BigFloat::abs(&self)
+    }
+
+    fn from_big(value: &BigFloat) -> BigFloat {
+        *value
+    }
+}
+
+impl FromStr for BigFloat {
+    type Err = String;
+
+    // Parses a decimal number like -1.25e-3 keeping every given digit
+    fn from_str(text: &str) -> Result<BigFloat, String> {
+        let invalid = || format!("invalid number '{}'", text);
+        let trimmed = text.trim();
+        let (negative, body) = match trimmed.strip_prefix('-') {
+            Some(body) => (true, body),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        let (mantissa, exponent) = match body.find(['e', 'E']) {
+            Some(i) => (&body[..i], body[i+1..].parse::<i32>().map_err(|_| invalid())?),
+            None => (body, 0),
+        };
+        let (integer_digits, fraction_digits) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if integer_digits.is_empty() && fraction_digits.is_empty() {
+            return Err(invalid());
+        }
+        let mut digits: Vec<u8> = Vec::new();
+        for c in integer_digits.chars().chain(fraction_digits.chars()) {
+            digits.push(c.to_digit(10).ok_or_else(invalid)? as u8);
+        }
+        // Position of the decimal point inside digits after applying the exponent
+        let point = integer_digits.len() as i64 + exponent as i64;
+        if point > 10 {
+            let leading_zeros = digits.iter().take_while(|&&d| d == 0).count() as i64;
+            if point - leading_zeros > 10 {
+                return Err(format!("number '{}' is too large", text));
+            }
+        }
+        if point < 0 {
+            let mut padded = vec![0u8; (-point) as usize];
+            padded.extend(digits);
+            digits = padded;
+        }
+        let point = point.max(0) as usize;
+        if digits.len() < point {
+            digits.resize(point, 0);
+        }
+
+        let mut result = BigFloat::zero();
+        let mut integer = 0u64;
+        for &d in &digits[..point] {
+            integer = integer * 10 + d as u64;
+        }
+        if integer > u32::MAX as u64 {
+            return Err(format!("number '{}' is too large", text));
+        }
+        result.limbs[0] = integer as u32;
+
+        // Keep enough limbs for every significant digit plus a guard limb
+        let mut fraction: Vec<u8> = digits[point..].to_vec();
+        result.len = BigFloat::limbs_for_bits(fraction.len() * 3322 / 1000 + 32);
+        for k in 1..result.len {
+            // Multiply the decimal fraction by 2^32, the overflow is the next limb
+            let mut carry = 0u64;
+            for d in fraction.iter_mut().rev() {
+                let value = *d as u64 * 4294967296 + carry;
+                *d = (value % 10) as u8;
+                carry = value / 10;
+            }
+            result.limbs[k] = carry as u32;
+        }
+        result.negative = negative;
+        result.normalize_sign();
+        Ok(result)
+    }
+}
+
+impl fmt::Display for BigFloat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Each limb holds a bit more than 9 decimal digits
+        let digits = f.precision().unwrap_or((self.len - 1) * 9);
+        write!(f, "{}", self.to_string_digits(digits))
+    }
+}
+
+impl fmt::Debug for BigFloat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
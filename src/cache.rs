@@ -0,0 +1,210 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use crate::math::{Real, Complex};
+use crate::color::Color;
+use crate::coloring::{Coloring, Histogram};
+use crate::image::{ImageBuffer, create_file};
+use crate::render::{RenderSettings, EscapeResult};
+
+const MAGIC: &[u8; 8] = b"MSITER01";
+
+// Escape results of every sample of a rendered image, recoloring it only needs the palette
+// and coloring to change.
+// File layout, little endian: magic, scene text length (u32) and UTF-8 text, width and
+// height (u32), flags (u8, 1: derivatives are stored, 2: trap distances are stored, 16: trap
+// image points are stored, 32: atom domains are stored), then per pixel the sample count (u32)
+// followed by every sample: iterations (u32), z (2 x f32), the derivative (2 x f64), the trap
+// distance (f32), the trap image point (2 x f32) and the atom domain (u32).
+pub struct IterationCache {
+    pub width: usize,
+    pub height: usize,
+    // Whether the samples carry the derivative needed by the distance coloring
+    pub derivative: bool,
+    // Whether the samples carry the trap distance needed by the orbit trap coloring
+    pub trap: bool,
+    // Whether the samples carry the first orbit value on the trap image of the image trap coloring
+    pub trap_point: bool,
+    // Whether the samples carry the atom domain of the atom domain coloring
+    pub atom_domain: bool,
+    // Whether interior samples were iterated and carry their period and interior distance
+    pub interior: bool,
+    // Whether the samples carry the orbit average of the stripe and triangle inequality colorings
+    pub average: bool,
+    // Row major, the results of every sample of a pixel
+    pub samples: Vec<Vec<EscapeResult>>,
+}
+
+impl IterationCache {
+    // Writes the cache along with scene, a description of the render settings
+    pub fn save(&self, path: &str, scene: &str) -> Result<(), String> {
+        let error = |e: std::io::Error| format!("cannot write {}: {}", path, e);
+        let file = create_file(path)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(MAGIC).map_err(error)?;
+        writer.write_all(&(scene.len() as u32).to_le_bytes()).map_err(error)?;
+        writer.write_all(scene.as_bytes()).map_err(error)?;
+        writer.write_all(&(self.width as u32).to_le_bytes()).map_err(error)?;
+        writer.write_all(&(self.height as u32).to_le_bytes()).map_err(error)?;
+        writer.write_all(&[self.derivative as u8 | (self.trap as u8) << 1 | (self.interior as u8) << 2 | (self.average as u8) << 3 | (self.trap_point as u8) << 4 | (self.atom_domain as u8) << 5]).map_err(error)?;
+        for pixel in &self.samples {
+            writer.write_all(&(pixel.len() as u32).to_le_bytes()).map_err(error)?;
+            for result in pixel {
+                writer.write_all(&result.iterations.to_le_bytes()).map_err(error)?;
+                writer.write_all(&(result.z.r as f32).to_le_bytes()).map_err(error)?;
+                writer.write_all(&(result.z.i as f32).to_le_bytes()).map_err(error)?;
+                if self.derivative {
+                    writer.write_all(&result.derivative.r.to_le_bytes()).map_err(error)?;
+                    writer.write_all(&result.derivative.i.to_le_bytes()).map_err(error)?;
+                }
+                if self.trap {
+                    writer.write_all(&(result.trap as f32).to_le_bytes()).map_err(error)?;
+                }
+                if self.trap_point {
+                    writer.write_all(&(result.trap_point.r as f32).to_le_bytes()).map_err(error)?;
+                    writer.write_all(&(result.trap_point.i as f32).to_le_bytes()).map_err(error)?;
+                }
+                if self.atom_domain {
+                    writer.write_all(&result.atom_domain.to_le_bytes()).map_err(error)?;
+                }
+                if self.interior {
+                    writer.write_all(&result.period.to_le_bytes()).map_err(error)?;
+                    writer.write_all(&(result.interior_distance as f32).to_le_bytes()).map_err(error)?;
+                }
+                if self.average {
+                    writer.write_all(&(result.average_sum as f32).to_le_bytes()).map_err(error)?;
+                    writer.write_all(&(result.average_last as f32).to_le_bytes()).map_err(error)?;
+                }
+            }
+        }
+        writer.flush().map_err(error)
+    }
+
+    // Reads a cache and the scene text stored with it
+    pub fn load(path: &str) -> Result<(IterationCache, String), String> {
+        let file = File::open(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let mut reader = BufReader::new(file);
+        let error = |e: std::io::Error| format!("cannot read {}: {}", path, e);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic).map_err(error)?;
+        if &magic != MAGIC {
+            return Err(format!("{} is not an iteration cache", path));
+        }
+        let scene_length = read_u32(&mut reader).map_err(error)? as usize;
+        let mut scene = vec![0u8; scene_length];
+        reader.read_exact(&mut scene).map_err(error)?;
+        let scene = String::from_utf8(scene).map_err(|_| format!("{}: the scene is not valid UTF-8", path))?;
+        let width = read_u32(&mut reader).map_err(error)? as usize;
+        let height = read_u32(&mut reader).map_err(error)? as usize;
+        let mut flag = [0u8; 1];
+        reader.read_exact(&mut flag).map_err(error)?;
+        let derivative = flag[0] & 1 != 0;
+        let trap = flag[0] & 2 != 0;
+        let interior = flag[0] & 4 != 0;
+        let average = flag[0] & 8 != 0;
+        let trap_point = flag[0] & 16 != 0;
+        let atom_domain = flag[0] & 32 != 0;
+
+        let mut samples = Vec::with_capacity(width * height);
+        for _ in 0..width * height {
+            let count = read_u32(&mut reader).map_err(error)? as usize;
+            let mut pixel = Vec::with_capacity(count);
+            for _ in 0..count {
+                let iterations = read_u32(&mut reader).map_err(error)?;
+                let z = Complex::new(read_f32(&mut reader).map_err(error)? as Real, read_f32(&mut reader).map_err(error)? as Real);
+                let derivative = if derivative {
+                    Complex::new(read_f64(&mut reader).map_err(error)?, read_f64(&mut reader).map_err(error)?)
+                }
+                else {
+                    Complex::new(1.0, 0.0)
+                };
+                let trap = if trap { read_f32(&mut reader).map_err(error)? as Real } else { Real::INFINITY };
+                let trap_point = if trap_point {
+                    Complex::new(read_f32(&mut reader).map_err(error)? as Real, read_f32(&mut reader).map_err(error)? as Real)
+                }
+                else {
+                    Complex::new(Real::NAN, Real::NAN)
+                };
+                let atom_domain = if atom_domain { read_u32(&mut reader).map_err(error)? } else { 0 };
+                let (period, interior_distance) = if interior {
+                    (read_u32(&mut reader).map_err(error)?, read_f32(&mut reader).map_err(error)? as Real)
+                }
+                else {
+                    (0, Real::INFINITY)
+                };
+                let (average_sum, average_last) = if average {
+                    (read_f32(&mut reader).map_err(error)? as Real, read_f32(&mut reader).map_err(error)? as Real)
+                }
+                else {
+                    (0.0, 0.0)
+                };
+                pixel.push(EscapeResult {iterations, z, derivative, trap, trap_point, atom_domain, period, interior_distance, average_sum, average_last});
+            }
+            samples.push(pixel);
+        }
+        Ok((IterationCache {width, height, derivative, trap, trap_point, atom_domain, interior, average, samples}, scene))
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> std::io::Result<f32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> std::io::Result<f64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+// Colors the cached samples with the palette and coloring of settings, the view of settings
+// has to match the one the cache was rendered with
+pub fn recolor(settings: &RenderSettings, cache: &IterationCache) -> Result<ImageBuffer, String> {
+    if settings.width != cache.width || settings.height != cache.height {
+        return Err(format!("the cache is {}x{} pixels, not {}x{}", cache.width, cache.height, settings.width, settings.height));
+    }
+    if settings.tracks_derivative() && !cache.derivative {
+        return Err(String::from("the cache has no derivatives, render it with --coloring distance or --lighting to recolor it this way"));
+    }
+    if settings.coloring.needs_trap() && !cache.trap {
+        return Err(format!("the cache has no trap distances, render it with --coloring {} to recolor it this way", settings.coloring));
+    }
+    if settings.tracked_trap_image().is_some() && !cache.trap_point {
+        return Err(format!("the cache has no trap image points, render it with --coloring {} to recolor it this way", settings.coloring));
+    }
+    if settings.tracks_atom_domain() && !cache.atom_domain {
+        return Err(format!("the cache has no atom domains, render it with --coloring {} to recolor it this way", settings.coloring));
+    }
+    if settings.coloring.needs_average() && !cache.average {
+        return Err(format!("the cache has no orbit averages, render it with --coloring {} to recolor it this way", settings.coloring));
+    }
+    if settings.interior.needs_orbit() && !cache.interior {
+        return Err(format!("the cache has no interior orbits, render it with --interior {} to recolor it this way", settings.interior));
+    }
+    let histogram = if settings.coloring == Coloring::Histogram {
+        let results: Vec<EscapeResult> = cache.samples.iter().flatten().copied().collect();
+        Some(Histogram::new(&results, settings.max_iterations))
+    }
+    else {
+        None
+    };
+    // Samples are averaged in linear light like in the render
+    let linear = settings.linear_colors();
+    let mut image = ImageBuffer::new(cache.width, cache.height);
+    for (pixel, results) in image.pixels.iter_mut().zip(&cache.samples) {
+        let mut sum = Color::new();
+        for result in results {
+            sum.add(linear.coloring.color(&linear, result, histogram.as_ref()).premultiplied());
+        }
+        sum.divide(results.len().max(1) as Real);
+        *pixel = settings.gamma.encode_color(sum.unpremultiplied());
+    }
+    settings.post.apply(&mut image, settings.gamma, 0, cache.height);
+    Ok(image)
+}
@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::color::Color;
+use crate::render::{TILE_WIDTH, TILE_HEIGHT};
+
+const MANIFEST_NAME: &str = "manifest.toml";
+
+// Directory holding the finished tiles of a render so an interrupted render can continue.
+// The manifest describes the scene, tiles are stored as f32 RGBA values named by their offset.
+pub struct Checkpoint {
+    directory: PathBuf,
+    manifest: String,
+    // Nothing is written until the render is interrupted, then all of its finished tiles are
+    deferred: bool,
+}
+
+impl Checkpoint {
+    // Starts a new checkpoint for scene, or continues the one in directory when resume is set.
+    // Resuming fails when the stored scene differs from scene.
+    pub fn new(directory: &str, scene: &str, resume: bool) -> Result<Checkpoint, String> {
+        let checkpoint = Checkpoint::deferred(directory, scene);
+        if resume {
+            let manifest_path = checkpoint.directory.join(MANIFEST_NAME);
+            let stored = fs::read_to_string(&manifest_path).map_err(|e| format!("cannot resume from {}: {}", manifest_path.display(), e))?;
+            if stored != checkpoint.manifest {
+                return Err(format!("cannot resume from {}, it was made with different settings", directory));
+            }
+            return Ok(Checkpoint {deferred: false, ..checkpoint});
+        }
+        checkpoint.start()?;
+        Ok(Checkpoint {deferred: false, ..checkpoint})
+    }
+
+    // Checkpoint for scene that is only written when the render is interrupted, for renders
+    // that didn't ask for one
+    pub fn deferred(directory: &str, scene: &str) -> Checkpoint {
+        Checkpoint {
+            directory: PathBuf::from(directory),
+            manifest: format!("{}tile-width = {}\ntile-height = {}\n", scene, TILE_WIDTH, TILE_HEIGHT),
+            deferred: true,
+        }
+    }
+
+    pub fn is_deferred(&self) -> bool {
+        self.deferred
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    // Creates the directory and writes the manifest, replacing the tiles of an earlier render
+    pub fn start(&self) -> Result<(), String> {
+        fs::create_dir_all(&self.directory).map_err(|e| format!("cannot create {}: {}", self.directory.display(), e))?;
+        self.remove()?;
+        let manifest_path = self.directory.join(MANIFEST_NAME);
+        fs::write(&manifest_path, &self.manifest).map_err(|e| format!("cannot write {}: {}", manifest_path.display(), e))
+    }
+
+    fn tile_path(&self, x: usize, y: usize) -> PathBuf {
+        self.directory.join(format!("tile_{}_{}.rgba", x, y))
+    }
+
+    // Colors of the finished tile at pixel offset (x, y), None if it wasn't rendered yet
+    pub fn load_tile(&self, x: usize, y: usize, pixel_count: usize) -> Option<Vec<Color>> {
+        let bytes = fs::read(self.tile_path(x, y)).ok()?;
+        if bytes.len() != pixel_count * 16 {
+            return None;
+        }
+        let value = |i: usize| f32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]) as f64;
+        Some((0..pixel_count).map(|pixel| {
+            let i = pixel * 16;
+            Color {r: value(i), g: value(i + 4), b: value(i + 8), a: value(i + 12)}
+        }).collect())
+    }
+
+    // Writes the tile through a temporary file so interruptions never leave partial tiles
+    pub fn save_tile(&self, x: usize, y: usize, colors: &[Color]) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity(colors.len() * 16);
+        for color in colors {
+            for value in [color.r, color.g, color.b, color.a] {
+                bytes.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+        }
+        let path = self.tile_path(x, y);
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, bytes).map_err(|e| format!("cannot write {}: {}", temporary.display(), e))?;
+        fs::rename(&temporary, &path).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    }
+
+    // Deletes the manifest and tiles, other files in the directory are kept
+    pub fn remove(&self) -> Result<(), String> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()),
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_tile = name.starts_with("tile_") && (name.ends_with(".rgba") || name.ends_with(".tmp"));
+            if is_tile || name == MANIFEST_NAME {
+                fs::remove_file(entry.path()).map_err(|e| format!("cannot remove {}: {}", entry.path().display(), e))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1047 @@
+use std::str::FromStr;
+use std::time::Duration;
+use mandelbrot_set::{Real, Float, Vec2, BigFloat, Fractal, Coloring, Transparency, PostProcess, Palette, PaletteSource, Interpolation, Color, parse_color, RenderSettings, ImageSettings, prepare_output, find_preset, Location, Lighting, Skew, TrapImage, IterationRange, Keyframe, Easing, JuliaPath, parse_config_file, parse_table_file, write_toml};
+
+pub const DEFAULT_VIEW_WIDTH: Real = 4.0;
+
+pub const RENDER_OPTIONS: &str = "Render options:
+    --fractal <type>        Fractal: mandelbrot, julia, burning-ship, tricorn, celtic,
+                            multibrot, formula, newton, nova, phoenix, magnet1, magnet2,
+                            lyapunov, hybrid, mandelbulb or quaternion-julia
+                            (default: mandelbrot)
+    --formula <expression>  Iterate z = expression from z = 0 at f64 precision, e.g.
+                            \"z^3 + c*z + c\"; knows z, c, i, pi, + - * / ^ and the
+                            functions sin, cos, exp, log, sqrt, conj, abs, re and im
+    --polynomial <p>        Render the Newton fractal of the polynomial, given by its
+                            coefficients from the highest power down (1/0/0/-1 for
+                            z^3 - 1) or its roots (roots:1/-0.5+0.866i/-0.5-0.866i);
+                            pixels get the color of the root they converge to, darker
+                            the more iterations it takes
+    --root-colors <colors>  Comma separated colors of the roots in order, e.g.
+                            #e04040,#40e040,#4040e0; given roots keep their order, others
+                            are sorted counterclockwise from the negative real axis
+                            (default: spread over the palette)
+    --hybrid <pattern>      Render the hybrid fractal iterating the formulas of the pattern
+                            in turn, one letter per iteration repeated: M mandelbrot,
+                            B burning ship, T tricorn, C celtic and P multibrot, e.g. MMMB
+                            for three mandelbrot steps and then a burning ship one
+    --sequence <AB...>      Render the Lyapunov fractal of the logistic map x' = r*x*(1 - x)
+                            with r taking the real part a and the imaginary part b of the
+                            pixel in the order of the sequence, e.g. AB or BBBBBBAAAAAA;
+                            stable pixels are colored by their Lyapunov exponent, chaotic
+                            ones like the interior
+    --ab-range <a0:a1,b0:b1>
+                            View the given ranges of a and b (default: 2:4,2:4)
+    --power <exponent>      Exponent of the multibrot formula z^power + c and of the nova
+                            formula z - R*(z^power - 1)/(power*z^(power-1)) + c (default: 3),
+                            and of the mandelbulb (default: 8)
+    --relaxation <re+imi>   Relaxation R of the nova formula (default: 1)
+    --phoenix-p <re+imi>    Factor p of the previous z in the phoenix formula
+                            z^2 + c + p*z_prev (default: -0.5)
+    --camera <x,y,z>        Position of the camera of the 3D fractals, y is up
+                            (default: 2,1.6,-3.4)
+    --look-at <x,y,z>       Point the camera looks at (default: 0,0,0)
+    --fov <degrees>         Horizontal field of view of the camera at zoom 1, zooming and
+                            panning move a window of its image (default: 45)
+    --quaternion-c <a,b,c,d>
+                            Constant c of the quaternion julia set z^2 + c, of which the 3D
+                            slice of the real and the i, j parts is rendered
+                            (default: -0.2,0.6,0.2,0)
+    --shadow-sharpness <k>  Sharpness of the soft shadows of the 3D fractals, larger values
+                            give narrower penumbras, 0 turns the shadows off (default: 16)
+    --julia-c <re+imi>      Constant c of the julia set and the phoenix (default:
+                            -0.8+0.156i, 0.5667 for the phoenix)
+    --preset <name>         Start from a famous location, its center, zoom and iteration
+                            count can be changed by later options; --list-presets shows them
+    --location <path>       Take the center, zoom, iteration count and rotation from a Kalles
+                            Fraktaler .kfr location file
+    --center <re+imi>       Center of the view (e.g. -0.7453+0.1127i or -0.7453,0.1127)
+    --corners <re1,im1,re2,im2>
+                            View of the rectangle between two opposite corners, widened to
+                            the aspect ratio of the image
+    --zoom <factor>         Zoom factor relative to a view 4 units wide (e.g. 1e12 or 10^12)
+    --magnification <factor>
+                            Magnification relative to a view 4 units high, as in Kalles
+                            Fraktaler and most deep zoom software
+    --view-width <width>    Width of the view on the real axis
+    --view-height <height>  Height of the view on the imaginary axis
+    --rotation <degrees>    Counterclockwise rotation of the view around its center
+                            (default: 0)
+    --skew <xx,xy,yx,yy>    Linear map of the rotated view, e.g. 2,0,0,0.5 stretches it
+                            horizontally; auto estimates the map that makes the minibrot
+                            nearest to the center round, none disables it (default: none)
+    --width <pixels>        Width of the output image
+    --height <pixels>       Height of the output image
+    --iterations <count>    Maximum iteration count
+    --escape-radius <radius>
+                            Radius beyond which an orbit escapes (default: 2, or 256
+                            and more for the colorings that need it)
+    --bailout <norm>        Norm compared with the escape radius: euclidean, manhattan
+                            (|re| + |im|) or max (max(|re|, |im|)) (default: euclidean)
+    --auto-iterations <bool>
+                            Derive the iteration count from the zoom depth, more for
+                            deeper views (default: false)
+    --min-iterations <count>
+                            Lower bound of the automatic iteration count (default:
+                            100); implies --auto-iterations
+    --max-iterations <count>
+                            Upper bound of the automatic iteration count (default:
+                            100000); implies --auto-iterations
+    --samples <count>       Samples per pixel, sets both the minimum and the maximum
+    --min-samples <count>   Samples every pixel gets, at most the maximum (default: 4)
+    --max-samples <count>   Samples of pixels on edges or with noisy colors (default: 16)
+    --supersample <factor>  Render at factor times the width and height and reduce the
+                            image with --filter, e.g. 3 with --samples 1 (default: 1)
+    --filter <filter>       Filter reducing supersampled images: box, lanczos or mitchell
+                            (default: lanczos)
+    --sample-threshold <e>  Color difference that marks a pixel for more samples
+                            (default: 0.01)
+    --subdivision <bool>    Skip the inside of areas with a uniform iteration count border
+                            (default: true)
+    --simd <bool>           Iterate four samples at once on CPUs with AVX (default: true)
+    --series-approximation <bool>
+                            Skip the first iterations of perturbed samples with a series
+                            fitted to the reference orbit (default: true)
+    --symmetry <bool>       Render one half of views mirrored about the real axis and
+                            copy the other (default: true)
+    --sampler <pattern>     Sample placement: random, stratified or sobol (default: stratified)
+    --palette <path>        Load the palette from a Fractint .map, GIMP .ggr, JSON or text
+                            file with one #rrggbb or r g b color per line, optionally
+                            preceded by a position in [0:1] and ':'
+    --palette-gen <spec>    Generate the palette: sine:a/b/c/d with r,g,b parameters of
+                            a + b*cos(2pi*(c*t + d)), hsv[:from,to,saturation,value] or
+                            cubehelix[:start,rotations,hue,gamma]
+    --palette-offset <f>    Rotate the palette by a fraction of its length, e.g. 0.5 starts
+                            halfway through it (default: 0)
+    --palette-interpolation <space>
+                            Blend neighbouring palette colors in rgb, oklab (perceptually
+                            even) or oklch (oklab keeping the saturation between distant
+                            hues) (default: rgb)
+    --coloring <mode>       Coloring: banded, smooth, histogram, distance, orbit-trap,
+                            image-trap (picture of --trap-image), stalks (Pickover stalks),
+                            atom-domain (iteration of the smallest |z|, also inside with
+                            --interior palette), stripe (stripe average), tia (triangle
+                            inequality average), binary (binary decomposition) or angle
+                            (escape angle) (default: banded)
+    --stripe-frequency <count>
+                            Number of stripes of the stripe coloring (default: 5)
+    --average-mix <weight>  Weight of the stripe or tia average against the smooth
+                            coloring, in [0:1] (default: 1)
+    --interior <mode>       Coloring of the points inside the set: palette, solid,
+                            magnitude (final |z|), period (of the attracting cycle) or
+                            distance (interior distance estimate, Mandelbrot only)
+                            (default: palette)
+    --interior-color <#rrggbb[aa]>
+                            Color of the solid interior and of the points whose period
+                            isn't found, #rrggbbaa colors have an alpha (default: #000000)
+    --transparent <part>    Leave the interior or the exterior transparent for compositing
+                            over other backgrounds, or none (default: none). Palette colors
+                            may have an alpha too
+    --gamma <srgb|value>    Encoding of the palette and image colors: samples and palette
+                            entries are blended in linear light decoded with the sRGB curve
+                            or a power law of the given exponent, 1 blends the stored
+                            values as they are (default: srgb)
+    --exposure <stops>      Brighten (or darken, negative) the image by stops (default: 0)
+    --contrast <factor>     Contrast around mid gray, above 1 increases it (default: 1)
+    --sharpen <amount>      Unsharp mask strength, e.g. 0.5 (default: 0)
+    --sharpen-radius <pixels>
+                            Blur radius of the unsharp mask (default: 1)
+    --bloom <strength>      Glow around bright filaments, e.g. 0.5 (default: 0)
+    --bloom-radius <pixels> Size of the glow (default: 8)
+    --bloom-threshold <luminance>
+                            Linear luminance above which colors glow (default: 0.8)
+    --vignette <strength>   Darken the corners, 1 makes them black (default: 0)
+                            Sharpening and bloom need the whole image, they don't work
+                            with --tiles or band by band rendering
+    --trap <shape>          Orbit trap of the orbit-trap coloring: point, line, circle or
+                            cross (default: point)
+    --trap-position <re+imi>
+                            Position of the orbit trap (default: 0)
+    --trap-scale <size>     Radius of the circle trap and the distance over which the
+                            color fades away from the trap (default: 0.5)
+    --trap-image <path>     PNG picture of the image-trap coloring, twice --trap-scale wide
+                            around --trap-position. Pixels take its color at the first
+                            orbit value on an opaque pixel, the orbit-trap color otherwise
+    --stalk-thickness <distance>
+                            Distance from the axes within which orbit values draw the
+                            stalks of the stalks coloring (default: 0.05)
+    --stalk-falloff <exponent>
+                            Fade from the middle of a stalk to its edge, larger values
+                            narrow the stalks (default: 1)
+    --stalk-color <#rrggbb[aa]>
+                            Color of the stalks (default: #ffffff)
+    --lighting <bool>       Shade escaped pixels as a lit 3D surface using the normals of
+                            the distance estimate (default: false)
+    --light-azimuth <degrees>
+                            Direction of the light, counterclockwise from the right
+                            (default: 45); implies --lighting
+    --light-elevation <degrees>
+                            Angle of the light above the image (default: 45); implies
+                            --lighting
+    --light-specular <strength>
+                            Strength of the highlights, 0 for matte (default: 0.3);
+                            implies --lighting
+    --precision <type>      Number type: auto, single, double, double-double, arbitrary
+                            or perturbation (default: auto)
+    --threads <count>       Number of worker threads (default: all cores)
+    --time-limit <duration> Stop adding samples once the render took this long (e.g. 90s,
+                            10m or 1h30m) and finish the rest with one sample per pixel,
+                            the share of pixels that got fewer samples is reported
+    --seed <number>         Seed of the anti-aliasing sample positions, renders with the
+                            same seed and settings are identical (default: random)
+    --config <path>         Read options from a TOML or JSON scene file, keys are the long
+                            option names without dashes; command line options override them
+    --format <format>       Image format: png, exr, jpeg, webp or avif (default: picked by the
+                            output extension), EXR files add float channels with the smooth
+                            iteration count, distance estimate, |z| and arg(z) of every pixel
+    --quality <percent>     Quality of JPEG, WebP and AVIF output, 1 to 100 (default: 90)
+    --ffmpeg <path>         ffmpeg executable used for WebP, AVIF and video output
+                            (default: ffmpeg)
+    --bit-depth <bits>      Bits per channel of PNG output: 8 or 16 (default: 8)
+    --color-type <type>     Channels of PNG output: gray, rgb or rgba (default: rgba)
+    -o, --output <path>     Output path
+    -h, --help              Print this message";
+
+// Parses a complex number written as "re+imi", "re-imi" or "re,im"
+pub fn parse_complex(text: &str) -> Result<Vec2<BigFloat>, String> {
+    let invalid = || format!("invalid complex number '{}'", text);
+    let number = |part: &str| part.trim().parse::<BigFloat>().map_err(|_| invalid());
+    let unit = |part: &str| match part {
+        "" | "+" => number("1"),
+        "-" => number("-1"),
+        imaginary => number(imaginary),
+    };
+    let text = text.trim();
+    if let Some((re, im)) = text.split_once(',') {
+        return Ok(Vec2::<BigFloat>{x: number(re)?, y: number(im)?});
+    }
+    let body = match text.strip_suffix('i') {
+        Some(body) => body,
+        None => return Ok(Vec2::<BigFloat>{x: number(text)?, y: BigFloat::zero()}),
+    };
+    // The sign separating both parts is the last one not following an exponent
+    let bytes = body.as_bytes();
+    let split = (1..bytes.len()).rev()
+        .find(|&i| (bytes[i] == b'+' || bytes[i] == b'-') && bytes[i-1] != b'e' && bytes[i-1] != b'E');
+    match split {
+        Some(i) => Ok(Vec2::<BigFloat>{x: number(&body[..i])?, y: unit(&body[i..])?}),
+        None => Ok(Vec2::<BigFloat>{x: BigFloat::zero(), y: unit(body)?}),
+    }
+}
+
+pub fn parse_real_complex(text: &str) -> Result<Vec2<Real>, String> {
+    let c = parse_complex(text)?;
+    Ok(Vec2::<Real>{x: c.x.to_real(), y: c.y.to_real()})
+}
+
+pub fn parse_value<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+// Parses a duration of numbers followed by h, m or s like 1h30m, plain numbers are seconds
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{}', expected e.g. 90s, 10m or 1h30m", text);
+    let mut seconds = 0.0;
+    let mut number = String::new();
+    for c in text.chars() {
+        let unit = match c {
+            'h' => 3600.0,
+            'm' => 60.0,
+            's' => 1.0,
+            _ => {
+                number.push(c);
+                continue;
+            }
+        };
+        seconds += number.parse::<f64>().map_err(|_| invalid())? * unit;
+        number.clear();
+    }
+    if !number.is_empty() {
+        seconds += number.parse::<f64>().map_err(|_| invalid())?;
+    }
+    if text.is_empty() || !seconds.is_finite() || seconds <= 0.0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+// Walks the command line, handing out flags and the values following them
+pub struct Flags<'a> {
+    args: &'a [String],
+    index: usize,
+}
+
+impl<'a> Flags<'a> {
+    pub fn new(args: &'a [String]) -> Flags<'a> {
+        Flags {args, index: 0}
+    }
+
+    pub fn next_flag(&mut self) -> Option<&'a str> {
+        let flag = self.args.get(self.index)?;
+        self.index += 1;
+        Some(flag)
+    }
+
+    pub fn value(&mut self, flag: &str) -> Result<&'a str, String> {
+        let value = self.args.get(self.index).ok_or_else(|| format!("missing value for {}", flag))?;
+        self.index += 1;
+        Ok(value)
+    }
+}
+
+// Render options shared by every command
+pub struct Options {
+    pub settings: RenderSettings,
+    pub output: Option<String>,
+    pub image: ImageSettings,
+    zoom: Option<Real>,
+    view_width: Option<Real>,
+    view_height: Option<Real>,
+    // Relative to a view 4 units high, replaced by a later zoom or view size
+    magnification: Option<Real>,
+    // Size of the rectangle of --corners, fitted into the view once the aspect ratio is known
+    fit_size: Option<Vec2<Real>>,
+    center_given: bool,
+    julia_c_given: bool,
+    power_given: bool,
+    iterations_given: bool,
+    // Estimate the skew once the view is known
+    auto_skew: bool,
+}
+
+impl Options {
+    pub fn new() -> Options {
+        Options {
+            settings: RenderSettings::new(),
+            output: None,
+            image: ImageSettings::new(),
+            zoom: None,
+            view_width: None,
+            view_height: None,
+            magnification: None,
+            fit_size: None,
+            center_given: false,
+            julia_c_given: false,
+            power_given: false,
+            iterations_given: false,
+            auto_skew: false,
+        }
+    }
+
+    // Applies a shared option, unknown flags are an error
+    pub fn apply(&mut self, flag: &str, flags: &mut Flags) -> Result<(), String> {
+        let settings = &mut self.settings;
+        match flag {
+            "--fractal" => settings.fractal = parse_value(flag, flags.value(flag)?)?,
+            "--formula" => {
+                settings.formula = Some(flags.value(flag)?.parse()?);
+                settings.fractal = Fractal::Formula;
+            }
+            "--polynomial" => {
+                settings.polynomial = Some(flags.value(flag)?.parse()?);
+                settings.fractal = Fractal::Newton;
+            }
+            "--hybrid" => {
+                settings.hybrid = Some(flags.value(flag)?.parse()?);
+                settings.fractal = Fractal::Hybrid;
+            }
+            "--sequence" => {
+                settings.lyapunov_sequence = Some(flags.value(flag)?.parse()?);
+                settings.fractal = Fractal::Lyapunov;
+            }
+            "--ab-range" => {
+                let (a, b) = parse_ab_range(flags.value(flag)?)?;
+                settings.center = Vec2::<BigFloat>{x: BigFloat::from_real((a.x + a.y) / 2.0), y: BigFloat::from_real((b.x + b.y) / 2.0)};
+                self.center_given = true;
+                self.zoom = None;
+                self.magnification = None;
+                self.view_width = Some(a.y - a.x);
+                self.view_height = Some(b.y - b.x);
+            }
+            "--root-colors" => settings.root_colors = flags.value(flag)?.split(',').map(parse_color).collect::<Result<_, _>>()?,
+            "--power" => {
+                settings.power = parse_value(flag, flags.value(flag)?)?;
+                self.power_given = true;
+            }
+            "--camera" => settings.camera.position = flags.value(flag)?.parse()?,
+            "--look-at" => settings.camera.look_at = flags.value(flag)?.parse()?,
+            "--fov" => settings.camera.fov = parse_value(flag, flags.value(flag)?)?,
+            "--quaternion-c" => settings.quaternion_c = parse_quaternion(flags.value(flag)?)?,
+            "--shadow-sharpness" => settings.shadow_sharpness = parse_value(flag, flags.value(flag)?)?,
+            "--relaxation" => settings.relaxation = parse_real_complex(flags.value(flag)?)?,
+            "--phoenix-p" => settings.phoenix_p = parse_real_complex(flags.value(flag)?)?,
+            "--julia-c" => {
+                settings.julia_c = parse_real_complex(flags.value(flag)?)?;
+                self.julia_c_given = true;
+            }
+            "--preset" => {
+                let name = flags.value(flag)?;
+                let preset = find_preset(name).ok_or_else(|| format!("unknown preset '{}', --list-presets shows them", name))?;
+                settings.fractal = Fractal::Mandelbrot;
+                settings.center = parse_complex(preset.center)?;
+                settings.max_iterations = preset.iterations;
+                self.center_given = true;
+                self.zoom = Some(preset.zoom);
+                self.magnification = None;
+                self.view_width = None;
+                self.view_height = None;
+            }
+            "--location" => {
+                let location = Location::load(flags.value(flag)?)?;
+                settings.center = location.center;
+                settings.max_iterations = location.iterations;
+                settings.rotation = location.rotation;
+                settings.fractal = Fractal::Mandelbrot;
+                self.center_given = true;
+                self.zoom = None;
+                self.magnification = None;
+                self.view_width = None;
+                self.view_height = Some(location.view_height);
+            }
+            "--center" => {
+                settings.center = parse_complex(flags.value(flag)?)?;
+                self.center_given = true;
+            }
+            "--corners" => {
+                let (center, size) = parse_corners(flags.value(flag)?)?;
+                settings.center = center;
+                self.center_given = true;
+                self.zoom = None;
+                self.magnification = None;
+                self.view_width = None;
+                self.view_height = None;
+                self.fit_size = Some(size);
+            }
+            "--zoom" => {
+                self.zoom = Some(parse_factor(flag, flags.value(flag)?)?);
+                self.magnification = None;
+            }
+            "--magnification" => {
+                self.magnification = Some(parse_factor(flag, flags.value(flag)?)?);
+                self.zoom = None;
+                self.view_width = None;
+                self.view_height = None;
+            }
+            "--view-width" => {
+                self.view_width = Some(parse_value(flag, flags.value(flag)?)?);
+                self.magnification = None;
+            }
+            "--view-height" => {
+                self.view_height = Some(parse_value(flag, flags.value(flag)?)?);
+                self.magnification = None;
+            }
+            "--rotation" => settings.rotation = parse_value(flag, flags.value(flag)?)?,
+            "--skew" => {
+                let value = flags.value(flag)?;
+                self.auto_skew = value == "auto";
+                settings.skew = match value {
+                    "auto" | "none" => None,
+                    _ => Some(value.parse()?),
+                };
+            }
+            "--width" => settings.width = parse_value(flag, flags.value(flag)?)?,
+            "--height" => settings.height = parse_value(flag, flags.value(flag)?)?,
+            "--iterations" => {
+                settings.max_iterations = parse_value(flag, flags.value(flag)?)?;
+                self.iterations_given = true;
+            }
+            "--escape-radius" => settings.escape_radius = Some(parse_value(flag, flags.value(flag)?)?),
+            "--bailout" => settings.bailout = parse_value(flag, flags.value(flag)?)?,
+            "--auto-iterations" => {
+                let enabled: bool = parse_value(flag, flags.value(flag)?)?;
+                settings.auto_iterations = if enabled { Some(settings.auto_iterations.unwrap_or_default()) } else { None };
+            }
+            "--min-iterations" => settings.auto_iterations.get_or_insert_with(IterationRange::new).min = parse_value(flag, flags.value(flag)?)?,
+            "--max-iterations" => settings.auto_iterations.get_or_insert_with(IterationRange::new).max = parse_value(flag, flags.value(flag)?)?,
+            "--samples" => {
+                settings.sample_count = parse_value(flag, flags.value(flag)?)?;
+                settings.min_sample_count = settings.sample_count;
+            }
+            "--min-samples" => settings.min_sample_count = parse_value(flag, flags.value(flag)?)?,
+            "--max-samples" => settings.sample_count = parse_value(flag, flags.value(flag)?)?,
+            "--supersample" => settings.supersample = parse_value(flag, flags.value(flag)?)?,
+            "--filter" => settings.filter = parse_value(flag, flags.value(flag)?)?,
+            "--sample-threshold" => settings.sample_threshold = parse_value(flag, flags.value(flag)?)?,
+            "--subdivision" => settings.subdivision = parse_value(flag, flags.value(flag)?)?,
+            "--symmetry" => settings.symmetry = parse_value(flag, flags.value(flag)?)?,
+            "--simd" => settings.simd = parse_value(flag, flags.value(flag)?)?,
+            "--series-approximation" => settings.series_approximation = parse_value(flag, flags.value(flag)?)?,
+            "--sampler" => settings.sampler = parse_value(flag, flags.value(flag)?)?,
+            // Keep the offset of an earlier --palette-offset
+            "--palette" => settings.palette = Palette {offset: settings.palette.offset, interpolation: settings.palette.interpolation, ..Palette::load(flags.value(flag)?)?},
+            "--palette-gen" => settings.palette = Palette {offset: settings.palette.offset, interpolation: settings.palette.interpolation, ..Palette::generate(flags.value(flag)?)?},
+            "--palette-offset" => settings.palette.offset = parse_value(flag, flags.value(flag)?)?,
+            "--palette-interpolation" => settings.palette.interpolation = parse_value(flag, flags.value(flag)?)?,
+            "--coloring" => settings.coloring = parse_value(flag, flags.value(flag)?)?,
+            "--interior" => settings.interior = parse_value(flag, flags.value(flag)?)?,
+            "--interior-color" => settings.interior_color = parse_color(flags.value(flag)?)?,
+            "--transparent" => settings.transparency = parse_value(flag, flags.value(flag)?)?,
+            "--exposure" => settings.post.exposure = parse_value(flag, flags.value(flag)?)?,
+            "--contrast" => settings.post.contrast = parse_value(flag, flags.value(flag)?)?,
+            "--sharpen" => settings.post.sharpen = parse_value(flag, flags.value(flag)?)?,
+            "--sharpen-radius" => settings.post.sharpen_radius = parse_value(flag, flags.value(flag)?)?,
+            "--bloom" => settings.post.bloom = parse_value(flag, flags.value(flag)?)?,
+            "--bloom-radius" => settings.post.bloom_radius = parse_value(flag, flags.value(flag)?)?,
+            "--bloom-threshold" => settings.post.bloom_threshold = parse_value(flag, flags.value(flag)?)?,
+            "--vignette" => settings.post.vignette = parse_value(flag, flags.value(flag)?)?,
+            "--gamma" => settings.gamma = parse_value(flag, flags.value(flag)?)?,
+            "--precision" => settings.precision = parse_value(flag, flags.value(flag)?)?,
+            "--threads" => settings.thread_count = parse_value(flag, flags.value(flag)?)?,
+            "--time-limit" => settings.time_limit = Some(parse_duration(flags.value(flag)?)?),
+            "--trap" => settings.orbit_trap.shape = parse_value(flag, flags.value(flag)?)?,
+            "--trap-position" => settings.orbit_trap.position = parse_real_complex(flags.value(flag)?)?,
+            "--trap-scale" => settings.orbit_trap.scale = parse_value(flag, flags.value(flag)?)?,
+            "--trap-image" => settings.trap_image = Some(TrapImage::load(flags.value(flag)?)?),
+            "--stalk-thickness" => settings.stalks.thickness = parse_value(flag, flags.value(flag)?)?,
+            "--stalk-falloff" => settings.stalks.falloff = parse_value(flag, flags.value(flag)?)?,
+            "--stalk-color" => settings.stalks.color = parse_color(flags.value(flag)?)?,
+            "--stripe-frequency" => settings.orbit_average.frequency = parse_value(flag, flags.value(flag)?)?,
+            "--average-mix" => settings.orbit_average.mix = parse_value(flag, flags.value(flag)?)?,
+            "--lighting" => {
+                let enabled: bool = parse_value(flag, flags.value(flag)?)?;
+                settings.lighting = if enabled { Some(settings.lighting.unwrap_or_default()) } else { None };
+            }
+            "--light-azimuth" => settings.lighting.get_or_insert_with(Lighting::new).azimuth = parse_value(flag, flags.value(flag)?)?,
+            "--light-elevation" => settings.lighting.get_or_insert_with(Lighting::new).elevation = parse_value(flag, flags.value(flag)?)?,
+            "--light-specular" => settings.lighting.get_or_insert_with(Lighting::new).specular = parse_value(flag, flags.value(flag)?)?,
+            "--seed" => settings.seed = Some(parse_value(flag, flags.value(flag)?)?),
+            "--format" => self.image.format = Some(parse_value(flag, flags.value(flag)?)?),
+            "--quality" => self.image.quality = parse_value(flag, flags.value(flag)?)?,
+            "--ffmpeg" => self.image.ffmpeg = String::from(flags.value(flag)?),
+            "--bit-depth" => self.image.bit_depth = parse_value(flag, flags.value(flag)?)?,
+            "--color-type" => self.image.color_type = parse_value(flag, flags.value(flag)?)?,
+            "-o" | "--output" => self.output = Some(String::from(flags.value(flag)?)),
+            _ => return Err(format!("unknown option '{}'", flag)),
+        }
+        Ok(())
+    }
+
+    // Validates the settings and resolves the view size from the given view options
+    pub fn finish(mut self) -> Result<(RenderSettings, Option<String>, ImageSettings), String> {
+        let settings = &mut self.settings;
+        if settings.width == 0 || settings.height == 0 {
+            return Err(String::from("image dimensions must be positive"));
+        }
+        if settings.sample_count == 0 || settings.min_sample_count == 0 {
+            return Err(String::from("sample count must be positive"));
+        }
+        if settings.supersample == 0 {
+            return Err(String::from("supersampling factor must be positive"));
+        }
+        if self.image.quality == 0 || self.image.quality > 100 {
+            return Err(String::from("quality must be between 1 and 100"));
+        }
+        if self.image.bit_depth != 8 && self.image.bit_depth != 16 {
+            return Err(String::from("bit depth must be 8 or 16"));
+        }
+        if settings.orbit_trap.scale <= 0.0 {
+            return Err(String::from("trap scale must be positive"));
+        }
+        if settings.stalks.thickness <= 0.0 || settings.stalks.falloff <= 0.0 {
+            return Err(String::from("stalk thickness and falloff must be positive"));
+        }
+        if settings.coloring.needs_trap_image() && settings.trap_image.is_none() {
+            return Err(format!("the {} coloring needs --trap-image", settings.coloring));
+        }
+        if !(0.0..=1.0).contains(&settings.orbit_average.mix) {
+            return Err(String::from("average mix must be in [0:1]"));
+        }
+        if settings.max_iterations == 0 {
+            return Err(String::from("iteration count must be positive"));
+        }
+        if settings.escape_radius.is_some_and(|radius| radius <= 0.0 || !radius.is_finite()) {
+            return Err(String::from("escape radius must be positive"));
+        }
+        if let Some(range) = &settings.auto_iterations {
+            if range.min == 0 || range.max < range.min {
+                return Err(String::from("the automatic iteration bounds must be positive with --min-iterations at most --max-iterations"));
+            }
+        }
+        if settings.thread_count == 0 {
+            return Err(String::from("thread count must be positive"));
+        }
+        if settings.fractal == Fractal::Formula && settings.formula.is_none() {
+            return Err(String::from("the formula fractal needs --formula"));
+        }
+        if settings.fractal == Fractal::Newton && settings.polynomial.is_none() {
+            return Err(String::from("the newton fractal needs --polynomial"));
+        }
+        if settings.fractal == Fractal::Lyapunov && settings.lyapunov_sequence.is_none() {
+            return Err(String::from("the lyapunov fractal needs --sequence"));
+        }
+        if settings.fractal == Fractal::Hybrid && settings.hybrid.is_none() {
+            return Err(String::from("the hybrid fractal needs --hybrid"));
+        }
+        // The classic phoenix set, the default constant is the one of a julia set
+        if settings.fractal == Fractal::Phoenix && !self.julia_c_given {
+            settings.julia_c = Vec2::<Real>{x: 0.5667, y: 0.0};
+        }
+        // The classic power 8 bulb
+        if settings.fractal == Fractal::Mandelbulb && !self.power_given {
+            settings.power = 8.0;
+        }
+        // The distance estimates gain no detail past 20 iterations
+        if settings.fractal.is_3d() && !self.iterations_given {
+            settings.max_iterations = 20;
+        }
+        if settings.fractal.is_3d() && (settings.camera.position - settings.camera.look_at).length() == 0.0 {
+            return Err(String::from("the camera can't look at its own position"));
+        }
+        if settings.shadow_sharpness < 0.0 {
+            return Err(String::from("shadow sharpness can't be negative"));
+        }
+        if settings.camera.fov <= 0.0 || settings.camera.fov >= 180.0 {
+            return Err(String::from("the field of view must be between 0 and 180 degrees"));
+        }
+        // The default view is a detail of the Mandelbrot set, show the whole set of other fractals
+        if settings.fractal != Fractal::Mandelbrot && !self.center_given && self.zoom.is_none() && self.magnification.is_none() && self.view_width.is_none() && self.view_height.is_none() {
+            let (center, zoom) = match settings.fractal {
+                Fractal::BurningShip => (Vec2::<Real>{x: -0.4, y: -0.5}, 1.0),
+                Fractal::Nova => (Vec2::<Real>{x: -0.5, y: 0.0}, 1.0),
+                Fractal::MagnetI => (Vec2::<Real>{x: 1.5, y: 0.0}, 0.5),
+                Fractal::MagnetII => (Vec2::<Real>{x: 1.0, y: 0.0}, 0.8),
+                // a and b in [2:4], where the logistic map turns chaotic
+                Fractal::Lyapunov => (Vec2::<Real>{x: 3.0, y: 3.0}, 2.0),
+                _ => (Vec2::<Real>::new(), 1.0),
+            };
+            settings.center = Vec2::<BigFloat>{x: BigFloat::from_real(center.x), y: BigFloat::from_real(center.y)};
+            self.zoom = Some(zoom);
+        }
+        let aspect_ratio = (settings.width as Real) / (settings.height as Real);
+        if let Some(zoom) = self.zoom {
+            self.view_width = Some(zoom_to_view_width(zoom)?);
+        }
+        if let Some(magnification) = self.magnification {
+            self.view_height = Some(DEFAULT_VIEW_WIDTH / magnification);
+        }
+        // The smallest view of the image's aspect ratio around the corners, unless the size was
+        // given after them
+        if let (Some(size), None, None) = (self.fit_size, self.view_width, self.view_height) {
+            self.view_width = Some(size.x.max(size.y * aspect_ratio));
+        }
+        settings.view_size = match (self.view_width, self.view_height) {
+            (Some(w), Some(h)) => Vec2::<Real>{x: w, y: h},
+            (Some(w), None) => Vec2::<Real>{x: w, y: w / aspect_ratio},
+            (None, Some(h)) => Vec2::<Real>{x: h * aspect_ratio, y: h},
+            (None, None) => Vec2::<Real>{x: settings.view_size.y * aspect_ratio, y: settings.view_size.y},
+        };
+        settings.update_auto_iterations();
+        if self.auto_skew {
+            settings.skew = Some(Skew::estimate(settings)?);
+        }
+        Ok((self.settings, self.output, self.image))
+    }
+}
+
+// Parses a,b,c,d into the quaternion a + bi + cj + dk
+fn parse_quaternion(text: &str) -> Result<[Real; 4], String> {
+    let invalid = || format!("invalid quaternion '{}', expected a,b,c,d", text);
+    let values = text.split(',').map(|value| value.trim().parse::<Real>()).collect::<Result<Vec<Real>, _>>().map_err(|_| invalid())?;
+    match values[..] {
+        [a, b, c, d] => Ok([a, b, c, d]),
+        _ => Err(invalid()),
+    }
+}
+
+// Parses re1,im1,re2,im2 into the center and size of the rectangle between the two corners
+fn parse_corners(text: &str) -> Result<(Vec2<BigFloat>, Vec2<Real>), String> {
+    let invalid = || format!("invalid corners '{}', expected re1,im1,re2,im2", text);
+    let values = text.split(',').map(|value| value.trim().parse::<BigFloat>()).collect::<Result<Vec<BigFloat>, _>>().map_err(|_| invalid())?;
+    let (re1, im1, re2, im2) = match values[..] {
+        [re1, im1, re2, im2] => (re1, im1, re2, im2),
+        _ => return Err(invalid()),
+    };
+    let size = Vec2::<Real>{x: (re2 - re1).abs().to_real(), y: (im2 - im1).abs().to_real()};
+    if size.x <= 0.0 || size.y <= 0.0 {
+        return Err(format!("the corners '{}' must differ in both coordinates", text));
+    }
+    let half = BigFloat::from_real(0.5);
+    let center = Vec2::<BigFloat>{x: (re1 + re2) * half, y: (im1 + im2) * half};
+    Ok((center, size))
+}
+
+// Parses a positive zoom factor, either a number or a power like 10^12
+fn parse_factor(flag: &str, value: &str) -> Result<Real, String> {
+    let factor = match value.split_once('^') {
+        Some((base, exponent)) => parse_value::<Real>(flag, base)?.powf(parse_value(flag, exponent)?),
+        None => parse_value(flag, value)?,
+    };
+    if !(factor.is_finite() && factor > 0.0) {
+        return Err(format!("{} must be a positive finite factor", flag));
+    }
+    Ok(factor)
+}
+
+// Parses a0:a1,b0:b1 into the increasing ranges (a0, a1) and (b0, b1)
+fn parse_ab_range(text: &str) -> Result<(Vec2<Real>, Vec2<Real>), String> {
+    let invalid = || format!("invalid range '{}', expected a0:a1,b0:b1", text);
+    let range = |part: &str| -> Result<Vec2<Real>, String> {
+        let (from, to) = part.split_once(':').ok_or_else(invalid)?;
+        let from = from.trim().parse::<Real>().map_err(|_| invalid())?;
+        let to = to.trim().parse::<Real>().map_err(|_| invalid())?;
+        if to <= from {
+            return Err(format!("range '{}' must be increasing", part));
+        }
+        Ok(Vec2::<Real>{x: from, y: to})
+    };
+    let (a, b) = text.split_once(',').ok_or_else(invalid)?;
+    Ok((range(a)?, range(b)?))
+}
+
+pub fn zoom_to_view_width(zoom: Real) -> Result<Real, String> {
+    if zoom <= 0.0 {
+        return Err(String::from("zoom must be positive"));
+    }
+    Ok(DEFAULT_VIEW_WIDTH / zoom)
+}
+
+// Parses the path of a Julia morph: circle:<center>/<radius>, line:<from>/<to> or
+// boundary[:<radius>]
+pub fn parse_julia_path(text: &str) -> Result<JuliaPath, String> {
+    let (kind, parameters) = text.split_once(':').unwrap_or((text, ""));
+    let parameters: Vec<&str> = if parameters.is_empty() { Vec::new() } else { parameters.split('/').collect() };
+    let error = |message: &str| format!("julia path '{}': {}", text, message);
+    let radius = |value: &str| parse_value::<Real>("the radius", value).map_err(|e| error(&e));
+    match (kind, &parameters[..]) {
+        ("circle", [center, value]) => Ok(JuliaPath::Circle {center: parse_real_complex(center).map_err(|e| error(&e))?, radius: radius(value)?}),
+        ("line", [from, to]) => Ok(JuliaPath::Line {from: parse_real_complex(from).map_err(|e| error(&e))?, to: parse_real_complex(to).map_err(|e| error(&e))?}),
+        ("boundary", []) => Ok(JuliaPath::Boundary {radius: 1.0}),
+        ("boundary", [value]) => Ok(JuliaPath::Boundary {radius: radius(value)?}),
+        ("circle", _) => Err(error("expected circle:<center>/<radius>")),
+        ("line", _) => Err(error("expected line:<from>/<to>")),
+        ("boundary", _) => Err(error("expected boundary[:<radius>]")),
+        _ => Err(format!("unknown julia path '{}', expected circle, line or boundary", kind)),
+    }
+}
+
+// One axis of the sweep command: a render option taking a number, or julia-re and julia-im
+// for the parts of --julia-c, and its values
+pub struct SweepAxis {
+    pub option: String,
+    pub values: Vec<String>,
+}
+
+impl SweepAxis {
+    // Sets the value of the axis on options, julia-re and julia-im go through apply_julia
+    pub fn apply(&self, options: &mut Options, index: usize) -> Result<(), String> {
+        if self.option == "julia-re" || self.option == "julia-im" {
+            return Ok(());
+        }
+        let args = [format!("--{}", self.option), self.values[index].clone()];
+        let mut flags = Flags::new(&args);
+        match flags.next_flag() {
+            Some(flag) => options.apply(flag, &mut flags),
+            None => Ok(()),
+        }
+    }
+
+    pub fn apply_julia(&self, settings: &mut RenderSettings, index: usize) -> Result<(), String> {
+        let value = || parse_value::<Real>(&self.option, &self.values[index]);
+        match self.option.as_str() {
+            "julia-re" => settings.julia_c.x = value()?,
+            "julia-im" => settings.julia_c.y = value()?,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    // Label of a cell of the contact sheet
+    pub fn label(&self, index: usize) -> String {
+        format!("{}={}", self.option, self.values[index])
+    }
+}
+
+// Parses an axis of the sweep command: <option>=<from>:<to>:<count> with count values evenly
+// spaced from from to to
+pub fn parse_sweep_axis(text: &str) -> Result<SweepAxis, String> {
+    let error = || format!("invalid sweep '{}', expected <option>=<from>:<to>:<count>", text);
+    let (option, range) = text.split_once('=').ok_or_else(error)?;
+    let parts: Vec<&str> = range.split(':').collect();
+    let (from, to, count): (Real, Real, usize) = match parts[..] {
+        [from, to, count] => (from.parse().map_err(|_| error())?, to.parse().map_err(|_| error())?, count.parse().map_err(|_| error())?),
+        _ => return Err(error()),
+    };
+    if count == 0 || option.is_empty() {
+        return Err(error());
+    }
+    let values = (0..count).map(|i| {
+        let value = if count == 1 { from } else { from + (to - from) * i as Real / (count - 1) as Real };
+        // Rounded so the labels stay short, adding zero turns -0 into 0
+        ((value * 1e9).round() / 1e9 + 0.0).to_string()
+    }).collect();
+    Ok(SweepAxis {option: String::from(option.trim_start_matches("--")), values})
+}
+
+// Views of a keyframe file, see parse_table_file: every [[keyframe]] has a center, a zoom or
+// view-width and optionally a rotation, iterations, palette-offset, julia-c and the easing of
+// the segment to the next keyframe
+pub fn parse_keyframes(path: &str) -> Result<Vec<Keyframe>, String> {
+    let mut keyframes = Vec::new();
+    for (index, pairs) in parse_table_file(path, "keyframe")?.into_iter().enumerate() {
+        let error = |message: String| format!("{}: keyframe {}: {}", path, index + 1, message);
+        let (mut center, mut view_width) = (None, None);
+        let mut keyframe = Keyframe::new(Vec2::<BigFloat>{x: BigFloat::zero(), y: BigFloat::zero()}, 0.0);
+        for (key, value) in &pairs {
+            let flag = format!("--{}", key);
+            match key.as_str() {
+                "center" => center = Some(parse_complex(value).map_err(error)?),
+                "zoom" => view_width = Some(zoom_to_view_width(parse_value(&flag, value).map_err(error)?).map_err(error)?),
+                "view-width" => view_width = Some(parse_value(&flag, value).map_err(error)?),
+                "rotation" => keyframe.rotation = Some(parse_value(&flag, value).map_err(error)?),
+                "iterations" => keyframe.iterations = Some(parse_value(&flag, value).map_err(error)?),
+                "palette-offset" => keyframe.palette_offset = Some(parse_value(&flag, value).map_err(error)?),
+                "julia-c" => keyframe.julia_c = Some(parse_real_complex(value).map_err(error)?),
+                "easing" => keyframe.easing = parse_value(&flag, value).map_err(error)?,
+                _ => return Err(error(format!("unknown key '{}'", key))),
+            }
+        }
+        keyframe.center = center.ok_or_else(|| error(String::from("missing center")))?;
+        keyframe.view_width = view_width.ok_or_else(|| error(String::from("missing zoom")))?;
+        keyframes.push(keyframe);
+    }
+    if keyframes.len() < 2 {
+        return Err(format!("{}: an animation needs at least two keyframes", path));
+    }
+    Ok(keyframes)
+}
+
+// Keyframe as the pairs of a keyframe file table
+pub fn keyframe_pairs(keyframe: &Keyframe) -> Vec<(String, String)> {
+    let digits = (-keyframe.view_width.log10()).max(0.0).ceil() as usize + 6;
+    let mut pairs = vec![
+        (String::from("center"), format!("{},{}", format_digits(&keyframe.center.x, digits), format_digits(&keyframe.center.y, digits))),
+        (String::from("zoom"), format!("{:e}", DEFAULT_VIEW_WIDTH / keyframe.view_width)),
+    ];
+    if let Some(rotation) = keyframe.rotation {
+        pairs.push((String::from("rotation"), rotation.to_string()));
+    }
+    if let Some(iterations) = keyframe.iterations {
+        pairs.push((String::from("iterations"), iterations.to_string()));
+    }
+    if let Some(offset) = keyframe.palette_offset {
+        pairs.push((String::from("palette-offset"), offset.to_string()));
+    }
+    if let Some(c) = keyframe.julia_c {
+        pairs.push((String::from("julia-c"), format!("{},{}", c.x, c.y)));
+    }
+    if keyframe.easing != Easing::Linear {
+        pairs.push((String::from("easing"), keyframe.easing.to_string()));
+    }
+    pairs
+}
+
+// Replaces every --config <path> with the options stored in the file.
+// The file options come first so the command line ones override them.
+pub fn expand_config(args: &[String]) -> Result<Vec<String>, String> {
+    let mut from_files = Vec::new();
+    let mut rest = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--config" {
+            let path = args.get(i+1).ok_or_else(|| String::from("missing value for --config"))?;
+            for (key, value) in parse_config_file(path)? {
+                from_files.push(format!("--{}", key));
+                from_files.push(value);
+            }
+            i += 2;
+        }
+        else {
+            rest.push(args[i].clone());
+            i += 1;
+        }
+    }
+    from_files.extend(rest);
+    Ok(from_files)
+}
+
+// Decimal text of the number without trailing zeros
+pub fn format_digits(value: &BigFloat, digits: usize) -> String {
+    let text = value.to_string_digits(digits);
+    if text.contains('.') {
+        String::from(text.trim_end_matches('0').trim_end_matches('.'))
+    }
+    else {
+        text
+    }
+}
+
+// Effective settings as config file pairs
+pub fn settings_to_pairs(settings: &RenderSettings, output: &str) -> Vec<(String, String)> {
+    // Enough digits to tell neighbouring pixels apart
+    let digits = (-settings.pixel_size().log10()).max(0.0).ceil() as usize + 3;
+    let mut pairs = vec![(String::from("fractal"), settings.fractal.to_string())];
+    if settings.fractal.is_julia() {
+        pairs.push((String::from("julia-c"), format!("{},{}", settings.julia_c.x, settings.julia_c.y)));
+    }
+    if settings.fractal == Fractal::Phoenix {
+        pairs.push((String::from("phoenix-p"), format!("{},{}", settings.phoenix_p.x, settings.phoenix_p.y)));
+    }
+    if let (Fractal::Formula, Some(formula)) = (settings.fractal, &settings.formula) {
+        pairs.push((String::from("formula"), formula.to_string()));
+    }
+    if matches!(settings.fractal, Fractal::Multibrot | Fractal::Nova | Fractal::Mandelbulb) || settings.hybrid.as_ref().is_some_and(|hybrid| settings.fractal == Fractal::Hybrid && hybrid.steps().contains(&Fractal::Multibrot)) {
+        pairs.push((String::from("power"), settings.power.to_string()));
+    }
+    if settings.fractal == Fractal::Nova {
+        pairs.push((String::from("relaxation"), format!("{},{}", settings.relaxation.x, settings.relaxation.y)));
+    }
+    if let (Fractal::Newton, Some(polynomial)) = (settings.fractal, &settings.polynomial) {
+        pairs.push((String::from("polynomial"), polynomial.to_string()));
+    }
+    if let (Fractal::Lyapunov, Some(sequence)) = (settings.fractal, &settings.lyapunov_sequence) {
+        pairs.push((String::from("sequence"), sequence.to_string()));
+    }
+    if let (Fractal::Hybrid, Some(hybrid)) = (settings.fractal, &settings.hybrid) {
+        pairs.push((String::from("hybrid"), hybrid.to_string()));
+    }
+    if settings.fractal.is_3d() {
+        pairs.push((String::from("camera"), settings.camera.position.to_string()));
+        pairs.push((String::from("look-at"), settings.camera.look_at.to_string()));
+        pairs.push((String::from("fov"), settings.camera.fov.to_string()));
+        pairs.push((String::from("shadow-sharpness"), settings.shadow_sharpness.to_string()));
+    }
+    if settings.fractal == Fractal::QuaternionJulia {
+        let c = settings.quaternion_c;
+        pairs.push((String::from("quaternion-c"), format!("{},{},{},{}", c[0], c[1], c[2], c[3])));
+    }
+    pairs.push((String::from("center"), format!("{},{}", format_digits(&settings.center.x, digits), format_digits(&settings.center.y, digits))));
+    pairs.push((String::from("view-width"), format!("{:e}", settings.view_size.x)));
+    pairs.push((String::from("view-height"), format!("{:e}", settings.view_size.y)));
+    if settings.rotation != 0.0 {
+        pairs.push((String::from("rotation"), settings.rotation.to_string()));
+    }
+    if let Some(skew) = &settings.skew {
+        pairs.push((String::from("skew"), skew.to_string()));
+    }
+    pairs.push((String::from("width"), settings.width.to_string()));
+    pairs.push((String::from("height"), settings.height.to_string()));
+    pairs.push((String::from("iterations"), settings.max_iterations.to_string()));
+    if let Some(radius) = settings.escape_radius {
+        pairs.push((String::from("escape-radius"), radius.to_string()));
+    }
+    pairs.push((String::from("bailout"), settings.bailout.to_string()));
+    if let Some(range) = &settings.auto_iterations {
+        pairs.push((String::from("auto-iterations"), String::from("true")));
+        pairs.push((String::from("min-iterations"), range.min.to_string()));
+        pairs.push((String::from("max-iterations"), range.max.to_string()));
+    }
+    pairs.push((String::from("min-samples"), settings.min_sample_count.to_string()));
+    pairs.push((String::from("max-samples"), settings.sample_count.to_string()));
+    if settings.supersample > 1 {
+        pairs.push((String::from("supersample"), settings.supersample.to_string()));
+        pairs.push((String::from("filter"), settings.filter.to_string()));
+    }
+    pairs.push((String::from("sample-threshold"), settings.sample_threshold.to_string()));
+    pairs.push((String::from("subdivision"), settings.subdivision.to_string()));
+    pairs.push((String::from("symmetry"), settings.symmetry.to_string()));
+    pairs.push((String::from("series-approximation"), settings.series_approximation.to_string()));
+    pairs.push((String::from("sampler"), settings.sampler.to_string()));
+    if let Some(seed) = settings.seed {
+        pairs.push((String::from("seed"), seed.to_string()));
+    }
+    match &settings.palette.source {
+        PaletteSource::File(path) => pairs.push((String::from("palette"), path.clone())),
+        PaletteSource::Generated(spec) => pairs.push((String::from("palette-gen"), spec.clone())),
+        PaletteSource::Builtin | PaletteSource::Custom => {}
+    }
+    if settings.palette.offset != 0.0 {
+        pairs.push((String::from("palette-offset"), settings.palette.offset.to_string()));
+    }
+    if settings.palette.interpolation != Interpolation::Rgb {
+        pairs.push((String::from("palette-interpolation"), settings.palette.interpolation.to_string()));
+    }
+    pairs.push((String::from("coloring"), settings.coloring.to_string()));
+    pairs.push((String::from("interior"), settings.interior.to_string()));
+    let hex = |color: &Color| {
+        let channel = |value: Real| (value * 255.0).round() as u8;
+        let alpha = if color.a < 1.0 { format!("{:02x}", channel(color.a)) } else { String::new() };
+        format!("#{:02x}{:02x}{:02x}{}", channel(color.r), channel(color.g), channel(color.b), alpha)
+    };
+    pairs.push((String::from("interior-color"), hex(&settings.interior_color)));
+    if !settings.root_colors.is_empty() {
+        pairs.push((String::from("root-colors"), settings.root_colors.iter().map(hex).collect::<Vec<String>>().join(",")));
+    }
+    if settings.transparency != Transparency::Opaque {
+        pairs.push((String::from("transparent"), settings.transparency.to_string()));
+    }
+    pairs.push((String::from("gamma"), settings.gamma.to_string()));
+    let (post, defaults) = (&settings.post, PostProcess::new());
+    for (key, value, default) in [("exposure", post.exposure, defaults.exposure), ("contrast", post.contrast, defaults.contrast),
+        ("sharpen", post.sharpen, defaults.sharpen), ("sharpen-radius", post.sharpen_radius, defaults.sharpen_radius),
+        ("bloom", post.bloom, defaults.bloom), ("bloom-radius", post.bloom_radius, defaults.bloom_radius),
+        ("bloom-threshold", post.bloom_threshold, defaults.bloom_threshold), ("vignette", post.vignette, defaults.vignette)] {
+        if value != default {
+            pairs.push((String::from(key), value.to_string()));
+        }
+    }
+    if let Some(lighting) = &settings.lighting {
+        pairs.push((String::from("lighting"), String::from("true")));
+        pairs.push((String::from("light-azimuth"), lighting.azimuth.to_string()));
+        pairs.push((String::from("light-elevation"), lighting.elevation.to_string()));
+        pairs.push((String::from("light-specular"), lighting.specular.to_string()));
+    }
+    if settings.coloring == Coloring::Stalks {
+        pairs.push((String::from("stalk-thickness"), settings.stalks.thickness.to_string()));
+        pairs.push((String::from("stalk-falloff"), settings.stalks.falloff.to_string()));
+        pairs.push((String::from("stalk-color"), hex(&settings.stalks.color)));
+    }
+    else if settings.coloring.needs_trap() {
+        let trap = &settings.orbit_trap;
+        pairs.push((String::from("trap"), trap.shape.to_string()));
+        pairs.push((String::from("trap-position"), format!("{},{}", trap.position.x, trap.position.y)));
+        pairs.push((String::from("trap-scale"), trap.scale.to_string()));
+    }
+    if let Some(image) = settings.tracked_trap_image() {
+        pairs.push((String::from("trap-image"), String::from(image.path())));
+    }
+    if settings.coloring.needs_average() {
+        pairs.push((String::from("stripe-frequency"), settings.orbit_average.frequency.to_string()));
+        pairs.push((String::from("average-mix"), settings.orbit_average.mix.to_string()));
+    }
+    pairs.push((String::from("precision"), settings.precision.to_string()));
+    pairs.push((String::from("output"), String::from(output)));
+    pairs
+}
+
+// Caption text with {center}, {zoom}, {iterations} and {fractal} replaced by the view's values
+pub fn expand_caption(caption: &str, settings: &RenderSettings) -> String {
+    let pairs = settings_to_pairs(settings, "");
+    let center = pairs.iter().find(|(key, _)| key == "center").map(|(_, value)| value.clone()).unwrap_or_default();
+    caption.replace("{center}", &center)
+        .replace("{zoom}", &format!("{:.3e}", DEFAULT_VIEW_WIDTH / settings.view_size.x))
+        .replace("{iterations}", &settings.max_iterations.to_string())
+        .replace("{fractal}", &settings.fractal.to_string())
+}
+
+// Text chunks of saved PNG images, the scene has every setting but the output path
+pub fn render_metadata(settings: &RenderSettings) -> Vec<(String, String)> {
+    let pairs: Vec<(String, String)> = settings_to_pairs(settings, "").into_iter().filter(|(key, _)| key != "output").collect();
+    let center = pairs.iter().find(|(key, _)| key == "center").map(|(_, value)| value.clone()).unwrap_or_default();
+    let palette = match &settings.palette.source {
+        PaletteSource::Builtin => String::from("builtin"),
+        PaletteSource::Custom => String::from("custom"),
+        PaletteSource::File(path) => path.clone(),
+        PaletteSource::Generated(spec) => spec.clone(),
+    };
+    let seed = settings.seed.map(|seed| seed.to_string()).unwrap_or_else(|| String::from("random"));
+    vec![
+        (String::from("Software"), format!("mandelbrot_set {}", env!("CARGO_PKG_VERSION"))),
+        (String::from("Center"), center),
+        (String::from("Zoom"), format!("{:e}", 4.0 / settings.view_size.x)),
+        (String::from("Iterations"), if settings.auto_iterations.is_some() { format!("{} (auto)", settings.max_iterations) } else { settings.max_iterations.to_string() }),
+        (String::from("Palette"), palette),
+        (String::from("Seed"), seed),
+        (String::from("Scene"), write_toml(&pairs)),
+    ]
+}
+
+// Writes the settings as TOML to path, or to stdout for "-"
+pub fn dump_config(settings: &RenderSettings, output: &str, path: &str) -> Result<(), String> {
+    let text = write_toml(&settings_to_pairs(settings, output));
+    if path == "-" {
+        print!("{}", text);
+        return Ok(());
+    }
+    prepare_output(path)?;
+    std::fs::write(path, text).map_err(|e| format!("cannot write {}: {}", path, e))
+}
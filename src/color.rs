@@ -0,0 +1,130 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::Real;
+
+#[derive(Copy, Clone, Default, PartialEq)]
+pub struct Color {
+    pub r: Real,
+    pub g: Real,
+    pub b: Real,
+    pub a: Real,
+}
+
+impl Color {
+    pub fn new() -> Color {
+        Color {r:0.0, g:0.0, b:0.0, a:0.0}
+    }
+
+    pub fn add(&mut self, rhs: Color) {
+        self.r += rhs.r;
+        self.g += rhs.g;
+        self.b += rhs.b;
+        self.a += rhs.a;
+    }
+
+    pub fn divide(&mut self, value: Real) {
+        self.r /= value;
+        self.g /= value;
+        self.b /= value;
+        self.a /= value;
+    }
+
+    // Color channels scaled by alpha, transparent samples then don't darken the average
+    pub fn premultiplied(&self) -> Color {
+        Color {r: self.r * self.a, g: self.g * self.a, b: self.b * self.a, a: self.a}
+    }
+
+    pub fn unpremultiplied(&self) -> Color {
+        if self.a <= 0.0 {
+            return Color::new();
+        }
+        Color {r: self.r / self.a, g: self.g / self.a, b: self.b / self.a, a: self.a}
+    }
+
+    // Lightness, green-red and blue-yellow of Ottosson's OKLab for a linear sRGB color
+    pub fn to_oklab(&self) -> [Real; 3] {
+        let l = (0.4122214708*self.r + 0.5363325363*self.g + 0.0514459929*self.b).cbrt();
+        let m = (0.2119034982*self.r + 0.6806995451*self.g + 0.1073969566*self.b).cbrt();
+        let s = (0.0883024619*self.r + 0.2817188376*self.g + 0.6299787005*self.b).cbrt();
+        [
+            0.2104542553*l + 0.7936177850*m - 0.0040720468*s,
+            1.9779984951*l - 2.4285922050*m + 0.4505937099*s,
+            0.0259040371*l + 0.7827717662*m - 0.8086757660*s,
+        ]
+    }
+
+    pub fn from_oklab(lab: [Real; 3], alpha: Real) -> Color {
+        let l = lab[0] + 0.3963377774*lab[1] + 0.2158037573*lab[2];
+        let m = lab[0] - 0.1055613458*lab[1] - 0.0638541728*lab[2];
+        let s = lab[0] - 0.0894841775*lab[1] - 1.2914855480*lab[2];
+        let (l, m, s) = (l*l*l, m*m*m, s*s*s);
+        Color {
+            r: 4.0767416621*l - 3.3077115913*m + 0.2309699292*s,
+            g: -1.2684380046*l + 2.6097574011*m - 0.3413193965*s,
+            b: -0.0041960863*l - 0.7034186147*m + 1.7076147010*s,
+            a: alpha,
+        }
+    }
+}
+
+// Transfer function between the stored color values of palettes and images and linear
+// light, in which samples are averaged and palette entries blended
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Gamma {
+    // Piecewise sRGB curve
+    Srgb,
+    // Plain power law, 1 blends the stored values directly
+    Power(Real),
+}
+
+impl Gamma {
+    pub fn decode(&self, value: Real) -> Real {
+        match *self {
+            Gamma::Srgb if value <= 0.04045 => value / 12.92,
+            Gamma::Srgb => ((value + 0.055) / 1.055).powf(2.4),
+            Gamma::Power(1.0) => value,
+            Gamma::Power(gamma) => value.max(0.0).powf(gamma),
+        }
+    }
+
+    pub fn encode(&self, value: Real) -> Real {
+        match *self {
+            Gamma::Srgb if value <= 0.0031308 => value.max(0.0) * 12.92,
+            Gamma::Srgb => 1.055 * value.powf(1.0 / 2.4) - 0.055,
+            Gamma::Power(1.0) => value,
+            Gamma::Power(gamma) => value.max(0.0).powf(1.0 / gamma),
+        }
+    }
+
+    // Alpha is linear already
+    pub fn decode_color(&self, color: Color) -> Color {
+        Color {r: self.decode(color.r), g: self.decode(color.g), b: self.decode(color.b), a: color.a}
+    }
+
+    pub fn encode_color(&self, color: Color) -> Color {
+        Color {r: self.encode(color.r), g: self.encode(color.g), b: self.encode(color.b), a: color.a}
+    }
+}
+
+impl FromStr for Gamma {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Gamma, String> {
+        if text == "srgb" {
+            return Ok(Gamma::Srgb);
+        }
+        match text.parse::<Real>() {
+            Ok(gamma) if gamma > 0.0 && gamma.is_finite() => Ok(Gamma::Power(gamma)),
+            _ => Err(format!("invalid gamma '{}', expected srgb or a positive number", text)),
+        }
+    }
+}
+
+impl fmt::Display for Gamma {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Gamma::Srgb => write!(f, "srgb"),
+            Gamma::Power(gamma) => write!(f, "{}", gamma),
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, clamp};
+use crate::color::Color;
+use crate::render::{RenderSettings, EscapeResult};
+use crate::interior::Interior;
+use crate::fractal::Fractal;
+use crate::raymarch;
+
+// Bailout radius used by the smooth coloring, large radii make the normalization accurate
+pub const SMOOTH_MAX_LENGTH: Real = 256.0;
+// Bailout radius of the orbit average colorings
+pub const AVERAGE_MAX_LENGTH: Real = 1e5;
+
+// Maps the escape result of a sample to a color
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Coloring {
+    // One palette entry per iteration count
+    Banded,
+    // Normalized iteration count interpolated between palette entries
+    Smooth,
+    // Palette spread over the cumulative distribution of the smooth iteration counts
+    Histogram,
+    // Exterior distance estimate, dark near the boundary and light away from it
+    Distance,
+    // Closest approach of the orbit to the orbit trap of the settings
+    OrbitTrap,
+    // Trap image at the first orbit value on one of its opaque pixels, the orbit trap coloring
+    // for orbits that miss it
+    ImageTrap,
+    // Pickover stalks, where the orbit came closest to the axes, over the smooth coloring
+    Stalks,
+    // Atom domain, the iteration at which |z| was smallest, one palette entry per iteration.
+    // With the palette interior the interior is colored by its atom domain too.
+    AtomDomain,
+    // Average of sin(frequency * arg z) over the orbit
+    Stripe,
+    // Triangle inequality average, where |z| falls between the bounds of |z^2| +- |c|
+    Tia,
+    // Binary decomposition: banded, with the opposite palette entry when z escaped below the real axis
+    Binary,
+    // Angle of z at escape around the palette (angular decomposition)
+    Angle,
+}
+
+impl FromStr for Coloring {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Coloring, String> {
+        match text {
+            "banded" => Ok(Coloring::Banded),
+            "smooth" => Ok(Coloring::Smooth),
+            "histogram" => Ok(Coloring::Histogram),
+            "distance" => Ok(Coloring::Distance),
+            "orbit-trap" => Ok(Coloring::OrbitTrap),
+            "image-trap" => Ok(Coloring::ImageTrap),
+            "stalks" => Ok(Coloring::Stalks),
+            "atom-domain" => Ok(Coloring::AtomDomain),
+            "stripe" => Ok(Coloring::Stripe),
+            "tia" => Ok(Coloring::Tia),
+            "binary" => Ok(Coloring::Binary),
+            "angle" => Ok(Coloring::Angle),
+            _ => Err(format!("unknown coloring '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for Coloring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Coloring::Banded => "banded",
+            Coloring::Smooth => "smooth",
+            Coloring::Histogram => "histogram",
+            Coloring::Distance => "distance",
+            Coloring::OrbitTrap => "orbit-trap",
+            Coloring::ImageTrap => "image-trap",
+            Coloring::Stalks => "stalks",
+            Coloring::AtomDomain => "atom-domain",
+            Coloring::Stripe => "stripe",
+            Coloring::Tia => "tia",
+            Coloring::Binary => "binary",
+            Coloring::Angle => "angle",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Part of the image left transparent for compositing over another background
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Transparency {
+    Opaque,
+    // Points inside the set
+    Interior,
+    // Escaped points
+    Exterior,
+}
+
+impl FromStr for Transparency {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Transparency, String> {
+        match text {
+            "none" => Ok(Transparency::Opaque),
+            "interior" => Ok(Transparency::Interior),
+            "exterior" => Ok(Transparency::Exterior),
+            _ => Err(format!("unknown transparency '{}', expected none, interior or exterior", text)),
+        }
+    }
+}
+
+impl fmt::Display for Transparency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transparency::Opaque => write!(f, "none"),
+            Transparency::Interior => write!(f, "interior"),
+            Transparency::Exterior => write!(f, "exterior"),
+        }
+    }
+}
+
+impl Coloring {
+    // Bailout radius the iteration has to use for this coloring
+    pub fn max_length(&self, default: Real) -> Real {
+        match self {
+            Coloring::Banded | Coloring::OrbitTrap | Coloring::ImageTrap | Coloring::AtomDomain => default,
+            // The decompositions also need it for cells of even size
+            Coloring::Smooth | Coloring::Histogram | Coloring::Distance | Coloring::Stalks | Coloring::Binary | Coloring::Angle => SMOOTH_MAX_LENGTH,
+            // The average converges with a large radius, where the last terms barely change it
+            Coloring::Stripe | Coloring::Tia => AVERAGE_MAX_LENGTH,
+        }
+    }
+
+    // Whether the iteration has to track the derivative of z
+    pub fn needs_derivative(&self) -> bool {
+        *self == Coloring::Distance
+    }
+
+    // Whether the iteration has to measure the orbit against the orbit trap, or the axes for the stalks
+    pub fn needs_trap(&self) -> bool {
+        matches!(self, Coloring::OrbitTrap | Coloring::ImageTrap | Coloring::Stalks)
+    }
+
+    // Whether the iteration has to find the first orbit value on the trap image
+    pub fn needs_trap_image(&self) -> bool {
+        *self == Coloring::ImageTrap
+    }
+
+    // Whether the iteration has to find the iteration at which |z| was smallest
+    pub fn needs_atom_domain(&self) -> bool {
+        *self == Coloring::AtomDomain
+    }
+
+    // Whether the iteration has to average a quantity over the orbit
+    pub fn needs_average(&self) -> bool {
+        matches!(self, Coloring::Stripe | Coloring::Tia)
+    }
+
+    // histogram is required by Coloring::Histogram, it falls back to smooth coloring without it.
+    // Escaped samples are shaded by the lighting of the settings.
+    pub fn color(&self, settings: &RenderSettings, result: &EscapeResult, histogram: Option<&Histogram>) -> Color {
+        let inside = result.iterations >= settings.max_iterations;
+        match settings.transparency {
+            Transparency::Interior if inside => return Color::new(),
+            Transparency::Exterior if !inside => return Color::new(),
+            _ => (),
+        }
+        let color = self.base_color(settings, result, histogram);
+        match &settings.lighting {
+            // 3D surfaces are lit as they are raymarched
+            Some(lighting) if result.iterations < settings.max_iterations && !settings.fractal.is_3d() => lighting.shade(color, result),
+            _ => color,
+        }
+    }
+
+    fn base_color(&self, settings: &RenderSettings, result: &EscapeResult, histogram: Option<&Histogram>) -> Color {
+        let palette = &settings.palette;
+        let max_iterations = settings.max_iterations;
+        let degree = settings.fractal.degree(settings.power);
+        // 3D fractals are lit surfaces in front of the interior color
+        if settings.fractal.is_3d() {
+            return raymarch::color(settings, result);
+        }
+        if result.iterations >= max_iterations {
+            // The distance coloring keeps its interior black unless another interior coloring is chosen
+            if *self == Coloring::Distance && settings.interior == Interior::Palette {
+                return Color {r: 0.0, g: 0.0, b: 0.0, a: 1.0};
+            }
+            if *self == Coloring::AtomDomain && settings.interior == Interior::Palette {
+                return palette.color(result.atom_domain.saturating_sub(1));
+            }
+            return settings.interior.color(settings, result);
+        }
+        // Newton fractals are colored by their roots whatever the coloring
+        if let (Fractal::Newton, Some(polynomial)) = (settings.fractal, &settings.polynomial) {
+            return polynomial.color(settings, result);
+        }
+        // So are Lyapunov fractals by their exponent
+        if let (Fractal::Lyapunov, Some(sequence)) = (settings.fractal, &settings.lyapunov_sequence) {
+            return sequence.color(settings, result);
+        }
+        if *self == Coloring::Distance {
+            // Fully light two pixels away from the boundary
+            let value = settings.gamma.decode(clamp(distance_estimate(result) / (2.0 * settings.pixel_size()), 0.0, 1.0).sqrt());
+            return Color {r: value, g: value, b: value, a: 1.0};
+        }
+        if let (Coloring::ImageTrap, Some(image)) = (self, &settings.trap_image) {
+            if !result.trap_point.r.is_nan() {
+                return image.color(&settings.orbit_trap, &result.trap_point);
+            }
+        }
+        match (self, histogram) {
+            (Coloring::Banded, _) => palette.color(result.iterations),
+            (Coloring::OrbitTrap, _) | (Coloring::ImageTrap, _) => {
+                // First palette entry on the trap, fading to the last one scale away from it
+                let last = (palette.colors.len() - 1) as Real;
+                palette.interpolate((1.0 - (-result.trap / settings.orbit_trap.scale).exp()) * last)
+            }
+            (Coloring::AtomDomain, _) => palette.color(result.atom_domain.saturating_sub(1)),
+            (Coloring::Stalks, _) => settings.stalks.color(settings, result),
+            (Coloring::Binary, _) => {
+                let offset = if result.z.i < 0.0 { palette.colors.len() as u32 / 2 } else { 0 };
+                palette.color(result.iterations.wrapping_add(offset))
+            }
+            (Coloring::Angle, _) => {
+                // [0:1) counterclockwise from the positive real axis
+                let turn = (result.z.i.atan2(result.z.r) / (2.0 * std::f64::consts::PI)).rem_euclid(1.0);
+                palette.interpolate(turn * palette.colors.len() as Real)
+            }
+            (Coloring::Stripe, _) | (Coloring::Tia, _) => {
+                let average = &settings.orbit_average;
+                let last = (palette.colors.len() - 1) as Real;
+                let color = palette.interpolate(average.value(settings, result) * last);
+                if average.mix >= 1.0 {
+                    return color;
+                }
+                let smooth = palette.interpolate(smooth_iterations(result, degree));
+                let channel = |a: Real, b: Real| b + (a - b) * average.mix;
+                Color {r: channel(color.r, smooth.r), g: channel(color.g, smooth.g), b: channel(color.b, smooth.b), a: channel(color.a, smooth.a)}
+            }
+            (Coloring::Histogram, Some(histogram)) => {
+                let last = (palette.colors.len() - 1) as Real;
+                palette.interpolate(histogram.value(smooth_iterations(result, degree)) * last)
+            }
+            _ => palette.interpolate(smooth_iterations(result, degree)),
+        }
+    }
+}
+
+// n + 1 - log_d(ln|z|) for a formula of degree d, continuous across iteration bands
+pub fn smooth_iterations(result: &EscapeResult, degree: Real) -> Real {
+    let log_length = result.z.length_squared().ln() / 2.0;
+    let value = result.iterations as Real + 1.0 - log_length.ln() / degree.ln();
+    value.max(0.0)
+}
+
+// 2|z|ln|z|/|dz|, distance from the escaped point to the set boundary
+pub fn distance_estimate(result: &EscapeResult) -> Real {
+    let length = result.z.length();
+    let derivative_length = result.derivative.length();
+    if derivative_length == 0.0 {
+        return Real::INFINITY;
+    }
+    2.0 * length * length.ln() / derivative_length
+}
+
+// Cumulative distribution of the escaped iteration counts
+pub struct Histogram {
+    // cdf[i] is the fraction of escaped samples with less than i iterations
+    cdf: Vec<Real>,
+}
+
+impl Histogram {
+    pub fn new(results: &[EscapeResult], max_iterations: u32) -> Histogram {
+        let mut counts = vec![0usize; max_iterations as usize + 1];
+        let mut escaped = 0usize;
+        for result in results.iter().filter(|result| result.iterations < max_iterations) {
+            counts[result.iterations as usize] += 1;
+            escaped += 1;
+        }
+        let mut cdf = Vec::with_capacity(counts.len() + 1);
+        let mut total = 0usize;
+        cdf.push(0.0);
+        for count in counts {
+            total += count;
+            cdf.push(total as Real / escaped.max(1) as Real);
+        }
+        Histogram {cdf}
+    }
+
+    // Position [0:1] of a smooth iteration count in the distribution
+    pub fn value(&self, smooth_iterations: Real) -> Real {
+        let last = self.cdf.len() - 1;
+        let index = smooth_iterations.max(0.0);
+        let lower = (index.floor() as usize).min(last);
+        let upper = (lower + 1).min(last);
+        let t = index - index.floor();
+        self.cdf[lower] + (self.cdf[upper] - self.cdf[lower]) * t
+    }
+}
@@ -0,0 +1,283 @@
+use std::path::Path;
+
+// Flat scene description: a list of keys with their values as text.
+// TOML files may only contain "key = value" lines, JSON files a single flat object.
+
+pub fn parse_config_file(path: &str) -> Result<Vec<(String, String)>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let is_json = Path::new(path).extension().map(|e| e.eq_ignore_ascii_case("json")).unwrap_or(false);
+    let result = if is_json { parse_json(&text) } else { parse_toml(&text) };
+    result.map_err(|e| format!("{}: {}", path, e))
+}
+
+// Reads a quoted string starting after the opening quote, returns it and the remaining text
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &text[i+1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, c)) => value.push(c),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    Err(String::from("unterminated string"))
+}
+
+// Parses a trimmed "key = value" line
+fn parse_toml_pair(line: &str) -> Result<(String, String), String> {
+    let (key, value) = line.split_once('=').ok_or_else(|| String::from("expected key = value"))?;
+    let key = key.trim().trim_matches('"');
+    let value = value.trim();
+    let value = match value.strip_prefix('"') {
+        Some(quoted) => {
+            let (value, rest) = parse_string(quoted)?;
+            let rest = rest.trim();
+            if !rest.is_empty() && !rest.starts_with('#') {
+                return Err(String::from("unexpected text after the value"));
+            }
+            value
+        }
+        // Bare values end at a comment
+        None => String::from(value.split('#').next().unwrap_or("").trim()),
+    };
+    if key.is_empty() || value.is_empty() {
+        return Err(String::from("expected key = value"));
+    }
+    Ok((String::from(key), value))
+}
+
+pub fn parse_toml(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(format!("line {}: tables are not supported", number + 1));
+        }
+        pairs.push(parse_toml_pair(line).map_err(|e| format!("line {}: {}", number + 1, e))?);
+    }
+    Ok(pairs)
+}
+
+// Scenes of a job list, see parse_table_file
+pub fn parse_job_file(path: &str) -> Result<Vec<Vec<(String, String)>>, String> {
+    parse_table_file(path, "job")
+}
+
+// Entries of a list of tables, e.g. jobs or keyframes. Every entry starts with the shared keys
+// so its own keys override them. TOML files hold the shared keys before the first [[table]]
+// and one table per entry, JSON files an array of flat objects or an object with the shared
+// keys and an array of entries named after the table with an s appended.
+pub fn parse_table_file(path: &str, table: &str) -> Result<Vec<Vec<(String, String)>>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let is_json = Path::new(path).extension().map(|e| e.eq_ignore_ascii_case("json")).unwrap_or(false);
+    let result = if is_json { parse_json_tables(&text, table) } else { parse_toml_tables(&text, table) };
+    let entries = result.map_err(|e| format!("{}: {}", path, e))?;
+    if entries.is_empty() {
+        return Err(format!("{}: no {}s", path, table));
+    }
+    Ok(entries)
+}
+
+fn parse_toml_tables(text: &str, table: &str) -> Result<Vec<Vec<(String, String)>>, String> {
+    let header = format!("[[{}]]", table);
+    let mut shared = Vec::new();
+    let mut jobs: Vec<Vec<(String, String)>> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            if line.split('#').next().unwrap_or("").trim() != header {
+                return Err(format!("line {}: only {} tables are supported", number + 1, header));
+            }
+            jobs.push(shared.clone());
+            continue;
+        }
+        let pair = parse_toml_pair(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        match jobs.last_mut() {
+            Some(job) => job.push(pair),
+            None => shared.push(pair),
+        }
+    }
+    Ok(jobs)
+}
+
+fn parse_json_tables(text: &str, table: &str) -> Result<Vec<Vec<(String, String)>>, String> {
+    let array = format!("{}s", table);
+    let (shared, entries) = match parse_json_document(text)? {
+        JsonValue::Array(entries) => (Vec::new(), entries),
+        JsonValue::Object(pairs) => {
+            let mut entries = None;
+            let mut shared = Vec::new();
+            for (key, value) in pairs {
+                match value {
+                    JsonValue::Array(items) if key == array => entries = Some(items),
+                    value => shared.push((key, value)),
+                }
+            }
+            (flat_pairs(shared)?, entries.ok_or_else(|| format!("expected a \"{}\" array", array))?)
+        }
+        _ => return Err(format!("expected a JSON array of {}", array)),
+    };
+    entries.into_iter().map(|entry| match entry {
+        JsonValue::Object(pairs) => {
+            let mut pairs = flat_pairs(pairs)?;
+            pairs.splice(0..0, shared.iter().cloned());
+            Ok(pairs)
+        }
+        _ => Err(format!("every {} must be a JSON object", table)),
+    }).collect()
+}
+
+// JSON document, numbers keep their text so no precision is lost
+#[derive(Clone, PartialEq, Debug)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+// Parses a value from the start of text, returns it and the remaining text
+fn parse_json_value(text: &str) -> Result<(JsonValue, &str), String> {
+    let text = text.trim_start();
+    if let Some(quoted) = text.strip_prefix('"') {
+        let (value, rest) = parse_string(quoted)?;
+        return Ok((JsonValue::String(value), rest));
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut items = Vec::new();
+        rest = rest.trim_start();
+        if let Some(end) = rest.strip_prefix(']') {
+            return Ok((JsonValue::Array(items), end));
+        }
+        loop {
+            let (item, after_item) = parse_json_value(rest)?;
+            items.push(item);
+            let after_item = after_item.trim_start();
+            if let Some(next) = after_item.strip_prefix(',') {
+                rest = next;
+            }
+            else if let Some(end) = after_item.strip_prefix(']') {
+                return Ok((JsonValue::Array(items), end));
+            }
+            else {
+                return Err(String::from("expected ',' or ']'"));
+            }
+        }
+    }
+    if let Some(mut rest) = text.strip_prefix('{') {
+        let mut pairs = Vec::new();
+        rest = rest.trim_start();
+        if let Some(end) = rest.strip_prefix('}') {
+            return Ok((JsonValue::Object(pairs), end));
+        }
+        loop {
+            let quoted = rest.trim_start().strip_prefix('"').ok_or_else(|| String::from("expected a quoted key"))?;
+            let (key, after_key) = parse_string(quoted)?;
+            let after_colon = after_key.trim_start().strip_prefix(':').ok_or_else(|| format!("expected ':' after \"{}\"", key))?;
+            let (value, after_value) = parse_json_value(after_colon)?;
+            pairs.push((key, value));
+            let after_value = after_value.trim_start();
+            if let Some(next) = after_value.strip_prefix(',') {
+                rest = next;
+            }
+            else if let Some(end) = after_value.strip_prefix('}') {
+                return Ok((JsonValue::Object(pairs), end));
+            }
+            else {
+                return Err(String::from("expected ',' or '}'"));
+            }
+        }
+    }
+    let end = text.find(|c: char| c == ',' || c == ']' || c == '}' || c.is_whitespace()).unwrap_or(text.len());
+    let (word, rest) = text.split_at(end);
+    let value = match word {
+        "null" => JsonValue::Null,
+        "true" => JsonValue::Bool(true),
+        "false" => JsonValue::Bool(false),
+        _ if word.parse::<f64>().is_ok() => JsonValue::Number(String::from(word)),
+        "" => return Err(String::from("expected a value")),
+        _ => return Err(format!("unexpected '{}'", word)),
+    };
+    Ok((value, rest))
+}
+
+pub fn parse_json_document(text: &str) -> Result<JsonValue, String> {
+    let (value, rest) = parse_json_value(text)?;
+    if !rest.trim().is_empty() {
+        return Err(String::from("unexpected text after the value"));
+    }
+    Ok(value)
+}
+
+// Flat object of scene options
+pub fn parse_json(text: &str) -> Result<Vec<(String, String)>, String> {
+    match parse_json_document(text)? {
+        JsonValue::Object(pairs) => flat_pairs(pairs),
+        _ => Err(String::from("expected a JSON object")),
+    }
+}
+
+// Values of a flat object as text
+fn flat_pairs(pairs: Vec<(String, JsonValue)>) -> Result<Vec<(String, String)>, String> {
+    pairs.into_iter().map(|(key, value)| match value {
+        JsonValue::String(text) | JsonValue::Number(text) => Ok((key, text)),
+        JsonValue::Bool(value) => Ok((key, value.to_string())),
+        _ => Err(format!("unsupported value for \"{}\"", key)),
+    }).collect()
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Writes entries as a list of TOML tables
+pub fn write_toml_tables(table: &str, entries: &[Vec<(String, String)>]) -> String {
+    entries.iter().map(|pairs| format!("[[{}]]\n{}", table, write_toml(pairs))).collect::<Vec<String>>().join("\n")
+}
+
+// Writes pairs as TOML, values that parse as numbers are left unquoted
+pub fn write_toml(pairs: &[(String, String)]) -> String {
+    let mut text = String::new();
+    for (key, value) in pairs {
+        let bare = value.parse::<f64>().is_ok() || value == "true" || value == "false";
+        text.push_str(&format!("{} = {}\n", key, if bare { value.clone() } else { quote(value) }));
+    }
+    text
+}
@@ -0,0 +1,196 @@
+// Full terminal dashboard of --progress tui: a live preview of the finished tiles, the
+// progress with the ETA, a graph of the pixels per second and the tile of every thread.
+// Every frame is redrawn in place over the previous one with ANSI escapes.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io::{stdout, Write};
+use std::time::{Duration, Instant};
+use mandelbrot_set::{Color, RenderProgress, TILE_WIDTH, TILE_HEIGHT, PREVIEW_SCALE};
+use crate::progress::{format_time, format_rate};
+use crate::terminal::{terminal_size, color_rgb};
+
+// Minimum time between two frames
+const FRAME_INTERVAL: Duration = Duration::from_millis(250);
+// Time over which every point of the rate graph is measured
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+// Characters of a thread in the thread list
+const THREAD_WIDTH: usize = 16;
+// Rows of the dashboard below the preview: title, bar, times, rate graph and the threads title
+const INFO_LINES: usize = 5;
+const GRAPH_LEVELS: [char; 8] = ['\u{2581}', '\u{2582}', '\u{2583}', '\u{2584}', '\u{2585}', '\u{2586}', '\u{2587}', '\u{2588}'];
+const UNRENDERED: [u8; 3] = [32, 32, 32];
+const BUSY: [u8; 3] = [120, 100, 40];
+
+pub struct Dashboard {
+    // Image at 1/PREVIEW_SCALE of the resolution, None where no tile was finished
+    width: usize,
+    height: usize,
+    preview: Vec<Option<Color>>,
+    // Pixels per second of every RATE_INTERVAL, the latest last
+    rates: VecDeque<f64>,
+    // Time and finished pixels of the start of the current rate interval
+    rate_start: Option<(Instant, usize)>,
+    last_frame: Option<Instant>,
+    // Lines of the frame on the screen, the next frame replaces them. Messages detach the
+    // dashboard through shared references of the reporter.
+    drawn_lines: Cell<usize>,
+}
+
+impl Dashboard {
+    pub fn new() -> Dashboard {
+        Dashboard {width: 0, height: 0, preview: Vec::new(), rates: VecDeque::new(), rate_start: None, last_frame: None, drawn_lines: Cell::new(0)}
+    }
+
+    // Forgets the previous render, the next frame is drawn below the current output
+    pub fn reset(&mut self) {
+        self.preview.clear();
+        self.width = 0;
+        self.height = 0;
+        self.rates.clear();
+        self.rate_start = None;
+        self.last_frame = None;
+        self.drawn_lines.set(0);
+    }
+
+    // Leaves the last frame on the screen, later output goes below it
+    pub fn detach(&self) {
+        self.drawn_lines.set(0);
+    }
+
+    // Takes the tiles of the progress and draws a frame when one is due, eta in seconds
+    pub fn update(&mut self, progress: &RenderProgress, label: Option<&str>, elapsed: f64, rate: f64, eta: Option<f64>) {
+        let (width, height) = (progress.width.div_ceil(PREVIEW_SCALE), progress.height.div_ceil(PREVIEW_SCALE));
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.preview = vec![None; width * height];
+        }
+        for tile in &progress.tiles {
+            for y in 0..tile.height.min(height.saturating_sub(tile.y)) {
+                for x in 0..tile.width.min(width.saturating_sub(tile.x)) {
+                    self.preview[(tile.y + y) * width + tile.x + x] = Some(tile.pixels[y * tile.width + x]);
+                }
+            }
+        }
+        let now = Instant::now();
+        match self.rate_start {
+            Some((start, pixels)) if now.duration_since(start) >= RATE_INTERVAL => {
+                let seconds = now.duration_since(start).as_secs_f64();
+                self.rates.push_back(progress.finished_pixels.saturating_sub(pixels) as f64 / seconds);
+                // More than any terminal is wide
+                if self.rates.len() > 1000 {
+                    self.rates.pop_front();
+                }
+                self.rate_start = Some((now, progress.finished_pixels));
+            }
+            Some(_) => (),
+            None => self.rate_start = Some((now, progress.finished_pixels)),
+        }
+        let finished = progress.finished_pixels >= progress.total_pixels;
+        if !finished && self.last_frame.is_some_and(|last| now.duration_since(last) < FRAME_INTERVAL) {
+            return;
+        }
+        self.last_frame = Some(now);
+        let frame = self.frame(progress, label, elapsed, rate, eta);
+        let mut out = stdout();
+        // Back to the first line of the previous frame and clear everything below
+        if self.drawn_lines.get() > 0 {
+            write!(out, "\x1b[{}A\r\x1b[J", self.drawn_lines.get()).unwrap();
+        }
+        out.write_all(frame.join("\n").as_bytes()).unwrap();
+        writeln!(out).unwrap();
+        out.flush().unwrap();
+        self.drawn_lines.set(frame.len());
+    }
+
+    fn frame(&self, progress: &RenderProgress, label: Option<&str>, elapsed: f64, rate: f64, eta: Option<f64>) -> Vec<String> {
+        let (columns, lines) = terminal_size();
+        let columns = columns.max(20);
+        let threads_per_line = (columns / THREAD_WIDTH).max(1);
+        let thread_lines = progress.threads.len().div_ceil(threads_per_line);
+        // One line is kept for the cursor
+        let preview_lines = lines.saturating_sub(INFO_LINES + thread_lines + 1).max(4);
+        let mut frame = self.preview_lines(progress, columns, preview_lines);
+
+        let title = match label {
+            Some(label) => format!("{}  {}x{}", label, progress.width, progress.height),
+            None => format!("Rendering {}x{}", progress.width, progress.height),
+        };
+        frame.push(title);
+        let percent = progress.percent() as usize;
+        let bar_width = columns.saturating_sub(8);
+        let filled = bar_width * percent / 100;
+        frame.push(format!("[{}{}] {:>3}%", "\u{2588}".repeat(filled), " ".repeat(bar_width - filled), percent));
+        let eta = eta.map(format_time).unwrap_or_else(|| String::from("-:--"));
+        frame.push(format!("{} elapsed, {} left, {:.1} samples per pixel", format_time(elapsed), eta, progress.samples_per_pixel()));
+        frame.push(self.rate_graph(columns, rate));
+        frame.push(format!("Threads ({}/{} busy)", progress.busy_threads(), progress.threads.len()));
+        for line in progress.threads.chunks(threads_per_line).enumerate().map(|(line, threads)| {
+            threads.iter().enumerate().map(|(i, tile)| {
+                let cell = match tile {
+                    Some(offset) => format!("{:>2} {},{}", line * threads_per_line + i + 1, offset.x, offset.y),
+                    None => format!("{:>2} idle", line * threads_per_line + i + 1),
+                };
+                format!("{:<width$}", cell, width = THREAD_WIDTH)
+            }).collect::<String>()
+        }) {
+            frame.push(line.trim_end().to_string());
+        }
+        frame
+    }
+
+    // Half block rows of the preview fitting in columns x lines, keeping the aspect ratio
+    fn preview_lines(&self, progress: &RenderProgress, columns: usize, lines: usize) -> Vec<String> {
+        if self.width == 0 || self.height == 0 {
+            return Vec::new();
+        }
+        let (image_width, image_height) = (progress.width as f64, progress.height as f64);
+        let scale = (columns as f64 / image_width).min(2.0 * lines as f64 / image_height);
+        let cells = ((image_width * scale).round() as usize).max(1);
+        let rows = ((image_height * scale).round() as usize).max(1);
+        // Tiles being rendered are marked
+        let busy = |x: usize, y: usize| progress.threads.iter().flatten().any(|offset| {
+            x >= offset.x && x < offset.x + TILE_WIDTH && y >= offset.y && y < offset.y + TILE_HEIGHT
+        });
+        let pixel = |cell: usize, row: usize| {
+            // Image position of the center of the cell half
+            let x = ((cell as f64 + 0.5) / scale) as usize;
+            let y = ((row as f64 + 0.5) / scale) as usize;
+            match self.preview[(y / PREVIEW_SCALE).min(self.height - 1) * self.width + (x / PREVIEW_SCALE).min(self.width - 1)] {
+                Some(color) => color_rgb(color),
+                None if busy(x, y) => BUSY,
+                None => UNRENDERED,
+            }
+        };
+        (0..rows.div_ceil(2)).map(|line| {
+            let mut text = String::new();
+            for cell in 0..cells {
+                let top = pixel(cell, 2 * line);
+                let bottom = if 2 * line + 1 < rows { pixel(cell, 2 * line + 1) } else { [0, 0, 0] };
+                text.push_str(&format!("\x1b[38;2;{};{};{};48;2;{};{};{}m\u{2580}", top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]));
+            }
+            text.push_str("\x1b[0m");
+            text
+        }).collect()
+    }
+
+    // Pixels per second of the last intervals as a bar graph with the current rate
+    fn rate_graph(&self, columns: usize, rate: f64) -> String {
+        let label = format_rate(rate);
+        let width = columns.saturating_sub(label.len() + 1);
+        let rates: Vec<f64> = self.rates.iter().skip(self.rates.len().saturating_sub(width)).cloned().collect();
+        let max = rates.iter().cloned().fold(0.0, f64::max);
+        let graph: String = rates.iter().map(|&rate| {
+            let level = if max > 0.0 { (rate / max * (GRAPH_LEVELS.len() - 1) as f64).round() as usize } else { 0 };
+            GRAPH_LEVELS[level]
+        }).collect();
+        format!("{}{} {}", graph, " ".repeat(width - rates.len()), label)
+    }
+}
+
+impl Default for Dashboard {
+    fn default() -> Dashboard {
+        Dashboard::new()
+    }
+}
@@ -0,0 +1,419 @@
+// Distributed rendering: the coordinator splits the image into jobs of whole tiles and hands
+// them to workers connecting over TCP, the job of a worker that disconnects or stops answering
+// goes to the next free one. The finished rows of jobs are written from top to bottom, so the
+// coordinator only holds the rows still waiting for a job.
+//
+// Every message is a frame of a 4 byte big endian length followed by that many bytes, the
+// first of which is the kind of the message. Numbers are big endian u32, colors f64 RGBA.
+//   coordinator to worker: 'S' the scene as TOML, 'P' the palette colors,
+//                          'J' a job (x, y, width, height), 'Q' nothing is left to do
+//   worker to coordinator: 'R' the result (x, y, width, height and the pixels row by row),
+//                          'E' an error message
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use mandelbrot_set::{Real, Color, ImageBuffer, RenderSettings, RenderProgress, RenderedTile, render_region, parse_toml, write_toml, Vec2, TILE_WIDTH, TILE_HEIGHT, PREVIEW_SCALE};
+use crate::cli::{Flags, Options, settings_to_pairs};
+use crate::progress::Reporter;
+
+// Largest frame accepted, well above the result of a job
+const MAX_FRAME: usize = 1 << 30;
+// Bytes of an encoded color
+const COLOR_BYTES: usize = 32;
+// Time between two progress reports of the coordinator
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+// Rectangle of whole tiles rendered by one worker
+#[derive(Clone, Copy)]
+struct Job {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Job {
+    fn encode(&self) -> Vec<u8> {
+        [self.x, self.y, self.width, self.height].iter().flat_map(|&value| (value as u32).to_be_bytes()).collect()
+    }
+
+    // Job at the start of a payload, returns it with the rest of the payload
+    fn decode(payload: &[u8]) -> Result<(Job, &[u8]), String> {
+        if payload.len() < 16 {
+            return Err(String::from("truncated job"));
+        }
+        let value = |i: usize| u32::from_be_bytes([payload[4*i], payload[4*i + 1], payload[4*i + 2], payload[4*i + 3]]) as usize;
+        Ok((Job {x: value(0), y: value(1), width: value(2), height: value(3)}, &payload[16..]))
+    }
+}
+
+fn write_frame(stream: &mut TcpStream, kind: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(5 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32 + 1).to_be_bytes());
+    frame.push(kind);
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)
+}
+
+// Kind and payload of the next frame
+fn read_frame(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length == 0 || length > MAX_FRAME {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!("invalid frame length {}", length)));
+    }
+    let mut frame = vec![0; length];
+    stream.read_exact(&mut frame)?;
+    let payload = frame.split_off(1);
+    Ok((frame[0], payload))
+}
+
+fn encode_colors(colors: &[Color], bytes: &mut Vec<u8>) {
+    for color in colors {
+        for channel in [color.r, color.g, color.b, color.a] {
+            bytes.extend_from_slice(&channel.to_be_bytes());
+        }
+    }
+}
+
+fn decode_colors(bytes: &[u8]) -> Vec<Color> {
+    let channel = |i: usize| {
+        let mut value = [0; 8];
+        value.copy_from_slice(&bytes[8*i..8*i + 8]);
+        Real::from_be_bytes(value)
+    };
+    (0..bytes.len() / COLOR_BYTES).map(|i| Color {r: channel(4*i), g: channel(4*i + 1), b: channel(4*i + 2), a: channel(4*i + 3)}).collect()
+}
+
+// Jobs waiting for a worker and the job of every connected worker
+struct ScheduleState {
+    pending: VecDeque<usize>,
+    done: Vec<bool>,
+    remaining: usize,
+    workers: BTreeMap<usize, Option<usize>>,
+    // Set when the render ends early, waiting workers quit
+    stopped: bool,
+}
+
+struct Schedule {
+    state: Mutex<ScheduleState>,
+    changed: Condvar,
+}
+
+impl Schedule {
+    fn new(job_count: usize) -> Schedule {
+        Schedule {
+            state: Mutex::new(ScheduleState {pending: (0..job_count).collect(), done: vec![false; job_count], remaining: job_count, workers: BTreeMap::new(), stopped: false}),
+            changed: Condvar::new(),
+        }
+    }
+
+    // Waits for a job for the worker, None once all jobs are done
+    fn next_job(&self, worker: usize) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.stopped || state.remaining == 0 {
+                state.workers.insert(worker, None);
+                return None;
+            }
+            if let Some(job) = state.pending.pop_front() {
+                state.workers.insert(worker, Some(job));
+                return Some(job);
+            }
+            state.workers.insert(worker, None);
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    // Marks the job of the worker as done, false when another worker finished it first
+    fn finish_job(&self, worker: usize, job: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.workers.insert(worker, None);
+        if state.done[job] {
+            return false;
+        }
+        state.done[job] = true;
+        state.remaining -= 1;
+        if state.remaining == 0 {
+            self.changed.notify_all();
+        }
+        true
+    }
+
+    // Forgets a worker and puts its unfinished job back in front of the queue
+    fn remove_worker(&self, worker: usize) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        let job = state.workers.remove(&worker).flatten().filter(|&job| !state.done[job]);
+        if let Some(job) = job {
+            state.pending.push_front(job);
+            self.changed.notify_one();
+        }
+        job
+    }
+
+    fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.changed.notify_all();
+    }
+
+    // Job of every connected worker
+    fn worker_jobs(&self) -> Vec<Option<usize>> {
+        self.state.lock().unwrap().workers.values().cloned().collect()
+    }
+}
+
+// What the worker connections tell the coordinator
+enum Event {
+    Connected(usize, SocketAddr),
+    Finished(usize, ImageBuffer),
+    Failed(usize, SocketAddr, String, Option<usize>),
+}
+
+pub struct Coordinator<'a> {
+    settings: &'a RenderSettings,
+    // Settings as TOML without the output and palette, the palette colors are sent separately
+    // so workers don't need the palette file
+    scene: String,
+    jobs: Vec<Job>,
+    // Time a worker may take to answer before its job goes to another one
+    job_timeout: Duration,
+}
+
+impl<'a> Coordinator<'a> {
+    // Jobs are job_tiles tiles wide and one tile high, the settings need a seed so every
+    // worker takes the same samples
+    pub fn new(settings: &'a RenderSettings, job_tiles: usize, job_timeout: Duration) -> Coordinator<'a> {
+        let pairs: Vec<(String, String)> = settings_to_pairs(settings, "").into_iter()
+            .filter(|(key, _)| key != "output" && key != "palette" && key != "palette-gen").collect();
+        let job_width = TILE_WIDTH * job_tiles.max(1);
+        let mut jobs = Vec::new();
+        for y in (0..settings.height).step_by(TILE_HEIGHT) {
+            for x in (0..settings.width).step_by(job_width) {
+                jobs.push(Job {x, y, width: job_width.min(settings.width - x), height: TILE_HEIGHT.min(settings.height - y)});
+            }
+        }
+        Coordinator {settings, scene: write_toml(&pairs), jobs, job_timeout}
+    }
+
+    // Listens for workers until every job is done, handing the finished rows of jobs to
+    // write_band from top to bottom
+    pub fn run(&self, address: &str, reporter: &mut Reporter, write_band: &mut dyn FnMut(&ImageBuffer) -> Result<(), String>) -> Result<(), String> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("cannot listen on {}: {}", address, e))?;
+        // Accepting is polled between the events of the workers
+        listener.set_nonblocking(true).map_err(|e| format!("cannot listen on {}: {}", address, e))?;
+        reporter.message(&format!("Waiting for workers on {}, {} jobs", address, self.jobs.len()));
+        let schedule = Schedule::new(self.jobs.len());
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            let mut next_worker = 1;
+            let result = self.collect(reporter, &schedule, &receiver, write_band, &mut || {
+                match listener.accept() {
+                    Ok((stream, address)) => {
+                        let (id, schedule, sender) = (next_worker, &schedule, sender.clone());
+                        next_worker += 1;
+                        scope.spawn(move || self.serve_worker(id, stream, address, schedule, &sender));
+                        Ok(())
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+                    Err(e) => Err(format!("cannot accept a worker: {}", e)),
+                }
+            });
+            // Idle workers quit, busy ones after their job
+            schedule.stop();
+            result
+        })
+    }
+
+    // Assembles the results of the workers, accept takes the next waiting connection
+    fn collect(&self, reporter: &mut Reporter, schedule: &Schedule, events: &mpsc::Receiver<Event>,
+        write_band: &mut dyn FnMut(&ImageBuffer) -> Result<(), String>, accept: &mut dyn FnMut() -> Result<(), String>) -> Result<(), String> {
+        let settings = self.settings;
+        let band_count = settings.height.div_ceil(TILE_HEIGHT);
+        let jobs_per_band = self.jobs.iter().filter(|job| job.y == 0).count();
+        // Rows of jobs with results, their image and the jobs they still wait for
+        let mut bands: BTreeMap<usize, (ImageBuffer, usize)> = BTreeMap::new();
+        let mut next_band = 0;
+        let mut finished_pixels = 0;
+        let mut tiles = Vec::new();
+        let mut last_report: Option<Instant> = None;
+        while next_band < band_count {
+            if let Err(message) = accept() {
+                reporter.message(&format!("error: {}", message));
+            }
+            match events.recv_timeout(REPORT_INTERVAL) {
+                Ok(Event::Connected(id, address)) => reporter.message(&format!("Worker {} connected from {}", id, address)),
+                Ok(Event::Failed(id, address, message, job)) => {
+                    let requeued = if job.is_some() { ", its job goes to the next free worker" } else { "" };
+                    reporter.message(&format!("Worker {} at {} failed: {}{}", id, address, message, requeued));
+                }
+                Ok(Event::Finished(index, image)) => {
+                    let job = &self.jobs[index];
+                    let band = job.y / TILE_HEIGHT;
+                    let (band_image, waiting) = bands.entry(band).or_insert_with(|| (ImageBuffer::new(settings.width, job.height), jobs_per_band));
+                    for y in 0..job.height {
+                        for x in 0..job.width {
+                            band_image.set(job.x + x, y, image.get(x, y));
+                        }
+                    }
+                    *waiting -= 1;
+                    finished_pixels += job.width * job.height;
+                    let tile = RenderedTile {x: job.x, y: job.y, width: job.width, height: job.height, pixels: image.pixels};
+                    tiles.push(tile.reduced(PREVIEW_SCALE));
+                    // Complete rows go out in order
+                    while bands.get(&next_band).is_some_and(|(_, waiting)| *waiting == 0) {
+                        let (mut band_image, _) = bands.remove(&next_band).unwrap();
+                        settings.post.apply(&mut band_image, settings.gamma, next_band * TILE_HEIGHT, settings.height);
+                        write_band(&band_image)?;
+                        next_band += 1;
+                    }
+                }
+                Err(_) => (),
+            }
+            if next_band == band_count || last_report.is_none_or(|last| last.elapsed() >= REPORT_INTERVAL) {
+                last_report = Some(Instant::now());
+                reporter.update(&RenderProgress {
+                    finished_pixels,
+                    total_pixels: settings.width * settings.height,
+                    width: settings.width,
+                    height: settings.height,
+                    threads: schedule.worker_jobs().into_iter().map(|job| job.map(|job| Vec2::<usize>{x: self.jobs[job].x, y: self.jobs[job].y})).collect(),
+                    samples: 0,
+                    reduced_pixels: 0,
+                    tiles: std::mem::take(&mut tiles),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_worker(&self, id: usize, stream: TcpStream, address: SocketAddr, schedule: &Schedule, events: &mpsc::Sender<Event>) {
+        // The receiver is gone when the render failed, the connection ends anyway
+        let _ = events.send(Event::Connected(id, address));
+        match self.exchange(id, stream, schedule, events) {
+            Ok(()) => {
+                schedule.remove_worker(id);
+            }
+            Err(message) => {
+                let job = schedule.remove_worker(id);
+                let _ = events.send(Event::Failed(id, address, message, job));
+            }
+        }
+    }
+
+    // Sends the scene and then jobs until none are left
+    fn exchange(&self, id: usize, mut stream: TcpStream, schedule: &Schedule, events: &mpsc::Sender<Event>) -> Result<(), String> {
+        let error = |e: io::Error| match e.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => String::from("no answer within the job timeout"),
+            ErrorKind::UnexpectedEof => String::from("disconnected"),
+            _ => e.to_string(),
+        };
+        // Accepted connections may inherit the non-blocking listener
+        stream.set_nonblocking(false).map_err(error)?;
+        stream.set_read_timeout(Some(self.job_timeout)).map_err(error)?;
+        stream.set_write_timeout(Some(self.job_timeout)).map_err(error)?;
+        stream.set_nodelay(true).map_err(error)?;
+        write_frame(&mut stream, b'S', self.scene.as_bytes()).map_err(error)?;
+        let mut palette = Vec::new();
+        encode_colors(&self.settings.palette.colors, &mut palette);
+        write_frame(&mut stream, b'P', &palette).map_err(error)?;
+        while let Some(index) = schedule.next_job(id) {
+            let job = &self.jobs[index];
+            write_frame(&mut stream, b'J', &job.encode()).map_err(error)?;
+            let (kind, payload) = read_frame(&mut stream).map_err(error)?;
+            match kind {
+                b'R' => {
+                    let (result, pixels) = Job::decode(&payload)?;
+                    if (result.x, result.y, result.width, result.height) != (job.x, job.y, job.width, job.height) || pixels.len() != job.width * job.height * COLOR_BYTES {
+                        return Err(String::from("the result doesn't match the job"));
+                    }
+                    if schedule.finish_job(id, index) {
+                        let image = ImageBuffer {width: job.width, height: job.height, pixels: decode_colors(pixels)};
+                        let _ = events.send(Event::Finished(index, image));
+                    }
+                }
+                b'E' => return Err(String::from_utf8_lossy(&payload).into_owned()),
+                _ => return Err(format!("unexpected message '{}'", kind as char)),
+            }
+        }
+        write_frame(&mut stream, b'Q', &[]).map_err(error)
+    }
+}
+
+// Connects to a coordinator and renders its jobs until it has none left, thread_count
+// replaces the thread count of the scene
+pub fn run_worker(address: &str, thread_count: Option<usize>, reporter: &Reporter) -> Result<(), String> {
+    let mut stream = TcpStream::connect(address).map_err(|e| format!("cannot connect to {}: {}", address, e))?;
+    stream.set_nodelay(true).map_err(|e| format!("cannot connect to {}: {}", address, e))?;
+    let lost = |e: io::Error| format!("lost the connection to the coordinator: {}", e);
+    let settings = match receive_scene(&mut stream, thread_count) {
+        Ok(settings) => settings,
+        Err(message) => {
+            // Tell the coordinator, the worker fails either way
+            let _ = write_frame(&mut stream, b'E', message.as_bytes());
+            return Err(message);
+        }
+    };
+    reporter.message(&format!("Connected to {}, rendering a {}x{} image on {} threads", address, settings.width, settings.height, settings.thread_count));
+    let mut job_count = 0;
+    loop {
+        let (kind, payload) = read_frame(&mut stream).map_err(lost)?;
+        match kind {
+            b'J' => {
+                let (job, _) = Job::decode(&payload)?;
+                let start_time = Instant::now();
+                let image = match render_region(&settings, job.x, job.y, job.width, job.height) {
+                    Ok(image) => image,
+                    Err(message) => {
+                        let _ = write_frame(&mut stream, b'E', message.as_bytes());
+                        return Err(message);
+                    }
+                };
+                let mut result = job.encode();
+                encode_colors(&image.pixels, &mut result);
+                write_frame(&mut stream, b'R', &result).map_err(lost)?;
+                job_count += 1;
+                reporter.message(&format!("Rendered {}x{} at {},{} in {} ms", job.width, job.height, job.x, job.y, start_time.elapsed().as_millis()));
+            }
+            b'Q' => {
+                reporter.message(&format!("The render is done, rendered {} jobs", job_count));
+                return Ok(());
+            }
+            _ => return Err(format!("unexpected message '{}' from the coordinator", kind as char)),
+        }
+    }
+}
+
+// Settings of the scene and palette frames
+fn receive_scene(stream: &mut TcpStream, thread_count: Option<usize>) -> Result<RenderSettings, String> {
+    let lost = |e: io::Error| format!("lost the connection to the coordinator: {}", e);
+    let (kind, scene) = read_frame(stream).map_err(lost)?;
+    if kind != b'S' {
+        return Err(String::from("the coordinator didn't send a scene"));
+    }
+    let pairs = parse_toml(&String::from_utf8_lossy(&scene)).map_err(|e| format!("invalid scene: {}", e))?;
+    let mut args = Vec::new();
+    for (key, value) in pairs {
+        args.push(format!("--{}", key));
+        args.push(value);
+    }
+    let mut options = Options::new();
+    let mut flags = Flags::new(&args);
+    while let Some(flag) = flags.next_flag() {
+        options.apply(flag, &mut flags).map_err(|e| format!("invalid scene: {}", e))?;
+    }
+    let (mut settings, _, _) = options.finish().map_err(|e| format!("invalid scene: {}", e))?;
+    let (kind, palette) = read_frame(stream).map_err(lost)?;
+    if kind != b'P' || palette.is_empty() || !palette.len().is_multiple_of(COLOR_BYTES) {
+        return Err(String::from("the coordinator didn't send a palette"));
+    }
+    settings.palette.colors = decode_colors(&palette);
+    if let Some(thread_count) = thread_count {
+        settings.thread_count = thread_count;
+    }
+    Ok(settings)
+}
@@ -0,0 +1,101 @@
+use std::ops::{Add, Sub, Mul, Neg};
+use crate::math::{Real, Float};
+use crate::big_float::BigFloat;
+
+// Unevaluated sum of two f64 values giving about 106 bits of mantissa
+#[derive(Copy, Clone, PartialEq, PartialOrd, Default)]
+pub struct DoubleDouble {
+    pub hi: f64,
+    pub lo: f64,
+}
+
+// Exact sum of two f64 values as hi + lo
+fn two_sum(a: f64, b: f64) -> DoubleDouble {
+    let s = a + b;
+    let bb = s - a;
+    let e = (a - (s - bb)) + (b - bb);
+    DoubleDouble {hi: s, lo: e}
+}
+
+fn quick_two_sum(a: f64, b: f64) -> DoubleDouble {
+    let s = a + b;
+    DoubleDouble {hi: s, lo: b - (s - a)}
+}
+
+// Exact product of two f64 values as hi + lo
+fn two_prod(a: f64, b: f64) -> DoubleDouble {
+    let p = a * b;
+    DoubleDouble {hi: p, lo: a.mul_add(b, -p)}
+}
+
+impl DoubleDouble {
+    pub fn new(hi: f64, lo: f64) -> DoubleDouble {
+        quick_two_sum(hi, lo)
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn add(self, rhs: DoubleDouble) -> DoubleDouble {
+        let s = two_sum(self.hi, rhs.hi);
+        let t = two_sum(self.lo, rhs.lo);
+        let s = quick_two_sum(s.hi, s.lo + t.hi);
+        quick_two_sum(s.hi, s.lo + t.lo)
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble {hi: -self.hi, lo: -self.lo}
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn sub(self, rhs: DoubleDouble) -> DoubleDouble {
+        self + (-rhs)
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, rhs: DoubleDouble) -> DoubleDouble {
+        let p = two_prod(self.hi, rhs.hi);
+        quick_two_sum(p.hi, p.lo + (self.hi * rhs.lo + self.lo * rhs.hi))
+    }
+}
+
+impl Float for DoubleDouble {
+    fn from_real(value: Real) -> DoubleDouble {
+        DoubleDouble {hi: value, lo: 0.0}
+    }
+
+    fn to_real(self) -> Real {
+        self.hi
+    }
+
+    fn abs(self) -> DoubleDouble {
+        if self.hi < 0.0 { -self } else { self }
+    }
+
+    fn sqrt(self) -> DoubleDouble {
+        if self.hi <= 0.0 {
+            return DoubleDouble::default();
+        }
+        // One Newton step on top of the f64 estimate
+        let x = self.hi.sqrt();
+        let residual = self - two_prod(x, x);
+        quick_two_sum(x, residual.hi / (2.0 * x))
+    }
+
+    fn from_big(value: &BigFloat) -> DoubleDouble {
+        let hi = value.to_real();
+        let lo = (*value - BigFloat::from_real(hi)).to_real();
+        quick_two_sum(hi, lo)
+    }
+}
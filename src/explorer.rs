@@ -0,0 +1,409 @@
+// Interactive explorer window on top of a minimal Xlib binding.
+// Drag with the left mouse button to pan, scroll to zoom, S saves the view
+// at full quality, R resets the view and Q or Escape quits. The right mouse
+// button shows the orbit of the point under the cursor, O hides it.
+
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+use std::ptr;
+use mandelbrot_set::{Real, Float, Vec2, BigFloat, Color, RenderSettings, ImageBuffer, ImageSettings, FrameCache, Skew, render, save_image_with, orbit_at, draw_orbit};
+
+type Display = c_void;
+type Window = c_ulong;
+type Atom = c_ulong;
+type Gc = *mut c_void;
+type Visual = c_void;
+
+#[repr(C)]
+struct XImage {
+    width: c_int,
+    height: c_int,
+    xoffset: c_int,
+    format: c_int,
+    data: *mut c_char,
+    // The rest of the structure is only accessed by Xlib
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct XButtonEvent {
+    kind: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut Display,
+    window: Window,
+    root: Window,
+    subwindow: Window,
+    time: c_ulong,
+    x: c_int,
+    y: c_int,
+    x_root: c_int,
+    y_root: c_int,
+    state: c_uint,
+    button: c_uint,
+    same_screen: c_int,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct XConfigureEvent {
+    kind: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut Display,
+    event: Window,
+    window: Window,
+    x: c_int,
+    y: c_int,
+    width: c_int,
+    height: c_int,
+    border_width: c_int,
+    above: Window,
+    override_redirect: c_int,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct XClientMessageEvent {
+    kind: c_int,
+    serial: c_ulong,
+    send_event: c_int,
+    display: *mut Display,
+    window: Window,
+    message_type: Atom,
+    format: c_int,
+    data: [c_long; 5],
+}
+
+// Key, button and motion events share the same layout
+#[repr(C)]
+union XEvent {
+    kind: c_int,
+    button: XButtonEvent,
+    configure: XConfigureEvent,
+    client: XClientMessageEvent,
+    pad: [c_long; 24],
+}
+
+const KEY_PRESS: c_int = 2;
+const BUTTON_PRESS: c_int = 4;
+const BUTTON_RELEASE: c_int = 5;
+const MOTION_NOTIFY: c_int = 6;
+const EXPOSE: c_int = 12;
+const CONFIGURE_NOTIFY: c_int = 22;
+const CLIENT_MESSAGE: c_int = 33;
+
+const KEY_PRESS_MASK: c_long = 1 << 0;
+const BUTTON_PRESS_MASK: c_long = 1 << 2;
+const BUTTON_RELEASE_MASK: c_long = 1 << 3;
+const BUTTON1_MOTION_MASK: c_long = 1 << 8;
+const EXPOSURE_MASK: c_long = 1 << 15;
+const STRUCTURE_NOTIFY_MASK: c_long = 1 << 17;
+
+const Z_PIXMAP: c_int = 2;
+
+const XK_ESCAPE: c_ulong = 0xff1b;
+const XK_K: c_ulong = 0x6b;
+const XK_O: c_ulong = 0x6f;
+const XK_Q: c_ulong = 0x71;
+const XK_R: c_ulong = 0x72;
+const XK_S: c_ulong = 0x73;
+
+#[link(name = "X11")]
+extern "C" {
+    fn XOpenDisplay(name: *const c_char) -> *mut Display;
+    fn XCloseDisplay(display: *mut Display) -> c_int;
+    fn XDefaultScreen(display: *mut Display) -> c_int;
+    fn XDefaultVisual(display: *mut Display, screen: c_int) -> *mut Visual;
+    fn XDefaultDepth(display: *mut Display, screen: c_int) -> c_int;
+    fn XDefaultGC(display: *mut Display, screen: c_int) -> Gc;
+    fn XRootWindow(display: *mut Display, screen: c_int) -> Window;
+    fn XBlackPixel(display: *mut Display, screen: c_int) -> c_ulong;
+    fn XCreateSimpleWindow(display: *mut Display, parent: Window, x: c_int, y: c_int, width: c_uint, height: c_uint,
+        border_width: c_uint, border: c_ulong, background: c_ulong) -> Window;
+    fn XStoreName(display: *mut Display, window: Window, name: *const c_char) -> c_int;
+    fn XSelectInput(display: *mut Display, window: Window, mask: c_long) -> c_int;
+    fn XMapWindow(display: *mut Display, window: Window) -> c_int;
+    fn XInternAtom(display: *mut Display, name: *const c_char, only_if_exists: c_int) -> Atom;
+    fn XSetWMProtocols(display: *mut Display, window: Window, protocols: *mut Atom, count: c_int) -> c_int;
+    fn XPending(display: *mut Display) -> c_int;
+    fn XNextEvent(display: *mut Display, event: *mut XEvent) -> c_int;
+    fn XLookupKeysym(event: *mut XEvent, index: c_int) -> c_ulong;
+    fn XCreateImage(display: *mut Display, visual: *mut Visual, depth: c_uint, format: c_int, offset: c_int,
+        data: *mut c_char, width: c_uint, height: c_uint, bitmap_pad: c_int, bytes_per_line: c_int) -> *mut XImage;
+    fn XDestroyImage(image: *mut XImage) -> c_int;
+    fn XPutImage(display: *mut Display, drawable: Window, gc: Gc, image: *mut XImage, src_x: c_int, src_y: c_int,
+        dest_x: c_int, dest_y: c_int, width: c_uint, height: c_uint) -> c_int;
+    fn XFlush(display: *mut Display) -> c_int;
+}
+
+// Downscale factors of the progressive passes, each pass uses a single sample
+const PASS_SCALES: [usize; 4] = [8, 4, 2, 1];
+const ZOOM_STEP: Real = 1.25;
+const ORBIT_COLOR: Color = Color {r: 1.0, g: 1.0, b: 1.0, a: 1.0};
+
+struct Window11 {
+    display: *mut Display,
+    window: Window,
+    screen: c_int,
+    delete_atom: Atom,
+}
+
+impl Window11 {
+    fn open(width: usize, height: usize) -> Result<Window11, String> {
+        unsafe {
+            let display = XOpenDisplay(ptr::null());
+            if display.is_null() {
+                return Err(String::from("cannot open the X display"));
+            }
+            let screen = XDefaultScreen(display);
+            let black = XBlackPixel(display, screen);
+            let window = XCreateSimpleWindow(display, XRootWindow(display, screen), 0, 0, width as c_uint, height as c_uint, 0, black, black);
+            XStoreName(display, window, b"Mandelbrot explorer\0".as_ptr() as *const c_char);
+            XSelectInput(display, window, KEY_PRESS_MASK | BUTTON_PRESS_MASK | BUTTON_RELEASE_MASK | BUTTON1_MOTION_MASK | EXPOSURE_MASK | STRUCTURE_NOTIFY_MASK);
+            let mut delete_atom = XInternAtom(display, b"WM_DELETE_WINDOW\0".as_ptr() as *const c_char, 0);
+            XSetWMProtocols(display, window, &mut delete_atom, 1);
+            XMapWindow(display, window);
+            XFlush(display);
+            Ok(Window11 {display, window, screen, delete_atom})
+        }
+    }
+
+    fn next_event(&self, wait: bool) -> Option<XEvent> {
+        unsafe {
+            if !wait && XPending(self.display) == 0 {
+                return None;
+            }
+            let mut event = XEvent {pad: [0; 24]};
+            XNextEvent(self.display, &mut event);
+            Some(event)
+        }
+    }
+
+    // Draws a frame of packed 0x00RRGGBB pixels
+    fn present(&self, pixels: &mut [u32], width: usize, height: usize) {
+        unsafe {
+            let visual = XDefaultVisual(self.display, self.screen);
+            let depth = XDefaultDepth(self.display, self.screen);
+            let image = XCreateImage(self.display, visual, depth as c_uint, Z_PIXMAP, 0, pixels.as_mut_ptr() as *mut c_char,
+                width as c_uint, height as c_uint, 32, 0);
+            if image.is_null() {
+                return;
+            }
+            XPutImage(self.display, self.window, XDefaultGC(self.display, self.screen), image, 0, 0, 0, 0, width as c_uint, height as c_uint);
+            // The pixel memory belongs to the caller
+            (*image).data = ptr::null_mut();
+            XDestroyImage(image);
+            XFlush(self.display);
+        }
+    }
+}
+
+impl Drop for Window11 {
+    fn drop(&mut self) {
+        unsafe {
+            XCloseDisplay(self.display);
+        }
+    }
+}
+
+// Scales a pass rendered at a fraction of the window size up to the window
+fn upscale(image: &ImageBuffer, width: usize, height: usize) -> Vec<u32> {
+    let mut pixels = vec![0u32; width * height];
+    for y in 0..height {
+        let source_y = (y * image.height / height).min(image.height - 1);
+        for x in 0..width {
+            let source_x = (x * image.width / width).min(image.width - 1);
+            let color = image.get(source_x, source_y);
+            let r = (color.r.clamp(0.0, 1.0) * 255.0) as u32;
+            let g = (color.g.clamp(0.0, 1.0) * 255.0) as u32;
+            let b = (color.b.clamp(0.0, 1.0) * 255.0) as u32;
+            pixels[y * width + x] = (r << 16) | (g << 8) | b;
+        }
+    }
+    pixels
+}
+
+// Draws the orbit of the point over the frame at the window resolution
+fn overlay_orbit(frame: &mut [u32], view: &RenderSettings, point: &Vec2<BigFloat>) {
+    let orbit = match orbit_at(view, view.offset_of(point)) {
+        Ok(orbit) => orbit,
+        Err(_) => return,
+    };
+    let mut layer = ImageBuffer::new(view.width, view.height);
+    draw_orbit(&mut layer, view, &orbit, ORBIT_COLOR);
+    for (pixel, color) in frame.iter_mut().zip(layer.pixels.iter()) {
+        if color.a > 0.0 {
+            *pixel = ((color.r * 255.0) as u32) << 16 | ((color.g * 255.0) as u32) << 8 | (color.b * 255.0) as u32;
+        }
+    }
+}
+
+enum Action {
+    None,
+    // The view changed and the progressive passes have to restart
+    Redraw,
+    // The current frame only needs to be shown again
+    Present,
+    // The orbit over the frame changed
+    Overlay,
+    Save,
+    Quit,
+}
+
+pub fn explore(settings: &RenderSettings, output: &str, image_settings: &ImageSettings) -> Result<(), String> {
+    // Keep the aspect ratio of the requested image in a window that fits on screen
+    let window_scale = (1280.0 / settings.width as Real).min(800.0 / settings.height as Real).min(1.0);
+    let mut view = settings.clone();
+    view.width = ((settings.width as Real * window_scale) as usize).max(1);
+    view.height = ((settings.height as Real * window_scale) as usize).max(1);
+    view.sample_count = 1;
+    let initial_view = view.clone();
+
+    let window = Window11::open(view.width, view.height)?;
+    let mut frame = vec![0u32; view.width * view.height];
+    let mut next_pass = 0usize;
+    // Last full resolution pass, panned and zoomed in views reuse its pixels
+    let mut cache = FrameCache::new();
+    let mut drag: Option<(c_int, c_int)> = None;
+    // Point whose orbit is shown over the view
+    let mut orbit: Option<Vec2<BigFloat>> = None;
+    // Last rendered pass without the orbit, shown again when the orbit changes
+    let mut last_image: Option<ImageBuffer> = None;
+
+    loop {
+        // Block for events only when there is nothing left to render
+        let wait = next_pass >= PASS_SCALES.len();
+        let mut action = Action::None;
+        while let Some(mut event) = window.next_event(wait && matches!(action, Action::None)) {
+            let event_action = unsafe {
+                match event.kind {
+                    KEY_PRESS => match XLookupKeysym(&mut event, 0) {
+                        XK_ESCAPE | XK_Q => Action::Quit,
+                        XK_S => Action::Save,
+                        XK_R => {
+                            let (width, height) = (view.width, view.height);
+                            view = initial_view.clone();
+                            view.view_size.x = view.view_size.y * width as Real / height as Real;
+                            view.width = width;
+                            view.height = height;
+                            Action::Redraw
+                        }
+                        // Toggles the estimated skew of the view center
+                        XK_K => {
+                            view.skew = match view.skew {
+                                Some(_) => None,
+                                None => Skew::estimate(&view).ok(),
+                            };
+                            Action::Redraw
+                        }
+                        XK_O => {
+                            orbit = None;
+                            Action::Overlay
+                        }
+                        _ => Action::None,
+                    },
+                    BUTTON_PRESS => match event.button.button {
+                        1 => {
+                            drag = Some((event.button.x, event.button.y));
+                            Action::None
+                        }
+                        3 => {
+                            let offset = view.pixel_to_offset(event.button.x as Real + 0.5, event.button.y as Real + 0.5);
+                            orbit = Some(Vec2::<BigFloat>{x: view.center.x + BigFloat::from_real(offset.x), y: view.center.y + BigFloat::from_real(offset.y)});
+                            Action::Overlay
+                        }
+                        4 | 5 => {
+                            let factor = if event.button.button == 4 { ZOOM_STEP } else { 1.0 / ZOOM_STEP };
+                            view.zoom_at(event.button.x as Real, event.button.y as Real, factor);
+                            Action::Redraw
+                        }
+                        _ => Action::None,
+                    },
+                    MOTION_NOTIFY | BUTTON_RELEASE => match drag {
+                        Some((x, y)) => {
+                            let from = view.pixel_to_offset(x as Real, y as Real);
+                            let to = view.pixel_to_offset(event.button.x as Real, event.button.y as Real);
+                            view.pan(mandelbrot_set::Vec2::<Real>{x: from.x - to.x, y: from.y - to.y});
+                            drag = if event.kind == MOTION_NOTIFY { Some((event.button.x, event.button.y)) } else { None };
+                            Action::Redraw
+                        }
+                        None => Action::None,
+                    },
+                    EXPOSE => Action::Present,
+                    CONFIGURE_NOTIFY => {
+                        let width = event.configure.width.max(1) as usize;
+                        let height = event.configure.height.max(1) as usize;
+                        if width != view.width || height != view.height {
+                            // Keep the vertical extent and widen or narrow the view
+                            view.view_size.x = view.view_size.y * width as Real / height as Real;
+                            view.width = width;
+                            view.height = height;
+                            frame = vec![0u32; width * height];
+                            Action::Redraw
+                        }
+                        else {
+                            Action::None
+                        }
+                    }
+                    CLIENT_MESSAGE if event.client.data[0] as Atom == window.delete_atom => Action::Quit,
+                    _ => Action::None,
+                }
+            };
+            action = match (action, event_action) {
+                (Action::Quit, _) | (_, Action::Quit) => Action::Quit,
+                (Action::Save, _) | (_, Action::Save) => Action::Save,
+                (Action::Redraw, _) | (_, Action::Redraw) => Action::Redraw,
+                (Action::Overlay, _) | (_, Action::Overlay) => Action::Overlay,
+                (Action::Present, _) | (_, Action::Present) => Action::Present,
+                _ => Action::None,
+            };
+        }
+
+        match action {
+            Action::Quit => return Ok(()),
+            Action::Save => {
+                // Render the current view at the requested resolution and sample count
+                let mut full = settings.clone();
+                full.center = view.center;
+                full.view_size.y = view.view_size.y;
+                full.view_size.x = view.view_size.y * settings.width as Real / settings.height as Real;
+                full.update_auto_iterations();
+                println!("Saving the current view to {}...", output);
+                save_image_with(&render(&full), output, image_settings)?;
+                println!("Saved buffer to {}", output);
+            }
+            Action::Redraw => {
+                // A pan by whole pixels only renders the strips that came into view, so it
+                // skips the coarse passes
+                let mut pass = view.preview(1);
+                cache.snap(&mut pass);
+                view.center = pass.center;
+                next_pass = if cache.reuses(&pass) { PASS_SCALES.len() - 1 } else { 0 };
+            }
+            Action::Present => window.present(&mut frame, view.width, view.height),
+            Action::Overlay => {
+                if let Some(image) = &last_image {
+                    frame = upscale(image, view.width, view.height);
+                    if let Some(point) = &orbit {
+                        overlay_orbit(&mut frame, &view, point);
+                    }
+                    window.present(&mut frame, view.width, view.height);
+                }
+            }
+            Action::None => {}
+        }
+
+        if next_pass < PASS_SCALES.len() {
+            let scale = PASS_SCALES[next_pass];
+            let pass = view.preview(scale);
+            let image = if scale == 1 { cache.render(&pass, |_| {}) } else { render(&pass) };
+            frame = upscale(&image, view.width, view.height);
+            if let Some(point) = &orbit {
+                overlay_orbit(&mut frame, &view, point);
+            }
+            window.present(&mut frame, view.width, view.height);
+            last_image = Some(image);
+            next_pass += 1;
+        }
+    }
+}
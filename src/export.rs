@@ -0,0 +1,81 @@
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crate::math::Real;
+use crate::coloring::{SMOOTH_MAX_LENGTH, smooth_iterations};
+use crate::cache::IterationCache;
+use crate::image::create_file;
+use crate::render::RenderSettings;
+
+// Mean iteration count of the samples of every pixel, row major. The counts are fractional
+// (normalized) when the coloring iterated to the smooth bailout radius, samples that never
+// escaped count as max_iterations.
+pub fn pixel_iterations(settings: &RenderSettings, cache: &IterationCache) -> Vec<Real> {
+    let smooth = settings.max_length() >= SMOOTH_MAX_LENGTH;
+    let degree = settings.fractal.degree(settings.power);
+    cache.samples.iter().map(|results| {
+        let sum: Real = results.iter().map(|result| {
+            if result.iterations >= settings.max_iterations || !smooth {
+                result.iterations as Real
+            }
+            else {
+                smooth_iterations(result, degree)
+            }
+        }).sum();
+        sum / results.len().max(1) as Real
+    }).collect()
+}
+
+// Logarithm of the iteration count of every pixel scaled to [0:1], the height of the mesh
+// export and the depth of stereo images
+pub fn pixel_heights(settings: &RenderSettings, cache: &IterationCache) -> Vec<Real> {
+    let values: Vec<Real> = pixel_iterations(settings, cache).iter().map(|iterations| iterations.max(0.0).ln_1p()).collect();
+    let highest = values.iter().cloned().fold(0.0, Real::max);
+    values.iter().map(|value| if highest > 0.0 { value / highest } else { 0.0 }).collect()
+}
+
+// Writes the iteration counts of pixel_iterations, the format is picked by the extension:
+// .npy a float64 array of shape (height, width), .csv one line per row, anything else raw
+// little endian float64 values with the dimensions in a .json file next to it
+pub fn export_data(path: &str, settings: &RenderSettings, cache: &IterationCache) -> Result<(), String> {
+    let values = pixel_iterations(settings, cache);
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    let error = |e: std::io::Error| format!("cannot write {}: {}", path, e);
+    let file = create_file(path)?;
+    let mut writer = BufWriter::new(file);
+    match extension.as_str() {
+        "npy" => write_npy(&mut writer, &values, cache.width, cache.height).map_err(error)?,
+        "csv" => {
+            for row in values.chunks(cache.width.max(1)) {
+                let line: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+                writeln!(writer, "{}", line.join(",")).map_err(error)?;
+            }
+        }
+        _ => {
+            for value in &values {
+                writer.write_all(&value.to_le_bytes()).map_err(error)?;
+            }
+            let metadata = format!("{{\"width\": {}, \"height\": {}, \"dtype\": \"<f8\", \"max_iterations\": {}}}\n",
+                cache.width, cache.height, settings.max_iterations);
+            let metadata_path = format!("{}.json", path);
+            std::fs::write(&metadata_path, metadata).map_err(|e| format!("cannot write {}: {}", metadata_path, e))?;
+        }
+    }
+    writer.flush().map_err(error)
+}
+
+// NumPy format version 1.0
+fn write_npy<W: Write>(writer: &mut W, values: &[Real], width: usize, height: usize) -> std::io::Result<()> {
+    const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+    let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}", height, width);
+    // The data starts 64 byte aligned, the header ends with a newline
+    let unpadded = MAGIC.len() + 2 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+    writer.write_all(MAGIC)?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
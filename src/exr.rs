@@ -0,0 +1,131 @@
+use std::io::{BufWriter, Write};
+use crate::math::Real;
+use crate::coloring::distance_estimate;
+use crate::image::{ImageBuffer, create_file};
+use crate::cache::IterationCache;
+use crate::render::RenderSettings;
+use crate::export::pixel_iterations;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+// Per pixel means of the samples besides the color
+struct DataChannels {
+    // Smooth iteration count, see pixel_iterations
+    iterations: Vec<Real>,
+    // Exterior distance estimate in plane units, 0 inside the set
+    distance: Option<Vec<Real>>,
+    // |z| and arg(z) of the last iteration
+    magnitude: Vec<Real>,
+    argument: Vec<Real>,
+}
+
+impl DataChannels {
+    fn new(settings: &RenderSettings, cache: &IterationCache) -> DataChannels {
+        let mut magnitude = Vec::with_capacity(cache.samples.len());
+        let mut argument = Vec::with_capacity(cache.samples.len());
+        let mut distance = Vec::with_capacity(cache.samples.len());
+        for results in &cache.samples {
+            let count = results.len().max(1) as Real;
+            magnitude.push(results.iter().map(|result| result.z.length()).sum::<Real>() / count);
+            // Circular mean, angles wrap around at pi
+            let (sin, cos) = results.iter().fold((0.0, 0.0), |(sin, cos), result| {
+                let angle = result.z.i.atan2(result.z.r);
+                (sin + angle.sin(), cos + angle.cos())
+            });
+            argument.push(sin.atan2(cos));
+            distance.push(results.iter().map(|result| {
+                if result.iterations >= settings.max_iterations { 0.0 } else { distance_estimate(result).min(Real::MAX) }
+            }).sum::<Real>() / count);
+        }
+        DataChannels {
+            iterations: pixel_iterations(settings, cache),
+            distance: if cache.derivative { Some(distance) } else { None },
+            magnitude,
+            argument,
+        }
+    }
+}
+
+fn write_attribute<W: Write>(writer: &mut W, name: &str, kind: &str, value: &[u8]) -> std::io::Result<()> {
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(kind.as_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(&(value.len() as i32).to_le_bytes())?;
+    writer.write_all(value)
+}
+
+// Writes an uncompressed scanline OpenEXR file with 32 bit float R, G, B and A channels.
+// With data, the smooth iteration count, the distance estimate (when the cache has
+// derivatives), |z| and arg(z) are stored in the iterations, distance, magnitude and
+// argument channels.
+pub fn save_exr(image: &ImageBuffer, path: &str, data: Option<(&RenderSettings, &IterationCache)>) -> Result<(), String> {
+    let colors = &image.pixels;
+    let mut channels: Vec<(&str, Vec<Real>)> = vec![
+        ("A", colors.iter().map(|color| color.a).collect()),
+        ("B", colors.iter().map(|color| color.b).collect()),
+        ("G", colors.iter().map(|color| color.g).collect()),
+        ("R", colors.iter().map(|color| color.r).collect()),
+    ];
+    // Channels are stored sorted by name
+    if let Some((settings, cache)) = data {
+        let data = DataChannels::new(settings, cache);
+        channels.push(("argument", data.argument));
+        if let Some(distance) = data.distance {
+            channels.push(("distance", distance));
+        }
+        channels.push(("iterations", data.iterations));
+        channels.push(("magnitude", data.magnitude));
+    }
+
+    let error = |e: std::io::Error| format!("cannot write {}: {}", path, e);
+    let file = create_file(path)?;
+    let mut writer = BufWriter::new(file);
+    let mut header = Vec::new();
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&2i32.to_le_bytes());
+
+    let mut channel_list = Vec::new();
+    for (name, _) in &channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        // pLinear and reserved bytes, then the x and y sampling
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    let mut window = Vec::new();
+    for value in [0, 0, image.width as i32 - 1, image.height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    write_attribute(&mut header, "channels", "chlist", &channel_list).map_err(error)?;
+    write_attribute(&mut header, "compression", "compression", &[0]).map_err(error)?;
+    write_attribute(&mut header, "dataWindow", "box2i", &window).map_err(error)?;
+    write_attribute(&mut header, "displayWindow", "box2i", &window).map_err(error)?;
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]).map_err(error)?;
+    write_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes()).map_err(error)?;
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0u8; 8]).map_err(error)?;
+    write_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes()).map_err(error)?;
+    header.push(0);
+    writer.write_all(&header).map_err(error)?;
+
+    // Offset table, one uncompressed scanline per block
+    let line_size = channels.len() * image.width * 4;
+    let first_line = header.len() + 8 * image.height;
+    for y in 0..image.height {
+        writer.write_all(&((first_line + y * (8 + line_size)) as u64).to_le_bytes()).map_err(error)?;
+    }
+    for y in 0..image.height {
+        writer.write_all(&(y as i32).to_le_bytes()).map_err(error)?;
+        writer.write_all(&(line_size as i32).to_le_bytes()).map_err(error)?;
+        for (_, values) in &channels {
+            for value in &values[y * image.width..(y + 1) * image.width] {
+                writer.write_all(&(*value as f32).to_le_bytes()).map_err(error)?;
+            }
+        }
+    }
+    writer.flush().map_err(error)
+}
@@ -0,0 +1,176 @@
+// C API for embedding the renderer, declared in include/mandelbrot_set.h. Settings live behind
+// an opaque pointer from mandelbrot_settings_new, functions that can fail return 0 on success
+// and -1 otherwise, with the message available from mandelbrot_last_error.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::slice;
+use crate::math::{Real, Vec2};
+use crate::big_float::BigFloat;
+use crate::palette::Palette;
+use crate::fractal::Fractal;
+use crate::render::{RenderSettings, render_into};
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+// Stores the error of a failed call for mandelbrot_last_error
+fn status(result: Result<(), String>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(message) => {
+            let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+            LAST_ERROR.with(|error| *error.borrow_mut() = message);
+            -1
+        },
+    }
+}
+
+unsafe fn settings_mut<'a>(settings: *mut RenderSettings) -> Result<&'a mut RenderSettings, String> {
+    settings.as_mut().ok_or_else(|| String::from("settings is null"))
+}
+
+unsafe fn text<'a>(value: *const c_char) -> Result<&'a str, String> {
+    if value.is_null() {
+        return Err(String::from("text is null"));
+    }
+    CStr::from_ptr(value).to_str().map_err(|_| String::from("text is not UTF-8"))
+}
+
+// Message of the last failed call on this thread, valid until the next failing call
+#[no_mangle]
+pub extern "C" fn mandelbrot_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ptr())
+}
+
+// Default settings, released with mandelbrot_settings_free
+#[no_mangle]
+pub extern "C" fn mandelbrot_settings_new() -> *mut RenderSettings {
+    Box::into_raw(Box::new(RenderSettings::new()))
+}
+
+// Safety: settings has to come from mandelbrot_settings_new or be null
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_free(settings: *mut RenderSettings) {
+    if !settings.is_null() {
+        drop(Box::from_raw(settings));
+    }
+}
+
+// Image size in pixels, the view keeps its width and follows the aspect ratio
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_set_size(settings: *mut RenderSettings, width: usize, height: usize) -> c_int {
+    status(settings_mut(settings).and_then(|settings| {
+        if width == 0 || height == 0 {
+            return Err(String::from("the image size must be positive"));
+        }
+        settings.width = width;
+        settings.height = height;
+        settings.view_size.y = settings.view_size.x * height as Real / width as Real;
+        Ok(())
+    }))
+}
+
+// View center as decimal text so deep zooms keep every digit
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_set_center(settings: *mut RenderSettings, x: *const c_char, y: *const c_char) -> c_int {
+    status(settings_mut(settings).and_then(|settings| {
+        settings.center = Vec2::<BigFloat>{x: text(x)?.parse()?, y: text(y)?.parse()?};
+        Ok(())
+    }))
+}
+
+// Width of the view in the complex plane, the height follows the aspect ratio
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_set_view_width(settings: *mut RenderSettings, view_width: Real) -> c_int {
+    status(settings_mut(settings).and_then(|settings| {
+        if view_width.is_nan() || view_width <= 0.0 {
+            return Err(String::from("the view width must be positive"));
+        }
+        settings.view_size = Vec2::<Real>{x: view_width, y: view_width * settings.height as Real / settings.width as Real};
+        Ok(())
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_set_iterations(settings: *mut RenderSettings, iterations: u32) -> c_int {
+    status(settings_mut(settings).and_then(|settings| {
+        if iterations == 0 {
+            return Err(String::from("iteration count must be positive"));
+        }
+        settings.max_iterations = iterations;
+        Ok(())
+    }))
+}
+
+// Anti-aliasing samples per pixel
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_set_samples(settings: *mut RenderSettings, samples: usize) -> c_int {
+    status(settings_mut(settings).and_then(|settings| {
+        if samples == 0 {
+            return Err(String::from("the sample count must be positive"));
+        }
+        settings.sample_count = samples;
+        settings.min_sample_count = settings.min_sample_count.min(samples);
+        Ok(())
+    }))
+}
+
+// Worker threads, 0 for one per core
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_set_threads(settings: *mut RenderSettings, threads: usize) -> c_int {
+    status(settings_mut(settings).map(|settings| {
+        settings.thread_count = if threads == 0 { RenderSettings::new().thread_count } else { threads };
+    }))
+}
+
+// Fixes the sample positions so renders are reproducible
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_set_seed(settings: *mut RenderSettings, seed: u64) -> c_int {
+    status(settings_mut(settings).map(|settings| settings.seed = Some(seed)))
+}
+
+// Fractal by name, like the --fractal option, except those needing a formula, polynomial,
+// sequence or pattern this API can't pass
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_set_fractal(settings: *mut RenderSettings, fractal: *const c_char) -> c_int {
+    status(settings_mut(settings).and_then(|settings| {
+        let fractal: Fractal = text(fractal)?.parse()?;
+        if matches!(fractal, Fractal::Formula | Fractal::Newton | Fractal::Lyapunov | Fractal::Hybrid) {
+            return Err(format!("the {} fractal needs options the C API can't set", fractal));
+        }
+        settings.fractal = fractal;
+        Ok(())
+    }))
+}
+
+// Coloring by name, like the --coloring option
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_set_coloring(settings: *mut RenderSettings, coloring: *const c_char) -> c_int {
+    status(settings_mut(settings).and_then(|settings| {
+        settings.coloring = text(coloring)?.parse()?;
+        Ok(())
+    }))
+}
+
+// Generated gradient, like the --palette-gen option
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_settings_set_palette(settings: *mut RenderSettings, spec: *const c_char) -> c_int {
+    status(settings_mut(settings).and_then(|settings| {
+        settings.palette = Palette {offset: settings.palette.offset, interpolation: settings.palette.interpolation, ..Palette::generate(text(spec)?)?};
+        Ok(())
+    }))
+}
+
+// Renders into buffer, which has to hold width * height * 4 bytes of RGBA
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_render(settings: *const RenderSettings, buffer: *mut u8, length: usize) -> c_int {
+    status(settings.as_ref().ok_or_else(|| String::from("settings is null")).and_then(|settings| {
+        if buffer.is_null() {
+            return Err(String::from("buffer is null"));
+        }
+        render_into(slice::from_raw_parts_mut(buffer, length), settings)
+    }))
+}
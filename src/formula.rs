@@ -0,0 +1,358 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, Complex};
+
+// Deepest evaluation stack a formula may need
+const MAX_STACK: usize = 32;
+// Deepest nesting of parentheses, signs and powers the parser recurses into
+const MAX_NESTING: usize = 256;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Function {
+    Sin,
+    Cos,
+    Exp,
+    Log,
+    Sqrt,
+    Conj,
+    Abs,
+    Re,
+    Im,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Function> {
+        match name {
+            "sin" => Some(Function::Sin),
+            "cos" => Some(Function::Cos),
+            "exp" => Some(Function::Exp),
+            "log" | "ln" => Some(Function::Log),
+            "sqrt" => Some(Function::Sqrt),
+            "conj" => Some(Function::Conj),
+            "abs" => Some(Function::Abs),
+            "re" => Some(Function::Re),
+            "im" => Some(Function::Im),
+            _ => None,
+        }
+    }
+}
+
+// Instructions of the stack machine a formula compiles to
+#[derive(Copy, Clone)]
+enum Op {
+    Constant(Complex<Real>),
+    Z,
+    C,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    // Power with a constant integer exponent
+    PowInt(i32),
+    Neg,
+    Call(Function),
+}
+
+// Value with its derivative with respect to the pixel
+#[derive(Copy, Clone)]
+struct Dual {
+    value: Complex<Real>,
+    derivative: Complex<Real>,
+}
+
+fn powi(z: &Complex<Real>, exponent: i32) -> Complex<Real> {
+    if exponent == 0 {
+        return Complex::from_real(1.0);
+    }
+    let power = z.powf(exponent.unsigned_abs() as Real);
+    if exponent < 0 { Complex::from_real(1.0) / power } else { power }
+}
+
+// Iteration formula given as an expression of z and c, e.g. "z^3 + c*z + c"
+#[derive(Clone)]
+pub struct Formula {
+    source: String,
+    ops: Vec<Op>,
+}
+
+impl Formula {
+    // Evaluates the formula, the derivative of the result is only computed when DERIVATIVE is set
+    pub fn step<const DERIVATIVE: bool>(&self, z: &Complex<Real>, dz: &Complex<Real>, c: &Complex<Real>) -> (Complex<Real>, Complex<Real>) {
+        let zero = Complex::new(0.0, 0.0);
+        let mut stack = [Dual {value: zero, derivative: zero}; MAX_STACK];
+        let mut top = 0;
+        for op in &self.ops {
+            match *op {
+                Op::Constant(value) => {
+                    stack[top] = Dual {value, derivative: zero};
+                    top += 1;
+                }
+                Op::Z => {
+                    stack[top] = Dual {value: *z, derivative: *dz};
+                    top += 1;
+                }
+                Op::C => {
+                    stack[top] = Dual {value: *c, derivative: Complex::new(1.0, 0.0)};
+                    top += 1;
+                }
+                Op::Neg => {
+                    let a = &mut stack[top - 1];
+                    a.value = -a.value;
+                    if DERIVATIVE {
+                        a.derivative = -a.derivative;
+                    }
+                }
+                Op::PowInt(exponent) => {
+                    let a = &mut stack[top - 1];
+                    if DERIVATIVE {
+                        // (a^n)' = n a^(n-1) a'
+                        a.derivative = powi(&a.value, exponent - 1) * a.derivative * exponent as Real;
+                    }
+                    a.value = powi(&a.value, exponent);
+                }
+                Op::Call(function) => {
+                    let a = &mut stack[top - 1];
+                    let (value, slope) = match function {
+                        Function::Sin => (Complex::new(a.value.r.sin() * a.value.i.cosh(), a.value.r.cos() * a.value.i.sinh()),
+                            Complex::new(a.value.r.cos() * a.value.i.cosh(), -a.value.r.sin() * a.value.i.sinh())),
+                        Function::Cos => (Complex::new(a.value.r.cos() * a.value.i.cosh(), -a.value.r.sin() * a.value.i.sinh()),
+                            Complex::new(-a.value.r.sin() * a.value.i.cosh(), -a.value.r.cos() * a.value.i.sinh())),
+                        Function::Exp => {
+                            let value = a.value.exp();
+                            (value, value)
+                        }
+                        Function::Log => (a.value.ln(), Complex::from_real(1.0) / a.value),
+                        Function::Sqrt => {
+                            let value = a.value.powf(0.5);
+                            (value, Complex::from_real(0.5) / value)
+                        }
+                        // Not analytic, the slopes only keep the derivative at a sensible scale
+                        Function::Conj => (a.value.conj(), Complex::new(1.0, 0.0)),
+                        Function::Abs => (Complex::new(a.value.r.abs(), a.value.i.abs()), Complex::new(1.0, 0.0)),
+                        Function::Re => (Complex::new(a.value.r, 0.0), Complex::new(1.0, 0.0)),
+                        Function::Im => (Complex::new(a.value.i, 0.0), Complex::new(1.0, 0.0)),
+                    };
+                    if DERIVATIVE {
+                        a.derivative = slope * a.derivative;
+                    }
+                    a.value = value;
+                }
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => {
+                    top -= 1;
+                    let b = stack[top];
+                    let a = &mut stack[top - 1];
+                    let value = match *op {
+                        Op::Add => a.value + b.value,
+                        Op::Sub => a.value - b.value,
+                        Op::Mul => a.value * b.value,
+                        Op::Div => a.value / b.value,
+                        _ => if a.value.r == 0.0 && a.value.i == 0.0 { zero } else { (b.value * a.value.ln()).exp() },
+                    };
+                    if DERIVATIVE {
+                        a.derivative = match *op {
+                            Op::Add => a.derivative + b.derivative,
+                            Op::Sub => a.derivative - b.derivative,
+                            Op::Mul => a.derivative * b.value + a.value * b.derivative,
+                            Op::Div => (a.derivative * b.value - a.value * b.derivative) / (b.value * b.value),
+                            // (a^b)' = a^b (b' ln a + b a'/a)
+                            _ => if a.value.r == 0.0 && a.value.i == 0.0 { zero } else {
+                                value * (b.derivative * a.value.ln() + b.value * a.derivative / a.value)
+                            },
+                        };
+                    }
+                    a.value = value;
+                }
+            }
+        }
+        (stack[0].value, stack[0].derivative)
+    }
+}
+
+impl FromStr for Formula {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Formula, String> {
+        let mut parser = Parser {text, position: 0, ops: Vec::new(), depth: 0, max_depth: 0, nesting: 0};
+        parser.expression()?;
+        parser.skip_spaces();
+        if parser.position < text.len() {
+            return Err(parser.error("unexpected character"));
+        }
+        if parser.max_depth > MAX_STACK {
+            return Err(format!("formula '{}' is too deeply nested", text));
+        }
+        Ok(Formula {source: String::from(text.trim()), ops: parser.ops})
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+// Recursive descent parser emitting the stack machine code while it reads
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    ops: Vec<Op>,
+    // Stack depth at the current point of the program
+    depth: usize,
+    max_depth: usize,
+    // Recursion depth of unary, which every nested part goes through
+    nesting: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at position {} of formula '{}'", message, self.position + 1, self.text)
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_spaces();
+        self.text[self.position..].chars().next()
+    }
+
+    fn emit(&mut self, op: Op) {
+        match op {
+            Op::Constant(_) | Op::Z | Op::C => self.depth += 1,
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => self.depth -= 1,
+            Op::PowInt(_) | Op::Neg | Op::Call(_) => {}
+        }
+        self.max_depth = self.max_depth.max(self.depth);
+        self.ops.push(op);
+    }
+
+    // expression = term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<(), String> {
+        self.term()?;
+        while let Some(operator) = self.peek().filter(|c| *c == '+' || *c == '-') {
+            self.position += 1;
+            self.term()?;
+            self.emit(if operator == '+' { Op::Add } else { Op::Sub });
+        }
+        Ok(())
+    }
+
+    // term = unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<(), String> {
+        self.unary()?;
+        while let Some(operator) = self.peek().filter(|c| *c == '*' || *c == '/') {
+            self.position += 1;
+            self.unary()?;
+            self.emit(if operator == '*' { Op::Mul } else { Op::Div });
+        }
+        Ok(())
+    }
+
+    // unary = '-' unary | power
+    fn unary(&mut self) -> Result<(), String> {
+        if self.nesting >= MAX_NESTING {
+            return Err(self.error("formula is nested too deeply"));
+        }
+        self.nesting += 1;
+        let result = if self.peek() == Some('-') {
+            self.position += 1;
+            self.unary().map(|()| self.emit(Op::Neg))
+        }
+        else {
+            self.power()
+        };
+        self.nesting -= 1;
+        result
+    }
+
+    // power = atom ('^' unary)?
+    fn power(&mut self) -> Result<(), String> {
+        self.atom()?;
+        if self.peek() == Some('^') {
+            self.position += 1;
+            let exponent_start = self.ops.len();
+            self.unary()?;
+            let exponent = &self.ops[exponent_start..];
+            let integer = match exponent {
+                [Op::Constant(value)] if value.i == 0.0 && value.r.fract() == 0.0 && value.r.abs() <= 64.0 => Some(value.r as i32),
+                [Op::Constant(value), Op::Neg] if value.i == 0.0 && value.r.fract() == 0.0 && value.r.abs() <= 64.0 => Some(-value.r as i32),
+                _ => None,
+            };
+            match integer {
+                Some(exponent) => {
+                    self.ops.truncate(exponent_start);
+                    self.depth -= 1;
+                    self.emit(Op::PowInt(exponent));
+                }
+                None => self.emit(Op::Pow),
+            }
+        }
+        Ok(())
+    }
+
+    // atom = number | 'i' | 'z' | 'c' | function '(' expression ')' | '(' expression ')'
+    fn atom(&mut self) -> Result<(), String> {
+        let c = self.peek().ok_or_else(|| self.error("unexpected end"))?;
+        let rest = &self.text[self.position..];
+        if c == '(' {
+            self.position += 1;
+            self.expression()?;
+            if self.peek() != Some(')') {
+                return Err(self.error("expected ')'"));
+            }
+            self.position += 1;
+            return Ok(());
+        }
+        if c.is_ascii_digit() || c == '.' {
+            let mut end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+            // Exponent of scientific notation
+            if rest[end..].starts_with(['e', 'E']) {
+                let exponent = &rest[end+1..];
+                let sign = if exponent.starts_with(['+', '-']) { 1 } else { 0 };
+                let digits = exponent[sign..].find(|c: char| !c.is_ascii_digit()).unwrap_or(exponent.len() - sign);
+                if digits > 0 {
+                    end += 1 + sign + digits;
+                }
+            }
+            let value: Real = rest[..end].parse().map_err(|_| self.error("invalid number"))?;
+            self.position += end;
+            // Imaginary literal like 0.5i
+            let imaginary = self.text[self.position..].starts_with('i')
+                && !self.text[self.position+1..].starts_with(|c: char| c.is_ascii_alphanumeric());
+            if imaginary {
+                self.position += 1;
+                self.emit(Op::Constant(Complex::new(0.0, value)));
+            }
+            else {
+                self.emit(Op::Constant(Complex::new(value, 0.0)));
+            }
+            return Ok(());
+        }
+        if c.is_ascii_alphabetic() {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+            let name = &rest[..end];
+            match name {
+                "z" => self.emit(Op::Z),
+                "c" => self.emit(Op::C),
+                "i" => self.emit(Op::Constant(Complex::new(0.0, 1.0))),
+                "pi" => self.emit(Op::Constant(Complex::new(std::f64::consts::PI, 0.0))),
+                _ => {
+                    let function = Function::from_name(name).ok_or_else(|| self.error(&format!("unknown name '{}'", name)))?;
+                    self.position += end;
+                    if self.peek() != Some('(') {
+                        return Err(self.error(&format!("expected '(' after {}", name)));
+                    }
+                    self.atom()?;
+                    self.emit(Op::Call(function));
+                    return Ok(());
+                }
+            }
+            self.position += end;
+            return Ok(());
+        }
+        Err(self.error("unexpected character"))
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, Float, Complex};
+use crate::render::RenderSettings;
+
+// Iteration formula family
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Fractal {
+    // z' = z^2 + c, z0 = 0, c = pixel
+    Mandelbrot,
+    // z' = z^2 + c, z0 = pixel, c = julia_c
+    Julia,
+    // z' = (|re z| + i|im z|)^2 + c
+    BurningShip,
+    // z' = conj(z)^2 + c
+    Tricorn,
+    // z' = |re z^2| + i im z^2 + c
+    Celtic,
+    // z' = z^power + c
+    Multibrot,
+    // User formula of RenderSettings, z0 = 0, c = pixel
+    Formula,
+    // Newton's method on the polynomial of RenderSettings, z0 = pixel
+    Newton,
+    // z' = z - R*(z^power - 1)/(power*z^(power-1)) + c, z0 = 1, R the relaxation of RenderSettings
+    Nova,
+    // z' = z^2 + c + p*z_prev, z0 = pixel, c = julia_c, p the phoenix_p of RenderSettings
+    Phoenix,
+    // z' = ((z^2 + c - 1)/(2z + c - 2))^2
+    MagnetI,
+    // z' = ((z^3 + 3(c-1)z + (c-1)(c-2))/(3z^2 + 3(c-2)z + (c-1)(c-2) + 1))^2
+    MagnetII,
+    // Lyapunov exponent of the logistic map forced by the sequence of RenderSettings, the pixel
+    // being (a, b)
+    Lyapunov,
+    // The formulas of the hybrid pattern of RenderSettings in turn, z0 = 0, c = pixel
+    Hybrid,
+    // Raymarched 3D power of the triplex z' = z^power + c seen by the camera of RenderSettings
+    Mandelbulb,
+    // Raymarched 3D slice of the quaternion julia set z' = z^2 + c, c the quaternion_c of
+    // RenderSettings
+    QuaternionJulia,
+}
+
+impl Fractal {
+    // Whether the pixel is the starting value instead of the constant
+    pub fn is_julia(&self) -> bool {
+        matches!(self, Fractal::Julia | Fractal::Phoenix)
+    }
+
+    // Whether orbits can also converge to a fixed point, which ends the iteration like an escape
+    pub fn converges(&self) -> bool {
+        matches!(self, Fractal::Nova | Fractal::MagnetI | Fractal::MagnetII)
+    }
+
+    // Rendered by raymarching a distance estimator instead of iterating the pixel
+    pub fn is_3d(&self) -> bool {
+        matches!(self, Fractal::Mandelbulb | Fractal::QuaternionJulia)
+    }
+
+    // Rational formulas need the division only f64 has
+    pub fn is_rational(&self) -> bool {
+        matches!(self, Fractal::Nova | Fractal::MagnetI | Fractal::MagnetII)
+    }
+
+    // z0 of the formulas whose pixel is the constant
+    pub fn start(&self) -> Complex<Real> {
+        if *self == Fractal::Nova { Complex::from_real(1.0) } else { Complex::from_real(0.0) }
+    }
+
+    // Plain z^2 + c, the only formulas the perturbation and SIMD kernels implement
+    pub fn is_quadratic(&self) -> bool {
+        matches!(self, Fractal::Mandelbrot | Fractal::Julia)
+    }
+
+    // Applies the formula once, previous is the iterate before z. The rational formulas are
+    // evaluated at f64.
+    pub fn step<T: Float>(&self, settings: &RenderSettings, z: &Complex<T>, previous: &Complex<T>, c: &Complex<T>) -> Complex<T> {
+        let two = T::from_real(2.0);
+        let next = match self {
+            Fractal::Mandelbrot | Fractal::Julia => z.squared(),
+            Fractal::BurningShip => Complex::new(z.r*z.r - z.i*z.i, two*(z.r*z.i).abs()),
+            Fractal::Tricorn => z.conj().squared(),
+            Fractal::Celtic => Complex::new((z.r*z.r - z.i*z.i).abs(), two*z.r*z.i),
+            Fractal::Multibrot => z.powf(settings.power),
+            Fractal::Phoenix => {
+                let p = &settings.phoenix_p;
+                z.squared() + *previous * Complex::new(T::from_real(p.x), T::from_real(p.y))
+            }
+            Fractal::Nova | Fractal::MagnetI | Fractal::MagnetII => {
+                let next = self.rational_step(settings, z.to_real(), c.to_real());
+                return Complex::new(T::from_real(next.r), T::from_real(next.i));
+            }
+            Fractal::Formula => unreachable!("user formulas are evaluated by Formula::step"),
+            Fractal::Newton => unreachable!("newton fractals are evaluated by Polynomial::escape_time"),
+            Fractal::Lyapunov => unreachable!("lyapunov fractals are evaluated by LyapunovSequence::escape_time"),
+            Fractal::Hybrid => unreachable!("hybrid fractals step with the formulas of their pattern"),
+            Fractal::Mandelbulb | Fractal::QuaternionJulia => unreachable!("3D fractals are raymarched"),
+        };
+        next + *c
+    }
+
+    fn rational_step(&self, settings: &RenderSettings, z: Complex<Real>, c: Complex<Real>) -> Complex<Real> {
+        let one = Complex::from_real(1.0);
+        let two = Complex::from_real(2.0);
+        match self {
+            Fractal::Nova => {
+                let power = settings.power;
+                let relaxation = Complex::new(settings.relaxation.x, settings.relaxation.y);
+                let lower = z.powf(power - 1.0);
+                z - relaxation * (lower * z - one) / (lower * power) + c
+            }
+            Fractal::MagnetI => ((z.squared() + c - one) / (z * 2.0 + c - two)).squared(),
+            _ => {
+                let (numerator, denominator) = magnet_ii_parts(z, c);
+                (numerator / denominator).squared()
+            }
+        }
+    }
+
+    // Growth of |z| per iteration once it is large, the base of the smooth iteration count
+    pub fn degree(&self, power: Real) -> Real {
+        match self {
+            Fractal::Multibrot => power,
+            _ => 2.0,
+        }
+    }
+
+    // Derivative of z0 with respect to the pixel
+    pub fn derivative_start(&self) -> Complex<Real> {
+        if self.is_julia() { Complex::new(1.0, 0.0) } else { Complex::new(0.0, 0.0) }
+    }
+
+    // Derivative of the next iterate from the current iterate z and its derivative.
+    // The folding formulas aren't analytic, their 2z*dz is only an estimate of the scale, as is
+    // the one of the phoenix, which leaves out the previous iterate.
+    pub fn next_derivative(&self, settings: &RenderSettings, z: &Complex<Real>, c: &Complex<Real>, derivative: &Complex<Real>) -> Complex<Real> {
+        let one = Complex::from_real(1.0);
+        // Derivatives of the step with respect to z and c
+        let (by_z, by_c) = match self {
+            Fractal::Multibrot => (z.powf(settings.power - 1.0) * settings.power, one),
+            Fractal::Nova => {
+                let power = settings.power;
+                let relaxation = Complex::new(settings.relaxation.x, settings.relaxation.y);
+                // d/dz (z^n - 1)/(n z^(n-1)) = (1 + (n-1)/z^n)/n
+                let inverse_power = one / z.powf(power);
+                (one - relaxation * (one + inverse_power * (power - 1.0)) * (1.0 / power), one)
+            }
+            Fractal::MagnetI => {
+                // w = n/d, z' = w^2
+                let numerator = z.squared() + *c - one;
+                let denominator = *z * 2.0 + *c - Complex::from_real(2.0);
+                let w = numerator / denominator;
+                let denominator_squared = denominator.squared();
+                let w_by_z = (*z * denominator * 2.0 - numerator * 2.0) / denominator_squared;
+                let w_by_c = (denominator - numerator) / denominator_squared;
+                (w * w_by_z * 2.0, w * w_by_c * 2.0)
+            }
+            Fractal::MagnetII => {
+                let (numerator, denominator) = magnet_ii_parts(*z, *c);
+                let w = numerator / denominator;
+                let denominator_squared = denominator.squared();
+                let numerator_by_z = z.squared() * 3.0 + (*c - one) * 3.0;
+                let denominator_by_z = *z * 6.0 + (*c - Complex::from_real(2.0)) * 3.0;
+                // Both depend on c through 3z + 2c - 3
+                let by_c = *z * 3.0 + *c * 2.0 - Complex::from_real(3.0);
+                let w_by_z = (numerator_by_z * denominator - numerator * denominator_by_z) / denominator_squared;
+                let w_by_c = by_c * (denominator - numerator) / denominator_squared;
+                (w * w_by_z * 2.0, w * w_by_c * 2.0)
+            }
+            _ => (*z * 2.0, one),
+        };
+        let next = by_z * *derivative;
+        if self.is_julia() { next } else { next + by_c }
+    }
+}
+
+impl FromStr for Fractal {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Fractal, String> {
+        match text {
+            "mandelbrot" => Ok(Fractal::Mandelbrot),
+            "julia" => Ok(Fractal::Julia),
+            "burning-ship" => Ok(Fractal::BurningShip),
+            "tricorn" => Ok(Fractal::Tricorn),
+            "celtic" => Ok(Fractal::Celtic),
+            "multibrot" => Ok(Fractal::Multibrot),
+            "formula" => Ok(Fractal::Formula),
+            "newton" => Ok(Fractal::Newton),
+            "nova" => Ok(Fractal::Nova),
+            "phoenix" => Ok(Fractal::Phoenix),
+            "magnet1" => Ok(Fractal::MagnetI),
+            "magnet2" => Ok(Fractal::MagnetII),
+            "lyapunov" => Ok(Fractal::Lyapunov),
+            "hybrid" => Ok(Fractal::Hybrid),
+            "mandelbulb" => Ok(Fractal::Mandelbulb),
+            "quaternion-julia" => Ok(Fractal::QuaternionJulia),
+            _ => Err(format!("unknown fractal '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for Fractal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Fractal::Mandelbrot => "mandelbrot",
+            Fractal::Julia => "julia",
+            Fractal::BurningShip => "burning-ship",
+            Fractal::Tricorn => "tricorn",
+            Fractal::Celtic => "celtic",
+            Fractal::Multibrot => "multibrot",
+            Fractal::Formula => "formula",
+            Fractal::Newton => "newton",
+            Fractal::Nova => "nova",
+            Fractal::Phoenix => "phoenix",
+            Fractal::MagnetI => "magnet1",
+            Fractal::MagnetII => "magnet2",
+            Fractal::Lyapunov => "lyapunov",
+            Fractal::Hybrid => "hybrid",
+            Fractal::Mandelbulb => "mandelbulb",
+            Fractal::QuaternionJulia => "quaternion-julia",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Formulas a hybrid fractal iterates in turn, one letter per iteration repeated: M for the
+// mandelbrot, B the burning ship, T the tricorn, C the celtic and P the multibrot formula, so
+// "MMMB" takes three mandelbrot steps and then a burning ship one
+#[derive(Clone, PartialEq, Debug)]
+pub struct HybridPattern {
+    steps: Vec<Fractal>,
+}
+
+impl HybridPattern {
+    // Formula of the step from iteration n to n + 1
+    pub fn fractal(&self, n: u32) -> Fractal {
+        self.steps[n as usize % self.steps.len()]
+    }
+
+    pub fn steps(&self) -> &[Fractal] {
+        &self.steps
+    }
+}
+
+impl FromStr for HybridPattern {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<HybridPattern, String> {
+        let steps = text.trim().chars().map(|step| match step.to_ascii_uppercase() {
+            'M' => Ok(Fractal::Mandelbrot),
+            'B' => Ok(Fractal::BurningShip),
+            'T' => Ok(Fractal::Tricorn),
+            'C' => Ok(Fractal::Celtic),
+            'P' => Ok(Fractal::Multibrot),
+            _ => Err(format!("invalid hybrid pattern '{}', expected a string of M, B, T, C and P", text)),
+        }).collect::<Result<Vec<Fractal>, String>>()?;
+        if steps.is_empty() {
+            return Err(String::from("the hybrid pattern is empty"));
+        }
+        Ok(HybridPattern {steps})
+    }
+}
+
+impl fmt::Display for HybridPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text: String = self.steps.iter().map(|step| match step {
+            Fractal::BurningShip => 'B',
+            Fractal::Tricorn => 'T',
+            Fractal::Celtic => 'C',
+            Fractal::Multibrot => 'P',
+            _ => 'M',
+        }).collect();
+        write!(f, "{}", text)
+    }
+}
+
+// Numerator and denominator of the fraction the magnet type II formula squares
+fn magnet_ii_parts(z: Complex<Real>, c: Complex<Real>) -> (Complex<Real>, Complex<Real>) {
+    let one = Complex::from_real(1.0);
+    let (c1, c2) = (c - one, c - Complex::from_real(2.0));
+    let numerator = z.squared() * z + c1 * z * 3.0 + c1 * c2;
+    let denominator = z.squared() * 3.0 + c2 * z * 3.0 + c1 * c2 + one;
+    (numerator, denominator)
+}
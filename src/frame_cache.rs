@@ -0,0 +1,258 @@
+use crate::math::{Real, Float, Vec2};
+use crate::color::Color;
+use crate::image::ImageBuffer;
+use crate::coloring::Coloring;
+use crate::postprocess::PostProcess;
+use crate::render::{RenderSettings, RenderProgress, render_with_progress, render_areas, render_guided};
+
+// Largest distance of a shift from whole pixels that still counts as whole
+const SHIFT_TOLERANCE: Real = 1e-3;
+// Magnification since a pixel was rendered up to which it can be interpolated, beyond it the
+// upsampling blurs and details smaller than the rendered pixels may have come into view.
+// Every pixel gets a limit between the two so the pixels rendered in one frame don't all
+// expire in the same later frame.
+const MIN_MAGNIFICATION: Real = 1.25;
+const MAX_MAGNIFICATION: Real = 2.0;
+
+// Keeps the last rendered frame so that the next one can reuse it. When the view only moved
+// by whole pixels the pixels both show are copied and just the strips that came into view are
+// rendered. When it zoomed in, pixels whose surroundings in the previous frame are smooth are
+// interpolated from it and only the others are rendered.
+pub struct FrameCache {
+    // Largest color difference between the previous pixels around a zoomed pixel that lets
+    // it be interpolated
+    pub threshold: Real,
+    // Settings of the cached frame with its seed
+    settings: Option<RenderSettings>,
+    // Colors before post-processing, effects like the vignette don't move with the view
+    image: ImageBuffer,
+    // Pixel size of the frame each pixel was last rendered in
+    resolutions: Vec<Real>,
+}
+
+impl FrameCache {
+    pub fn new() -> FrameCache {
+        FrameCache {threshold: 0.05, settings: None, image: ImageBuffer::new(0, 0), resolutions: Vec::new()}
+    }
+
+    pub fn clear(&mut self) {
+        self.settings = None;
+    }
+
+    // Settings of the cached frame when the frame of settings differs from it in no more than
+    // its view and the iteration count, and the colors of both don't depend on the whole view
+    fn previous(&self, settings: &RenderSettings) -> Option<&RenderSettings> {
+        let previous = self.settings.as_ref()?;
+        let same_seed = settings.seed.is_none() || settings.seed == previous.seed;
+        // The interrupt and time limit only matter to unfinished frames, which aren't kept
+        let same = settings.fractal == previous.fractal && settings.julia_c == previous.julia_c && settings.power == previous.power
+            && settings.relaxation == previous.relaxation && settings.phoenix_p == previous.phoenix_p
+            && settings.formula.as_ref().map(|formula| formula.to_string()) == previous.formula.as_ref().map(|formula| formula.to_string())
+            && settings.polynomial.as_ref().map(|polynomial| polynomial.to_string()) == previous.polynomial.as_ref().map(|polynomial| polynomial.to_string())
+            && settings.root_colors == previous.root_colors && settings.lyapunov_sequence == previous.lyapunov_sequence
+            && settings.hybrid == previous.hybrid && settings.camera == previous.camera
+            && settings.quaternion_c == previous.quaternion_c && settings.shadow_sharpness == previous.shadow_sharpness
+            && settings.width == previous.width && settings.height == previous.height
+            && settings.rotation == previous.rotation && settings.skew == previous.skew && settings.auto_iterations == previous.auto_iterations
+            && settings.escape_radius == previous.escape_radius && settings.bailout == previous.bailout
+            && settings.sample_count == previous.sample_count && settings.min_sample_count == previous.min_sample_count
+            && settings.sample_threshold == previous.sample_threshold && settings.subdivision == previous.subdivision
+            && settings.sampler == previous.sampler && settings.precision == previous.precision && settings.palette == previous.palette
+            && settings.coloring == previous.coloring && settings.interior == previous.interior && settings.interior_color == previous.interior_color
+            && settings.transparency == previous.transparency && settings.gamma == previous.gamma && settings.orbit_trap == previous.orbit_trap
+            && settings.trap_image == previous.trap_image && settings.stalks == previous.stalks && settings.orbit_average == previous.orbit_average && settings.lighting == previous.lighting
+            && settings.track_derivative == previous.track_derivative && same_seed;
+        // Histogram colors depend on the whole view, supersampled pixels on their neighbours
+        if !same || settings.coloring == Coloring::Histogram || settings.supersample > 1 || previous.supersample > 1 {
+            return None;
+        }
+        Some(previous)
+    }
+
+    // Pixels the view of settings moved by from the cached frame, so pixel (x, y) shows what
+    // pixel (x + dx, y + dy) showed. None when the frames differ in more than the center.
+    fn shift(&self, settings: &RenderSettings) -> Option<Vec2<Real>> {
+        let previous = self.previous(settings)?;
+        if settings.view_size != previous.view_size || settings.max_iterations != previous.max_iterations {
+            return None;
+        }
+        let center = previous_position(previous, center_offset(previous, settings));
+        Some(Vec2::<Real>{x: center.x - settings.width as Real / 2.0, y: center.y - settings.height as Real / 2.0})
+    }
+
+    // Colors of the pixels of a view zoomed in from the cached frame that can be interpolated
+    // from it and their resolutions, None when the view isn't a zoom of the cached frame
+    fn zoom_guide(&self, settings: &RenderSettings) -> Option<(Vec<Option<Color>>, Vec<Real>)> {
+        let previous = self.previous(settings)?;
+        let (scale_x, scale_y) = (settings.view_size.x / previous.view_size.x, settings.view_size.y / previous.view_size.y);
+        // Zooming out would alias, the previous pixels are smaller than the new ones
+        if scale_x > 1.0 || (scale_x - scale_y).abs() > 1e-9 * scale_x {
+            return None;
+        }
+        let (width, height) = (settings.width, settings.height);
+        let mut guide = vec![None; width * height];
+        let pixel_size = settings.pixel_size();
+        let mut resolutions = vec![pixel_size; width * height];
+        let center = center_offset(previous, settings);
+        for y in 0..height {
+            for x in 0..width {
+                let offset = settings.pixel_to_offset(x as Real + 0.5, y as Real + 0.5);
+                let position = previous_position(previous, Vec2::<Real>{x: center.x + offset.x, y: center.y + offset.y});
+                // Pixel centers at +0.5, the four around the position
+                let (left, top) = ((position.x - 0.5).floor(), (position.y - 0.5).floor());
+                if left < 0.0 || top < 0.0 || left + 1.0 >= width as Real || top + 1.0 >= height as Real {
+                    continue;
+                }
+                let (tx, ty) = (position.x - 0.5 - left, position.y - 0.5 - top);
+                let (left, top) = (left as usize, top as usize);
+                let corners = [(left, top), (left + 1, top), (left, top + 1), (left + 1, top + 1)];
+                let resolution = corners.iter().map(|&(x, y)| self.resolutions[y * width + x]).fold(0.0, Real::max);
+                let colors = corners.map(|(x, y)| self.image.get(x, y));
+                let smooth = colors.iter().all(|color| color_distance(color, &colors[0]) <= self.threshold);
+                if smooth && resolution < pixel_size * magnification_limit(x, y) {
+                    let weights = [(1.0 - tx) * (1.0 - ty), tx * (1.0 - ty), (1.0 - tx) * ty, tx * ty];
+                    let mut color = Color {r: 0.0, g: 0.0, b: 0.0, a: 0.0};
+                    for (corner, weight) in colors.iter().zip(weights) {
+                        color.add(Color {r: corner.r * weight, g: corner.g * weight, b: corner.b * weight, a: corner.a * weight});
+                    }
+                    guide[y * width + x] = Some(color);
+                    resolutions[y * width + x] = resolution;
+                }
+            }
+        }
+        Some((guide, resolutions))
+    }
+
+    // Whole pixel shift from the cached frame when the frame can reuse some of its pixels
+    fn whole_shift(&self, settings: &RenderSettings) -> Option<(isize, isize)> {
+        let shift = self.shift(settings)?;
+        let (dx, dy) = (shift.x.round(), shift.y.round());
+        let whole = (shift.x - dx).abs() < SHIFT_TOLERANCE && (shift.y - dy).abs() < SHIFT_TOLERANCE;
+        let overlaps = dx.abs() < settings.width as Real && dy.abs() < settings.height as Real;
+        if whole && overlaps { Some((dx as isize, dy as isize)) } else { None }
+    }
+
+    // Whether render copies pixels of the cached frame
+    pub fn reuses(&self, settings: &RenderSettings) -> bool {
+        self.whole_shift(settings).is_some()
+    }
+
+    // Moves the center of settings by less than half a pixel so its view is a whole pixel
+    // shift of the cached frame, when it otherwise could reuse it
+    pub fn snap(&self, settings: &mut RenderSettings) {
+        let shift = match self.shift(settings) {
+            Some(shift) if shift.x.abs() < settings.width as Real && shift.y.abs() < settings.height as Real => shift,
+            _ => return,
+        };
+        let (half_width, half_height) = (settings.width as Real / 2.0, settings.height as Real / 2.0);
+        let offset = settings.pixel_to_offset(half_width + shift.x.round() - shift.x, half_height + shift.y.round() - shift.y);
+        settings.pan(offset);
+    }
+
+    // Renders the frame of the settings and keeps it for the next one
+    pub fn render<F: FnMut(&RenderProgress)>(&mut self, settings: &RenderSettings, mut progress: F) -> ImageBuffer {
+        let mut raw_settings = RenderSettings {post: PostProcess::new(), ..settings.clone()};
+        let previous_seed = self.settings.as_ref().and_then(|previous| previous.seed);
+        let (image, resolutions) = if let Some((dx, dy)) = self.whole_shift(settings) {
+            raw_settings.seed = previous_seed;
+            self.shifted(&raw_settings, dx, dy, progress)
+        }
+        else if let Some((guide, resolutions)) = self.zoom_guide(settings) {
+            raw_settings.seed = previous_seed;
+            (render_guided(&raw_settings, &guide, progress), resolutions)
+        }
+        else {
+            raw_settings.seed = Some(settings.seed.unwrap_or_else(rand::random));
+            (render_with_progress(&raw_settings, &mut progress), vec![settings.pixel_size(); settings.width * settings.height])
+        };
+        // Stopped frames miss tiles
+        if settings.is_interrupted() {
+            self.clear();
+        }
+        else {
+            self.image = ImageBuffer {width: image.width, height: image.height, pixels: image.pixels.clone()};
+            self.resolutions = resolutions;
+            self.settings = Some(raw_settings);
+        }
+        let mut image = image;
+        settings.post.apply(&mut image, settings.gamma, 0, settings.height);
+        image
+    }
+
+    // The cached frame moved by dx, dy pixels with the uncovered strips rendered, and the
+    // resolutions of its pixels
+    fn shifted<F: FnMut(&RenderProgress)>(&self, settings: &RenderSettings, dx: isize, dy: isize, progress: F) -> (ImageBuffer, Vec<Real>) {
+        let (width, height) = (settings.width, settings.height);
+        let mut image = ImageBuffer::new(width, height);
+        let mut resolutions = vec![settings.pixel_size(); width * height];
+        for y in 0..height {
+            let source_y = y as isize + dy;
+            if source_y < 0 || source_y >= height as isize {
+                continue;
+            }
+            for x in 0..width {
+                let source_x = x as isize + dx;
+                if source_x >= 0 && source_x < width as isize {
+                    image.set(x, y, self.image.get(source_x as usize, source_y as usize));
+                    resolutions[y * width + x] = self.resolutions[source_y as usize * width + source_x as usize];
+                }
+            }
+        }
+        // A full height strip of columns and the rest of the uncovered rows beside it
+        let (columns, rows) = (dx.unsigned_abs(), dy.unsigned_abs());
+        let column_start = if dx > 0 { width - columns } else { 0 };
+        let row_start = if dy > 0 { height - rows } else { 0 };
+        let areas: Vec<(usize, usize, usize, usize)> = [
+            (column_start, 0, columns, height),
+            (if dx > 0 { 0 } else { columns }, row_start, width - columns, rows),
+        ].iter().cloned().filter(|&(_, _, width, height)| width > 0 && height > 0).collect();
+        let strips = render_areas(settings, &areas, progress);
+        for (&(left, top, _, _), strip) in areas.iter().zip(&strips) {
+            for y in 0..strip.height {
+                for x in 0..strip.width {
+                    image.set(left + x, top + y, strip.get(x, y));
+                }
+            }
+        }
+        (image, resolutions)
+    }
+}
+
+// Offset of the center of the frame of settings from the center of the previous frame
+fn center_offset(previous: &RenderSettings, settings: &RenderSettings) -> Vec2<Real> {
+    Vec2::<Real>{x: (settings.center.x - previous.center.x).to_real(), y: (settings.center.y - previous.center.y).to_real()}
+}
+
+// Position in pixels on the previous frame of the offset from its center, the inverse of
+// RenderSettings::pixel_to_offset of the previous frame
+fn previous_position(previous: &RenderSettings, offset: Vec2<Real>) -> Vec2<Real> {
+    let (x, y) = match &previous.skew {
+        Some(skew) => {
+            let unskewed = skew.inverse().apply(offset);
+            (unskewed.x, unskewed.y)
+        }
+        None => (offset.x, offset.y),
+    };
+    let (sin, cos) = (-previous.rotation).to_radians().sin_cos();
+    let unrotated = Vec2::<Real>{x: x * cos - y * sin, y: x * sin + y * cos};
+    Vec2::<Real>{
+        x: (unrotated.x / previous.view_size.x + 0.5) * previous.width as Real,
+        y: (0.5 - unrotated.y / previous.view_size.y) * previous.height as Real,
+    }
+}
+
+// Magnification at which the pixel is rendered again, spread over [MIN_MAGNIFICATION:MAX_MAGNIFICATION)
+fn magnification_limit(x: usize, y: usize) -> Real {
+    let hash = (x as u32).wrapping_mul(0x9e3779b9) ^ (y as u32).wrapping_mul(0x85ebca6b);
+    MIN_MAGNIFICATION + (MAX_MAGNIFICATION - MIN_MAGNIFICATION) * (hash >> 8) as Real / (1u32 << 24) as Real
+}
+
+fn color_distance(a: &Color, b: &Color) -> Real {
+    (a.r - b.r).abs().max((a.g - b.g).abs()).max((a.b - b.b).abs()).max((a.a - b.a).abs())
+}
+
+impl Default for FrameCache {
+    fn default() -> FrameCache {
+        FrameCache::new()
+    }
+}
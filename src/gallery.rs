@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use rand::Rng;
+use crate::math::{Real, Float, Complex, Vec2};
+use crate::big_float::BigFloat;
+use crate::color::Color;
+use crate::coloring::{Coloring, distance_estimate};
+use crate::image::ImageBuffer;
+use crate::font::{GLYPH_WIDTH, GLYPH_HEIGHT, draw_text};
+use crate::render::{RenderSettings, Bailout, escape_time, render_with_cache};
+
+// Longer side of the grid the interest score is computed on
+const SCORE_GRID_SIZE: usize = 64;
+// Each step of the walk toward the boundary narrows the view by this factor
+const WALK_FACTOR: Real = 8.0;
+// Random points tried per step before the walk gives up
+const WALK_ATTEMPTS: usize = 200;
+// Longer side of the grid searched for detail by detail_offset and the regions per side
+const DETAIL_GRID_SIZE: usize = 128;
+const DETAIL_REGIONS: usize = 8;
+
+// Moves the view of settings to a random spot of the boundary inside its current view, with
+// the given view width. Walks toward the boundary in steps, every step picks a point whose
+// distance estimate is below a quarter of the next view width. Returns false when no point
+// close enough was found, settings are unchanged then.
+pub fn random_boundary_view<R: Rng>(settings: &mut RenderSettings, view_width: Real, rng: &mut R) -> bool {
+    let aspect_ratio = settings.width as Real / settings.height as Real;
+    let mut probe = settings.clone();
+    probe.view_size = Vec2::<Real>{x: view_width, y: view_width / aspect_ratio};
+    probe.update_auto_iterations();
+    // The distance estimate needs the derivative and a large escape radius
+    probe.coloring = Coloring::Distance;
+    probe.escape_radius = None;
+    probe.bailout = Bailout::Euclidean;
+    probe.lighting = None;
+    probe.track_derivative = true;
+
+    let distance = |x: Real, y: Real| {
+        let result = escape_time(&probe, Complex::new(x, y));
+        if result.iterations < probe.max_iterations { distance_estimate(&result) } else { Real::INFINITY }
+    };
+    let mut center = Vec2::<Real>{x: settings.center.x.to_real(), y: settings.center.y.to_real()};
+    let mut size = settings.view_size;
+    while size.x > view_width {
+        let next_width = (size.x / WALK_FACTOR).max(view_width);
+        let found = (0..WALK_ATTEMPTS).map(|_| Vec2::<Real>{
+            x: center.x + rng.gen_range(-0.5, 0.5) * size.x,
+            y: center.y + rng.gen_range(-0.5, 0.5) * size.y,
+        }).find(|point| distance(point.x, point.y) < next_width / 4.0);
+        match found {
+            Some(point) => center = point,
+            None => return false,
+        }
+        size = Vec2::<Real>{x: next_width, y: next_width / aspect_ratio};
+    }
+    settings.center = Vec2::<BigFloat>{x: BigFloat::from_real(center.x), y: BigFloat::from_real(center.y)};
+    settings.view_size = size;
+    settings.update_auto_iterations();
+    true
+}
+
+// Shannon entropy in bits of a list of iteration counts
+fn entropy<I: Iterator<Item = u32>>(iterations: I) -> Real {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for count in iterations {
+        *counts.entry(count).or_insert(0) += 1;
+    }
+    let total: usize = counts.values().sum();
+    counts.values().map(|&count| {
+        let probability = count as Real / total as Real;
+        -probability * probability.log2()
+    }).sum()
+}
+
+// Iteration counts of a one sample grid over the view with the given longer side, and the
+// settings of the grid
+fn iteration_grid(settings: &RenderSettings, size: usize) -> (RenderSettings, Vec<u32>) {
+    let scale = settings.width.max(settings.height).div_ceil(size).max(1);
+    let grid = settings.preview(scale);
+    let (_, cache) = render_with_cache(&grid, |_| {});
+    let iterations = cache.samples.iter().map(|samples| samples.first().map_or(0, |result| result.iterations)).collect();
+    (grid, iterations)
+}
+
+// How much detail the view shows: Shannon entropy in bits of the iteration counts of a coarse
+// one sample grid. Views that are mostly inside the set or a single smooth band score low.
+pub fn interest_score(settings: &RenderSettings) -> Real {
+    entropy(iteration_grid(settings, SCORE_GRID_SIZE).1.into_iter())
+}
+
+// Offset from the view center of the part of the view with the most detail. The view is split
+// into regions scored by the entropy of their iteration counts, regions farther from the
+// center count up to half less so paths following the detail don't jump around.
+// Views without any detail give a zero offset.
+pub fn detail_offset(settings: &RenderSettings) -> Vec2<Real> {
+    let (grid, iterations) = iteration_grid(settings, DETAIL_GRID_SIZE);
+    let (width, height) = (grid.width, grid.height);
+    let mut best = (0.0, Vec2::<Real>::new());
+    for region_y in 0..DETAIL_REGIONS {
+        for region_x in 0..DETAIL_REGIONS {
+            let (x0, x1) = (region_x * width / DETAIL_REGIONS, (region_x + 1) * width / DETAIL_REGIONS);
+            let (y0, y1) = (region_y * height / DETAIL_REGIONS, (region_y + 1) * height / DETAIL_REGIONS);
+            let score = entropy((y0..y1).flat_map(|y| (x0..x1).map(move |x| (x, y))).map(|(x, y)| iterations[y * width + x]));
+            let center = Vec2::<Real>{x: (x0 + x1) as Real / 2.0, y: (y0 + y1) as Real / 2.0};
+            // 0 at the view center, 1 in the corners
+            let distance = ((center.x / width as Real - 0.5).powi(2) + (center.y / height as Real - 0.5).powi(2)).sqrt() / 0.5f64.sqrt();
+            let score = score * (1.0 - 0.5 * distance);
+            if score > best.0 {
+                best = (score, grid.pixel_to_offset(center.x, center.y));
+            }
+        }
+    }
+    best.1
+}
+
+// Arranges images of the same size in a grid, row by row, separated by gap pixels of the
+// background color
+pub fn contact_sheet(images: &[ImageBuffer], columns: usize, gap: usize, background: Color) -> ImageBuffer {
+    labeled_contact_sheet(images, &[], columns, gap, background, background)
+}
+
+// Contact sheet with lines of text in the foreground color below every image, labels[i]
+// belongs to images[i]. Lines wider than the images are cut off.
+pub fn labeled_contact_sheet(images: &[ImageBuffer], labels: &[Vec<String>], columns: usize, gap: usize, background: Color, foreground: Color) -> ImageBuffer {
+    let (width, height) = images.first().map_or((0, 0), |image| (image.width, image.height));
+    let columns = columns.clamp(1, images.len().max(1));
+    let rows = images.len().div_ceil(columns);
+    // Larger text on larger images
+    let scale = (width / 256).max(1);
+    let line_height = (GLYPH_HEIGHT + 3) * scale;
+    let label_height = labels.iter().map(|lines| lines.len()).max().unwrap_or(0) * line_height;
+    let cell_height = height + label_height;
+    let mut sheet = ImageBuffer::new(columns * (width + gap) + gap, rows * (cell_height + gap) + gap);
+    for y in 0..sheet.height {
+        for x in 0..sheet.width {
+            sheet.set(x, y, background);
+        }
+    }
+    for (index, image) in images.iter().enumerate() {
+        let left = gap + (index % columns) * (width + gap);
+        let top = gap + (index / columns) * (cell_height + gap);
+        for y in 0..height.min(image.height) {
+            for x in 0..width.min(image.width) {
+                sheet.set(left + x, top + y, image.get(x, y));
+            }
+        }
+        for (line, text) in labels.get(index).into_iter().flatten().enumerate() {
+            let fitting: String = text.chars().take((width / scale + 1) / (GLYPH_WIDTH + 1)).collect();
+            draw_text(&mut sheet, left, top + height + 2 * scale + line * line_height, &fitting, scale, foreground);
+        }
+    }
+    sheet
+}
@@ -0,0 +1,77 @@
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use crate::image::ImageBuffer;
+use crate::render::{RenderSettings, RenderProgress, RenderedTile, render_cancellable};
+
+// Render running on a background thread for hosts that keep a user interface responsive:
+// the host polls the progress, draws the finished tiles as they arrive and may cancel.
+// Dropping the handle cancels the render and waits for the workers to stop.
+pub struct RenderHandle {
+    cancel: Arc<AtomicBool>,
+    progress: Arc<Mutex<RenderProgress>>,
+    tiles: mpsc::Receiver<RenderedTile>,
+    thread: Option<thread::JoinHandle<ImageBuffer>>,
+}
+
+impl RenderHandle {
+    // Starts rendering the settings on the worker pool of settings.thread_count threads
+    pub fn start(settings: &RenderSettings) -> RenderHandle {
+        let settings = settings.clone();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let progress = Arc::new(Mutex::new(RenderProgress {
+            finished_pixels: 0,
+            total_pixels: settings.width * settings.height,
+            width: settings.width,
+            height: settings.height,
+            threads: Vec::new(),
+            samples: 0,
+            reduced_pixels: 0,
+            tiles: Vec::new(),
+        }));
+        let (sender, tiles) = mpsc::channel();
+        let thread = {
+            let (cancel, progress) = (Arc::clone(&cancel), Arc::clone(&progress));
+            thread::spawn(move || render_cancellable(&settings, &cancel, &sender, |state| *progress.lock().unwrap() = state.clone()))
+        };
+        RenderHandle {cancel, progress, tiles, thread: Some(thread)}
+    }
+
+    // Latest progress, updated about ten times a second
+    pub fn progress(&self) -> RenderProgress {
+        self.progress.lock().unwrap().clone()
+    }
+
+    // Stops the render after the tiles being rendered, wait returns None afterwards
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(|thread| thread.is_finished())
+    }
+
+    // Finished tiles in the order they were rendered, try_iter polls without blocking
+    pub fn tiles(&self) -> &mpsc::Receiver<RenderedTile> {
+        &self.tiles
+    }
+
+    // Waits for the render, the image is None when it was cancelled
+    pub fn wait(mut self) -> Option<ImageBuffer> {
+        let image = self.thread.take()?.join().expect("render thread panicked");
+        if self.is_cancelled() { None } else { Some(image) }
+    }
+}
+
+impl Drop for RenderHandle {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.cancel();
+            let _ = thread.join();
+        }
+    }
+}
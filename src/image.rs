@@ -0,0 +1,500 @@
+use std::path::Path;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process::{Command, Stdio};
+use deflate::write::ZlibEncoder;
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, clamp};
+use crate::color::Color;
+use crate::cache::IterationCache;
+use crate::render::RenderSettings;
+use crate::exr::save_exr;
+use crate::jpeg::save_jpeg;
+
+// File format of saved images
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ImageFormat {
+    Png,
+    // Float channels, with the iteration data of the samples when available
+    Exr,
+    Jpeg,
+    // Encoded by ffmpeg
+    Webp,
+    Avif,
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<ImageFormat, String> {
+        match text {
+            "png" => Ok(ImageFormat::Png),
+            "exr" => Ok(ImageFormat::Exr),
+            "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+            "webp" => Ok(ImageFormat::Webp),
+            "avif" => Ok(ImageFormat::Avif),
+            _ => Err(format!("unknown image format '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Exr => "exr",
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Avif => "avif",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ImageFormat {
+    // Whether the format stores per pixel iteration data next to the colors
+    pub fn has_data_channels(&self) -> bool {
+        *self == ImageFormat::Exr
+    }
+}
+
+// Channels written to the image file
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ColorType {
+    // Luminance of the color
+    Gray,
+    Rgb,
+    Rgba,
+}
+
+impl FromStr for ColorType {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<ColorType, String> {
+        match text {
+            "gray" | "grey" => Ok(ColorType::Gray),
+            "rgb" => Ok(ColorType::Rgb),
+            "rgba" => Ok(ColorType::Rgba),
+            _ => Err(format!("unknown color type '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for ColorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ColorType::Gray => "gray",
+            ColorType::Rgb => "rgb",
+            ColorType::Rgba => "rgba",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ColorType {
+    fn channels(&self) -> usize {
+        match self {
+            ColorType::Gray => 1,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ImageSettings {
+    // Picked by the output extension when None
+    pub format: Option<ImageFormat>,
+    pub color_type: ColorType,
+    // 8 or 16 bits per channel
+    pub bit_depth: u8,
+    // Quality [1:100] of the lossy formats
+    pub quality: u32,
+    // Name or path of the ffmpeg executable encoding WebP and AVIF
+    pub ffmpeg: String,
+    // Keyword and text pairs stored in PNG text chunks
+    pub text: Vec<(String, String)>,
+}
+
+impl ImageSettings {
+    pub fn new() -> ImageSettings {
+        ImageSettings {format: None, color_type: ColorType::Rgba, bit_depth: 8, quality: 90, ffmpeg: String::from("ffmpeg"), text: Vec::new()}
+    }
+
+    pub fn format_for(&self, path: &str) -> ImageFormat {
+        let extension = Path::new(path).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        match self.format {
+            Some(format) => format,
+            None => extension.parse().unwrap_or(ImageFormat::Png),
+        }
+    }
+}
+
+impl Default for ImageSettings {
+    fn default() -> ImageSettings {
+        ImageSettings::new()
+    }
+}
+
+// Row major color buffer
+pub struct ImageBuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl ImageBuffer {
+    pub fn new(width: usize, height: usize) -> ImageBuffer {
+        ImageBuffer {width, height, pixels: vec![Color::new(); width * height]}
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    // Interleaved 8 bit RGBA bytes, row major
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut rgba_data = vec![0u8; 4 * self.width * self.height];
+        for (i, color) in self.pixels.iter().enumerate() {
+            rgba_data[4 * i] = (color.r * 255.0) as u8;
+            rgba_data[4 * i + 1] = (color.g * 255.0) as u8;
+            rgba_data[4 * i + 2] = (color.b * 255.0) as u8;
+            rgba_data[4 * i + 3] = (color.a * 255.0) as u8;
+        }
+        rgba_data
+    }
+
+    // Interleaved channels of the color type, 16 bit samples are big endian
+    pub fn to_bytes(&self, color_type: ColorType, bit_depth: u8) -> Vec<u8> {
+        let max = if bit_depth == 16 { 65535.0 } else { 255.0 };
+        let mut data = Vec::with_capacity(color_type.channels() * (bit_depth as usize / 8) * self.pixels.len());
+        let mut push = |value: Real| {
+            let sample = (clamp(value, 0.0, 1.0) * max) as u16;
+            if bit_depth == 16 {
+                data.extend_from_slice(&sample.to_be_bytes());
+            }
+            else {
+                data.push(sample as u8);
+            }
+        };
+        for color in &self.pixels {
+            match color_type {
+                ColorType::Gray => push(0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b),
+                ColorType::Rgb | ColorType::Rgba => {
+                    push(color.r);
+                    push(color.g);
+                    push(color.b);
+                    if color_type == ColorType::Rgba {
+                        push(color.a);
+                    }
+                }
+            }
+        }
+        data
+    }
+}
+
+// Creates the missing directories leading to path and checks that path can be a file
+pub fn prepare_output(path: &str) -> Result<(), String> {
+    let path = Path::new(path);
+    if path.is_dir() {
+        return Err(format!("cannot write {}: it is a directory", path.display()));
+    }
+    match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => {
+            std::fs::create_dir_all(directory).map_err(|e| format!("cannot create the directory {}: {}", directory.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+// Creates the file at path along with its missing directories
+pub(crate) fn create_file(path: &str) -> Result<File, String> {
+    prepare_output(path)?;
+    File::create(path).map_err(|e| format!("cannot write {}: {}", path, e))
+}
+
+pub fn save_image(image: &ImageBuffer, path: &str) -> Result<(), String> {
+    save_image_with(image, path, &ImageSettings::new())
+}
+
+pub fn save_image_with(image: &ImageBuffer, path: &str, settings: &ImageSettings) -> Result<(), String> {
+    save_render(image, path, settings, None)
+}
+
+// Saves a rendered image, formats with data channels fill them from the samples in data
+pub fn save_render(image: &ImageBuffer, path: &str, settings: &ImageSettings, data: Option<(&RenderSettings, &IterationCache)>) -> Result<(), String> {
+    match settings.format_for(path) {
+        ImageFormat::Png => {}
+        ImageFormat::Exr => return save_exr(image, path, data),
+        ImageFormat::Jpeg => return save_jpeg(image, path, settings.quality, settings.color_type == ColorType::Gray),
+        ImageFormat::Webp => return save_with_ffmpeg(image, path, &settings.ffmpeg, &["-c:v", "libwebp", "-quality", &settings.quality.to_string()]),
+        ImageFormat::Avif => {
+            // Constant rate factor 0 (lossless) to 63
+            let crf = (63 * (100 - settings.quality.clamp(1, 100)) / 99).to_string();
+            return save_with_ffmpeg(image, path, &settings.ffmpeg, &["-c:v", "libaom-av1", "-still-picture", "1", "-crf", &crf]);
+        }
+    }
+    let depth = match settings.bit_depth {
+        8 => png::BitDepth::Eight,
+        16 => png::BitDepth::Sixteen,
+        depth => return Err(format!("unsupported bit depth {}, expected 8 or 16", depth)),
+    };
+    let color = match settings.color_type {
+        ColorType::Gray => png::ColorType::Grayscale,
+        ColorType::Rgb => png::ColorType::RGB,
+        ColorType::Rgba => png::ColorType::RGBA,
+    };
+    let error = |e: &dyn fmt::Display| format!("cannot write {}: {}", path, e);
+    let file = create_file(path)?;
+    let w = &mut BufWriter::new(file);
+
+    let mut encoder = png::Encoder::new(w, image.width as u32, image.height as u32);
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    let mut writer = encoder.write_header().map_err(|e| error(&e))?;
+    for (keyword, text) in &settings.text {
+        let (name, data) = text_chunk(keyword, text);
+        writer.write_chunk(name, &data).map_err(|e| error(&e))?;
+    }
+
+    writer.write_image_data(&image.to_bytes(settings.color_type, settings.bit_depth)).map_err(|e| error(&e))
+}
+
+// tEXt chunk of the keyword and text, iTXt when the text isn't ASCII
+fn text_chunk(keyword: &str, text: &str) -> ([u8; 4], Vec<u8>) {
+    let mut data = Vec::from(keyword.as_bytes());
+    data.push(0);
+    if text.is_ascii() {
+        data.extend_from_slice(text.as_bytes());
+        return (*b"tEXt", data);
+    }
+    // Uncompressed, without language tag and translated keyword
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+    (*b"iTXt", data)
+}
+
+// Decodes a PNG file of any color type, 16 bit samples are reduced to 8 bits
+pub fn load_png(path: &str) -> Result<ImageBuffer, String> {
+    let error = |e: &dyn fmt::Display| format!("cannot read {}: {}", path, e);
+    let file = File::open(path).map_err(|e| error(&e))?;
+    let mut decoder = png::Decoder::new(file);
+    // Palettes and low bit depths become 8 bit gray or RGB, transparency chunks an alpha channel
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info().map_err(|e| error(&e))?;
+    let mut data = vec![0u8; info.buffer_size()];
+    reader.next_frame(&mut data).map_err(|e| error(&e))?;
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::RGB => 3,
+        png::ColorType::RGBA => 4,
+        png::ColorType::Indexed => return Err(format!("cannot read {}: unexpanded palette", path)),
+    };
+    let (width, height) = (info.width as usize, info.height as usize);
+    let mut image = ImageBuffer::new(width, height);
+    for y in 0..height {
+        let row = &data[y * info.line_size..];
+        for x in 0..width {
+            let sample = |channel: usize| row[x * channels + channel] as Real / 255.0;
+            let color = match channels {
+                1 => Color {r: sample(0), g: sample(0), b: sample(0), a: 1.0},
+                2 => Color {r: sample(0), g: sample(0), b: sample(0), a: sample(1)},
+                3 => Color {r: sample(0), g: sample(1), b: sample(2), a: 1.0},
+                _ => Color {r: sample(0), g: sample(1), b: sample(2), a: sample(3)},
+            };
+            image.set(x, y, color);
+        }
+    }
+    Ok(image)
+}
+
+// Keyword and text pairs of the uncompressed tEXt and iTXt chunks of a PNG file
+pub fn read_png_text(path: &str) -> Result<Vec<(String, String)>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Err(format!("{} is not a PNG file", path));
+    }
+    let mut text = Vec::new();
+    let mut position = 8;
+    while position + 8 <= bytes.len() {
+        let length = u32::from_be_bytes([bytes[position], bytes[position + 1], bytes[position + 2], bytes[position + 3]]) as usize;
+        let name = &bytes[position + 4..position + 8];
+        let data = match bytes.get(position + 8..position + 8 + length) {
+            Some(data) => data,
+            None => return Err(format!("{} is truncated", path)),
+        };
+        position += 12 + length;
+        let (keyword, rest) = match data.iter().position(|&b| b == 0) {
+            Some(end) => (String::from_utf8_lossy(&data[..end]).into_owned(), &data[end + 1..]),
+            None => continue,
+        };
+        match name {
+            // Latin-1
+            b"tEXt" => text.push((keyword, rest.iter().map(|&b| b as char).collect())),
+            // Compression flag and method, then the language tag and translated keyword
+            b"iTXt" if rest.len() >= 2 && rest[0] == 0 => {
+                let mut fields = rest[2..].splitn(3, |&b| b == 0);
+                if let (Some(_), Some(_), Some(value)) = (fields.next(), fields.next(), fields.next()) {
+                    text.push((keyword, String::from_utf8_lossy(value).into_owned()));
+                }
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+// Pipes the RGBA data of the image into ffmpeg, encoding a single frame with codec
+fn save_with_ffmpeg(image: &ImageBuffer, path: &str, ffmpeg: &str, codec: &[&str]) -> Result<(), String> {
+    prepare_output(path)?;
+    let size = format!("{}x{}", image.width, image.height);
+    let mut child = Command::new(ffmpeg)
+        .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba", "-s", &size, "-i", "-", "-frames:v", "1"])
+        .args(codec)
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot start {}: {}", ffmpeg, e))?;
+    let written = child.stdin.take().unwrap().write_all(&image.to_rgba8());
+    let status = child.wait().map_err(|e| format!("ffmpeg failed: {}", e))?;
+    written.map_err(|e| format!("cannot write the image to ffmpeg: {}", e))?;
+    if !status.success() {
+        return Err(format!("ffmpeg exited with {}", status));
+    }
+    Ok(())
+}
+
+// Bytes of image data per IDAT chunk
+const PNG_CHUNK_SIZE: usize = 1 << 20;
+
+// Chunks of a PNG file written as they are filled
+struct PngChunks {
+    file: BufWriter<File>,
+    buffer: Vec<u8>,
+}
+
+// Writes a PNG chunk: length, name, data and the CRC of name and data
+pub(crate) fn write_png_chunk<W: Write>(out: &mut W, name: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(name);
+    crc.update(data);
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(name)?;
+    out.write_all(data)?;
+    out.write_all(&crc.finalize().to_be_bytes())
+}
+
+// Appends a row with the Sub filter: the difference to the byte of the pixel on the left
+pub(crate) fn filter_row(row: &[u8], pixel_size: usize, filtered: &mut Vec<u8>) {
+    filtered.push(1);
+    filtered.extend(row.iter().enumerate().map(|(i, byte)| if i < pixel_size { *byte } else { byte.wrapping_sub(row[i - pixel_size]) }));
+}
+
+impl PngChunks {
+    fn write_chunk(&mut self, name: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+        write_png_chunk(&mut self.file, name, data)
+    }
+
+    fn flush_data(&mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            let data = std::mem::take(&mut self.buffer);
+            self.write_chunk(b"IDAT", &data)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for PngChunks {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= PNG_CHUNK_SIZE {
+            self.flush_data()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Writes a PNG file band by band, so images larger than the memory can be saved
+pub struct PngStream {
+    encoder: ZlibEncoder<PngChunks>,
+    settings: ImageSettings,
+    width: usize,
+    rows_left: usize,
+    path: String,
+}
+
+impl PngStream {
+    pub fn new(path: &str, width: usize, height: usize, settings: &ImageSettings) -> Result<PngStream, String> {
+        let error = |e: std::io::Error| format!("cannot write {}: {}", path, e);
+        if settings.format_for(path) != ImageFormat::Png {
+            return Err(String::from("only PNG images can be written band by band"));
+        }
+        let color_type = match settings.color_type {
+            ColorType::Gray => 0,
+            ColorType::Rgb => 2,
+            ColorType::Rgba => 6,
+        };
+        let file = create_file(path)?;
+        let mut chunks = PngChunks {file: BufWriter::new(file), buffer: Vec::new()};
+        chunks.file.write_all(b"\x89PNG\r\n\x1a\n").map_err(error)?;
+        let mut header = Vec::new();
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(height as u32).to_be_bytes());
+        // Depth, color type, compression, filter and interlace methods
+        header.extend_from_slice(&[settings.bit_depth, color_type, 0, 0, 0]);
+        chunks.write_chunk(b"IHDR", &header).map_err(error)?;
+        for (keyword, text) in &settings.text {
+            let (name, data) = text_chunk(keyword, text);
+            chunks.write_chunk(&name, &data).map_err(error)?;
+        }
+        Ok(PngStream {
+            encoder: ZlibEncoder::new(chunks, deflate::Compression::Default),
+            settings: settings.clone(),
+            width,
+            rows_left: height,
+            path: String::from(path),
+        })
+    }
+
+    // Appends the rows of band, which has to be as wide as the image
+    pub fn write_band(&mut self, band: &ImageBuffer) -> Result<(), String> {
+        if band.width != self.width || band.height > self.rows_left {
+            return Err(String::from("the band doesn't fit the image"));
+        }
+        let bytes = band.to_bytes(self.settings.color_type, self.settings.bit_depth);
+        let pixel_size = self.settings.color_type.channels() * self.settings.bit_depth as usize / 8;
+        let row_size = pixel_size * self.width;
+        let mut filtered = Vec::with_capacity(row_size + 1);
+        for row in bytes.chunks(row_size) {
+            filtered.clear();
+            filter_row(row, pixel_size, &mut filtered);
+            self.encoder.write_all(&filtered).map_err(|e| format!("cannot write {}: {}", self.path, e))?;
+        }
+        self.rows_left -= band.height;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        let path = self.path;
+        let error = |e: std::io::Error| format!("cannot write {}: {}", path, e);
+        if self.rows_left != 0 {
+            return Err(format!("cannot write {}: {} rows are missing", path, self.rows_left));
+        }
+        let mut chunks = self.encoder.finish().map_err(error)?;
+        chunks.flush_data().map_err(error)?;
+        chunks.write_chunk(b"IEND", &[]).map_err(error)?;
+        chunks.file.flush().map_err(error)
+    }
+}
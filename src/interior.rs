@@ -0,0 +1,124 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, Complex, clamp};
+use crate::color::Color;
+use crate::fractal::Fractal;
+use crate::render::{RenderSettings, EscapeResult};
+
+// Colors samples that never escaped, independently of the exterior coloring
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Interior {
+    // Palette entry of the iteration limit
+    Palette,
+    // The interior color of the settings
+    Solid,
+    // |z| of the last iteration
+    Magnitude,
+    // Period of the attracting cycle the orbit fell into
+    Period,
+    // Interior distance estimate on a logarithmic scale (Mandelbrot set only)
+    Distance,
+}
+
+impl FromStr for Interior {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Interior, String> {
+        match text {
+            "palette" => Ok(Interior::Palette),
+            "solid" => Ok(Interior::Solid),
+            "magnitude" => Ok(Interior::Magnitude),
+            "period" => Ok(Interior::Period),
+            "distance" => Ok(Interior::Distance),
+            _ => Err(format!("unknown interior coloring '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for Interior {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Interior::Palette => "palette",
+            Interior::Solid => "solid",
+            Interior::Magnitude => "magnitude",
+            Interior::Period => "period",
+            Interior::Distance => "distance",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Interior {
+    // Whether interior samples have to be iterated instead of skipped by the cardioid test
+    pub fn needs_orbit(&self) -> bool {
+        matches!(self, Interior::Magnitude | Interior::Period | Interior::Distance)
+    }
+
+    // Whether the iteration has to find the period of the cycle
+    pub fn needs_period(&self) -> bool {
+        matches!(self, Interior::Period | Interior::Distance)
+    }
+
+    pub fn color(&self, settings: &RenderSettings, result: &EscapeResult) -> Color {
+        let palette = &settings.palette;
+        let last = (palette.colors.len() - 1) as Real;
+        match self {
+            Interior::Palette => palette.color(result.iterations),
+            Interior::Solid => settings.interior_color,
+            Interior::Magnitude => palette.interpolate(clamp(result.z.length() / 2.0, 0.0, 1.0) * last),
+            // Orbits that didn't settle within the iteration limit have no period
+            Interior::Period if result.period > 0 => palette.color(result.period - 1),
+            // First palette entry on the boundary, the last one 256 pixels away from it
+            Interior::Distance if result.interior_distance.is_finite() => {
+                let value = clamp((1.0 + result.interior_distance / settings.pixel_size()).log2() / 8.0, 0.0, 1.0);
+                palette.interpolate(value * last)
+            }
+            Interior::Period | Interior::Distance => settings.interior_color,
+        }
+    }
+}
+
+// Smallest period of the cycle through z dividing candidate, the number of iterations between
+// two matching orbit values found by the cycle detection
+pub fn minimal_period(settings: &RenderSettings, z: Complex<Real>, c: Complex<Real>, candidate: u32, tolerance: Real) -> u32 {
+    let mut w = z;
+    for period in 1..candidate {
+        // The phoenix, the only formula using the previous iterate, skips the cycle detection
+        w = settings.fractal.step(settings, &w, &w, &c);
+        let difference = w - z;
+        if candidate.is_multiple_of(period) && difference.length_squared() < tolerance*tolerance {
+            return period;
+        }
+    }
+    candidate
+}
+
+// Interior distance estimate of c from a point z of its attracting cycle of the given period,
+// the derivatives of the period-fold iterate with respect to z and c. Mandelbrot set only.
+pub fn interior_distance(settings: &RenderSettings, z: Complex<Real>, c: Complex<Real>, period: u32) -> Real {
+    if settings.fractal != Fractal::Mandelbrot || period == 0 {
+        return Real::INFINITY;
+    }
+    let one = Complex::from_real(1.0);
+    let mut z = z;
+    let mut dz = one;
+    let mut dc = Complex::from_real(0.0);
+    let mut dzdz = Complex::from_real(0.0);
+    let mut dcdz = Complex::from_real(0.0);
+    for _ in 0..period {
+        dcdz = (z * dcdz + dz * dc) * 2.0;
+        dc = z * dc * 2.0 + one;
+        dzdz = (dz * dz + z * dzdz) * 2.0;
+        dz = z * dz * 2.0;
+        z = z.squared() + c;
+    }
+    // (1 - |dz|^2) / |dcdz + dzdz * dc / (1 - dz)|
+    if (one - dz).length_squared() == 0.0 {
+        return Real::INFINITY;
+    }
+    let bottom = (dcdz + dzdz * dc / (one - dz)).length();
+    if bottom == 0.0 {
+        return Real::INFINITY;
+    }
+    ((1.0 - dz.length_squared()) / bottom).max(0.0)
+}
@@ -0,0 +1,64 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set by the first Ctrl-C, renders stop after their tiles in progress
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod platform {
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+    use super::INTERRUPTED;
+
+    const SIGINT: c_int = 2;
+    const SIG_DFL: usize = 0;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: usize) -> usize;
+    }
+
+    // Only async-signal-safe calls: a second Ctrl-C gets the default handler and quits at once
+    extern "C" fn handle_interrupt(_: c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        unsafe { signal(SIGINT, SIG_DFL); }
+    }
+
+    pub fn install() {
+        unsafe { signal(SIGINT, handle_interrupt as extern "C" fn(c_int) as usize); }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::atomic::Ordering;
+    use super::INTERRUPTED;
+
+    const CTRL_C_EVENT: u32 = 0;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<extern "system" fn(u32) -> i32>, add: i32) -> i32;
+    }
+
+    // Handles the first Ctrl-C, later ones fall through to the default handler that quits
+    extern "system" fn handle_interrupt(event: u32) -> i32 {
+        (event == CTRL_C_EVENT && !INTERRUPTED.swap(true, Ordering::SeqCst)) as i32
+    }
+
+    pub fn install() {
+        unsafe { SetConsoleCtrlHandler(Some(handle_interrupt), 1); }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    pub fn install() {}
+}
+
+// Makes Ctrl-C set INTERRUPTED instead of killing the process
+pub fn install() {
+    platform::install();
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,300 @@
+use std::io::{BufWriter, Write};
+use crate::math::{Real, clamp};
+use crate::image::{ImageBuffer, create_file};
+
+// Baseline JPEG encoder: 4:4:4 YCbCr (or grayscale), the example quantization and
+// Huffman tables of the specification (ITU T.81 Annex K) scaled like libjpeg does
+
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+const LUMINANCE_QUANTIZATION: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+const CHROMINANCE_QUANTIZATION: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+const LUMINANCE_DC_COUNTS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const CHROMINANCE_DC_COUNTS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_SYMBOLS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const LUMINANCE_AC_COUNTS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const LUMINANCE_AC_SYMBOLS: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+const CHROMINANCE_AC_COUNTS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const CHROMINANCE_AC_SYMBOLS: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+// Code and length of every symbol of a Huffman table
+struct HuffmanTable {
+    codes: [(u16, u8); 256],
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8; 16], symbols: &[u8]) -> HuffmanTable {
+        let mut codes = [(0, 0); 256];
+        let mut code = 0u16;
+        let mut symbol = 0;
+        for (length, count) in counts.iter().enumerate() {
+            for _ in 0..*count {
+                codes[symbols[symbol] as usize] = (code, length as u8 + 1);
+                code += 1;
+                symbol += 1;
+            }
+            code <<= 1;
+        }
+        HuffmanTable {codes}
+    }
+}
+
+// Entropy coded data with 0xff bytes stuffed
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, bits: u16, length: u8) {
+        self.buffer = (self.buffer << length) | (bits as u32 & ((1 << length) - 1));
+        self.count += length as u32;
+        while self.count >= 8 {
+            let byte = (self.buffer >> (self.count - 8)) as u8;
+            self.bytes.push(byte);
+            if byte == 0xff {
+                self.bytes.push(0);
+            }
+            self.count -= 8;
+        }
+    }
+
+    // Pads the last byte with ones
+    fn flush(&mut self) {
+        if self.count > 0 {
+            self.write(0x7f, 8 - self.count as u8);
+        }
+    }
+}
+
+fn scale_quantization(table: &[u16; 64], quality: u32) -> [u16; 64] {
+    let quality = quality.clamp(1, 100);
+    let scale = if quality < 50 { 5000 / quality } else { 200 - 2 * quality };
+    let mut scaled = [0; 64];
+    for (scaled, base) in scaled.iter_mut().zip(table) {
+        *scaled = ((*base as u32 * scale + 50) / 100).clamp(1, 255) as u16;
+    }
+    scaled
+}
+
+// Bit length of the magnitude of value, and its bits as stored after the category
+fn category(value: i32) -> (u8, u16) {
+    let magnitude = value.unsigned_abs();
+    let length = (32 - magnitude.leading_zeros()) as u8;
+    let bits = if value < 0 { value - 1 } else { value };
+    (length, bits as u16)
+}
+
+struct Component {
+    quantization: [u16; 64],
+    dc: HuffmanTable,
+    ac: HuffmanTable,
+    previous_dc: i32,
+}
+
+impl Component {
+    fn encode_block(&mut self, block: &[Real; 64], cosines: &[[Real; 8]; 8], writer: &mut BitWriter) {
+        // Forward DCT
+        let mut coefficients = [0.0; 64];
+        for v in 0..8 {
+            for u in 0..8 {
+                let mut sum = 0.0;
+                for y in 0..8 {
+                    for x in 0..8 {
+                        sum += block[y * 8 + x] * cosines[x][u] * cosines[y][v];
+                    }
+                }
+                let cu = if u == 0 { std::f64::consts::FRAC_1_SQRT_2 } else { 1.0 };
+                let cv = if v == 0 { std::f64::consts::FRAC_1_SQRT_2 } else { 1.0 };
+                coefficients[v * 8 + u] = sum * cu * cv / 4.0;
+            }
+        }
+        let quantized: Vec<i32> = ZIGZAG.iter().enumerate()
+            .map(|(i, natural)| (coefficients[*natural] / self.quantization[i] as Real).round() as i32)
+            .collect();
+
+        let (length, bits) = category(quantized[0] - self.previous_dc);
+        self.previous_dc = quantized[0];
+        let (code, code_length) = self.dc.codes[length as usize];
+        writer.write(code, code_length);
+        writer.write(bits, length);
+
+        let mut zeros = 0;
+        for value in &quantized[1..] {
+            if *value == 0 {
+                zeros += 1;
+                continue;
+            }
+            while zeros >= 16 {
+                let (code, code_length) = self.ac.codes[0xf0];
+                writer.write(code, code_length);
+                zeros -= 16;
+            }
+            let (length, bits) = category(*value);
+            let (code, code_length) = self.ac.codes[(zeros << 4) | length as usize];
+            writer.write(code, code_length);
+            writer.write(bits, length);
+            zeros = 0;
+        }
+        if zeros > 0 {
+            let (code, code_length) = self.ac.codes[0x00];
+            writer.write(code, code_length);
+        }
+    }
+}
+
+fn write_segment<W: Write>(writer: &mut W, marker: u8, data: &[u8]) -> std::io::Result<()> {
+    writer.write_all(&[0xff, marker])?;
+    writer.write_all(&(data.len() as u16 + 2).to_be_bytes())?;
+    writer.write_all(data)
+}
+
+fn huffman_segment(class: u8, counts: &[u8; 16], symbols: &[u8]) -> Vec<u8> {
+    let mut data = vec![class];
+    data.extend_from_slice(counts);
+    data.extend_from_slice(symbols);
+    data
+}
+
+// quality is in [1:100]
+pub fn save_jpeg(image: &ImageBuffer, path: &str, quality: u32, gray: bool) -> Result<(), String> {
+    if image.width > 65535 || image.height > 65535 {
+        return Err(String::from("JPEG images are at most 65535 pixels wide and high"));
+    }
+    let luminance = scale_quantization(&LUMINANCE_QUANTIZATION, quality);
+    let chrominance = scale_quantization(&CHROMINANCE_QUANTIZATION, quality);
+    let mut components = vec![Component {
+        quantization: luminance,
+        dc: HuffmanTable::new(&LUMINANCE_DC_COUNTS, &DC_SYMBOLS),
+        ac: HuffmanTable::new(&LUMINANCE_AC_COUNTS, &LUMINANCE_AC_SYMBOLS),
+        previous_dc: 0,
+    }];
+    if !gray {
+        for _ in 0..2 {
+            components.push(Component {
+                quantization: chrominance,
+                dc: HuffmanTable::new(&CHROMINANCE_DC_COUNTS, &DC_SYMBOLS),
+                ac: HuffmanTable::new(&CHROMINANCE_AC_COUNTS, &CHROMINANCE_AC_SYMBOLS),
+                previous_dc: 0,
+            });
+        }
+    }
+
+    let mut cosines = [[0.0; 8]; 8];
+    for (x, row) in cosines.iter_mut().enumerate() {
+        for (u, cosine) in row.iter_mut().enumerate() {
+            *cosine = ((2 * x + 1) as Real * u as Real * std::f64::consts::PI / 16.0).cos();
+        }
+    }
+    let mut writer = BitWriter {bytes: Vec::new(), buffer: 0, count: 0};
+    let mut blocks = vec![[0.0; 64]; components.len()];
+    for block_y in (0..image.height).step_by(8) {
+        for block_x in (0..image.width).step_by(8) {
+            // Edge blocks repeat the last row and column
+            for y in 0..8 {
+                for x in 0..8 {
+                    let color = image.get((block_x + x).min(image.width - 1), (block_y + y).min(image.height - 1));
+                    let (r, g, b) = (clamp(color.r, 0.0, 1.0) * 255.0, clamp(color.g, 0.0, 1.0) * 255.0, clamp(color.b, 0.0, 1.0) * 255.0);
+                    blocks[0][y * 8 + x] = 0.299 * r + 0.587 * g + 0.114 * b - 128.0;
+                    if !gray {
+                        blocks[1][y * 8 + x] = -0.168736 * r - 0.331264 * g + 0.5 * b;
+                        blocks[2][y * 8 + x] = 0.5 * r - 0.418688 * g - 0.081312 * b;
+                    }
+                }
+            }
+            for (component, block) in components.iter_mut().zip(&blocks) {
+                component.encode_block(block, &cosines, &mut writer);
+            }
+        }
+    }
+    writer.flush();
+
+    let error = |e: std::io::Error| format!("cannot write {}: {}", path, e);
+    let file = create_file(path)?;
+    let mut file = BufWriter::new(file);
+    file.write_all(&[0xff, 0xd8]).map_err(error)?;
+    write_segment(&mut file, 0xe0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0").map_err(error)?;
+    for (id, table) in [luminance, chrominance].iter().enumerate().take(components.len().min(2)) {
+        let mut data = vec![id as u8];
+        data.extend(ZIGZAG.iter().map(|natural| table[*natural] as u8));
+        write_segment(&mut file, 0xdb, &data).map_err(error)?;
+    }
+
+    let mut frame = vec![8];
+    frame.extend_from_slice(&(image.height as u16).to_be_bytes());
+    frame.extend_from_slice(&(image.width as u16).to_be_bytes());
+    frame.push(components.len() as u8);
+    for id in 0..components.len() {
+        frame.extend_from_slice(&[id as u8 + 1, 0x11, id.min(1) as u8]);
+    }
+    write_segment(&mut file, 0xc0, &frame).map_err(error)?;
+
+    write_segment(&mut file, 0xc4, &huffman_segment(0x00, &LUMINANCE_DC_COUNTS, &DC_SYMBOLS)).map_err(error)?;
+    write_segment(&mut file, 0xc4, &huffman_segment(0x10, &LUMINANCE_AC_COUNTS, &LUMINANCE_AC_SYMBOLS)).map_err(error)?;
+    if !gray {
+        write_segment(&mut file, 0xc4, &huffman_segment(0x01, &CHROMINANCE_DC_COUNTS, &DC_SYMBOLS)).map_err(error)?;
+        write_segment(&mut file, 0xc4, &huffman_segment(0x11, &CHROMINANCE_AC_COUNTS, &CHROMINANCE_AC_SYMBOLS)).map_err(error)?;
+    }
+
+    let mut scan = vec![components.len() as u8];
+    for id in 0..components.len() {
+        scan.extend_from_slice(&[id as u8 + 1, if id == 0 { 0x00 } else { 0x11 }]);
+    }
+    scan.extend_from_slice(&[0, 63, 0]);
+    write_segment(&mut file, 0xda, &scan).map_err(error)?;
+    file.write_all(&writer.bytes).map_err(error)?;
+    file.write_all(&[0xff, 0xd9]).map_err(error)?;
+    file.flush().map_err(error)
+}
@@ -0,0 +1,97 @@
+mod math;
+mod double_double;
+mod big_float;
+mod perturbation;
+mod color;
+mod palette;
+mod sampler;
+mod coloring;
+mod fractal;
+mod formula;
+mod newton;
+mod lyapunov;
+mod raymarch;
+mod image;
+mod render;
+mod scheduler;
+mod frame_cache;
+mod handle;
+mod cache;
+mod export;
+mod mesh;
+mod stereo;
+mod exr;
+mod jpeg;
+mod checkpoint;
+mod postprocess;
+mod resample;
+mod simd;
+mod animation;
+mod video;
+mod animated;
+mod pyramid;
+mod config;
+mod gallery;
+mod font;
+mod preset;
+mod orbit_trap;
+mod lighting;
+mod skew;
+mod interior;
+mod average;
+mod stalks;
+mod location;
+mod minibrot;
+mod rays;
+mod overlay;
+mod annotation;
+mod stamp;
+#[cfg(not(target_arch = "wasm32"))]
+mod ffi;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+pub use math::{Real, Float, Complex, Vec2};
+pub use double_double::DoubleDouble;
+pub use big_float::BigFloat;
+pub use color::{Color, Gamma};
+pub use palette::{Palette, PaletteSource, Interpolation, parse_color};
+pub use sampler::Sampler;
+pub use coloring::{Coloring, Transparency, Histogram, smooth_iterations, distance_estimate};
+pub use fractal::{Fractal, HybridPattern};
+pub use orbit_trap::{OrbitTrap, TrapShape, TrapImage};
+pub use lighting::Lighting;
+pub use skew::Skew;
+pub use minibrot::Minibrot;
+pub use rays::{ExternalAngle, trace_ray, external_angle, draw_rays};
+pub use overlay::{point_to_pixel, draw_path, draw_orbit};
+pub use annotation::Annotations;
+pub use stamp::{Stamp, Corner};
+pub use interior::Interior;
+pub use average::OrbitAverage;
+pub use stalks::Stalks;
+pub use formula::Formula;
+pub use newton::Polynomial;
+pub use lyapunov::LyapunovSequence;
+pub use raymarch::{Camera, Vec3};
+pub use image::{ImageBuffer, ImageSettings, ImageFormat, ColorType, save_image, save_image_with, prepare_output, save_render, read_png_text, load_png, PngStream};
+pub use render::{RenderSettings, IterationRange, Precision, Bailout, EscapeResult, RenderProgress, render, render_with_progress, render_with_cache, render_with_checkpoint, render_in_bands, render_region, render_into, RenderedTile, escape_time_at, orbit_at, TILE_WIDTH, TILE_HEIGHT, PREVIEW_SCALE};
+pub use frame_cache::FrameCache;
+pub use handle::RenderHandle;
+pub use checkpoint::Checkpoint;
+pub use postprocess::PostProcess;
+pub use resample::Filter;
+pub use cache::{IterationCache, recolor};
+pub use export::{pixel_iterations, pixel_heights, export_data};
+pub use mesh::{MeshSettings, export_mesh, is_mesh_path};
+pub use stereo::{Stereo, StereoMode, render_stereo};
+pub use animation::{Animation, Keyframe, Easing, JuliaPath};
+pub use video::{VideoSettings, VideoEncoder, is_video_path};
+pub use animated::{AnimatedFormat, AnimatedEncoder};
+pub use pyramid::{TileLayout, TilePyramid};
+pub use location::Location;
+pub use simd::{available as simd_available, escape_time_x4};
+pub use preset::{Preset, PRESETS, find_preset};
+pub use gallery::{random_boundary_view, interest_score, detail_offset, contact_sheet, labeled_contact_sheet};
+pub use font::draw_text;
+pub use config::{JsonValue, parse_config_file, parse_job_file, parse_table_file, parse_toml, parse_json, parse_json_document, write_toml, write_toml_tables};
@@ -0,0 +1,72 @@
+use crate::math::{Real, clamp};
+use crate::color::Color;
+use crate::render::EscapeResult;
+
+// Shades escaped samples as a height field lit from one direction ("slope" coloring).
+// The surface normal comes from u = z/dz, the direction in which the distance estimate grows.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Lighting {
+    // Direction of the light in the image plane, counterclockwise from the right, in degrees
+    pub azimuth: Real,
+    // Angle of the light above the image plane in degrees
+    pub elevation: Real,
+    // Share of the light that doesn't depend on the slope
+    pub ambient: Real,
+    // Strength of the Blinn-Phong highlight
+    pub specular: Real,
+    pub shininess: Real,
+}
+
+impl Lighting {
+    pub fn new() -> Lighting {
+        Lighting {azimuth: 45.0, elevation: 45.0, ambient: 0.2, specular: 0.3, shininess: 20.0}
+    }
+
+    // Lambert diffuse and Blinn-Phong specular shading of color, the viewer looks straight down
+    pub fn shade(&self, color: Color, result: &EscapeResult) -> Color {
+        let z = result.z;
+        let dz = result.derivative;
+        // u = z / dz, normalized
+        let (r, i) = (z.r*dz.r + z.i*dz.i, z.i*dz.r - z.r*dz.i);
+        let length = (r*r + i*i).sqrt();
+        if length == 0.0 || !length.is_finite() {
+            return color;
+        }
+        // The slope is 45 degrees everywhere, the normal leans towards u
+        let scale = (2.0 as Real).sqrt().recip();
+        let normal = [r / length * scale, i / length * scale, scale];
+        let (brightness, highlight) = self.intensity(&normal, 1.0);
+        let channel = |value: Real| clamp(value * brightness + highlight, 0.0, 1.0);
+        Color {r: channel(color.r), g: channel(color.g), b: channel(color.b), a: color.a}
+    }
+
+    // Unit vector towards the light, x pointing right, y up and z towards the viewer
+    pub fn direction(&self) -> [Real; 3] {
+        let (azimuth, elevation) = (self.azimuth.to_radians(), self.elevation.to_radians());
+        [elevation.cos() * azimuth.cos(), elevation.cos() * azimuth.sin(), elevation.sin()]
+    }
+
+    // Brightness factor and added highlight of a surface with the given unit normal, in the
+    // axes of direction. The share of the light that isn't shadowed scales all but the ambient light.
+    pub fn intensity(&self, normal: &[Real; 3], unshadowed: Real) -> (Real, Real) {
+        let light = self.direction();
+        let dot = |a: &[Real; 3], b: &[Real; 3]| a[0]*b[0] + a[1]*b[1] + a[2]*b[2];
+        let diffuse = dot(normal, &light).max(0.0);
+        // Halfway vector between the light and the viewer (0, 0, 1)
+        let half = [light[0], light[1], light[2] + 1.0];
+        let half_length = dot(&half, &half).sqrt();
+        let highlight = if half_length > 0.0 {
+            (dot(normal, &half) / half_length).max(0.0).powf(self.shininess) * self.specular
+        }
+        else {
+            0.0
+        };
+        (self.ambient + (1.0 - self.ambient) * diffuse * unshadowed, highlight * unshadowed)
+    }
+}
+
+impl Default for Lighting {
+    fn default() -> Lighting {
+        Lighting::new()
+    }
+}
@@ -0,0 +1,86 @@
+use crate::math::{Real, Vec2};
+use crate::big_float::BigFloat;
+use crate::render::RenderSettings;
+
+// View of a Kalles Fraktaler location file (.kfr). The files are "Key: value" lines,
+// the zoom is relative to a view 4 units high and the rotation is in degrees.
+pub struct Location {
+    pub center: Vec2<BigFloat>,
+    // Height of the view on the imaginary axis
+    pub view_height: Real,
+    pub iterations: u32,
+    pub rotation: Real,
+}
+
+impl Location {
+    pub fn from_settings(settings: &RenderSettings) -> Location {
+        Location {
+            center: settings.center,
+            view_height: settings.view_size.y,
+            iterations: settings.max_iterations,
+            rotation: settings.rotation,
+        }
+    }
+
+    // Moves the view of settings to the location, the aspect ratio of the image is kept
+    pub fn apply(&self, settings: &mut RenderSettings) {
+        settings.center = self.center;
+        settings.view_size = Vec2::<Real>{x: self.view_height * settings.width as Real / settings.height as Real, y: self.view_height};
+        settings.max_iterations = self.iterations;
+        settings.rotation = self.rotation;
+    }
+
+    // Reads the Re, Im, Zoom, Iterations and RotateAngle keys, the others are ignored
+    pub fn parse(text: &str) -> Result<Location, String> {
+        let mut re = None;
+        let mut im = None;
+        let mut zoom = None;
+        let mut iterations = None;
+        let mut rotation = 0.0;
+        for line in text.lines() {
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+            let invalid = || format!("invalid {} '{}'", key, value);
+            match key {
+                "Re" => re = Some(value.parse::<BigFloat>()?),
+                "Im" => im = Some(value.parse::<BigFloat>()?),
+                "Zoom" => zoom = Some(value.parse::<Real>().map_err(|_| invalid())?),
+                // Some versions write the iteration count as a float
+                "Iterations" => iterations = Some(value.parse::<Real>().map_err(|_| invalid())? as u32),
+                "RotateAngle" => rotation = value.parse::<Real>().map_err(|_| invalid())?,
+                "FractalType" if value != "0" => return Err(format!("fractal type {} is not supported, only the Mandelbrot set (0) is", value)),
+                "Power" if value != "2" => return Err(format!("power {} is not supported, only 2 is", value)),
+                _ => {}
+            }
+        }
+        let zoom = zoom.ok_or("the location has no Zoom")?;
+        if zoom <= 0.0 || !zoom.is_finite() {
+            return Err(format!("invalid Zoom '{}'", zoom));
+        }
+        Ok(Location {
+            center: Vec2::<BigFloat>{x: re.ok_or("the location has no Re")?, y: im.ok_or("the location has no Im")?},
+            view_height: 4.0 / zoom,
+            iterations: iterations.ok_or("the location has no Iterations")?.max(1),
+            rotation,
+        })
+    }
+
+    // Location file text, the center has enough digits for the view height
+    pub fn to_kfr(&self) -> String {
+        let digits = (-self.view_height.log10()).max(0.0).ceil() as usize + 10;
+        format!("Re: {}\r\nIm: {}\r\nZoom: {:e}\r\nIterations: {}\r\nRotateAngle: {}\r\nFractalType: 0\r\nPower: 2\r\n",
+            self.center.x.to_string_digits(digits), self.center.y.to_string_digits(digits), 4.0 / self.view_height, self.iterations, self.rotation)
+    }
+
+    pub fn load(path: &str) -> Result<Location, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        Location::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        crate::image::prepare_output(path)?;
+        std::fs::write(path, self.to_kfr()).map_err(|e| format!("cannot write {}: {}", path, e))
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, Complex, clamp};
+use crate::color::Color;
+use crate::render::{RenderSettings, EscapeResult};
+
+// Iterations of the logistic map that settle the orbit before the exponent is measured
+const LYAPUNOV_WARMUP: u32 = 64;
+
+// Forcing sequence of the Lyapunov fractal, like "AB" or "BBBBBBAAAAAA". The pixel at (a, b)
+// iterates the logistic map x' = r*x*(1 - x) from x = 0.5 with r taking a and b in the order of
+// the sequence, repeated.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LyapunovSequence {
+    // Whether each step takes b
+    steps: Vec<bool>,
+}
+
+impl LyapunovSequence {
+    // Measures the Lyapunov exponent, the average of ln|r*(1 - 2x)|, over max_iterations steps
+    // after the warmup. Chaotic samples, with a positive exponent, get max_iterations and color
+    // like the interior, the stable ones keep their exponent in z.r.
+    pub fn escape_time(&self, settings: &RenderSettings, position: Complex<Real>) -> EscapeResult {
+        let r = |n: u32| if self.steps[n as usize % self.steps.len()] { position.i } else { position.r };
+        let mut x: Real = 0.5;
+        for n in 0..LYAPUNOV_WARMUP {
+            x = r(n) * x * (1.0 - x);
+        }
+        let mut sum = 0.0;
+        for n in LYAPUNOV_WARMUP..LYAPUNOV_WARMUP + settings.max_iterations {
+            let rate = r(n);
+            sum += (rate * (1.0 - 2.0 * x)).abs().ln();
+            x = rate * x * (1.0 - x);
+        }
+        let exponent = sum / settings.max_iterations as Real;
+        // Rates outside [0:4] send x to infinity, which makes the exponent NaN
+        let iterations = if exponent < 0.0 { 0 } else { settings.max_iterations };
+        EscapeResult {iterations, z: Complex::new(exponent, 0.0), derivative: Complex::new(0.0, 0.0), trap: Real::INFINITY, trap_point: Complex::new(Real::NAN, Real::NAN), atom_domain: 0, period: 0, interior_distance: Real::INFINITY, average_sum: 0.0, average_last: 0.0}
+    }
+
+    // Palette color of a stable sample, from the first entry at exponent 0 towards the last
+    // one as the exponent goes to minus infinity
+    pub fn color(&self, settings: &RenderSettings, result: &EscapeResult) -> Color {
+        let palette = &settings.palette;
+        let last = (palette.colors.len() - 1) as Real;
+        palette.interpolate(clamp(1.0 - result.z.r.exp(), 0.0, 1.0) * last)
+    }
+}
+
+impl FromStr for LyapunovSequence {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<LyapunovSequence, String> {
+        let steps = text.trim().chars().map(|step| match step.to_ascii_uppercase() {
+            'A' => Ok(false),
+            'B' => Ok(true),
+            _ => Err(format!("invalid lyapunov sequence '{}', expected a string of A and B", text)),
+        }).collect::<Result<Vec<bool>, String>>()?;
+        if steps.is_empty() {
+            return Err(String::from("the lyapunov sequence is empty"));
+        }
+        Ok(LyapunovSequence {steps})
+    }
+}
+
+impl fmt::Display for LyapunovSequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text: String = self.steps.iter().map(|&b| if b { 'B' } else { 'A' }).collect();
+        write!(f, "{}", text)
+    }
+}
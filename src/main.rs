@@ -1,306 +1,1742 @@
-use std::path::Path;
-use std::fs::File;
-use std::io::{BufWriter, stdout, Write};
-use std::time;
-use std::sync::{Mutex, Arc, mpsc::channel, mpsc::Sender};
-use rand::Rng;
+mod cli;
+mod progress;
+mod interrupt;
+mod serve;
+mod distributed;
+mod bench;
+mod terminal;
+mod dashboard;
+#[cfg(feature = "explorer")]
+mod explorer;
+
+use std::env;
+use std::process;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::thread;
+use std::time::{Duration, Instant};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use mandelbrot_set::{Real, Float, Complex, Fractal, RenderProgress, Animation, Keyframe, Easing, VideoSettings, VideoEncoder, AnimatedFormat, AnimatedEncoder, TileLayout, TilePyramid, RenderSettings, FrameCache, IterationCache, ImageBuffer, ImageFormat, render, render_with_progress, render_with_cache, render_with_checkpoint, render_in_bands, Checkpoint, PngStream, Color, recolor, export_data, MeshSettings, export_mesh, is_mesh_path, Stereo, StereoMode, Minibrot, Interior, BigFloat, escape_time_at, orbit_at, smooth_iterations, distance_estimate, ExternalAngle, trace_ray, external_angle, draw_rays, draw_orbit, Annotations, Stamp, load_png, parse_color, render_stereo, ImageSettings, save_image, save_image_with, save_render, read_png_text, prepare_output, PRESETS, Location, is_video_path, random_boundary_view, interest_score, contact_sheet, labeled_contact_sheet, parse_toml, parse_config_file, parse_job_file, write_toml, write_toml_tables, detail_offset, Vec2};
+use progress::{Reporter, ProgressMode};
+use terminal::TerminalGraphics;
+use cli::{Flags, Options, parse_complex, parse_value, parse_duration, parse_keyframes, parse_julia_path, parse_sweep_axis, keyframe_pairs, zoom_to_view_width, format_digits, expand_config, dump_config, settings_to_pairs, render_metadata, expand_caption, RENDER_OPTIONS, DEFAULT_VIEW_WIDTH};
+
+// MiB of colors an image may use before it is rendered band by band
+const DEFAULT_MEMORY_BUDGET: usize = 2048;
+// Iteration levels the rays of --ray follow, deeper ones come closer to their landing points
+const DEFAULT_RAY_DEPTH: usize = 64;
+// Pixels per side of --tiles tiles
+const DEFAULT_TILE_SIZE: usize = 256;
+// Resolution divisors of the --preview passes
+const PREVIEW_SCALES: [usize; 3] = [8, 4, 2];
+
+const USAGE: &str = "Usage: mandelbrot_set [COMMAND] [OPTIONS]
+
+Commands:
+    render                  Render a single image (default)
+    explore                 Open an interactive explorer window; drag to pan, scroll to zoom,
+                            S saves the view to the output path, R resets, Q quits, the
+                            right mouse button shows the orbit of a point and O hides it
+    animate                 Render a zoom animation as numbered frames
+    zoom-path               Zoom in from the view step by step, every step toward the part
+                            with the most detail, and write the views as a keyframe file for
+                            animate --path
+    explore-random          Score many random views near the boundary of the set by their
+                            detail and render the best ones as thumbnails, with a contact
+                            sheet and a .kfr location file each, into the output directory
+    sweep                   Render a grid of small images varying one or two options and
+                            save them as one contact sheet labeled with the values
+    locate                  Find the minibrot of lowest period in the view with Newton's
+                            method and print its period, size and nucleus to as many
+                            digits as zooming into it needs
+    rays                    Trace the parameter rays of rational external angles as
+                            coordinate lists, or find the external angle of a point
+    probe [x y]             Print the coordinate, iteration count, smooth iteration count,
+                            distance estimate, period of interior points and last orbit
+                            values of the pixel x y of the image, or of --complex re,im
+    recolor <cache>         Color an iteration cache again, render options other than the
+                            palette and coloring are taken from the cache
+    info <png>              Print the render parameters stored in a PNG image
+    rerender <png>          Render the scene stored in a PNG image again, e.g. at a new
+                            --width; giving only one dimension keeps the aspect ratio
+    serve                   Run an HTTP server rendering map tiles on demand at /z/x/y.png
+                            with a Leaflet map page at /, so the set can be browsed from any
+                            browser
+    coordinator             Render an image on several machines: split it into jobs of
+                            tiles and hand them to the workers connecting over TCP, a job
+                            of a worker that fails goes to another one
+    worker                  Connect to a coordinator and render its jobs until the image
+                            is done
+    bench                   Render the standard scenes wide, boundary and deep with the
+                            scalar and SIMD kernels on 1, 2, 4 ... threads and print the
+                            pixels per second of each as a table
+    watch <dir>             Render every scene or job list file (.toml or .json) dropped
+                            into the directory, save the images next to it named after the
+                            file and move it to dir/done, or to dir/failed with the error
+                            in <name>.error. Options given on the command line override
+                            those of every job
+    batch <jobs>            Render every scene of a job list: a TOML file with one [[job]]
+                            table of options per scene, keys before the first table apply
+                            to all of them, or a JSON array of scene objects. Options given
+                            on the command line override those of every job
+
+Global options:
+    --list-presets          List the locations of --preset and exit
+    --quiet                 Only print errors, same as --progress none
+    --progress <mode>       Progress output: bar, json (one object per line with the
+                            percentage, elapsed time, ETA, pixels per second and the
+                            tile of every thread), tui (a dashboard with a live preview
+                            of the finished tiles, a graph of the pixels per second and
+                            the tile of every thread) or none (default: bar)
+    --tui                   Same as --progress tui
+
+Render command options:
+    --dump-config <path>    Write the effective settings as TOML to path (- for stdout)
+                            instead of rendering
+    --save-iterations <path>
+                            Also write the escape results of every sample to path, the
+                            recolor command can then change the palette or coloring
+                            without iterating again
+    --checkpoint <dir>      Save finished tiles to dir while rendering, they are deleted
+                            once the image is saved. Without it Ctrl-C writes the partial
+                            image and the finished tiles to the output path + .checkpoint
+    --resume                Continue the interrupted render of --checkpoint, the settings
+                            have to be the same
+    --preview <path>        Write quick one sample previews at 1/8, 1/4 and 1/2 of the
+                            resolution to path before the full render, to check the
+                            framing early
+    --preview-terminal <mode>
+                            Print a preview fitting the terminal (COLUMNS x LINES) instead
+                            of rendering, to check the framing over SSH: blocks (24 bit
+                            color half blocks), sixel or kitty graphics
+    --memory <MiB>          Memory for the image colors (default: 2048), larger PNG images
+                            are rendered and written in bands of rows
+    --tiles <path>          Write a zoomable tile pyramid instead of the image, band by band
+                            like large PNG images: a Deep Zoom image for OpenSeadragon for a
+                            .dzi path, <path>/<z>/<x>/<y>.png tiles for Leaflet otherwise.
+                            --format jpeg writes JPEG tiles
+    --tile-size <pixels>    Width and height of the tiles, even (default: 256)
+    --save-location <path>  Also write the center, zoom, iteration count and rotation as a
+                            Kalles Fraktaler .kfr location file
+    --export-data <path>    Also write the mean iteration count of every pixel (fractional
+                            for smooth colorings) as a .npy array, .csv rows or raw f64
+                            values with a .json description of the dimensions
+    --stereo <mode>         Render the views of a left and a right eye, combined into a
+                            red-cyan anaglyph or a side-by-side pair twice as wide: 3D
+                            fractals from two cameras, 2D ones with the logarithm of the
+                            iteration count as depth
+    --eye-separation <units>
+                            Distance between the eyes of 3D fractals (default: a thirtieth
+                            of the distance to the point the camera looks at)
+    --stereo-depth <pixels> Largest shift between the eyes of 2D fractals (default: a
+                            fortieth of the image width)
+    --export-mesh <path>    Also write a closed height field mesh for 3D printing with one
+                            vertex per pixel, the logarithm of its mean iteration count as
+                            elevation, as a .obj or binary .stl file
+    --mesh-scale <units>    Distance between neighbouring vertices of the mesh (default: 1)
+    --mesh-height <units>   Elevation of the highest pixel of the mesh above its base
+                            (default: a tenth of the mesh width)
+    --mesh-base <units>     Thickness of the base below the mesh (default: a fiftieth of
+                            the mesh width)
+    --ray <p/q>             Draw the parameter ray of the external angle p/q turns over
+                            the mandelbrot fractal, may be repeated
+    --ray-color <color>     Color of the rays (default: #ffffff)
+    --ray-depth <levels>    Iterations the rays follow towards their landing points
+                            (default: 64)
+    --orbit <re,im>         Draw the orbit of the point as a path through its values up
+                            to the escape or the iteration limit, may be repeated
+    --orbit-color <color>   Color of the orbits (default: #ffffff)
+    --annotate <parts>      Draw coordinate annotations over the image of a 2D fractal,
+                            comma separated: axes, grid (lines at round coordinates),
+                            labels (their coordinates along the bottom and left edges),
+                            scale-bar or all
+    --annotation-color <color>
+                            Color of the annotations, gridlines are translucent (default:
+                            #ffffff)
+    --caption <text>        Stamp a caption into a corner of the image, {center}, {zoom},
+                            {iterations} and {fractal} are replaced by the view's values
+    --caption-color <color> Color of the caption, which has a dark shadow (default: #ffffff)
+    --watermark <png>       Stamp a PNG image into the corner, below or above the caption
+    --stamp-corner <corner> Corner of the caption and watermark: top-left, top-right,
+                            bottom-left or bottom-right (default: bottom-left)
+    --stamp-opacity <0-1>   Opacity of the caption and watermark (default: 0.8)
+
+Random exploration options:
+    --count <number>        Thumbnails to render (default: 16)
+    --candidates <number>   Random views to score (default: 200)
+    --min-zoom <factor>     Smallest zoom of the random views (default: 10)
+    --max-zoom <factor>     Largest zoom of the random views (default: 1e6)
+                            The views are searched within the view of the render options
+                            (default: the whole set); the thumbnails are 256x256 with
+                            automatic iterations unless the options say otherwise, and
+                            --seed repeats the same views
+
+Sweep options:
+    --x <option>=<from>:<to>:<count>
+                            Option varied from column to column, count values evenly spaced
+                            from from to to, e.g. power=2:6:5. Any render option taking a
+                            number, or julia-re and julia-im for the parts of --julia-c
+    --y <option>=<from>:<to>:<count>
+                            Option varied from row to row, optional
+                            The images are 160x160 unless the options say otherwise, the
+                            output path is the contact sheet (default: output/sweep.png)
+
+Zoom path options:
+    --end-zoom <factor>     Zoom of the last keyframe (default: 1e10)
+    --zoom-factor <factor>  Zoom between two keyframes (default: 2)
+                            The path starts at the view of the render options (default: the
+                            whole set), the output path is the keyframe file (default:
+                            output/zoom_path.toml)
+
+Locate options:
+    --iterations <count>    Largest period to look for (default: 10000)
+                            The search covers the view of the render options, e.g.
+                            --center -1.75+0i --zoom 100
+
+Probe options:
+    --complex <re,im>       Point to probe instead of a pixel
+                            The pixel is one of the image of the render options, whose
+                            iteration count, precision and fractal apply to the point
+
+Rays options:
+    --angle <p/q>           Print the points of the parameter ray of the external angle
+                            p/q turns as re im lines, may be repeated
+    --depth <levels>        Iterations the rays follow towards their landing points
+                            (default: 64)
+    --point <re+imi>        Print the external angle of a point outside the set, may be
+                            repeated
+    --iterations <count>    Escape time limit of the points (default: 10000)
+
+Serve options:
+    --address <host:port>   Address to listen on (default: 127.0.0.1:8080)
+    --cache <dir>           Directory of rendered tiles (default: output/tile_cache), every
+                            scene gets its own subdirectory
+    --tile-size <pixels>    Width and height of the tiles (default: 256)
+                            Tile 0/0/0 shows the view of the render options (default: the
+                            whole set) with automatic iterations unless the options say
+                            otherwise
+
+Watch options:
+    --interval <time>       Time between two scans of the directory, files modified more
+                            recently are left for the next one (default: 2s)
+    --once                  Render the files already there and exit
+
+Coordinator options:
+    --listen <host:port>    Address the workers connect to (default: 0.0.0.0:7070)
+    --job-tiles <count>     Tiles per job, a job is one row of tiles high (default: 32)
+    --job-timeout <time>    Time a worker may take for a job before it goes to another
+                            worker, e.g. 90s or 1h (default: 30m)
+                            The render options describe the image, PNG images are written
+                            row by row as the jobs come in. Supersampling, sharpening and
+                            bloom aren't supported
+
+Worker options:
+    --connect <host:port>   Address of the coordinator
+    --threads <count>       Threads rendering the jobs (default: one per core)
+
+Bench options:
+    --size <width>x<height> Image size of every scene (default: 320x240)
+    --samples <count>       Samples per pixel (default: 4)
+    --scene <name>          Only run this scene, may be repeated
+    --max-threads <count>   Largest thread count (default: one per core)
+    --repeat <count>        Render every configuration this often and keep the fastest
+                            (default: 1)
+
+Animation options:
+    --path <path>           Zoom through the keyframes of a TOML file instead of from the
+                            start to the end view: one [[keyframe]] table per view with its
+                            center, zoom (or view-width) and optionally rotation, iterations,
+                            palette-offset, julia-c and the easing of the segment to the
+                            next keyframe. Parameters a keyframe leaves out keep their last
+                            value, every segment takes the same number of frames; zoom-path
+                            writes such files
+    --julia-path <path>     Morph the Julia set with a fixed view by moving its constant c along
+                            circle:<center>/<radius>, line:<from>/<to> or boundary[:<radius>]
+                            (main cardioid of the Mandelbrot set, through the c whose fixed
+                            point has multipliers of the given size, default: 1). Circles and
+                            the boundary loop seamlessly
+    --start-center <re+imi> Center of the first frame (default: --center)
+    --end-center <re+imi>   Center of the last frame (default: --center)
+    --start-zoom <factor>   Zoom of the first frame (default: 1)
+    --end-zoom <factor>     Zoom of the last frame (default: --zoom)
+    --start-rotation <degrees>
+                            Rotation of the first frame (default: --rotation)
+    --end-rotation <degrees>
+                            Rotation of the last frame (default: --rotation), the view turns
+                            at a constant rate over the eased time
+    --frames <count>        Number of frames (default: 100)
+    --easing <curve>        Easing of the whole animation: linear, ease-in, ease-out or
+                            ease-in-out (smoothstep) (default: linear)
+    --fps <rate>            Frame rate of video output (default: 30)
+    --crf <factor>          Constant rate factor of video output, lower is better (default: 18)
+    --frame-delay <ms>      Time each frame of a GIF or APNG is shown (default: 1000/fps)
+    --loop-count <count>    Times a GIF or APNG plays, 0 loops forever (default: 0)
+    --reuse                 Reuse the previous frame: while the view pans the center of every
+                            frame is rounded to whole pixels from the previous one and only
+                            the pixels that came into view are rendered, while it zooms in
+                            the smooth parts of the previous frame are upsampled and only the
+                            other pixels are rendered. Other frames are rendered in full
+    --reuse-threshold <error>
+                            Largest color difference (0 to 1) between neighbouring pixels
+                            of the previous frame that counts as smooth, implies --reuse.
+                            Higher values render fewer pixels and blur more (default: 0.05)
+                            Frames are written to the output path with # replaced by the
+                            frame number (default: output/frame_#####.png), an .mp4, .webm,
+                            .mkv or .mov output path encodes a video through ffmpeg instead,
+                            a .gif or .apng path writes an animated image without ffmpeg
+";
+
+// Failure of a command, decides the exit code and whether the usage hint is shown
+enum Error {
+    // Invalid command line, nothing was rendered
+    Usage(String),
+    // The command line was fine but running it failed, e.g. a file couldn't be written
+    Failed(String),
+    // Ctrl-C stopped the command, what was saved has been reported
+    Interrupted,
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::Failed(message)
+    }
+}
+
+fn print_presets() {
+    for preset in PRESETS {
+        println!("{:<16}{} (zoom {:e})", preset.name, preset.description, preset.zoom);
+    }
+}
+
+fn print_usage() -> Result<(), Error> {
+    println!("{}\n{}", USAGE, RENDER_OPTIONS);
+    Ok(())
+}
+
+fn fail(error: Error) -> ! {
+    match error {
+        Error::Usage(message) => {
+            eprintln!("error: {}\nRun mandelbrot_set --help for the list of options", message);
+            process::exit(2);
+        }
+        Error::Failed(message) => {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        }
+        // 128 + SIGINT, like a shell reports a process killed by Ctrl-C
+        Error::Interrupted => process::exit(130),
+    }
+}
+
+// Returns None when the help message was requested
+fn parse_render(args: &[String]) -> Result<Option<(RenderSettings, String, ImageSettings)>, String> {
+    let mut options = Options::new();
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "-h" | "--help" => return Ok(None),
+            _ => options.apply(flag, &mut flags)?,
+        }
+    }
+    let (settings, output, image_settings) = options.finish()?;
+    Ok(Some((settings, output.unwrap_or_else(|| String::from("output/image.png")), image_settings)))
+}
+
+// Options only the render command has
+struct RenderOutputs<'a> {
+    dump_path: Option<&'a str>,
+    cache_path: Option<&'a str>,
+    data_path: Option<&'a str>,
+    mesh_path: Option<&'a str>,
+    mesh: MeshSettings,
+    stereo: Option<Stereo>,
+    rays: Vec<ExternalAngle>,
+    ray_color: Color,
+    ray_depth: usize,
+    orbits: Vec<Vec2<BigFloat>>,
+    orbit_color: Color,
+    annotations: Annotations,
+    stamp: Stamp,
+    location_path: Option<&'a str>,
+    checkpoint_path: Option<&'a str>,
+    preview_path: Option<&'a str>,
+    terminal_preview: Option<TerminalGraphics>,
+    tiles_path: Option<&'a str>,
+    tile_size: usize,
+    resume: bool,
+    memory_budget: usize,
+}
 
-type Real = f32;
+// Takes the options of the render command out of args, returns them with the remaining arguments
+fn parse_render_outputs(args: &[String]) -> Result<(RenderOutputs<'_>, Vec<String>), String> {
+    let mut outputs = RenderOutputs {
+        dump_path: None,
+        cache_path: None,
+        data_path: None,
+        mesh_path: None,
+        mesh: MeshSettings::new(),
+        stereo: None,
+        rays: Vec::new(),
+        ray_color: Color {r: 1.0, g: 1.0, b: 1.0, a: 1.0},
+        ray_depth: DEFAULT_RAY_DEPTH,
+        orbits: Vec::new(),
+        orbit_color: Color {r: 1.0, g: 1.0, b: 1.0, a: 1.0},
+        annotations: Annotations::new(),
+        stamp: Stamp::new(),
+        location_path: None,
+        checkpoint_path: None,
+        preview_path: None,
+        terminal_preview: None,
+        tiles_path: None,
+        tile_size: DEFAULT_TILE_SIZE,
+        resume: false,
+        memory_budget: DEFAULT_MEMORY_BUDGET,
+    };
+    let mut rest = Vec::new();
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "--dump-config" => outputs.dump_path = Some(flags.value(flag)?),
+            "--save-iterations" => outputs.cache_path = Some(flags.value(flag)?),
+            "--export-data" => outputs.data_path = Some(flags.value(flag)?),
+            "--export-mesh" => outputs.mesh_path = Some(flags.value(flag)?),
+            "--mesh-scale" => outputs.mesh.scale = parse_value(flag, flags.value(flag)?)?,
+            "--mesh-height" => outputs.mesh.height = Some(parse_value(flag, flags.value(flag)?)?),
+            "--mesh-base" => outputs.mesh.base = Some(parse_value(flag, flags.value(flag)?)?),
+            "--stereo" => {
+                let mode: StereoMode = parse_value(flag, flags.value(flag)?)?;
+                outputs.stereo.get_or_insert(Stereo::new(mode)).mode = mode;
+            }
+            "--eye-separation" => outputs.stereo.get_or_insert(Stereo::new(StereoMode::Anaglyph)).eye_separation = Some(parse_value(flag, flags.value(flag)?)?),
+            "--stereo-depth" => outputs.stereo.get_or_insert(Stereo::new(StereoMode::Anaglyph)).depth = Some(parse_value(flag, flags.value(flag)?)?),
+            "--ray" => outputs.rays.push(parse_value(flag, flags.value(flag)?)?),
+            "--ray-color" => outputs.ray_color = parse_color(flags.value(flag)?)?,
+            "--ray-depth" => outputs.ray_depth = parse_value(flag, flags.value(flag)?)?,
+            "--orbit" => outputs.orbits.push(parse_complex(flags.value(flag)?)?),
+            "--orbit-color" => outputs.orbit_color = parse_color(flags.value(flag)?)?,
+            "--annotate" => {
+                let parts: Annotations = parse_value(flag, flags.value(flag)?)?;
+                outputs.annotations = Annotations {color: outputs.annotations.color, ..parts};
+            }
+            "--annotation-color" => outputs.annotations.color = parse_color(flags.value(flag)?)?,
+            "--caption" => outputs.stamp.caption = Some(String::from(flags.value(flag)?)),
+            "--caption-color" => outputs.stamp.color = parse_color(flags.value(flag)?)?,
+            "--watermark" => outputs.stamp.watermark = Some(load_png(flags.value(flag)?)?),
+            "--stamp-corner" => outputs.stamp.corner = parse_value(flag, flags.value(flag)?)?,
+            "--stamp-opacity" => outputs.stamp.opacity = parse_value(flag, flags.value(flag)?)?,
+            "--save-location" => outputs.location_path = Some(flags.value(flag)?),
+            "--checkpoint" => outputs.checkpoint_path = Some(flags.value(flag)?),
+            "--resume" => outputs.resume = true,
+            "--preview" => outputs.preview_path = Some(flags.value(flag)?),
+            "--preview-terminal" => outputs.terminal_preview = Some(parse_value(flag, flags.value(flag)?)?),
+            "--tiles" => outputs.tiles_path = Some(flags.value(flag)?),
+            "--tile-size" => outputs.tile_size = parse_value(flag, flags.value(flag)?)?,
+            "--memory" => outputs.memory_budget = parse_value(flag, flags.value(flag)?)?,
+            _ => rest.push(String::from(flag)),
+        }
+    }
+    Ok((outputs, rest))
+}
 
-#[derive(Copy, Clone)]
-struct Complex {
-    r: Real,
-    i: Real,
+// Ends the render on the reporter, with the samples reached when a time limit was set
+fn finish_render(reporter: &mut Reporter, settings: &RenderSettings) {
+    reporter.finish();
+    let progress = match (settings.time_limit, reporter.last_progress()) {
+        (Some(_), Some(progress)) => progress,
+        _ => return,
+    };
+    let message = if progress.reduced_pixels > 0 {
+        format!("Time limit reached, {:.1}% of the pixels got fewer samples than requested ({:.1} samples per pixel on average, up to {})",
+            progress.reduced_pixels as f64 * 100.0 / progress.finished_pixels.max(1) as f64, progress.samples_per_pixel(), settings.sample_count)
+    }
+    else {
+        format!("Finished within the time limit with {:.1} samples per pixel on average", progress.samples_per_pixel())
+    };
+    reporter.message(&message);
 }
 
-impl Complex {
-    fn squared(&self) -> Complex {
-        Complex {r: self.r*self.r - self.i*self.i, i: 2.0*self.r*self.i}
+fn render_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let (outputs, rest) = parse_render_outputs(args).map_err(Error::Usage)?;
+    let RenderOutputs {dump_path, cache_path, data_path, mesh_path, mesh, stereo, rays, ray_color, ray_depth, orbits, orbit_color, annotations, mut stamp, location_path, checkpoint_path, preview_path, terminal_preview, tiles_path, tile_size, resume, memory_budget} = outputs;
+    let (mut settings, output, mut image_settings) = match parse_render(&rest).map_err(Error::Usage)? {
+        Some(arguments) => arguments,
+        None => return print_usage(),
+    };
+    if let Some(path) = dump_path {
+        return Ok(dump_config(&settings, &output, path)?);
+    }
+    if let Some(graphics) = terminal_preview {
+        let image = render(&graphics.preview_settings(&settings));
+        return Ok(graphics.print(&image, &mut std::io::stdout()).map_err(|e| format!("cannot print the preview: {}", e))?);
+    }
+    let data_channels = image_settings.format_for(&output).has_data_channels();
+    if data_channels {
+        settings.track_derivative = true;
+    }
+    let needs_cache = cache_path.is_some() || data_path.is_some() || mesh_path.is_some() || data_channels;
+    if mesh_path.is_some_and(|path| !is_mesh_path(path)) {
+        return Err(Error::Usage(String::from("--export-mesh writes .obj or .stl files")));
+    }
+    if mesh.scale <= 0.0 || mesh.height.is_some_and(|height| height < 0.0) || mesh.base.is_some_and(|base| base < 0.0) {
+        return Err(Error::Usage(String::from("the mesh scale must be positive, its height and base can't be negative")));
+    }
+    if resume && checkpoint_path.is_none() {
+        return Err(Error::Usage(String::from("--resume needs --checkpoint")));
+    }
+    if checkpoint_path.is_some() && needs_cache {
+        return Err(Error::Usage(String::from("checkpoints only store colors, they can't be combined with iteration data output")));
+    }
+    if tiles_path.is_some() && needs_cache {
+        return Err(Error::Usage(String::from("tiles only store colors, they can't be combined with iteration data output")));
+    }
+    if stereo.is_some() && (needs_cache || checkpoint_path.is_some() || tiles_path.is_some()) {
+        return Err(Error::Usage(String::from("stereo images can't be combined with iteration data output, checkpoints or tiles")));
+    }
+    if stereo.is_some_and(|stereo| stereo.eye_separation.is_some_and(|separation| separation < 0.0) || stereo.depth.is_some_and(|depth| depth < 0.0)) {
+        return Err(Error::Usage(String::from("the eye separation and stereo depth can't be negative")));
+    }
+    let overlays = !rays.is_empty() || !orbits.is_empty() || !annotations.is_empty() || !stamp.is_empty();
+    if overlays && (stereo.is_some() || tiles_path.is_some()) {
+        return Err(Error::Usage(String::from("rays, orbits, annotations and captions can't be drawn over stereo images or tiles")));
+    }
+    if !(0.0..=1.0).contains(&stamp.opacity) {
+        return Err(Error::Usage(String::from("--stamp-opacity must be between 0 and 1")));
+    }
+    stamp.caption = stamp.caption.map(|caption| expand_caption(&caption, &settings));
+    if !annotations.is_empty() {
+        annotations.check(&settings).map_err(Error::Usage)?;
+    }
+    if !rays.is_empty() && (settings.fractal != Fractal::Mandelbrot || settings.fractal.is_3d()) {
+        return Err(Error::Usage(format!("rays can only be drawn over the mandelbrot fractal, not {}", settings.fractal)));
+    }
+    let orbits = orbits.iter().map(|point| orbit_at(&settings, settings.offset_of(point))).collect::<Result<Vec<_>, _>>().map_err(Error::Usage)?;
+    if settings.supersample > 1 && (needs_cache || tiles_path.is_some()) {
+        return Err(Error::Usage(String::from("--supersample can't be combined with iteration data output or --tiles")));
+    }
+    // The tiles replace the image
+    let output = tiles_path.map(String::from).unwrap_or(output);
+    let pyramid = match tiles_path {
+        Some(path) => Some(TilePyramid::new(path, settings.width, settings.height, tile_size, TileLayout::for_path(path), &image_settings).map_err(Error::Usage)?),
+        None => None,
+    };
+    // Fail before rendering rather than after it when the outputs can't be written
+    for path in [Some(output.as_str()).filter(|_| pyramid.is_none()), cache_path, data_path, mesh_path, preview_path].iter().flatten() {
+        prepare_output(path)?;
+    }
+    if let Some(path) = location_path {
+        Location::from_settings(&settings).save(path)?;
+        reporter.message(&format!("Saved location to {}", path));
+    }
+
+    // The output path doesn't change the image
+    let pairs: Vec<(String, String)> = settings_to_pairs(&settings, &output).into_iter().filter(|(key, _)| key != "output").collect();
+    let checkpoint = match checkpoint_path {
+        Some(directory) => Some(Checkpoint::new(directory, &write_toml(&pairs), resume)?),
+        // Without --checkpoint the finished tiles are only written when Ctrl-C stops the render
+        None if !needs_cache => Some(Checkpoint::deferred(&format!("{}.checkpoint", output), &write_toml(&pairs))),
+        None => None,
+    };
+    interrupt::install();
+    settings.interrupt = Some(&interrupt::INTERRUPTED);
+    // A known seed lets rerender repeat the image exactly
+    if settings.seed.is_none() {
+        settings.seed = Some(rand::random());
+    }
+    image_settings.text = render_metadata(&settings);
+    // Rows of colors fitting in the memory budget, supersampled images are held enlarged
+    let band_height = memory_budget * 1024 * 1024 / (settings.width * settings.supersample.pow(2) * mem::size_of::<Color>());
+    let stream = band_height < settings.height;
+    if stream && needs_cache {
+        return Err(Error::Usage(String::from("the image doesn't fit in --memory, iteration data can't be written band by band")));
+    }
+    if stream && stereo.is_some() {
+        return Err(Error::Usage(String::from("the image doesn't fit in --memory, stereo images can't be rendered band by band")));
+    }
+    if stream && overlays {
+        return Err(Error::Usage(String::from("the image doesn't fit in --memory, rays, orbits, annotations and captions can't be drawn band by band")));
+    }
+    if stream && settings.supersample > 1 {
+        return Err(Error::Usage(String::from("the supersampled image doesn't fit in --memory, it can't be rendered band by band")));
     }
 
-    fn add(&self, rhs: &Complex) -> Complex {
-        Complex {r: self.r + rhs.r, i: self.i + rhs.i}
+    if settings.auto_iterations.is_some() {
+        reporter.message(&format!("Using {} iterations", settings.max_iterations));
+    }
+    if let Some(path) = preview_path {
+        // Coarse passes first so a wrong framing shows before the full render
+        for scale in PREVIEW_SCALES.iter() {
+            save_image(&render(&settings.preview(*scale)), path)?;
+            reporter.message(&format!("Saved 1/{} resolution preview to {}", scale, path));
+        }
+        if interrupt::interrupted() {
+            return Err(Error::Interrupted);
+        }
+    }
+    reporter.message("Drawing the buffer...");
+    reporter.start();
+    if let Some(mut pyramid) = pyramid {
+        let max_level = pyramid.max_level();
+        render_in_bands(&settings, band_height.min(settings.height), checkpoint.as_ref(), |progress| reporter.update(progress), |band| pyramid.write_band(band))?;
+        pyramid.finish()?;
+        finish_render(reporter, &settings);
+        if interrupt::interrupted() {
+            return report_interrupted(reporter, &output, checkpoint.as_ref());
+        }
+        if let Some(checkpoint) = checkpoint.filter(|checkpoint| !checkpoint.is_deferred()) {
+            checkpoint.remove()?;
+        }
+        reporter.message(&format!("Saved tiles of levels 0 to {} to {}", max_level, output));
+        return Ok(());
+    }
+    if let Some(stereo) = stereo {
+        let image = render_stereo(&settings, &stereo, |progress| reporter.update(progress));
+        finish_render(reporter, &settings);
+        if interrupt::interrupted() {
+            return Err(Error::Interrupted);
+        }
+        save_render(&image, &output, &image_settings, None)?;
+        reporter.message(&format!("Saved {} stereo image to {}", stereo.mode, output));
+        return Ok(());
+    }
+    if stream {
+        let mut png = PngStream::new(&output, settings.width, settings.height, &image_settings)?;
+        render_in_bands(&settings, band_height, checkpoint.as_ref(), |progress| reporter.update(progress), |band| png.write_band(band))?;
+        png.finish()?;
+        finish_render(reporter, &settings);
+        if interrupt::interrupted() {
+            return report_interrupted(reporter, &output, checkpoint.as_ref());
+        }
+        if let Some(checkpoint) = checkpoint.filter(|checkpoint| !checkpoint.is_deferred()) {
+            checkpoint.remove()?;
+        }
+        reporter.message(&format!("Saved buffer to {}", output));
+        return Ok(());
+    }
+    let rays: Vec<_> = rays.iter().map(|angle| trace_ray(angle, ray_depth)).collect();
+    let draw_overlays = |image: &mut ImageBuffer| {
+        draw_rays(image, &settings, &rays, ray_color);
+        for orbit in &orbits {
+            draw_orbit(image, &settings, orbit, orbit_color);
+        }
+        if !annotations.is_empty() {
+            annotations.draw(image, &settings);
+        }
+        stamp.draw(image);
+    };
+    if let Some(checkpoint) = checkpoint {
+        let mut image = render_with_checkpoint(&settings, &checkpoint, |progress| reporter.update(progress))?;
+        finish_render(reporter, &settings);
+        draw_overlays(&mut image);
+        save_render(&image, &output, &image_settings, None)?;
+        if interrupt::interrupted() {
+            return report_interrupted(reporter, &output, Some(&checkpoint));
+        }
+        if !checkpoint.is_deferred() {
+            checkpoint.remove()?;
+        }
+        reporter.message(&format!("Saved buffer to {}", output));
+        return Ok(());
     }
 
-    fn length(&self) -> Real {
-        (self.r*self.r + self.i*self.i).sqrt()
+    let (mut image, cache) = render_with_cache(&settings, |progress| reporter.update(progress));
+    finish_render(reporter, &settings);
+    draw_overlays(&mut image);
+    if interrupt::interrupted() {
+        save_render(&image, &output, &image_settings, None)?;
+        return report_interrupted(reporter, &output, None);
+    }
+    if let Some(path) = cache_path {
+        let scene = write_toml(&settings_to_pairs(&settings, &output));
+        cache.save(path, &scene)?;
+        reporter.message(&format!("Saved iterations to {}", path));
+    }
+    if let Some(path) = data_path {
+        export_data(path, &settings, &cache)?;
+        reporter.message(&format!("Saved iteration counts to {}", path));
+    }
+    if let Some(path) = mesh_path {
+        export_mesh(path, &settings, &cache, &mesh)?;
+        reporter.message(&format!("Saved mesh to {}", path));
     }
+    save_render(&image, &output, &image_settings, Some((&settings, &cache)))?;
+    reporter.message(&format!("Saved buffer to {}", output));
+    Ok(())
 }
 
-#[derive(Copy, Clone)]
-struct Vec2<T> {
-    x: T,
-    y: T,
+// Tells how to continue a render stopped by Ctrl-C after its partial image was saved
+fn report_interrupted(reporter: &Reporter, output: &str, checkpoint: Option<&Checkpoint>) -> Result<(), Error> {
+    reporter.message(&format!("Interrupted, saved the partial image to {}", output));
+    match checkpoint {
+        Some(checkpoint) if checkpoint.is_deferred() => {
+            let directory = checkpoint.directory().display();
+            reporter.message(&format!("Saved the finished tiles to {}, run the same command with --checkpoint {} --resume to render the rest", directory, directory));
+        }
+        Some(checkpoint) => reporter.message(&format!("Run the same command with --resume to render the rest from {}", checkpoint.directory().display())),
+        None => reporter.message("Iteration data isn't saved for interrupted renders, run the command again to render the whole image"),
+    }
+    Err(Error::Interrupted)
 }
 
-impl Vec2<usize> {
-    fn new() -> Vec2<usize> {
-        Vec2{x:0, y:0}
+fn recolor_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let (path, rest) = match args.split_first() {
+        Some((path, rest)) if !path.starts_with('-') => (path, rest),
+        _ if args.iter().any(|arg| arg == "-h" || arg == "--help") => return print_usage(),
+        _ => return Err(Error::Usage(String::from("missing the iteration cache path"))),
+    };
+    let (cache, scene) = IterationCache::load(path)?;
+    // The stored scene comes first so the command line options override it
+    let mut scene_args = Vec::new();
+    for (key, value) in parse_toml(&scene).map_err(|e| format!("{}: {}", path, e))? {
+        scene_args.push(format!("--{}", key));
+        scene_args.push(value);
     }
+    scene_args.extend_from_slice(rest);
+    let (settings, output, mut image_settings) = match parse_render(&scene_args).map_err(Error::Usage)? {
+        Some(arguments) => arguments,
+        None => return print_usage(),
+    };
+    prepare_output(&output)?;
+    image_settings.text = render_metadata(&settings);
+
+    let start_time = Instant::now();
+    let image = recolor(&settings, &cache)?;
+    reporter.message(&format!("Recolored {} in {} ms", path, start_time.elapsed().as_millis()));
+    save_render(&image, &output, &image_settings, Some((&settings, &cache)))?;
+    reporter.message(&format!("Saved buffer to {}", output));
+    Ok(())
 }
 
-impl Vec2<Real> {
-    fn new() -> Vec2<Real> {
-        Vec2{x:0.0, y:0.0}
+// Path of the PNG image given as the first argument of info and rerender
+fn png_argument<'a>(args: &'a [String], command: &str) -> Result<Option<(&'a String, &'a [String])>, Error> {
+    match args.split_first() {
+        Some((path, rest)) if !path.starts_with('-') => Ok(Some((path, rest))),
+        _ if args.iter().any(|arg| arg == "-h" || arg == "--help") => Ok(None),
+        _ => Err(Error::Usage(format!("{} needs the path of a PNG image", command))),
     }
 }
 
-#[derive(Copy, Clone)]
-struct Color {
-    r: Real,
-    g: Real,
-    b: Real,
-    a: Real,
+fn info_command(args: &[String]) -> Result<(), Error> {
+    let path = match png_argument(args, "info")? {
+        Some((path, _)) => path,
+        None => return print_usage(),
+    };
+    let text = read_png_text(path)?;
+    if text.is_empty() {
+        return Err(Error::Failed(format!("{} has no text chunks", path)));
+    }
+    for (keyword, value) in text {
+        if value.contains('\n') {
+            println!("{}:\n{}", keyword, value.trim_end());
+        }
+        else {
+            println!("{}: {}", keyword, value);
+        }
+    }
+    Ok(())
 }
 
-impl Color {
-    fn new() -> Color {
-        Color {r:0.0, g:0.0, b:0.0, a:0.0}
+// Renders the scene stored in a PNG image again, the command line options override it
+fn rerender_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let (path, rest) = match png_argument(args, "rerender")? {
+        Some(arguments) => arguments,
+        None => return print_usage(),
+    };
+    let scene = read_png_text(path)?.into_iter().find(|(keyword, _)| keyword == "Scene").map(|(_, scene)| scene)
+        .ok_or_else(|| format!("{} has no render parameters", path))?;
+    let pairs = parse_toml(&scene).map_err(|e| format!("{}: {}", path, e))?;
+    let size = |key: &str| pairs.iter().find(|(name, _)| name == key).and_then(|(_, value)| value.parse::<usize>().ok());
+    let given = |flag: &str| rest.iter().position(|arg| arg == flag).and_then(|i| rest.get(i + 1)).and_then(|value| value.parse::<usize>().ok());
+    let mut scene_args = Vec::new();
+    for (key, value) in &pairs {
+        // The view height follows the aspect ratio of the new resolution
+        if key != "view-height" {
+            scene_args.push(format!("--{}", key));
+            scene_args.push(value.clone());
+        }
+    }
+    // Changing only one dimension scales the other one along
+    if let (Some(width), Some(height)) = (size("width"), size("height")) {
+        match (given("--width"), given("--height")) {
+            (Some(new_width), None) => scene_args.extend([String::from("--height"), (new_width * height / width.max(1)).max(1).to_string()]),
+            (None, Some(new_height)) => scene_args.extend([String::from("--width"), (new_height * width / height.max(1)).max(1).to_string()]),
+            _ => {}
+        }
     }
+    scene_args.extend_from_slice(rest);
+    render_command(&scene_args, reporter)
+}
 
-    fn add(&mut self, rhs: Color) {
-        self.r += rhs.r;
-        self.g += rhs.g;
-        self.b += rhs.b;
-        self.a += rhs.a;
+#[cfg(feature = "explorer")]
+fn explore_command(args: &[String]) -> Result<(), Error> {
+    match parse_render(args).map_err(Error::Usage)? {
+        Some((settings, output, image_settings)) => Ok(explorer::explore(&settings, &output, &image_settings)?),
+        None => print_usage(),
     }
+}
+
+#[cfg(not(feature = "explorer"))]
+fn explore_command(_args: &[String]) -> Result<(), Error> {
+    Err(Error::Usage(String::from("the explorer is not available, rebuild with --features explorer")))
+}
 
-    fn divide(&mut self, value: Real) {
-        self.r /= value;
-        self.g /= value;
-        self.b /= value;
-        self.a /= value;
+// Replaces the last run of '#' in pattern with the zero padded frame number
+fn frame_path(pattern: &str, frame: usize) -> String {
+    match pattern.rfind('#') {
+        Some(end) => {
+            let start = pattern[..end].rfind(|c| c != '#').map(|i| i + 1).unwrap_or(0);
+            format!("{}{:0width$}{}", &pattern[..start], frame, &pattern[end+1..], width = end + 1 - start)
+        }
+        None => {
+            let (stem, extension) = pattern.rsplit_once('.').unwrap_or((pattern, "png"));
+            format!("{}_{:05}.{}", stem, frame, extension)
+        }
     }
 }
 
-fn save_image(color_buffer: &[Color], width: usize, height: usize, path: &str) {
-    let path = Path::new(path);
-    let file = File::create(path).unwrap();
-    let ref mut w = BufWriter::new(file);
-    
-    let mut encoder = png::Encoder::new(w, width as u32, height as u32);
-    encoder.set_color(png::ColorType::RGBA);
-    encoder.set_depth(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
-    
-    let mut rgba_data = vec![0u8; 4 * width * height];
-    for y in 0..height {
-        for x in 0..width {
-            rgba_data[4 * (y * width + x) + 0] = (color_buffer[y * width + x].r * 255.0) as u8;
-            rgba_data[4 * (y * width + x) + 1] = (color_buffer[y * width + x].g * 255.0) as u8;
-            rgba_data[4 * (y * width + x) + 2] = (color_buffer[y * width + x].b * 255.0) as u8;
-            rgba_data[4 * (y * width + x) + 3] = (color_buffer[y * width + x].a * 255.0) as u8;
+// Settings of the animate command
+struct AnimationOptions {
+    animation: Animation,
+    settings: RenderSettings,
+    output: String,
+    image_settings: ImageSettings,
+    video_settings: VideoSettings,
+    // Take the pixels a frame shares with the previous one when the view pans, and interpolate
+    // the smooth parts of the previous one when it zooms in, with the largest color difference
+    // that counts as smooth
+    reuse: Option<Real>,
+}
+
+// Returns None when the help message was requested
+fn parse_animation(args: &[String]) -> Result<Option<AnimationOptions>, String> {
+    let mut options = Options::new();
+    let mut start_center = None;
+    let mut end_center = None;
+    let mut start_zoom = None;
+    let mut end_zoom = None;
+    let mut start_rotation = None;
+    let mut end_rotation = None;
+    let mut path = None;
+    let mut julia_path = None;
+    let mut frame_count = 100usize;
+    let mut easing = Easing::Linear;
+    let mut video_settings = VideoSettings::new();
+    let mut reuse = None;
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "-h" | "--help" => return Ok(None),
+            "--start-center" => start_center = Some(parse_complex(flags.value(flag)?)?),
+            "--end-center" => end_center = Some(parse_complex(flags.value(flag)?)?),
+            "--start-zoom" => start_zoom = Some(parse_value(flag, flags.value(flag)?)?),
+            "--path" => path = Some(flags.value(flag)?),
+            "--julia-path" => julia_path = Some(parse_julia_path(flags.value(flag)?)?),
+            "--end-zoom" => end_zoom = Some(parse_value(flag, flags.value(flag)?)?),
+            "--start-rotation" => start_rotation = Some(parse_value(flag, flags.value(flag)?)?),
+            "--end-rotation" => end_rotation = Some(parse_value(flag, flags.value(flag)?)?),
+            "--frames" => frame_count = parse_value(flag, flags.value(flag)?)?,
+            "--easing" => easing = parse_value(flag, flags.value(flag)?)?,
+            "--fps" => video_settings.fps = parse_value(flag, flags.value(flag)?)?,
+            "--crf" => video_settings.crf = parse_value(flag, flags.value(flag)?)?,
+            "--frame-delay" => video_settings.delay = Some(parse_value(flag, flags.value(flag)?)?),
+            "--loop-count" => video_settings.loop_count = parse_value(flag, flags.value(flag)?)?,
+            "--reuse" => reuse = Some(reuse.unwrap_or(FrameCache::new().threshold)),
+            "--reuse-threshold" => reuse = Some(parse_value(flag, flags.value(flag)?)?),
+            _ => options.apply(flag, &mut flags)?,
         }
     }
+    // Morphs render the Julia set, in the whole set view unless the view options are given
+    if julia_path.is_some() {
+        match options.settings.fractal {
+            Fractal::Mandelbrot => options.settings.fractal = Fractal::Julia,
+            Fractal::Julia => {}
+            _ => return Err(String::from("--julia-path needs the julia fractal")),
+        }
+    }
+    let (settings, output, image_settings) = options.finish()?;
+    let output = output.unwrap_or_else(|| String::from("output/frame_#####.png"));
+    video_settings.ffmpeg = image_settings.ffmpeg.clone();
+    if frame_count == 0 {
+        return Err(String::from("frame count must be positive"));
+    }
+    let keyframes = match path {
+        Some(path) => {
+            if start_center.is_some() || end_center.is_some() || start_zoom.is_some() || end_zoom.is_some() || start_rotation.is_some() || end_rotation.is_some() {
+                return Err(String::from("--path can't be combined with the --start and --end options"));
+            }
+            parse_keyframes(path)?
+        }
+        None => {
+            let end_view_width = match end_zoom {
+                Some(zoom) => zoom_to_view_width(zoom)?,
+                None => settings.view_size.x,
+            };
+            // The view of morphs stays fixed
+            let start_view_width = match (start_zoom, &julia_path) {
+                (Some(zoom), _) => zoom_to_view_width(zoom)?,
+                (None, Some(_)) => settings.view_size.x,
+                (None, None) => zoom_to_view_width(1.0)?,
+            };
+            vec![
+                Keyframe {
+                    rotation: Some(start_rotation.unwrap_or(settings.rotation)),
+                    ..Keyframe::new(start_center.unwrap_or(settings.center), start_view_width)
+                },
+                Keyframe {
+                    rotation: Some(end_rotation.unwrap_or(settings.rotation)),
+                    ..Keyframe::new(end_center.unwrap_or(settings.center), end_view_width)
+                },
+            ]
+        }
+    };
+    let animation = Animation {keyframes, frame_count, easing, julia_path};
+    Ok(Some(AnimationOptions {animation, settings, output, image_settings, video_settings, reuse}))
+}
 
-    writer.write_image_data(&rgba_data).unwrap();
+// Where the animate command writes its frames
+enum FrameOutput {
+    Images,
+    Video(VideoEncoder),
+    // GIF or APNG
+    Animated(AnimatedEncoder),
 }
 
-// Prints the progress [0:100] as a bar in the console
-fn print_progress(progress: u32) {
-    let mut progress_bar = String::from("[");
-    for i in 0..50 {
-        if i < progress/2 {
-            progress_bar.push('=');
+fn animate_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let AnimationOptions {animation, settings, output, mut image_settings, video_settings, reuse} = match parse_animation(args).map_err(Error::Usage)? {
+        Some(options) => options,
+        None => return print_usage(),
+    };
+    let frame_count = animation.frame_count;
+    prepare_output(&frame_path(&output, 0))?;
+
+    let mut frames = if is_video_path(&output) {
+        FrameOutput::Video(VideoEncoder::new(&output, settings.width, settings.height, &video_settings)?)
+    }
+    else if AnimatedFormat::from_path(&output).is_some() {
+        FrameOutput::Animated(AnimatedEncoder::new(&output, settings.width, settings.height, frame_count, &video_settings)?)
+    }
+    else {
+        FrameOutput::Images
+    };
+
+    reporter.message(&format!("Rendering {} frames...", frame_count));
+    reporter.start();
+    let frame_pixels = settings.width * settings.height;
+    let mut cache = FrameCache::new();
+    if let Some(threshold) = reuse {
+        cache.threshold = threshold;
+    }
+    for frame in 0..frame_count {
+        let mut frame_settings = animation.frame_settings(&settings, frame);
+        // Progress of the whole animation
+        let report = |progress: &RenderProgress| reporter.update(&RenderProgress {
+            finished_pixels: frame * frame_pixels + progress.finished_pixels,
+            total_pixels: frame_count * frame_pixels,
+            ..progress.clone()
+        });
+        let image = if reuse.is_some() {
+            // Round pans to whole pixels so the frame can take the pixels of the previous one
+            cache.snap(&mut frame_settings);
+            cache.render(&frame_settings, report)
         }
         else {
-            progress_bar.push(' ');
-        }
-    }
-    progress_bar.push_str("]");
-    print!("\r{} {}%  ", progress_bar, progress);
-    stdout().flush().unwrap();
-}
-
-fn clamp<T: PartialOrd>(value: T, min: T, max: T) -> T {
-    if value < min {
-        return min;
-    }
-    else if value > max {
-        return max;
-    }
-    value
-}
-
-fn divide_roundup(numinator: usize, denominator: usize) -> usize {
-    if numinator%denominator == 0 {
-        return numinator/denominator;
-    }
-    numinator/denominator+1
-}
-
-fn min<T: PartialOrd>(a: T, b: T) -> T {
-    if a < b {
-        return a;
-    }
-    b
-}
-
-// Render Parameters
-// COLOR PALLETE SOURCE: https://stackoverflow.com/a/16505538/9218594
-const COLOR_PALETTE: [Color; 16] = [
-    Color {r:0.26, g:0.1,  b:0.06, a:1.0}, 
-    Color {r:0.1,  g:0.03, b:0.1,  a:1.0}, 
-    Color {r:0.3,  g:0.01, b:0.18, a:1.0}, 
-    Color {r:0.02, g:0.02, b:0.28, a:1.0}, 
-    Color {r:0.0,  g:0.03, b:0.4,  a:1.0}, 
-    Color {r:0.05, g:0.17, b:0.54, a:1.0}, 
-    Color {r:0.1,  g:0.3,  b:0.7,  a:1.0}, 
-    Color {r:0.25, g:0.5,  b:0.82, a:1.0}, 
-    Color {r:0.53, g:0.71, b:0.9,  a:1.0}, 
-    Color {r:0.83, g:0.93, b:0.97, a:1.0}, 
-    Color {r:0.95, g:0.91, b:0.75, a:1.0}, 
-    Color {r:0.97, g:0.78, b:0.37, a:1.0}, 
-    Color {r:1.0,  g:0.67, b:0.0,  a:1.0}, 
-    Color {r:0.8,  g:0.5,  b:0.0,  a:1.0}, 
-    Color {r:0.6,  g:0.34, b:0.0,  a:1.0}, 
-    Color {r:0.42, g:0.2,  b:0.02, a:1.0} 
-    ];
-const MAX_ITERATIONS: u32 = 250;
-const MAX_LENGTH: Real = 2.0;
-
-#[derive(Copy, Clone)]
-struct ThreadDescryptor {
-    offset: Vec2<usize>,
-    thread_size: Vec2<usize>,
-    color_buffer_size: Vec2<usize>,
-    sample_count: usize,
-    center: Vec2<Real>,
-    view_size: Vec2<Real>,
-}
-
-impl ThreadDescryptor {
-    fn new() -> ThreadDescryptor {
-        ThreadDescryptor {
-            offset: Vec2::<usize>::new(),
-            thread_size: Vec2::<usize>::new(),
-            color_buffer_size: Vec2::<usize>::new(),
-            sample_count: 0,
-            center: Vec2::<Real>::new(),
-            view_size: Vec2::<Real>::new(),
-        }
-    }
-}
-
-fn thread_worker(color_buffer: Arc<Mutex<Vec<Color>>>, desc: ThreadDescryptor, id: usize, finishing_sender: Sender<usize>) {
-    let mut temp_color_buffer = vec![Color::new(); desc.thread_size.x * desc.thread_size.y];
-    let mut rng = rand::thread_rng();
-
-    //println!("New Thread: x {}, y {}, width {}, height {}", desc.offset.x, desc.offset.y, desc.thread_size.x, desc.thread_size.y);
-
-    // Render the fractal into the temporary color buffer
-    for y in 0..desc.thread_size.y {
-        for x in 0..desc.thread_size.x {
-            let mut pixel_color = Color::new();
-            // Stochastic Sampling
-            for _ in 0..desc.sample_count {
-                let mut norm_pos = Vec2::<Real>::new();
-                norm_pos.x = (((x+desc.offset.x) as Real) + rng.gen_range(-0.5, 0.5))/(desc.color_buffer_size.x as Real) * 2.0 - 1.0; // [-1:1]
-                norm_pos.y = -((((y+desc.offset.y) as Real) + rng.gen_range(-0.5, 0.5))/(desc.color_buffer_size.y as Real) * 2.0 - 1.0); // [-1:1]
-                let mut pos = Complex {r: 0.0, i: 0.0};
-                pos.r = desc.center.x + norm_pos.x * desc.view_size.x / 2.0; // real axis
-                pos.i = desc.center.y + norm_pos.y * desc.view_size.y / 2.0; // imaginary axis
-                let mut iterations: u32 = 0;
-                let mut temp = Complex {r: 0.0, i: 0.0};
-                while temp.length() <= MAX_LENGTH && iterations < MAX_ITERATIONS {
-                    temp = temp.squared().add(&pos);
-                    iterations += 1;
+            render_with_progress(&frame_settings, report)
+        };
+        match &mut frames {
+            FrameOutput::Video(video) => video.write_frame(&image)?,
+            FrameOutput::Animated(animated) => animated.write_frame(&image)?,
+            FrameOutput::Images => {
+                image_settings.text = render_metadata(&frame_settings);
+                save_image_with(&image, &frame_path(&output, frame), &image_settings)?;
+            }
+        }
+    }
+    reporter.finish();
+    match frames {
+        FrameOutput::Video(video) => {
+            video.finish()?;
+            reporter.message(&format!("Saved video to {}", output));
+        }
+        FrameOutput::Animated(animated) => {
+            animated.finish()?;
+            reporter.message(&format!("Saved animation to {}", output));
+        }
+        FrameOutput::Images => reporter.message(&format!("Saved frames to {}", frame_path(&output, 0))),
+    }
+    Ok(())
+}
+
+// Options of explore-random that differ from the render command, later options override them
+const RANDOM_DEFAULTS: [&str; 12] = ["--center", "-0.5", "--zoom", "1", "--width", "256", "--height", "256",
+    "--auto-iterations", "true", "--output", "output/gallery"];
+
+fn explore_random_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let mut options = Options::new();
+    let mut count = 16usize;
+    let mut candidates = 200usize;
+    let mut min_zoom: Real = 10.0;
+    let mut max_zoom: Real = 1e6;
+    let defaults: Vec<String> = RANDOM_DEFAULTS.iter().map(|arg| String::from(*arg)).collect();
+    let args: Vec<String> = defaults.into_iter().chain(args.iter().cloned()).collect();
+    let mut flags = Flags::new(&args);
+    while let Some(flag) = flags.next_flag() {
+        let parsed = match flag {
+            "-h" | "--help" => return print_usage(),
+            "--count" => parse_value(flag, flags.value(flag)?).map(|value| count = value),
+            "--candidates" => parse_value(flag, flags.value(flag)?).map(|value| candidates = value),
+            "--min-zoom" => parse_value(flag, flags.value(flag)?).map(|value| min_zoom = value),
+            "--max-zoom" => parse_value(flag, flags.value(flag)?).map(|value| max_zoom = value),
+            _ => options.apply(flag, &mut flags),
+        };
+        parsed.map_err(Error::Usage)?;
+    }
+    let (mut settings, output, image_settings) = options.finish().map_err(Error::Usage)?;
+    let directory = output.unwrap_or_default();
+    if count == 0 || candidates == 0 {
+        return Err(Error::Usage(String::from("--count and --candidates must be positive")));
+    }
+    if min_zoom <= 0.0 || max_zoom < min_zoom {
+        return Err(Error::Usage(String::from("the zoom range must be positive with --min-zoom at most --max-zoom")));
+    }
+    if matches!(settings.fractal, Fractal::Formula | Fractal::Newton | Fractal::Lyapunov) || settings.fractal.is_3d() {
+        return Err(Error::Usage(String::from("random exploration doesn't support formulas, newton, lyapunov and 3D fractals")));
+    }
+    let sheet_path = format!("{}/contact_sheet.png", directory);
+    prepare_output(&sheet_path)?;
+
+    // The seed picks the views as well as the sample positions
+    let seed = *settings.seed.get_or_insert_with(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    reporter.message(&format!("Scoring {} random views...", candidates));
+    let mut scored = Vec::new();
+    for _ in 0..candidates {
+        let zoom = rng.gen_range(min_zoom.ln(), max_zoom.ln() + Real::EPSILON).exp();
+        let mut view = settings.clone();
+        if random_boundary_view(&mut view, zoom_to_view_width(zoom)?, &mut rng) {
+            scored.push((interest_score(&view), view));
+        }
+    }
+    if scored.is_empty() {
+        return Err(Error::Failed(String::from("no view close enough to the boundary was found, try a larger --view-width or smaller zooms")));
+    }
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(count);
+    reporter.message(&format!("Rendering the {} most detailed of {} views...", scored.len(), candidates));
+
+    reporter.start();
+    let thumbnail_pixels = settings.width * settings.height;
+    let total_pixels = scored.len() * thumbnail_pixels;
+    let mut thumbnails = Vec::new();
+    let mut summary = Vec::new();
+    let mut image_settings = image_settings;
+    for (index, (score, view)) in scored.iter().enumerate() {
+        let image = render_with_progress(view, |progress| reporter.update(&RenderProgress {
+            finished_pixels: index * thumbnail_pixels + progress.finished_pixels,
+            total_pixels,
+            ..progress.clone()
+        }));
+        let path = format!("{}/{:02}.png", directory, index + 1);
+        image_settings.text = render_metadata(view);
+        save_image_with(&image, &path, &image_settings)?;
+        Location::from_settings(view).save(&format!("{}/{:02}.kfr", directory, index + 1))?;
+        thumbnails.push(image);
+        summary.push(format!("{:02}: score {:.2}, center {}{:+}i, zoom {:.3e}", index + 1, score,
+            view.center.x.to_real(), view.center.y.to_real(), DEFAULT_VIEW_WIDTH / view.view_size.x));
+    }
+    reporter.finish();
+    for line in &summary {
+        reporter.message(line);
+    }
+    let columns = (thumbnails.len() as Real).sqrt().ceil() as usize;
+    save_image(&contact_sheet(&thumbnails, columns, 4, Color {r: 0.0, g: 0.0, b: 0.0, a: 1.0}), &sheet_path)?;
+    reporter.message(&format!("Saved thumbnails, location files and the contact sheet to {}", directory));
+    Ok(())
+}
+
+// Options of sweep that differ from the render command, later options override them
+const SWEEP_DEFAULTS: [&str; 6] = ["--width", "160", "--height", "160", "--output", "output/sweep.png"];
+
+fn sweep_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let defaults: Vec<String> = SWEEP_DEFAULTS.iter().map(|arg| String::from(*arg)).collect();
+    let args: Vec<String> = defaults.into_iter().chain(args.iter().cloned()).collect();
+    let (mut x_axis, mut y_axis) = (None, None);
+    let mut rest = Vec::new();
+    let mut flags = Flags::new(&args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "-h" | "--help" => return print_usage(),
+            "--x" => x_axis = Some(flags.value(flag).and_then(parse_sweep_axis).map_err(Error::Usage)?),
+            "--y" => y_axis = Some(flags.value(flag).and_then(parse_sweep_axis).map_err(Error::Usage)?),
+            _ => rest.push(String::from(flag)),
+        }
+    }
+    let x_axis = x_axis.ok_or_else(|| Error::Usage(String::from("sweep needs --x <option>=<from>:<to>:<count>")))?;
+    let rows = y_axis.as_ref().map_or(1, |axis| axis.values.len());
+    let columns = x_axis.values.len();
+
+    // Settings of every cell, row by row, all errors show before rendering
+    let mut cells = Vec::with_capacity(rows * columns);
+    let mut sheet_output = None;
+    for row in 0..rows {
+        for column in 0..columns {
+            let mut options = Options::new();
+            let mut flags = Flags::new(&rest);
+            while let Some(flag) = flags.next_flag() {
+                options.apply(flag, &mut flags).map_err(Error::Usage)?;
+            }
+            let axes = [Some((&x_axis, column)), y_axis.as_ref().map(|axis| (axis, row))];
+            for (axis, index) in axes.iter().flatten() {
+                axis.apply(&mut options, *index).map_err(Error::Usage)?;
+            }
+            let (mut settings, output, image_settings) = options.finish().map_err(Error::Usage)?;
+            for (axis, index) in axes.iter().flatten() {
+                axis.apply_julia(&mut settings, *index).map_err(Error::Usage)?;
+            }
+            let labels: Vec<String> = axes.iter().flatten().map(|(axis, index)| axis.label(*index)).collect();
+            sheet_output.get_or_insert((output.unwrap_or_default(), image_settings));
+            cells.push((settings, labels));
+        }
+    }
+    let (output, image_settings) = sheet_output.unwrap_or_default();
+    prepare_output(&output)?;
+    // Every cell gets the same sample positions
+    let seed = cells[0].0.seed.unwrap_or_else(rand::random);
+
+    reporter.message(&format!("Rendering {} images...", cells.len()));
+    reporter.start();
+    let total_pixels: usize = cells.iter().map(|(settings, _)| settings.width * settings.height).sum();
+    let mut finished_pixels = 0;
+    let mut images = Vec::with_capacity(cells.len());
+    for (settings, _) in cells.iter_mut() {
+        settings.seed = Some(seed);
+        let image = render_with_progress(settings, |progress| reporter.update(&RenderProgress {
+            finished_pixels: finished_pixels + progress.finished_pixels,
+            total_pixels,
+            ..progress.clone()
+        }));
+        finished_pixels += settings.width * settings.height;
+        images.push(image);
+    }
+    reporter.finish();
+    let labels: Vec<Vec<String>> = cells.into_iter().map(|(_, labels)| labels).collect();
+    let sheet = labeled_contact_sheet(&images, &labels, columns, 4, Color {r: 0.0, g: 0.0, b: 0.0, a: 1.0}, Color {r: 1.0, g: 1.0, b: 1.0, a: 1.0});
+    save_image_with(&sheet, &output, &image_settings)?;
+    reporter.message(&format!("Saved the contact sheet to {}", output));
+    Ok(())
+}
+
+// Options of serve that differ from the render command, later options override them
+const SERVE_DEFAULTS: [&str; 6] = ["--center", "-0.5", "--zoom", "1", "--auto-iterations", "true"];
+
+fn serve_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let mut options = Options::new();
+    let mut address = String::from("127.0.0.1:8080");
+    let mut cache = String::from("output/tile_cache");
+    let mut tile_size = DEFAULT_TILE_SIZE;
+    let defaults: Vec<String> = SERVE_DEFAULTS.iter().map(|arg| String::from(*arg)).collect();
+    let args: Vec<String> = defaults.into_iter().chain(args.iter().cloned()).collect();
+    let mut flags = Flags::new(&args);
+    while let Some(flag) = flags.next_flag() {
+        let parsed = match flag {
+            "-h" | "--help" => return print_usage(),
+            "--address" => flags.value(flag).map(|value| address = String::from(value)),
+            "--cache" => flags.value(flag).map(|value| cache = String::from(value)),
+            "--tile-size" => parse_value(flag, flags.value(flag)?).map(|value| tile_size = value),
+            _ => options.apply(flag, &mut flags),
+        };
+        parsed.map_err(Error::Usage)?;
+    }
+    if tile_size == 0 {
+        return Err(Error::Usage(String::from("tile size must be positive")));
+    }
+    // Tile 0/0/0 is a square of the view width
+    options.settings.width = tile_size;
+    options.settings.height = tile_size;
+    let (settings, _, image_settings) = options.finish().map_err(Error::Usage)?;
+    let scene = write_toml(&settings_to_pairs(&settings, ""));
+    let server = serve::TileServer::new(&settings, &image_settings, &cache, &scene);
+    Ok(server.run(&address, reporter)?)
+}
+
+fn coordinator_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let mut options = Options::new();
+    let mut address = String::from("0.0.0.0:7070");
+    let mut job_tiles = 32;
+    let mut job_timeout = parse_duration("30m")?;
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        let parsed = match flag {
+            "-h" | "--help" => return print_usage(),
+            "--listen" => flags.value(flag).map(|value| address = String::from(value)),
+            "--job-tiles" => parse_value(flag, flags.value(flag)?).map(|value| job_tiles = value),
+            "--job-timeout" => flags.value(flag).and_then(parse_duration).map(|value| job_timeout = value),
+            _ => options.apply(flag, &mut flags),
+        };
+        parsed.map_err(Error::Usage)?;
+    }
+    if job_tiles == 0 {
+        return Err(Error::Usage(String::from("tiles per job must be positive")));
+    }
+    let (mut settings, output, mut image_settings) = options.finish().map_err(Error::Usage)?;
+    let output = output.unwrap_or_else(|| String::from("output/image.png"));
+    if settings.supersample > 1 || settings.post.needs_neighbours() {
+        return Err(Error::Usage(String::from("distributed renders don't support --supersample, sharpening or bloom")));
+    }
+    prepare_output(&output)?;
+    // The workers need the same seed to take the samples of one image
+    if settings.seed.is_none() {
+        settings.seed = Some(rand::random());
+    }
+    image_settings.text = render_metadata(&settings);
+    let coordinator = distributed::Coordinator::new(&settings, job_tiles, job_timeout);
+    reporter.start();
+    if image_settings.format_for(&output) == ImageFormat::Png {
+        let mut png = PngStream::new(&output, settings.width, settings.height, &image_settings)?;
+        coordinator.run(&address, reporter, &mut |band| png.write_band(band))?;
+        png.finish()?;
+        reporter.finish();
+    }
+    else {
+        // Other formats are written at once
+        let mut image = ImageBuffer::new(settings.width, settings.height);
+        let mut first_row = 0;
+        coordinator.run(&address, reporter, &mut |band| {
+            image.pixels[first_row * settings.width..(first_row + band.height) * settings.width].copy_from_slice(&band.pixels);
+            first_row += band.height;
+            Ok(())
+        })?;
+        reporter.finish();
+        save_image_with(&image, &output, &image_settings)?;
+    }
+    reporter.message(&format!("Saved buffer to {}", output));
+    Ok(())
+}
+
+fn worker_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let mut address = None;
+    let mut thread_count = None;
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        let parsed = match flag {
+            "-h" | "--help" => return print_usage(),
+            "--connect" => flags.value(flag).map(|value| address = Some(String::from(value))),
+            "--threads" => parse_value(flag, flags.value(flag)?).map(|value| thread_count = Some(value)),
+            _ => Err(format!("unknown worker option '{}'", flag)),
+        };
+        parsed.map_err(Error::Usage)?;
+    }
+    let address = address.ok_or_else(|| Error::Usage(String::from("worker needs --connect <host:port>")))?;
+    if thread_count == Some(0) {
+        return Err(Error::Usage(String::from("thread count must be positive")));
+    }
+    Ok(distributed::run_worker(&address, thread_count, reporter)?)
+}
+
+// Parses <width>x<height>
+fn parse_size(text: &str) -> Result<(usize, usize), String> {
+    let (width, height) = text.split_once('x').ok_or_else(|| format!("invalid size '{}', expected <width>x<height>", text))?;
+    Ok((parse_value("--size", width)?, parse_value("--size", height)?))
+}
+
+fn bench_command(args: &[String]) -> Result<(), Error> {
+    let mut options = bench::BenchOptions::new();
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        let parsed = match flag {
+            "-h" | "--help" => return print_usage(),
+            "--size" => flags.value(flag).and_then(parse_size).map(|(width, height)| {
+                options.width = width;
+                options.height = height;
+            }),
+            "--samples" => parse_value(flag, flags.value(flag)?).map(|value| options.samples = value),
+            "--scene" => flags.value(flag).map(|value| options.scenes.push(String::from(value))),
+            "--max-threads" => parse_value(flag, flags.value(flag)?).map(|value| options.max_threads = value),
+            "--repeat" => parse_value(flag, flags.value(flag)?).map(|value| options.repeat = value),
+            _ => Err(format!("unknown bench option '{}'", flag)),
+        };
+        parsed.map_err(Error::Usage)?;
+    }
+    if options.width == 0 || options.height == 0 || options.samples == 0 || options.max_threads == 0 {
+        return Err(Error::Usage(String::from("size, samples and threads must be positive")));
+    }
+    bench::run(&options).map_err(Error::Usage)
+}
+
+// Options of locate that differ from the render command, later options override them
+const LOCATE_DEFAULTS: [&str; 2] = ["--iterations", "10000"];
+
+// Prints the nucleus of the minibrot of lowest period in the view and the command line
+// options of a view framing it
+fn locate_command(args: &[String]) -> Result<(), Error> {
+    let mut options = Options::new();
+    let defaults: Vec<String> = LOCATE_DEFAULTS.iter().map(|arg| String::from(*arg)).collect();
+    let args: Vec<String> = defaults.into_iter().chain(args.iter().cloned()).collect();
+    let mut flags = Flags::new(&args);
+    while let Some(flag) = flags.next_flag() {
+        if flag == "-h" || flag == "--help" {
+            return print_usage();
+        }
+        options.apply(flag, &mut flags).map_err(Error::Usage)?;
+    }
+    let (settings, _, _) = options.finish().map_err(Error::Usage)?;
+    let minibrot = Minibrot::locate(&settings)?;
+    // Enough digits to tell the pixels of a view framing it apart
+    let digits = (-minibrot.size.log10()).max(0.0).ceil() as usize + 10;
+    let (re, im) = (format_digits(&minibrot.nucleus.x, digits), format_digits(&minibrot.nucleus.y, digits));
+    let sign = if im.starts_with('-') { "" } else { "+" };
+    println!("Period: {}", minibrot.period);
+    println!("Nucleus: {}{}{}i", re, sign, im);
+    println!("Size: {:.6e}", minibrot.size);
+    println!("Angle: {:.2} degrees", minibrot.angle);
+    println!("View: --center {}{}{}i --zoom {:.6e}", re, sign, im, 1.0 / minibrot.size);
+    Ok(())
+}
+
+// Orbit values probe prints before the escape
+const PROBE_ORBIT_TAIL: usize = 8;
+
+fn probe_command(args: &[String]) -> Result<(), Error> {
+    let (pixel, rest) = match args {
+        [x, y, rest @ ..] if !x.starts_with('-') && !y.starts_with('-') => {
+            let x: usize = parse_value("the pixel column", x).map_err(Error::Usage)?;
+            let y: usize = parse_value("the pixel row", y).map_err(Error::Usage)?;
+            (Some((x, y)), rest)
+        }
+        _ => (None, args),
+    };
+    let mut point = None;
+    let mut options = Options::new();
+    let mut flags = Flags::new(rest);
+    while let Some(flag) = flags.next_flag() {
+        let parsed = match flag {
+            "-h" | "--help" => return print_usage(),
+            "--complex" => flags.value(flag).and_then(parse_complex).map(|value| point = Some(value)),
+            _ => options.apply(flag, &mut flags),
+        };
+        parsed.map_err(Error::Usage)?;
+    }
+    let (mut settings, _, _) = options.finish().map_err(Error::Usage)?;
+    let offset = match (pixel, point) {
+        (Some(_), Some(_)) => return Err(Error::Usage(String::from("probe takes either a pixel or --complex, not both"))),
+        (None, None) => return Err(Error::Usage(String::from("probe needs the x y of a pixel or --complex re,im"))),
+        (Some((x, y)), None) => {
+            if x >= settings.width || y >= settings.height {
+                return Err(Error::Usage(format!("pixel {} {} is outside the {}x{} image", x, y, settings.width, settings.height)));
+            }
+            println!("Pixel: {} {}", x, y);
+            settings.pixel_to_offset(x as Real + 0.5, y as Real + 0.5)
+        }
+        (None, Some(point)) => {
+            settings.center = point;
+            Vec2::<Real>{x: 0.0, y: 0.0}
+        }
+    };
+    settings.track_derivative = true;
+    // The period and interior distance are only found for interior colorings that need them
+    if !settings.interior.needs_period() {
+        settings.interior = Interior::Distance;
+    }
+    let result = escape_time_at(&settings, settings.effective_precision(), settings.arbitrary_limbs(), offset);
+    let digits = (-settings.pixel_size().log10()).max(0.0).ceil() as usize + 3;
+    let (re, im) = (settings.center.x + BigFloat::from_real(offset.x), settings.center.y + BigFloat::from_real(offset.y));
+    let format_complex = |c: Complex<Real>| format!("{}{:+}i", c.r, c.i);
+    println!("Point: {},{}", format_digits(&re, digits), format_digits(&im, digits));
+    if result.iterations < settings.max_iterations {
+        println!("Iterations: {} (escaped)", result.iterations);
+        println!("Smooth: {}", smooth_iterations(&result, settings.fractal.degree(settings.power)));
+        let distance = distance_estimate(&result);
+        println!("Distance estimate: {:e} ({:.3} pixels)", distance, distance / settings.pixel_size());
+    }
+    else {
+        println!("Iterations: {} (limit reached, interior)", result.iterations);
+        if result.period > 0 {
+            println!("Period: {}", result.period);
+            println!("Interior distance: {:e}", result.interior_distance);
+        }
+        else {
+            println!("Period: no cycle found within the iteration limit");
+        }
+    }
+    println!("Final z: {} (|z| = {})", format_complex(result.z), result.z.length());
+    println!("Derivative: {}", format_complex(result.derivative));
+    if let Ok(orbit) = orbit_at(&settings, offset) {
+        let first = orbit.len().saturating_sub(PROBE_ORBIT_TAIL);
+        println!("Orbit:");
+        for (iteration, z) in orbit.iter().enumerate().skip(first) {
+            println!("    z{} = {}", iteration, format_complex(*z));
+        }
+    }
+    Ok(())
+}
+
+fn rays_command(args: &[String]) -> Result<(), Error> {
+    let mut angles: Vec<ExternalAngle> = Vec::new();
+    let mut points = Vec::new();
+    let mut depth = DEFAULT_RAY_DEPTH;
+    let mut max_iterations: u32 = 10000;
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        let parsed = match flag {
+            "-h" | "--help" => return print_usage(),
+            "--angle" => parse_value(flag, flags.value(flag)?).map(|angle| angles.push(angle)),
+            "--point" => flags.value(flag).and_then(parse_complex).map(|point| points.push(point)),
+            "--depth" => parse_value(flag, flags.value(flag)?).map(|value| depth = value),
+            "--iterations" => parse_value(flag, flags.value(flag)?).map(|value| max_iterations = value),
+            _ => Err(format!("unknown rays option '{}'", flag)),
+        };
+        parsed.map_err(Error::Usage)?;
+    }
+    if angles.is_empty() && points.is_empty() {
+        return Err(Error::Usage(String::from("rays needs an --angle to trace or a --point to find the external angle of")));
+    }
+    for angle in &angles {
+        println!("# Ray {} = {}", angle, angle.binary());
+        for c in trace_ray(angle, depth) {
+            println!("{} {}", c.r, c.i);
+        }
+        println!();
+    }
+    for point in &points {
+        let c = Complex::new(point.x.to_real(), point.y.to_real());
+        let digits = external_angle(c, max_iterations)?;
+        let binary: String = digits.iter().map(|&digit| if digit { '1' } else { '0' }).collect();
+        let turns = digits.iter().rev().fold(0.0, |value: Real, &digit| (value + if digit { 1.0 } else { 0.0 }) / 2.0);
+        println!("Point: {}{:+}i", c.r, c.i);
+        println!("Angle: .{} (binary)", binary);
+        println!("Angle: {:.15} turns", turns);
+    }
+    Ok(())
+}
+
+// Options of zoom-path that differ from the render command, later options override them
+const ZOOM_PATH_DEFAULTS: [&str; 8] = ["--center", "-0.5", "--zoom", "1", "--auto-iterations", "true", "--output", "output/zoom_path.toml"];
+
+fn zoom_path_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let mut options = Options::new();
+    let mut end_zoom: Real = 1e10;
+    let mut zoom_factor: Real = 2.0;
+    let defaults: Vec<String> = ZOOM_PATH_DEFAULTS.iter().map(|arg| String::from(*arg)).collect();
+    let args: Vec<String> = defaults.into_iter().chain(args.iter().cloned()).collect();
+    let mut flags = Flags::new(&args);
+    while let Some(flag) = flags.next_flag() {
+        let parsed = match flag {
+            "-h" | "--help" => return print_usage(),
+            "--end-zoom" => parse_value(flag, flags.value(flag)?).map(|value| end_zoom = value),
+            "--zoom-factor" => parse_value(flag, flags.value(flag)?).map(|value| zoom_factor = value),
+            _ => options.apply(flag, &mut flags),
+        };
+        parsed.map_err(Error::Usage)?;
+    }
+    let (mut settings, output, _) = options.finish().map_err(Error::Usage)?;
+    let output = output.unwrap_or_default();
+    let end_view_width = zoom_to_view_width(end_zoom).map_err(Error::Usage)?;
+    if zoom_factor <= 1.0 {
+        return Err(Error::Usage(String::from("zoom factor must be above 1")));
+    }
+    if end_view_width >= settings.view_size.x {
+        return Err(Error::Usage(String::from("--end-zoom must be deeper than the start view")));
+    }
+    prepare_output(&output)?;
+
+    // Every step zooms in on the part of the current view with the most detail
+    let steps = (settings.view_size.x / end_view_width).ln() / zoom_factor.ln();
+    let steps = steps.ceil() as usize;
+    let aspect_ratio = settings.view_size.y / settings.view_size.x;
+    let rotation = Some(settings.rotation).filter(|&rotation| rotation != 0.0);
+    let mut keyframes = vec![Keyframe {rotation, ..Keyframe::new(settings.center, settings.view_size.x)}];
+    for step in 0..steps {
+        settings.pan(detail_offset(&settings));
+        let view_width = (settings.view_size.x / zoom_factor).max(end_view_width);
+        settings.view_size = Vec2::<Real>{x: view_width, y: view_width * aspect_ratio};
+        settings.update_auto_iterations();
+        keyframes.push(Keyframe::new(settings.center, view_width));
+        reporter.message(&format!("Step {}/{}: zoom {:.3e}", step + 1, steps, DEFAULT_VIEW_WIDTH / view_width));
+    }
+    let tables: Vec<Vec<(String, String)>> = keyframes.iter().map(keyframe_pairs).collect();
+    let text = format!("# Zoom path, render it with: animate --path {}\n{}", output, write_toml_tables("keyframe", &tables));
+    std::fs::write(&output, text).map_err(|e| format!("cannot write {}: {}", output, e))?;
+    reporter.message(&format!("Saved {} keyframes to {}", keyframes.len(), output));
+    Ok(())
+}
+
+// A scene of the batch command
+struct Job {
+    settings: RenderSettings,
+    output: String,
+    image_settings: ImageSettings,
+}
+
+fn batch_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let (path, rest) = match args.split_first() {
+        Some((path, rest)) if !path.starts_with('-') => (path, rest),
+        _ if args.iter().any(|arg| arg == "-h" || arg == "--help") => return print_usage(),
+        _ => return Err(Error::Usage(String::from("missing the job list path"))),
+    };
+    // Every job is validated before the first one renders, command line options override the jobs
+    let mut jobs: Vec<Job> = Vec::new();
+    for (index, pairs) in parse_job_file(path)?.into_iter().enumerate() {
+        let mut job_args = Vec::new();
+        for (key, value) in pairs {
+            job_args.push(format!("--{}", key));
+            job_args.push(value);
+        }
+        job_args.extend_from_slice(rest);
+        let (settings, output, image_settings) = match parse_render(&job_args).map_err(|e| Error::Usage(format!("job {}: {}", index + 1, e)))? {
+            Some(arguments) => arguments,
+            None => return print_usage(),
+        };
+        if let Some(other) = jobs.iter().position(|job| job.output == output) {
+            return Err(Error::Usage(format!("jobs {} and {} both write {}", other + 1, index + 1, output)));
+        }
+        jobs.push(Job {settings, output, image_settings});
+    }
+    for job in &jobs {
+        prepare_output(&job.output)?;
+    }
+
+    interrupt::install();
+    let total_pixels: usize = jobs.iter().map(|job| job.settings.width * job.settings.height).sum();
+    let mut finished_pixels = 0;
+    let mut failed = 0;
+    let job_count = jobs.len();
+    reporter.message(&format!("Rendering {} jobs...", job_count));
+    for (index, job) in jobs.iter_mut().enumerate() {
+        let Job {settings, output, image_settings} = job;
+        if settings.seed.is_none() {
+            settings.seed = Some(rand::random());
+        }
+        settings.interrupt = Some(&interrupt::INTERRUPTED);
+        image_settings.text = render_metadata(settings);
+        // The bar shows the progress of all jobs, the label the one of the current job
+        let job_pixels = settings.width * settings.height;
+        reporter.start();
+        let image = render_with_progress(settings, |progress| {
+            reporter.set_label(Some(format!("job {}/{} {}%", index + 1, job_count, progress.percent())));
+            reporter.update(&RenderProgress {
+                finished_pixels: finished_pixels + progress.finished_pixels * job_pixels / progress.total_pixels.max(1),
+                total_pixels,
+                ..progress.clone()
+            });
+        });
+        finished_pixels += job_pixels;
+        reporter.finish();
+        // A failed job doesn't stop the others
+        match save_render(&image, output, image_settings, None) {
+            Ok(()) => reporter.message(&format!("Saved job {}/{} to {}", index + 1, job_count, output)),
+            Err(message) => {
+                eprintln!("error: job {}: {}", index + 1, message);
+                failed += 1;
+            }
+        }
+        if interrupt::interrupted() {
+            reporter.message(&format!("Interrupted, saved the partial image of job {}, the remaining jobs were skipped", index + 1));
+            return Err(Error::Interrupted);
+        }
+    }
+    if failed > 0 {
+        return Err(Error::Failed(format!("{} of {} jobs failed", failed, job_count)));
+    }
+    reporter.message(&format!("Rendered {} jobs", job_count));
+    Ok(())
+}
+
+// Waits for scene and job files in a drop folder, renders them next to it and moves them to
+// its done folder, or to its failed folder with the error in <name>.error
+fn watch_command(args: &[String], reporter: &mut Reporter) -> Result<(), Error> {
+    let (directory, args) = match args.split_first() {
+        Some((directory, rest)) if !directory.starts_with('-') => (directory, rest),
+        _ if args.iter().any(|arg| arg == "-h" || arg == "--help") => return print_usage(),
+        _ => return Err(Error::Usage(String::from("missing the directory to watch"))),
+    };
+    let mut interval = parse_duration("2s")?;
+    let mut once = false;
+    let mut rest = Vec::new();
+    let mut flags = Flags::new(args);
+    while let Some(flag) = flags.next_flag() {
+        match flag {
+            "-h" | "--help" => return print_usage(),
+            "--interval" => interval = flags.value(flag).and_then(parse_duration).map_err(Error::Usage)?,
+            "--once" => once = true,
+            _ => rest.push(String::from(flag)),
+        }
+    }
+    // Options for every job, checked before waiting
+    parse_render(&rest).map_err(Error::Usage)?;
+    if !Path::new(directory).is_dir() {
+        return Err(Error::Usage(format!("{} is not a directory", directory)));
+    }
+    for folder in ["done", "failed"] {
+        let path = Path::new(directory).join(folder);
+        std::fs::create_dir_all(&path).map_err(|e| format!("cannot create {}: {}", path.display(), e))?;
+    }
+
+    interrupt::install();
+    reporter.message(&format!("Watching {} for .toml and .json job files", directory));
+    loop {
+        // Files still being written are left for the next scan
+        let settle = if once { Duration::ZERO } else { interval };
+        for path in waiting_job_files(Path::new(directory), settle)? {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            reporter.message(&format!("Rendering {}", name));
+            let (folder, message) = match render_job_file(&path, &rest, reporter) {
+                Ok(outputs) => ("done", format!("Saved {}", outputs.join(", "))),
+                Err(Error::Interrupted) => {
+                    reporter.message(&format!("Interrupted, {} stays in {}", name, directory));
+                    return Err(Error::Interrupted);
+                }
+                Err(Error::Usage(message)) | Err(Error::Failed(message)) => {
+                    eprintln!("error: {}: {}", name, message);
+                    let error_path = Path::new(directory).join("failed").join(format!("{}.error", name));
+                    std::fs::write(&error_path, format!("{}\n", message)).map_err(|e| format!("cannot write {}: {}", error_path.display(), e))?;
+                    ("failed", format!("Moved {} to the failed folder", name))
                 }
-                pixel_color.add(COLOR_PALETTE[(iterations%16) as usize]);
+            };
+            let moved = Path::new(directory).join(folder).join(&name);
+            std::fs::rename(&path, &moved).map_err(|e| format!("cannot move {} to {}: {}", path.display(), moved.display(), e))?;
+            reporter.message(&message);
+        }
+        if once {
+            return Ok(());
+        }
+        let start_time = Instant::now();
+        while start_time.elapsed() < interval {
+            if interrupt::interrupted() {
+                return Err(Error::Interrupted);
             }
-            pixel_color.divide(desc.sample_count as Real);
-            temp_color_buffer[y * desc.thread_size.x + x] = pixel_color;
+            thread::sleep(Duration::from_millis(100).min(interval));
         }
     }
+}
 
-    // copy the temporary color buffer after locking the color_buffer mutex
-    let mut cb = color_buffer.lock().unwrap();
-    for y in 0..desc.thread_size.y {
-        for x in 0..desc.thread_size.x {
-            cb[(y+desc.offset.y) * desc.color_buffer_size.x + (x+desc.offset.x)] = temp_color_buffer[y * desc.thread_size.x + x];
+// Job and scene files of the directory not modified for settle, the oldest first
+fn waiting_job_files(directory: &Path, settle: Duration) -> Result<Vec<PathBuf>, Error> {
+    let entries = std::fs::read_dir(directory).map_err(|e| format!("cannot read {}: {}", directory.display(), e))?;
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        if name.starts_with('.') || (extension != "toml" && extension != "json") {
+            continue;
+        }
+        let modified = match entry.metadata() {
+            Ok(metadata) if metadata.is_file() => metadata.modified().ok(),
+            _ => continue,
+        };
+        if settle.is_zero() || modified.and_then(|time| time.elapsed().ok()).is_some_and(|age| age >= settle) {
+            files.push((modified, path));
         }
     }
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
 
-    finishing_sender.send(id).unwrap();
+// Renders a scene or job list file with the render options of the watch command, the images
+// go next to the file named after it. Returns the output paths.
+fn render_job_file(path: &Path, rest: &[String], reporter: &mut Reporter) -> Result<Vec<String>, Error> {
+    let path_text = path.to_string_lossy();
+    // A scene file holds one job, a job list [[job]] tables
+    let entries = match parse_config_file(&path_text) {
+        Ok(pairs) => vec![pairs],
+        Err(_) => parse_job_file(&path_text)?,
+    };
+    let stem = path.with_extension("").to_string_lossy().into_owned();
+    let mut jobs = Vec::with_capacity(entries.len());
+    for (index, pairs) in entries.iter().enumerate() {
+        let mut job_args = Vec::new();
+        for (key, value) in pairs {
+            job_args.push(format!("--{}", key));
+            job_args.push(value.clone());
+        }
+        job_args.extend_from_slice(rest);
+        let (settings, output, image_settings) = parse_render(&job_args).map_err(|e| format!("job {}: {}", index + 1, e))?
+            .ok_or_else(|| format!("job {}: unexpected --help", index + 1))?;
+        // Only the extension of the output path of the job is kept
+        let extension = Path::new(&output).extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_else(|| String::from("png"));
+        let output = if entries.len() == 1 { format!("{}.{}", stem, extension) } else { format!("{}_{}.{}", stem, index + 1, extension) };
+        jobs.push(Job {settings, output, image_settings});
+    }
+    let job_count = jobs.len();
+    let mut outputs = Vec::with_capacity(job_count);
+    for (index, job) in jobs.iter_mut().enumerate() {
+        let Job {settings, output, image_settings} = job;
+        if settings.seed.is_none() {
+            settings.seed = Some(rand::random());
+        }
+        settings.interrupt = Some(&interrupt::INTERRUPTED);
+        image_settings.text = render_metadata(settings);
+        reporter.start();
+        let image = render_with_progress(settings, |progress| {
+            reporter.set_label(Some(format!("job {}/{}", index + 1, job_count)));
+            reporter.update(progress);
+        });
+        reporter.finish();
+        if interrupt::interrupted() {
+            return Err(Error::Interrupted);
+        }
+        save_render(&image, output, image_settings, None)?;
+        outputs.push(output.clone());
+    }
+    reporter.set_label(None);
+    Ok(outputs)
 }
 
 fn main() {
-    const BUFFER_WIDTH: usize = 1366; // 7680; // 3840; // 1366;
-    const BUFFER_HEIGHT: usize = 768; // 4320; // 2160; // 768;
-    const BUFFER_ASPECT_RATIO: Real = (BUFFER_WIDTH as Real) / (BUFFER_HEIGHT as Real);
-    const SAMPLE_COUNT: usize = 16;
-    const CENTER_X: Real = -0.7453;
-    const CENTER_Y: Real = 0.1127;
-    const VIEW_WIDTH: Real = 6.5E-4 * BUFFER_ASPECT_RATIO;
-    const VIEW_HEIGHT: Real = 6.5E-4;
-    const THREAD_WIDTH: usize = 128;
-    const THREAD_HEIGHT: usize = 128;
-    const THREAD_COUNT: usize = 4;
-
-    // Row major
-    let color_buffer = Arc::new(Mutex::new(vec![Color::new(); BUFFER_WIDTH * BUFFER_HEIGHT]));
-
-    println!("Drawing the buffer...");
-    print_progress(0);
-    let start_time = time::Instant::now();
-
-    // Fill threads descryptors
-    let mut threads_descryptors = Vec::new();
-    for y in 0..divide_roundup(BUFFER_HEIGHT, THREAD_HEIGHT) {
-        for x in 0..divide_roundup(BUFFER_WIDTH, THREAD_WIDTH) {
-            let mut new_desc = ThreadDescryptor::new();
-            new_desc.offset = Vec2::<usize>{x: x * THREAD_WIDTH, y: y * THREAD_HEIGHT};
-            let max_width = BUFFER_WIDTH - x*THREAD_WIDTH;
-            let max_height = BUFFER_HEIGHT - y*THREAD_HEIGHT;
-            new_desc.thread_size = Vec2::<usize>{x: clamp(THREAD_WIDTH, 0, max_width-1), y: clamp(THREAD_HEIGHT, 0, max_height-1)};
-            new_desc.color_buffer_size = Vec2::<usize>{x: BUFFER_WIDTH, y: BUFFER_HEIGHT};
-            new_desc.sample_count = SAMPLE_COUNT;
-            new_desc.center = Vec2::<Real>{x: CENTER_X, y:CENTER_Y};
-            new_desc.view_size = Vec2::<Real>{x: VIEW_WIDTH, y:VIEW_HEIGHT};
-            threads_descryptors.push(new_desc);
-        }
-    }
-
-    // Spawn threads
-    let mut next_thread = 0usize;
-    let mut threads = Vec::<thread::JoinHandle<()>>::new();
-    let (sender, receiver) = channel::<usize>();
-    // Spawn THREAD_COUNT thread first
-    for i in 0..min(threads_descryptors.len(), THREAD_COUNT) {
-        let descryptor = threads_descryptors[i];
-        let color_buffer_clone = color_buffer.clone();
-        let sender_clone = sender.clone();
-        threads.push(thread::spawn(move || thread_worker(color_buffer_clone, descryptor, i, sender_clone)));
-        next_thread += 1;
-    }
-    let mut finished_threads = 0usize;
-    while finished_threads < threads_descryptors.len() {
-        let finished_id = receiver.recv().unwrap();
-        finished_threads += 1;
-        let progress = finished_threads*100/threads_descryptors.len();
-        print_progress(progress as u32);
-        // print!("{}/{}   ", finished_threads, threads_descryptors.len()); // thread number
-        // Spawn a new thread if needed
-        if next_thread < threads_descryptors.len() {
-            let descryptor = threads_descryptors[next_thread];
-            let color_buffer_clone = color_buffer.clone();
-            let sender_clone = sender.clone();
-            // Replace the finished thread handle
-            threads[finished_id] = thread::spawn(move || thread_worker(color_buffer_clone, descryptor, finished_id, sender_clone));
-            next_thread += 1;
-        }
-    }
-
-    // join all threads
-    for thread in threads {
-        thread.join().unwrap();
-    }
-
-    let duration = time::Instant::now().duration_since(start_time).as_secs();
-    println!("\nFinished rendering in {}h{}m{}s", (duration/60/60), (duration/60)%60, duration%60);
-
-    let cb = color_buffer.lock().unwrap();
-    save_image(&cb, BUFFER_WIDTH, BUFFER_HEIGHT, "output/image.png");
-    println!("Saved buffer to image.png");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (command, rest) = match args.first().map(|arg| arg.as_str()) {
+        Some(command) if !command.starts_with('-') => (command, &args[1..]),
+        _ => ("render", &args[..]),
+    };
+    let rest = match expand_config(rest) {
+        Ok(rest) => rest,
+        Err(message) => fail(Error::Usage(message)),
+    };
+    // Progress options apply to every command
+    let mut mode = ProgressMode::Bar;
+    let mut command_args = Vec::new();
+    let mut flags = Flags::new(&rest);
+    while let Some(flag) = flags.next_flag() {
+        let parsed = match flag {
+            "--quiet" => Ok(ProgressMode::Quiet),
+            "--tui" => Ok(ProgressMode::Tui),
+            "--list-presets" => {
+                print_presets();
+                return;
+            }
+            "--progress" => flags.value(flag).and_then(|value| parse_value(flag, value)),
+            _ => {
+                command_args.push(String::from(flag));
+                continue;
+            }
+        };
+        match parsed {
+            Ok(parsed) => mode = parsed,
+            Err(message) => fail(Error::Usage(message)),
+        }
+    }
+    let mut reporter = Reporter::new(mode);
+    let result = match command {
+        "render" => render_command(&command_args, &mut reporter),
+        "explore" => explore_command(&command_args),
+        "animate" => animate_command(&command_args, &mut reporter),
+        "recolor" => recolor_command(&command_args, &mut reporter),
+        "info" => info_command(&command_args),
+        "rerender" => rerender_command(&command_args, &mut reporter),
+        "batch" => batch_command(&command_args, &mut reporter),
+        "watch" => watch_command(&command_args, &mut reporter),
+        "explore-random" => explore_random_command(&command_args, &mut reporter),
+        "sweep" => sweep_command(&command_args, &mut reporter),
+        "zoom-path" => zoom_path_command(&command_args, &mut reporter),
+        "locate" => locate_command(&command_args),
+        "rays" => rays_command(&command_args),
+        "probe" => probe_command(&command_args),
+        "serve" => serve_command(&command_args, &mut reporter),
+        "coordinator" => coordinator_command(&command_args, &mut reporter),
+        "worker" => worker_command(&command_args, &mut reporter),
+        "bench" => bench_command(&command_args),
+        _ => fail(Error::Usage(format!("unknown command '{}'", command))),
+    };
+    if let Err(error) = result {
+        fail(error);
+    }
 }
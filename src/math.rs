@@ -0,0 +1,213 @@
+use std::ops::{Add, Sub, Mul, Div, Neg};
+use crate::big_float::BigFloat;
+
+pub type Real = f64;
+
+// Number types the escape time iteration can run with
+pub trait Float: Copy + PartialOrd + Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> + Neg<Output=Self> {
+    fn from_real(value: Real) -> Self;
+    fn to_real(self) -> Real;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+
+    fn from_big(value: &BigFloat) -> Self {
+        Self::from_real(value.to_real())
+    }
+}
+
+impl Float for f32 {
+    fn from_real(value: Real) -> f32 {
+        value as f32
+    }
+
+    fn to_real(self) -> Real {
+        self as Real
+    }
+
+    fn sqrt(self) -> f32 {
+        f32::sqrt(self)
+    }
+
+    fn abs(self) -> f32 {
+        f32::abs(self)
+    }
+}
+
+impl Float for f64 {
+    fn from_real(value: Real) -> f64 {
+        value
+    }
+
+    fn to_real(self) -> Real {
+        self
+    }
+
+    fn sqrt(self) -> f64 {
+        f64::sqrt(self)
+    }
+
+    fn abs(self) -> f64 {
+        f64::abs(self)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Complex<T> {
+    pub r: T,
+    pub i: T,
+}
+
+impl<T: Float> Complex<T> {
+    pub fn new(r: T, i: T) -> Complex<T> {
+        Complex {r, i}
+    }
+
+    pub fn from_real(r: Real) -> Complex<T> {
+        Complex {r: T::from_real(r), i: T::from_real(0.0)}
+    }
+
+    pub fn squared(&self) -> Complex<T> {
+        Complex {r: self.r*self.r - self.i*self.i, i: T::from_real(2.0)*self.r*self.i}
+    }
+
+    pub fn conj(&self) -> Complex<T> {
+        Complex {r: self.r, i: -self.i}
+    }
+
+    pub fn to_real(&self) -> Complex<Real> {
+        Complex {r: self.r.to_real(), i: self.i.to_real()}
+    }
+
+    // Integer powers by repeated squaring keep the precision of T, others go through polar form at f64
+    pub fn powf(&self, power: Real) -> Complex<T> {
+        if power >= 1.0 && power.fract() == 0.0 && power <= u32::MAX as Real {
+            let mut exponent = power as u32;
+            let mut base = *self;
+            let mut result: Option<Complex<T>> = None;
+            while exponent > 0 {
+                if exponent & 1 != 0 {
+                    result = Some(match result { Some(value) => value * base, None => base });
+                }
+                exponent >>= 1;
+                if exponent > 0 {
+                    base = base.squared();
+                }
+            }
+            return result.unwrap_or(*self);
+        }
+        let (r, i) = (self.r.to_real(), self.i.to_real());
+        if r == 0.0 && i == 0.0 {
+            return *self;
+        }
+        let length = (r*r + i*i).sqrt().powf(power);
+        let angle = i.atan2(r) * power;
+        Complex::new(T::from_real(length * angle.cos()), T::from_real(length * angle.sin()))
+    }
+
+    // |z|^2, cheaper than length() for comparisons
+    pub fn length_squared(&self) -> T {
+        self.r*self.r + self.i*self.i
+    }
+
+    pub fn length(&self) -> T {
+        self.length_squared().sqrt()
+    }
+}
+
+impl<T: Float> Add for Complex<T> {
+    type Output = Complex<T>;
+
+    fn add(self, rhs: Complex<T>) -> Complex<T> {
+        Complex {r: self.r + rhs.r, i: self.i + rhs.i}
+    }
+}
+
+impl<T: Float> Sub for Complex<T> {
+    type Output = Complex<T>;
+
+    fn sub(self, rhs: Complex<T>) -> Complex<T> {
+        Complex {r: self.r - rhs.r, i: self.i - rhs.i}
+    }
+}
+
+impl<T: Float> Mul for Complex<T> {
+    type Output = Complex<T>;
+
+    fn mul(self, rhs: Complex<T>) -> Complex<T> {
+        Complex {r: self.r*rhs.r - self.i*rhs.i, i: self.r*rhs.i + self.i*rhs.r}
+    }
+}
+
+// Scaling by a real factor
+impl<T: Float> Mul<T> for Complex<T> {
+    type Output = Complex<T>;
+
+    fn mul(self, rhs: T) -> Complex<T> {
+        Complex {r: self.r*rhs, i: self.i*rhs}
+    }
+}
+
+impl<T: Float> Neg for Complex<T> {
+    type Output = Complex<T>;
+
+    fn neg(self) -> Complex<T> {
+        Complex {r: -self.r, i: -self.i}
+    }
+}
+
+// Division, exp and ln need operations only f64 has
+impl Div for Complex<Real> {
+    type Output = Complex<Real>;
+
+    fn div(self, rhs: Complex<Real>) -> Complex<Real> {
+        let denominator = rhs.length_squared();
+        Complex {r: (self.r*rhs.r + self.i*rhs.i) / denominator, i: (self.i*rhs.r - self.r*rhs.i) / denominator}
+    }
+}
+
+impl Complex<Real> {
+    pub fn exp(&self) -> Complex<Real> {
+        let length = self.r.exp();
+        Complex {r: length * self.i.cos(), i: length * self.i.sin()}
+    }
+
+    // Principal branch
+    pub fn ln(&self) -> Complex<Real> {
+        Complex {r: self.length().ln(), i: self.i.atan2(self.r)}
+    }
+}
+
+#[derive(Copy, Clone, Default, PartialEq)]
+pub struct Vec2<T> {
+    pub x: T,
+    pub y: T,
+}
+
+impl Vec2<usize> {
+    pub fn new() -> Vec2<usize> {
+        Vec2{x:0, y:0}
+    }
+}
+
+impl Vec2<Real> {
+    pub fn new() -> Vec2<Real> {
+        Vec2{x:0.0, y:0.0}
+    }
+}
+
+pub fn clamp<T: PartialOrd>(value: T, min: T, max: T) -> T {
+    if value < min {
+        return min;
+    }
+    else if value > max {
+        return max;
+    }
+    value
+}
+
+pub fn divide_roundup(numinator: usize, denominator: usize) -> usize {
+    if numinator.is_multiple_of(denominator) {
+        return numinator/denominator;
+    }
+    numinator/denominator+1
+}
@@ -0,0 +1,134 @@
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crate::math::Real;
+use crate::cache::IterationCache;
+use crate::export::pixel_heights;
+use crate::image::create_file;
+use crate::render::RenderSettings;
+
+// Size of the height field mesh, in the units of the file
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MeshSettings {
+    // Distance between the vertices of neighbouring pixels
+    pub scale: Real,
+    // Elevation of the highest pixel above the base, None for a tenth of the mesh width
+    pub height: Option<Real>,
+    // Thickness of the solid base below the lowest pixel, None for a fiftieth of the mesh width
+    pub base: Option<Real>,
+}
+
+impl MeshSettings {
+    pub fn new() -> MeshSettings {
+        MeshSettings {scale: 1.0, height: None, base: None}
+    }
+}
+
+impl Default for MeshSettings {
+    fn default() -> MeshSettings {
+        MeshSettings::new()
+    }
+}
+
+// Whether the extension of path names a mesh format export_mesh writes
+pub fn is_mesh_path(path: &str) -> bool {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    extension == "obj" || extension == "stl"
+}
+
+// Writes the pixels as a closed height field solid, one vertex per pixel with the logarithm of
+// its iteration count as elevation, so it can be 3D printed: .obj as a Wavefront OBJ file,
+// .stl as a binary STL file. x points right and y up the image, z up from the bottom at 0.
+pub fn export_mesh(path: &str, settings: &RenderSettings, cache: &IterationCache, mesh: &MeshSettings) -> Result<(), String> {
+    let (width, height) = (cache.width, cache.height);
+    if width < 2 || height < 2 {
+        return Err(String::from("a mesh needs an image of at least 2x2 pixels"));
+    }
+    let values = pixel_heights(settings, cache);
+    let mesh_width = (width - 1) as Real * mesh.scale;
+    let elevation = mesh.height.unwrap_or(mesh_width / 10.0);
+    let base = mesh.base.unwrap_or(mesh_width / 50.0);
+
+    // The top vertices row by row, then the bottom of the perimeter and the bottom center
+    let mut vertices = Vec::with_capacity(width * height + 2 * (width + height));
+    for y in 0..height {
+        for x in 0..width {
+            vertices.push([x as Real * mesh.scale, (height - 1 - y) as Real * mesh.scale, base + values[y * width + x] * elevation]);
+        }
+    }
+    let top = |x: usize, y: usize| y * width + x;
+    // Counterclockwise seen from above, starting at the lower left corner
+    let mut perimeter = Vec::with_capacity(2 * (width + height));
+    perimeter.extend((0..width - 1).map(|x| top(x, height - 1)));
+    perimeter.extend((1..height).rev().map(|y| top(width - 1, y)));
+    perimeter.extend((1..width).rev().map(|x| top(x, 0)));
+    perimeter.extend((0..height - 1).map(|y| top(0, y)));
+    let bottom_start = vertices.len();
+    for &index in &perimeter {
+        let [x, y, _] = vertices[index];
+        vertices.push([x, y, 0.0]);
+    }
+    let center = vertices.len();
+    vertices.push([mesh_width / 2.0, (height - 1) as Real * mesh.scale / 2.0, 0.0]);
+
+    // Counterclockwise seen from outside
+    let mut triangles = Vec::with_capacity(2 * (width - 1) * (height - 1) + 3 * perimeter.len());
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            // y grows down the image
+            let (upper_left, upper_right, lower_left, lower_right) = (top(x, y), top(x + 1, y), top(x, y + 1), top(x + 1, y + 1));
+            triangles.push([lower_left, lower_right, upper_right]);
+            triangles.push([lower_left, upper_right, upper_left]);
+        }
+    }
+    for i in 0..perimeter.len() {
+        let next = (i + 1) % perimeter.len();
+        let (top_a, top_b, bottom_a, bottom_b) = (perimeter[i], perimeter[next], bottom_start + i, bottom_start + next);
+        triangles.push([bottom_a, bottom_b, top_b]);
+        triangles.push([bottom_a, top_b, top_a]);
+        triangles.push([center, bottom_b, bottom_a]);
+    }
+
+    let error = |e: std::io::Error| format!("cannot write {}: {}", path, e);
+    let file = create_file(path)?;
+    let mut writer = BufWriter::new(file);
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    match extension.as_str() {
+        "obj" => write_obj(&mut writer, &vertices, &triangles).map_err(error)?,
+        "stl" => write_stl(&mut writer, &vertices, &triangles).map_err(error)?,
+        _ => return Err(format!("unknown mesh format of {}, expected .obj or .stl", path)),
+    }
+    writer.flush().map_err(error)
+}
+
+fn write_obj<W: Write>(writer: &mut W, vertices: &[[Real; 3]], triangles: &[[usize; 3]]) -> std::io::Result<()> {
+    for [x, y, z] in vertices {
+        writeln!(writer, "v {} {} {}", x, y, z)?;
+    }
+    // OBJ indices start at 1
+    for [a, b, c] in triangles {
+        writeln!(writer, "f {} {} {}", a + 1, b + 1, c + 1)?;
+    }
+    Ok(())
+}
+
+// 80 byte header, the triangle count and for every triangle its normal and corners as f32
+// followed by an unused attribute word
+fn write_stl<W: Write>(writer: &mut W, vertices: &[[Real; 3]], triangles: &[[usize; 3]]) -> std::io::Result<()> {
+    let mut header = [0u8; 80];
+    let title = b"height field";
+    header[..title.len()].copy_from_slice(title);
+    writer.write_all(&header)?;
+    writer.write_all(&(triangles.len() as u32).to_le_bytes())?;
+    for &[a, b, c] in triangles {
+        let (a, b, c) = (vertices[a], vertices[b], vertices[c]);
+        let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+        let normal = [u[1]*v[2] - u[2]*v[1], u[2]*v[0] - u[0]*v[2], u[0]*v[1] - u[1]*v[0]];
+        let length = (normal[0]*normal[0] + normal[1]*normal[1] + normal[2]*normal[2]).sqrt();
+        let normal = if length > 0.0 { [normal[0] / length, normal[1] / length, normal[2] / length] } else { [0.0; 3] };
+        for value in normal.iter().chain(a.iter()).chain(b.iter()).chain(c.iter()) {
+            writer.write_all(&(*value as f32).to_le_bytes())?;
+        }
+        writer.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use crate::math::{Real, Float, Complex, Vec2};
+use crate::big_float::BigFloat;
+use crate::fractal::Fractal;
+use crate::render::RenderSettings;
+
+// Newton steps towards the nucleus at each precision
+const NUCLEUS_STEPS: usize = 64;
+// Fractional bits beyond the scale of the view or the minibrot, so the nucleus is exact far
+// below the pixels of a view framing it
+const NUCLEUS_GUARD_BITS: Real = 64.0;
+
+// Minibrot of the Mandelbrot set found by Minibrot::locate, an approximate copy of the whole set
+// scaled by size and rotated by angle around its nucleus
+pub struct Minibrot {
+    // Center of the main cardioid, the point whose orbit returns to 0 after period iterations
+    pub nucleus: Vec2<BigFloat>,
+    pub period: u32,
+    // Size relative to the whole set, so a view DEFAULT_VIEW_WIDTH * size wide frames it
+    pub size: Real,
+    // Counterclockwise rotation relative to the whole set in degrees
+    pub angle: Real,
+}
+
+impl Minibrot {
+    // Finds the minibrot of lowest period within the view of settings: the period is the first
+    // iteration at which the disk around the view center covering the view is mapped around 0,
+    // Newton's method on z_period(c) = 0 then converges to its nucleus. The iteration limit of
+    // settings bounds the period.
+    pub fn locate(settings: &RenderSettings) -> Result<Minibrot, String> {
+        if settings.fractal != Fractal::Mandelbrot {
+            return Err(format!("minibrots can only be located in the mandelbrot fractal, not {}", settings.fractal));
+        }
+        let radius = settings.view_size.x.max(settings.view_size.y) / 2.0;
+        let mut limbs = limbs_for_scale(radius);
+        let mut c = Complex::new(settings.center.x.with_precision(limbs), settings.center.y.with_precision(limbs));
+        let period = ball_period(&c, radius, settings.max_iterations)
+            .ok_or_else(|| format!("no minibrot of period up to {} in the view, zoom out or raise the iteration count", settings.max_iterations))?;
+        // The nucleus is refined again whenever the minibrot turns out to need more precision
+        let mut tolerance = radius;
+        let size = loop {
+            c = nucleus(&c, period, tolerance).ok_or_else(|| format!("Newton's method doesn't find the nucleus of period {}", period))?;
+            let size = size(&c, period);
+            if !(size.length() > 0.0 && size.length().is_finite()) {
+                return Err(String::from("the size of the minibrot can't be estimated"));
+            }
+            let needed = limbs_for_scale(size.length());
+            if needed <= limbs {
+                break size;
+            }
+            limbs = needed;
+            tolerance = size.length();
+            c = Complex::new(c.r.with_precision(limbs), c.i.with_precision(limbs));
+        };
+        Ok(Minibrot {
+            nucleus: Vec2::<BigFloat>{x: c.r, y: c.i},
+            period,
+            size: size.length(),
+            angle: size.i.atan2(size.r).to_degrees(),
+        })
+    }
+}
+
+fn limbs_for_scale(scale: Real) -> usize {
+    BigFloat::limbs_for_bits((-scale.log2() + NUCLEUS_GUARD_BITS).max(0.0).ceil() as usize)
+}
+
+// First iteration at which |z| < radius * |dz/dc|: to first order the disk of the radius around
+// c then covers 0, which happens first for the period of the lowest period nucleus in the disk
+fn ball_period(c: &Complex<BigFloat>, radius: Real, max_iterations: u32) -> Option<u32> {
+    let mut z = *c;
+    let mut derivative = Complex::new(1.0, 0.0);
+    for n in 1..=max_iterations {
+        let value = z.to_real();
+        if value.length() < radius * derivative.length() {
+            return Some(n);
+        }
+        if value.length_squared() > 1e10 {
+            return None;
+        }
+        derivative = value * derivative * 2.0 + Complex::new(1.0, 0.0);
+        z = z.squared() + *c;
+    }
+    None
+}
+
+// Newton's method on z_period(c) = 0 from c, until the step is a tiny fraction of the tolerance
+fn nucleus(c: &Complex<BigFloat>, period: u32, tolerance: Real) -> Option<Complex<BigFloat>> {
+    let mut c = *c;
+    for _ in 0..NUCLEUS_STEPS {
+        let zero = BigFloat::zero().with_precision(c.r.precision());
+        let mut z = Complex::new(zero, zero);
+        let mut derivative = Complex::new(0.0, 0.0);
+        for _ in 0..period {
+            derivative = z.to_real() * derivative * 2.0 + Complex::new(1.0, 0.0);
+            z = z.squared() + c;
+        }
+        let step = z.to_real() / derivative;
+        if !(step.r.is_finite() && step.i.is_finite()) {
+            return None;
+        }
+        c = Complex::new(c.r - BigFloat::from_real(step.r), c.i - BigFloat::from_real(step.i));
+        if step.length() < tolerance * 1e-12 {
+            return Some(c);
+        }
+    }
+    None
+}
+
+// Scale and rotation of the minibrot at the nucleus relative to the whole set, 1 / (b * l^2)
+// with l the derivative of z by z over the cycle and b the sum of the inverses of its partial
+// products
+fn size(nucleus: &Complex<BigFloat>, period: u32) -> Complex<Real> {
+    let one = Complex::new(1.0, 0.0);
+    let zero = BigFloat::zero().with_precision(nucleus.r.precision());
+    // The orbit near the nucleus needs its full precision, the products don't
+    let mut z = Complex::new(zero, zero);
+    let (mut l, mut b) = (one, one);
+    for _ in 1..period {
+        z = z.squared() + *nucleus;
+        l = z.to_real() * l * 2.0;
+        b = b + one / l;
+    }
+    one / (b * l * l)
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, Complex, clamp};
+use crate::color::Color;
+use crate::render::{RenderSettings, EscapeResult};
+
+// Distance from a root at which the Newton iteration counts as converged
+const NEWTON_TOLERANCE: Real = 1e-6;
+// Smooth iteration count over which the root colors fade to half their brightness
+const NEWTON_FADE: Real = 8.0;
+// Durand-Kerner iterations finding the roots of a polynomial given by its coefficients
+const ROOT_ITERATIONS: usize = 500;
+
+// Polynomial of the Newton fractal, given either by its coefficients from the highest power
+// down, "1/0/0/-1" for z^3 - 1, or by its roots, "roots:1/-0.5+0.866i/-0.5-0.866i". Given
+// roots keep their order, found ones are sorted by their angle.
+#[derive(Clone)]
+pub struct Polynomial {
+    source: String,
+    // Highest power first
+    coefficients: Vec<Complex<Real>>,
+    roots: Vec<Complex<Real>>,
+}
+
+impl Polynomial {
+    pub fn roots(&self) -> &[Complex<Real>] {
+        &self.roots
+    }
+
+    // Value and derivative at z by Horner's scheme
+    fn evaluate(&self, z: Complex<Real>) -> (Complex<Real>, Complex<Real>) {
+        let mut value = Complex::new(0.0, 0.0);
+        let mut derivative = Complex::new(0.0, 0.0);
+        for coefficient in &self.coefficients {
+            derivative = derivative * z + value;
+            value = value * z + *coefficient;
+        }
+        (value, derivative)
+    }
+
+    // Index of the root closest to z and its distance
+    fn closest_root(&self, z: Complex<Real>) -> (usize, Real) {
+        self.roots.iter().enumerate()
+            .map(|(index, root)| (index, (z - *root).length()))
+            .fold((0, Real::INFINITY), |closest, candidate| if candidate.1 < closest.1 { candidate } else { closest })
+    }
+
+    // Runs the Newton iteration z' = z - p(z)/p'(z) from the pixel at position. Converged
+    // samples keep the point next to their root in z, the others get max_iterations.
+    pub fn escape_time(&self, settings: &RenderSettings, position: Complex<Real>) -> EscapeResult {
+        let mut z = position;
+        let mut iterations = settings.max_iterations;
+        for n in 0..settings.max_iterations {
+            if self.closest_root(z).1 < NEWTON_TOLERANCE {
+                iterations = n;
+                break;
+            }
+            let (value, derivative) = self.evaluate(z);
+            z = z - value / derivative;
+            // Critical points of p send the iteration to infinity
+            if !z.r.is_finite() || !z.i.is_finite() {
+                break;
+            }
+        }
+        EscapeResult {iterations, z, derivative: Complex::new(0.0, 0.0), trap: Real::INFINITY, trap_point: Complex::new(Real::NAN, Real::NAN), atom_domain: 0, period: 0, interior_distance: Real::INFINITY, average_sum: 0.0, average_last: 0.0}
+    }
+
+    // Color of the root the sample converged to, darker the more iterations it took. The
+    // convergence is quadratic, the distance to the root squares every iteration, which gives
+    // a continuous iteration count.
+    pub fn color(&self, settings: &RenderSettings, result: &EscapeResult) -> Color {
+        let (root, distance) = self.closest_root(result.z);
+        let color = match settings.root_colors.get(root) {
+            Some(color) => *color,
+            // Spread the roots evenly over the palette
+            None => {
+                let palette = &settings.palette;
+                palette.interpolate(root as Real * palette.colors.len() as Real / self.roots.len() as Real)
+            }
+        };
+        let ratio = clamp(distance.ln() / NEWTON_TOLERANCE.ln(), 1.0, 2.0);
+        let smooth = (result.iterations as Real - ratio.log2()).max(0.0);
+        let brightness = (0.5 as Real).powf(smooth / NEWTON_FADE);
+        Color {r: color.r * brightness, g: color.g * brightness, b: color.b * brightness, a: color.a}
+    }
+}
+
+impl FromStr for Polynomial {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Polynomial, String> {
+        let source = String::from(text.trim());
+        let (given_roots, list) = match source.strip_prefix("roots:") {
+            Some(list) => (true, list),
+            None => (false, source.as_str()),
+        };
+        let values = list.split('/').map(parse_number).collect::<Result<Vec<Complex<Real>>, String>>()?;
+        if given_roots {
+            // Expand (z - r1)(z - r2)...
+            let mut coefficients = vec![Complex::new(1.0, 0.0)];
+            for root in &values {
+                coefficients.push(Complex::new(0.0, 0.0));
+                for i in (1..coefficients.len()).rev() {
+                    coefficients[i] = coefficients[i] - coefficients[i - 1] * *root;
+                }
+            }
+            return Ok(Polynomial {source, coefficients, roots: values});
+        }
+        let first = values.iter().position(|coefficient| coefficient.length_squared() > 0.0).unwrap_or(values.len());
+        let coefficients = values[first..].to_vec();
+        if coefficients.len() < 2 {
+            return Err(format!("polynomial '{}' has no roots", text));
+        }
+        let roots = find_roots(&coefficients);
+        Ok(Polynomial {source, coefficients, roots})
+    }
+}
+
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+// "a", "bi" or "a+bi" with f64 parts
+fn parse_number(text: &str) -> Result<Complex<Real>, String> {
+    let invalid = || format!("invalid complex number '{}'", text);
+    let number = |part: &str| part.trim().parse::<Real>().map_err(|_| invalid());
+    let unit = |part: &str| match part {
+        "" | "+" => Ok(1.0),
+        "-" => Ok(-1.0),
+        imaginary => number(imaginary),
+    };
+    let text = text.trim();
+    let body = match text.strip_suffix('i') {
+        Some(body) => body,
+        None => return Ok(Complex::new(number(text)?, 0.0)),
+    };
+    // The sign separating both parts is the last one not following an exponent
+    let bytes = body.as_bytes();
+    let split = (1..bytes.len()).rev()
+        .find(|&i| (bytes[i] == b'+' || bytes[i] == b'-') && bytes[i-1] != b'e' && bytes[i-1] != b'E');
+    match split {
+        Some(i) => Ok(Complex::new(number(&body[..i])?, unit(&body[i..])?)),
+        None => Ok(Complex::new(0.0, unit(body)?)),
+    }
+}
+
+// Roots of the polynomial with the given coefficients, highest power first, by the
+// Durand-Kerner method: every estimate moves by p(z) divided by its distances to the others
+fn find_roots(coefficients: &[Complex<Real>]) -> Vec<Complex<Real>> {
+    let leading = coefficients[0];
+    let monic: Vec<Complex<Real>> = coefficients.iter().map(|coefficient| *coefficient / leading).collect();
+    let degree = monic.len() - 1;
+    // Powers of a number that is neither real nor a root of unity as the starting points
+    let seed = Complex::new(0.4, 0.9);
+    let mut roots = Vec::with_capacity(degree);
+    let mut power = Complex::new(1.0, 0.0);
+    for _ in 0..degree {
+        roots.push(power);
+        power = power * seed;
+    }
+    for _ in 0..ROOT_ITERATIONS {
+        let mut largest_step: Real = 0.0;
+        for i in 0..degree {
+            let value = monic.iter().fold(Complex::new(0.0, 0.0), |value, coefficient| value * roots[i] + *coefficient);
+            let denominator = (0..degree).filter(|&j| j != i).fold(Complex::new(1.0, 0.0), |product, j| product * (roots[i] - roots[j]));
+            let step = value / denominator;
+            if step.r.is_finite() && step.i.is_finite() {
+                roots[i] = roots[i] - step;
+                largest_step = largest_step.max(step.length());
+            }
+        }
+        if largest_step < 1e-15 {
+            break;
+        }
+    }
+    // In a fixed order for the root colors, counterclockwise from the negative real axis
+    roots.sort_by(|a, b| a.i.atan2(a.r).total_cmp(&b.i.atan2(b.r)).then(a.length().total_cmp(&b.length())));
+    roots
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::math::{Real, Complex, Vec2, clamp};
+use crate::color::{Color, Gamma};
+use crate::image::{ImageBuffer, load_png};
+
+// Shape the orbit is measured against by the orbit trap coloring
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TrapShape {
+    Point,
+    // Horizontal line through the trap position
+    Line,
+    // Circle around the trap position with the trap scale as radius
+    Circle,
+    // Horizontal and vertical lines through the trap position
+    Cross,
+}
+
+impl FromStr for TrapShape {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<TrapShape, String> {
+        match text {
+            "point" => Ok(TrapShape::Point),
+            "line" => Ok(TrapShape::Line),
+            "circle" => Ok(TrapShape::Circle),
+            "cross" => Ok(TrapShape::Cross),
+            _ => Err(format!("unknown orbit trap '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for TrapShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TrapShape::Point => "point",
+            TrapShape::Line => "line",
+            TrapShape::Circle => "circle",
+            TrapShape::Cross => "cross",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct OrbitTrap {
+    pub shape: TrapShape,
+    pub position: Vec2<Real>,
+    // Size of the trap, the color fades over this distance
+    pub scale: Real,
+}
+
+impl OrbitTrap {
+    pub fn new() -> OrbitTrap {
+        OrbitTrap {shape: TrapShape::Point, position: Vec2::<Real>::new(), scale: 0.5}
+    }
+
+    // Distance of an orbit value to the trap shape
+    pub fn distance(&self, z: &Complex<Real>) -> Real {
+        let (x, y) = (z.r - self.position.x, z.i - self.position.y);
+        match self.shape {
+            TrapShape::Point => (x*x + y*y).sqrt(),
+            TrapShape::Line => y.abs(),
+            TrapShape::Circle => ((x*x + y*y).sqrt() - self.scale).abs(),
+            TrapShape::Cross => x.abs().min(y.abs()),
+        }
+    }
+}
+
+impl Default for OrbitTrap {
+    fn default() -> OrbitTrap {
+        OrbitTrap::new()
+    }
+}
+
+// Picture of the image-trap coloring, spanning twice the trap scale horizontally around the trap
+// position with its top up the imaginary axis. The pixels are shared between the clones of the
+// settings.
+#[derive(Clone)]
+pub struct TrapImage {
+    path: String,
+    pixels: Arc<ImageBuffer>,
+}
+
+impl TrapImage {
+    pub fn load(path: &str) -> Result<TrapImage, String> {
+        let pixels = load_png(path)?;
+        if pixels.width == 0 || pixels.height == 0 {
+            return Err(format!("the trap image {} is empty", path));
+        }
+        Ok(TrapImage {path: String::from(path), pixels: Arc::new(pixels)})
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // Copy with the pixels decoded to linear light
+    pub fn decoded(&self, gamma: Gamma) -> TrapImage {
+        let mut pixels = ImageBuffer::new(self.pixels.width, self.pixels.height);
+        for (pixel, color) in pixels.pixels.iter_mut().zip(&self.pixels.pixels) {
+            *pixel = gamma.decode_color(*color);
+        }
+        TrapImage {path: self.path.clone(), pixels: Arc::new(pixels)}
+    }
+
+    // Position of an orbit value on the image in pixels
+    fn position(&self, trap: &OrbitTrap, z: &Complex<Real>) -> (Real, Real) {
+        let pixel_size = 2.0 * trap.scale / self.pixels.width as Real;
+        let x = (z.r - trap.position.x) / pixel_size + self.pixels.width as Real / 2.0;
+        let y = self.pixels.height as Real / 2.0 - (z.i - trap.position.y) / pixel_size;
+        (x, y)
+    }
+
+    // Whether an orbit value falls on a pixel that isn't fully transparent, the orbit passes
+    // through the transparent ones
+    pub fn covers(&self, trap: &OrbitTrap, z: &Complex<Real>) -> bool {
+        let (x, y) = self.position(trap, z);
+        if !(x >= 0.0 && y >= 0.0 && x < self.pixels.width as Real && y < self.pixels.height as Real) {
+            return false;
+        }
+        self.pixels.get(x as usize, y as usize).a > 0.0
+    }
+
+    // Color of the image at an orbit value, interpolated between the pixel centers
+    pub fn color(&self, trap: &OrbitTrap, z: &Complex<Real>) -> Color {
+        let (x, y) = self.position(trap, z);
+        let (last_x, last_y) = ((self.pixels.width - 1) as Real, (self.pixels.height - 1) as Real);
+        let (x, y) = (clamp(x - 0.5, 0.0, last_x), clamp(y - 0.5, 0.0, last_y));
+        let (left, top) = (x.floor() as usize, y.floor() as usize);
+        let (right, bottom) = ((left + 1).min(self.pixels.width - 1), (top + 1).min(self.pixels.height - 1));
+        let (fx, fy) = (x.fract(), y.fract());
+        let mix = |a: Color, b: Color, t: Real| Color {r: a.r + (b.r - a.r) * t, g: a.g + (b.g - a.g) * t, b: a.b + (b.b - a.b) * t, a: a.a + (b.a - a.a) * t};
+        let upper = mix(self.pixels.get(left, top), self.pixels.get(right, top), fx);
+        let lower = mix(self.pixels.get(left, bottom), self.pixels.get(right, bottom), fx);
+        mix(upper, lower, fy)
+    }
+}
+
+// Images are told apart by their path
+impl PartialEq for TrapImage {
+    fn eq(&self, other: &TrapImage) -> bool {
+        self.path == other.path
+    }
+}
@@ -0,0 +1,97 @@
+use crate::math::{Real, Float, Complex, Vec2};
+use crate::big_float::BigFloat;
+use crate::color::Color;
+use crate::image::ImageBuffer;
+use crate::render::RenderSettings;
+
+// Pixel position of a point of the complex plane in the view of settings
+pub fn point_to_pixel(settings: &RenderSettings, point: &Complex<Real>) -> Vec2<Real> {
+    settings.offset_to_pixel(settings.offset_of(&Vec2::<BigFloat>{x: BigFloat::from_real(point.r), y: BigFloat::from_real(point.i)}))
+}
+
+// Draws the points of the complex plane joined by lines of single pixels over a render of the
+// view of settings, the parts outside the image are clipped
+pub fn draw_path(image: &mut ImageBuffer, settings: &RenderSettings, points: &[Complex<Real>], color: Color) {
+    let pixels: Vec<Vec2<Real>> = points.iter().map(|point| point_to_pixel(settings, point)).collect();
+    for pair in pixels.windows(2) {
+        draw_segment(image, pair[0], pair[1], color);
+    }
+}
+
+// Draws the line between two pixel positions, returns the part of it inside the image
+pub fn draw_segment(image: &mut ImageBuffer, a: Vec2<Real>, b: Vec2<Real>, color: Color) -> Option<(Vec2<Real>, Vec2<Real>)> {
+    let (a, b) = clip(a, b, image.width as Real, image.height as Real)?;
+    draw_line(image, a, b, color);
+    Some((a, b))
+}
+
+// Draws an orbit of orbit_at as a path through its values with a dot on each
+pub fn draw_orbit(image: &mut ImageBuffer, settings: &RenderSettings, orbit: &[Complex<Real>], color: Color) {
+    draw_path(image, settings, orbit, color);
+    for z in orbit {
+        draw_dot(image, point_to_pixel(settings, z), color);
+    }
+}
+
+// Part of the segment from a to b inside [0, width) x [0, height), Liang-Barsky clipping
+pub fn clip(a: Vec2<Real>, b: Vec2<Real>, width: Real, height: Real) -> Option<(Vec2<Real>, Vec2<Real>)> {
+    if !(a.x.is_finite() && a.y.is_finite() && b.x.is_finite() && b.y.is_finite()) {
+        return None;
+    }
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let (mut start, mut end): (Real, Real) = (0.0, 1.0);
+    // Just inside the far edges so the last pixels stay in the image
+    let (right, bottom) = (width - 1e-9, height - 1e-9);
+    for &(p, q) in [(-dx, a.x), (dx, right - a.x), (-dy, a.y), (dy, bottom - a.y)].iter() {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        }
+        else if p < 0.0 {
+            start = start.max(q / p);
+        }
+        else {
+            end = end.min(q / p);
+        }
+    }
+    if start > end {
+        return None;
+    }
+    let at = |t: Real| Vec2::<Real>{x: a.x + dx * t, y: a.y + dy * t};
+    Some((at(start), at(end)))
+}
+
+fn draw_line(image: &mut ImageBuffer, a: Vec2<Real>, b: Vec2<Real>, color: Color) {
+    let steps = (b.x - a.x).abs().max((b.y - a.y).abs()).ceil().max(1.0) as usize;
+    let mut last = None;
+    for step in 0..=steps {
+        let t = step as Real / steps as Real;
+        let (x, y) = (a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t);
+        // Translucent lines would darken pixels hit twice
+        let pixel = (x.floor() as i64, y.floor() as i64);
+        if last != Some(pixel) {
+            set_pixel(image, x, y, color);
+            last = Some(pixel);
+        }
+    }
+}
+
+// 3x3 pixel square centered on the pixel
+fn draw_dot(image: &mut ImageBuffer, center: Vec2<Real>, color: Color) {
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            set_pixel(image, center.x + dx as Real, center.y + dy as Real, color);
+        }
+    }
+}
+
+// Blends the color over the pixel by its alpha
+pub fn set_pixel(image: &mut ImageBuffer, x: Real, y: Real, color: Color) {
+    if x >= 0.0 && y >= 0.0 && (x as usize) < image.width && (y as usize) < image.height {
+        let (x, y) = (x as usize, y as usize);
+        let below = image.get(x, y);
+        let mix = |over: Real, under: Real| under + (over - under) * color.a;
+        image.set(x, y, Color {r: mix(color.r, below.r), g: mix(color.g, below.g), b: mix(color.b, below.b), a: below.a + (1.0 - below.a) * color.a});
+    }
+}
@@ -0,0 +1,458 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use crate::math::{Real, clamp};
+use crate::color::Color;
+use crate::config::{JsonValue, parse_json_document};
+
+// COLOR PALLETE SOURCE: https://stackoverflow.com/a/16505538/9218594
+const DEFAULT_COLORS: [Color; 16] = [
+    Color {r:0.26, g:0.1,  b:0.06, a:1.0}, 
+    Color {r:0.1,  g:0.03, b:0.1,  a:1.0}, 
+    Color {r:0.3,  g:0.01, b:0.18, a:1.0}, 
+    Color {r:0.02, g:0.02, b:0.28, a:1.0}, 
+    Color {r:0.0,  g:0.03, b:0.4,  a:1.0}, 
+    Color {r:0.05, g:0.17, b:0.54, a:1.0}, 
+    Color {r:0.1,  g:0.3,  b:0.7,  a:1.0}, 
+    Color {r:0.25, g:0.5,  b:0.82, a:1.0}, 
+    Color {r:0.53, g:0.71, b:0.9,  a:1.0}, 
+    Color {r:0.83, g:0.93, b:0.97, a:1.0}, 
+    Color {r:0.95, g:0.91, b:0.75, a:1.0}, 
+    Color {r:0.97, g:0.78, b:0.37, a:1.0}, 
+    Color {r:1.0,  g:0.67, b:0.0,  a:1.0}, 
+    Color {r:0.8,  g:0.5,  b:0.0,  a:1.0}, 
+    Color {r:0.6,  g:0.34, b:0.0,  a:1.0}, 
+    Color {r:0.42, g:0.2,  b:0.02, a:1.0} 
+    ];
+
+// Entries a gradient with positioned stops is resampled to
+const GRADIENT_SAMPLES: usize = 256;
+
+// How a palette was created, so scene files can refer to it again
+#[derive(Clone, PartialEq, Debug)]
+pub enum PaletteSource {
+    Builtin,
+    // Colors given in code
+    Custom,
+    File(String),
+    // Procedural gradient specification, see Palette::generate
+    Generated(String),
+}
+
+// Color space neighbouring palette entries are blended in
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Interpolation {
+    // Straight lines between the linear RGB colors
+    Rgb,
+    // Perceptually even steps of lightness and color
+    Oklab,
+    // OKLab lightness and chroma with the hue turning the short way round, keeps the
+    // midpoints between complementary colors saturated
+    Oklch,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Interpolation, String> {
+        match text {
+            "rgb" => Ok(Interpolation::Rgb),
+            "oklab" => Ok(Interpolation::Oklab),
+            "oklch" => Ok(Interpolation::Oklch),
+            _ => Err(format!("unknown palette interpolation '{}', expected rgb, oklab or oklch", text)),
+        }
+    }
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Interpolation::Rgb => write!(f, "rgb"),
+            Interpolation::Oklab => write!(f, "oklab"),
+            Interpolation::Oklch => write!(f, "oklch"),
+        }
+    }
+}
+
+// A cyclic list of colors indexed by iteration count
+#[derive(Clone, PartialEq)]
+pub struct Palette {
+    pub colors: Vec<Color>,
+    pub source: PaletteSource,
+    // Rotation of the colors as a fraction of the palette length, for color cycling
+    pub offset: Real,
+    pub interpolation: Interpolation,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Color>) -> Palette {
+        Palette {colors, source: PaletteSource::Custom, offset: 0.0, interpolation: Interpolation::Rgb}
+    }
+
+    // Loads a Fractint .map, GIMP .ggr, JSON or plain text palette, picked by the extension
+    pub fn load(path: &str) -> Result<Palette, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+        let colors = match extension.as_str() {
+            "map" => parse_map(&text),
+            "ggr" => parse_ggr(&text),
+            "json" => parse_json_palette(&text),
+            _ => parse_text_palette(&text),
+        }.map_err(|e| format!("{}: {}", path, e))?;
+        if colors.is_empty() {
+            return Err(format!("{}: the palette has no colors", path));
+        }
+        Ok(Palette {colors, source: PaletteSource::File(String::from(path)), offset: 0.0, interpolation: Interpolation::Rgb})
+    }
+
+    // Builds a gradient from a specification:
+    //   sine:a/b/c/d                      a + b*cos(2pi*(c*t + d)), each parameter "r,g,b" or one value
+    //   hsv[:from,to[,saturation,value]]  hue sweep in degrees (default 0,360,1,1)
+    //   cubehelix[:start,rotations,hue,gamma]  Green's cubehelix (default 0.5,-1.5,1,1)
+    pub fn generate(spec: &str) -> Result<Palette, String> {
+        let (kind, parameters) = match spec.split_once(':') {
+            Some((kind, parameters)) => (kind.trim(), parameters.trim()),
+            None => (spec.trim(), ""),
+        };
+        let error = |e: String| format!("palette '{}': {}", spec, e);
+        let colors: Vec<Color> = match kind {
+            "sine" => {
+                let vectors = parameters.split('/').map(parse_vector).collect::<Result<Vec<_>, _>>().map_err(error)?;
+                let (a, b, c, d) = match vectors[..] {
+                    [a, b, c, d] => (a, b, c, d),
+                    _ => return Err(error(String::from("expected four parameters a/b/c/d"))),
+                };
+                gradient(|t| {
+                    let channel = |i: usize| a[i] + b[i] * (2.0 * std::f64::consts::PI * (c[i] * t + d[i])).cos();
+                    Color {r: channel(0), g: channel(1), b: channel(2), a: 1.0}
+                })
+            }
+            "hsv" => {
+                let [from, to, saturation, value] = parse_parameters(parameters, [0.0, 360.0, 1.0, 1.0]).map_err(error)?;
+                gradient(|t| hsv(from + (to - from) * t, saturation, value))
+            }
+            "cubehelix" => {
+                let [start, rotations, hue, gamma] = parse_parameters(parameters, [0.5, -1.5, 1.0, 1.0]).map_err(error)?;
+                gradient(|t| cubehelix(t, start, rotations, hue, gamma))
+            }
+            _ => return Err(format!("unknown palette generator '{}', expected sine, hsv or cubehelix", kind)),
+        };
+        let colors = colors.into_iter().map(|c| Color {r: clamp(c.r, 0.0, 1.0), g: clamp(c.g, 0.0, 1.0), b: clamp(c.b, 0.0, 1.0), a: 1.0}).collect();
+        Ok(Palette {colors, source: PaletteSource::Generated(String::from(spec)), offset: 0.0, interpolation: Interpolation::Rgb})
+    }
+
+    pub fn color(&self, index: u32) -> Color {
+        if self.offset != 0.0 {
+            return self.interpolate(index as Real);
+        }
+        self.colors[(index as usize) % self.colors.len()]
+    }
+
+    // Linear interpolation between the entries around a fractional index
+    pub fn interpolate(&self, index: Real) -> Color {
+        let length = self.colors.len();
+        let index = (index + self.offset * length as Real).rem_euclid(length as Real);
+        let lower = index.floor();
+        let t = index - lower;
+        let a = self.colors[(lower as usize) % length];
+        let b = self.colors[(lower as usize + 1) % length];
+        let mix = |a: Real, b: Real| a + (b - a) * t;
+        match self.interpolation {
+            Interpolation::Rgb => Color {r: mix(a.r, b.r), g: mix(a.g, b.g), b: mix(a.b, b.b), a: mix(a.a, b.a)},
+            Interpolation::Oklab => {
+                let (a_lab, b_lab) = (a.to_oklab(), b.to_oklab());
+                Color::from_oklab([mix(a_lab[0], b_lab[0]), mix(a_lab[1], b_lab[1]), mix(a_lab[2], b_lab[2])], mix(a.a, b.a))
+            }
+            Interpolation::Oklch => {
+                let (a_lab, b_lab) = (a.to_oklab(), b.to_oklab());
+                let (a_chroma, b_chroma) = (a_lab[1].hypot(a_lab[2]), b_lab[1].hypot(b_lab[2]));
+                // Grays have no hue, they take the hue of the other color
+                let a_hue = if a_chroma < 1e-6 { b_lab[2].atan2(b_lab[1]) } else { a_lab[2].atan2(a_lab[1]) };
+                let b_hue = if b_chroma < 1e-6 { a_hue } else { b_lab[2].atan2(b_lab[1]) };
+                let turn = 2.0 * std::f64::consts::PI;
+                let hue = a_hue + ((b_hue - a_hue + turn / 2.0).rem_euclid(turn) - turn / 2.0) * t;
+                let chroma = mix(a_chroma, b_chroma);
+                Color::from_oklab([mix(a_lab[0], b_lab[0]), chroma * hue.cos(), chroma * hue.sin()], mix(a.a, b.a))
+            }
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {colors: DEFAULT_COLORS.to_vec(), source: PaletteSource::Builtin, offset: 0.0, interpolation: Interpolation::Rgb}
+    }
+}
+
+fn rgb8(r: Real, g: Real, b: Real) -> Color {
+    Color {r: r / 255.0, g: g / 255.0, b: b / 255.0, a: 1.0}
+}
+
+fn lerp(a: &Color, b: &Color, t: Real) -> Color {
+    Color {
+        r: a.r + (b.r - a.r) * t,
+        g: a.g + (b.g - a.g) * t,
+        b: a.b + (b.b - a.b) * t,
+        a: a.a + (b.a - a.a) * t,
+    }
+}
+
+// "#rrggbb[aa]" or "r g b [a]" with 0-255 components, opaque without an alpha
+pub fn parse_color(text: &str) -> Result<Color, String> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6 || hex.len() == 8).ok_or_else(|| format!("invalid color '{}'", text))?;
+        // #rrggbbaa carries an alpha
+        let (value, alpha) = if hex.len() == 8 { (value >> 8, (value & 0xff) as Real) } else { (value, 255.0) };
+        return Ok(Color {a: alpha / 255.0, ..rgb8((value >> 16) as Real, ((value >> 8) & 0xff) as Real, (value & 0xff) as Real)});
+    }
+    let components: Vec<Real> = text.split_whitespace().map(|c| c.parse::<Real>()).collect::<Result<_, _>>().map_err(|_| format!("invalid color '{}'", text))?;
+    match components[..] {
+        [r, g, b] => Ok(rgb8(r, g, b)),
+        [r, g, b, a] => Ok(Color {a: a / 255.0, ..rgb8(r, g, b)}),
+        _ => Err(format!("invalid color '{}'", text)),
+    }
+}
+
+// Samples a gradient given as (position [0:1], color) stops sorted by position
+fn sample_stops(stops: &[(Real, Color)]) -> Vec<Color> {
+    (0..GRADIENT_SAMPLES).map(|i| {
+        let position = i as Real / GRADIENT_SAMPLES as Real;
+        let next = stops.iter().position(|(p, _)| *p > position).unwrap_or(stops.len());
+        if next == 0 {
+            return stops[0].1;
+        }
+        if next == stops.len() {
+            return stops[stops.len() - 1].1;
+        }
+        let (p0, c0) = &stops[next - 1];
+        let (p1, c1) = &stops[next];
+        lerp(c0, c1, (position - p0) / (p1 - p0))
+    }).collect()
+}
+
+// Colors evenly spread when no positions are given, otherwise a resampled gradient
+fn colors_from_stops(positions: Vec<Option<Real>>, colors: Vec<Color>) -> Result<Vec<Color>, String> {
+    if positions.iter().all(|p| p.is_none()) {
+        return Ok(colors);
+    }
+    let mut stops = Vec::with_capacity(colors.len());
+    for (position, color) in positions.into_iter().zip(colors) {
+        let position = position.ok_or_else(|| String::from("either every color or none has a position"))?;
+        stops.push((clamp(position, 0.0, 1.0), color));
+    }
+    stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    Ok(sample_stops(&stops))
+}
+
+// Fractint: one "r g b" line per entry, anything after the third number is a comment
+fn parse_map(text: &str) -> Result<Vec<Color>, String> {
+    let mut colors = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        let components: Vec<&str> = line.split_whitespace().take(3).collect();
+        let color = parse_color(&components.join(" ")).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        colors.push(color);
+    }
+    Ok(colors)
+}
+
+// One color per line, "#rrggbb[aa]" or "r g b [a]", optionally preceded by a position and ':'
+fn parse_text_palette(text: &str) -> Result<Vec<Color>, String> {
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") || line.starts_with(';') {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+        let (position, color) = match line.split_once(':') {
+            Some((position, color)) => (Some(position.trim().parse::<Real>().map_err(|_| error(format!("invalid position '{}'", position)))?), color),
+            None => (None, line),
+        };
+        positions.push(position);
+        colors.push(parse_color(color).map_err(error)?);
+    }
+    colors_from_stops(positions, colors)
+}
+
+fn json_color(value: &JsonValue) -> Result<Color, String> {
+    match value {
+        JsonValue::String(text) => parse_color(text),
+        JsonValue::Array(items) => {
+            let components: Vec<Real> = items.iter().filter_map(|item| item.as_number()).collect();
+            match components[..] {
+                [r, g, b] if items.len() == 3 => Ok(rgb8(r, g, b)),
+                [r, g, b, a] if items.len() == 4 => Ok(Color {a: a / 255.0, ..rgb8(r, g, b)}),
+                _ => Err(String::from("colors must have three or four components")),
+            }
+        }
+        _ => Err(String::from("expected a color")),
+    }
+}
+
+// An array (or {"colors": array}) of colors or {"position": p, "color": c} stops
+fn parse_json_palette(text: &str) -> Result<Vec<Color>, String> {
+    let document = parse_json_document(text)?;
+    let items = match document.get("colors").unwrap_or(&document) {
+        JsonValue::Array(items) => items,
+        _ => return Err(String::from("expected an array of colors")),
+    };
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    for item in items {
+        match item.get("color") {
+            Some(color) => {
+                let position = item.get("position").and_then(|p| p.as_number()).ok_or_else(|| String::from("stops need a numeric position"))?;
+                positions.push(Some(position));
+                colors.push(json_color(color)?);
+            }
+            None => {
+                positions.push(None);
+                colors.push(json_color(item)?);
+            }
+        }
+    }
+    colors_from_stops(positions, colors)
+}
+
+// GIMP gradient segment
+struct GgrSegment {
+    left: Real,
+    middle: Real,
+    right: Real,
+    left_color: Color,
+    right_color: Color,
+    blending: u32,
+}
+
+impl GgrSegment {
+    fn color(&self, position: Real) -> Color {
+        let width = self.right - self.left;
+        let position = if width > 0.0 { (position - self.left) / width } else { 0.0 };
+        let middle = if width > 0.0 { (self.middle - self.left) / width } else { 0.5 };
+        // Piecewise linear through the midpoint, reshaped by the blending function:
+        // 1 curved, 2 sine, 3 sphere increasing, 4 sphere decreasing, 5 step
+        let t = if position <= middle {
+            if middle > 0.0 { 0.5 * position / middle } else { 0.0 }
+        }
+        else if middle < 1.0 {
+            0.5 + 0.5 * (position - middle) / (1.0 - middle)
+        }
+        else {
+            1.0
+        };
+        let t = match self.blending {
+            1 => position.powf((0.5 as Real).ln() / middle.max(1e-10).ln()),
+            2 => ((-std::f64::consts::FRAC_PI_2 + std::f64::consts::PI * t).sin() + 1.0) / 2.0,
+            3 => (1.0 - (t - 1.0)*(t - 1.0)).sqrt(),
+            4 => 1.0 - (1.0 - t*t).sqrt(),
+            5 => if position < middle { 0.0 } else { 1.0 },
+            _ => t,
+        };
+        lerp(&self.left_color, &self.right_color, t)
+    }
+}
+
+// GIMP .ggr: a header, the segment count and one segment per line. HSV color blending
+// is approximated in RGB.
+fn parse_ggr(text: &str) -> Result<Vec<Color>, String> {
+    let mut lines = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty());
+    if lines.next() != Some("GIMP Gradient") {
+        return Err(String::from("missing the 'GIMP Gradient' header"));
+    }
+    let mut count_line = lines.next().ok_or_else(|| String::from("missing the segment count"))?;
+    if count_line.starts_with("Name:") {
+        count_line = lines.next().ok_or_else(|| String::from("missing the segment count"))?;
+    }
+    let count: usize = count_line.parse().map_err(|_| format!("invalid segment count '{}'", count_line))?;
+    let mut segments = Vec::with_capacity(count);
+    for line in lines.take(count) {
+        let values: Vec<Real> = line.split_whitespace().map(|v| v.parse::<Real>()).collect::<Result<_, _>>().map_err(|_| format!("invalid segment '{}'", line))?;
+        if values.len() < 13 {
+            return Err(format!("invalid segment '{}'", line));
+        }
+        segments.push(GgrSegment {
+            left: values[0],
+            middle: values[1],
+            right: values[2],
+            left_color: Color {r: values[3], g: values[4], b: values[5], a: values[6]},
+            right_color: Color {r: values[7], g: values[8], b: values[9], a: values[10]},
+            blending: values[11] as u32,
+        });
+    }
+    if segments.len() != count {
+        return Err(String::from("fewer segments than announced"));
+    }
+    Ok((0..GRADIENT_SAMPLES).map(|i| {
+        let position = i as Real / (GRADIENT_SAMPLES - 1) as Real;
+        let segment = segments.iter().find(|s| position <= s.right).unwrap_or(&segments[segments.len() - 1]);
+        segment.color(position)
+    }).collect())
+}
+
+// Samples f over [0:1), the palette wraps around at the end
+fn gradient<F: Fn(Real) -> Color>(f: F) -> Vec<Color> {
+    (0..GRADIENT_SAMPLES).map(|i| f(i as Real / GRADIENT_SAMPLES as Real)).collect()
+}
+
+// "r,g,b" or a single value used for every channel
+fn parse_vector(text: &str) -> Result<[Real; 3], String> {
+    let values: Vec<Real> = text.split(',').map(|v| v.trim().parse::<Real>()).collect::<Result<_, _>>().map_err(|_| format!("invalid vector '{}'", text))?;
+    match values[..] {
+        [v] => Ok([v, v, v]),
+        [r, g, b] => Ok([r, g, b]),
+        _ => Err(format!("invalid vector '{}', expected one or three values", text)),
+    }
+}
+
+// Comma separated values, missing trailing ones keep their defaults
+fn parse_parameters<const N: usize>(text: &str, defaults: [Real; N]) -> Result<[Real; N], String> {
+    let mut values = defaults;
+    if text.is_empty() {
+        return Ok(values);
+    }
+    let items: Vec<&str> = text.split(',').collect();
+    if items.len() > N {
+        return Err(format!("expected at most {} parameters", N));
+    }
+    for (value, item) in values.iter_mut().zip(items) {
+        *value = item.trim().parse().map_err(|_| format!("invalid number '{}'", item.trim()))?;
+    }
+    Ok(values)
+}
+
+// hue in degrees, saturation and value in [0:1]
+fn hsv(hue: Real, saturation: Real, value: Real) -> Color {
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    Color {r: r + m, g: g + m, b: b + m, a: 1.0}
+}
+
+// D. A. Green, "A colour scheme for the display of astronomical intensity images" (2011)
+fn cubehelix(t: Real, start: Real, rotations: Real, hue: Real, gamma: Real) -> Color {
+    let lightness = t.powf(gamma);
+    let amplitude = hue * lightness * (1.0 - lightness) / 2.0;
+    let angle = 2.0 * std::f64::consts::PI * (start / 3.0 + rotations * t);
+    let (sin, cos) = angle.sin_cos();
+    Color {
+        r: lightness + amplitude * (-0.14861 * cos + 1.78277 * sin),
+        g: lightness + amplitude * (-0.29227 * cos - 0.90649 * sin),
+        b: lightness + amplitude * (1.97294 * cos),
+        a: 1.0,
+    }
+}
@@ -0,0 +1,318 @@
+use crate::math::{Real, Float, Complex, Vec2};
+use crate::big_float::BigFloat;
+use crate::render::{RenderSettings, EscapeResult, Periodicity};
+
+// Pauldelbrot's glitch criterion: |Z+d| < GLITCH_TOLERANCE * |Z| loses the delta precision
+const GLITCH_TOLERANCE: Real = 1e-3;
+// Secondary references a single tile may create before falling back to full precision
+const MAX_SECONDARY_REFERENCES: usize = 4;
+// Powers of the initial delta in the series approximation
+const SERIES_TERMS: usize = 8;
+// Largest error of the approximated delta of a probe point relative to its iterated delta.
+// Near the boundary later iterations magnify the error a lot, larger tolerances change the
+// escape iteration of many pixels.
+const SERIES_TOLERANCE: Real = 1e-12;
+// Newton steps towards the center of a glitch and the step, in pixels, at which it stops
+const NEWTON_STEPS: usize = 16;
+const NEWTON_ACCURACY: Real = 1e-3;
+
+// Series approximation of the delta orbits: after skip iterations the delta of a point whose
+// initial delta is d is sum coefficients[k] * (d / radius)^(k + 1). Scaling by the radius of
+// the view keeps the coefficients in the f64 range at any depth.
+struct Series {
+    skip: usize,
+    radius: Real,
+    coefficients: [Complex<Real>; SERIES_TERMS],
+}
+
+impl Series {
+    // Approximated delta and its derivative for the initial delta
+    fn evaluate(&self, delta: Complex<Real>) -> (Complex<Real>, Complex<Real>) {
+        let u = delta * (1.0 / self.radius);
+        let mut value = Complex::new(0.0, 0.0);
+        let mut derivative = Complex::new(0.0, 0.0);
+        for (k, coefficient) in self.coefficients.iter().enumerate().rev() {
+            value = (value + *coefficient) * u;
+            derivative = derivative * u + *coefficient * (k + 1) as Real;
+        }
+        (value, derivative * (1.0 / self.radius))
+    }
+}
+
+// High precision orbit of a reference point stored at f64 precision, only for z^2 + c
+pub struct ReferenceOrbit {
+    // Offset of the reference point from the view center
+    offset: Vec2<Real>,
+    orbit: Vec<Complex<Real>>,
+    // Iterations the points around the reference can skip
+    series: Option<Series>,
+}
+
+impl ReferenceOrbit {
+    // Iterates center + offset with the given number of BigFloat limbs
+    pub fn new(settings: &RenderSettings, offset: Vec2<Real>, limbs: usize) -> ReferenceOrbit {
+        let center = &settings.center;
+        let limit = settings.bailout.limit(settings.max_length());
+        let position = Complex::new(
+            (center.x + BigFloat::from_real(offset.x)).with_precision(limbs),
+            (center.y + BigFloat::from_real(offset.y)).with_precision(limbs));
+        let zero = BigFloat::zero().with_precision(limbs);
+        let (mut z, c) = if settings.fractal.is_julia() {
+            (position, Complex::new(BigFloat::from_real(settings.julia_c.x), BigFloat::from_real(settings.julia_c.y)))
+        }
+        else {
+            (Complex::new(zero, zero), position)
+        };
+        let mut orbit = Vec::with_capacity(settings.max_iterations as usize + 1);
+        for _ in 0..=settings.max_iterations {
+            let value = z.to_real();
+            orbit.push(value);
+            if settings.bailout.escaped(&value, limit) {
+                break;
+            }
+            z = z.squared() + c;
+        }
+        ReferenceOrbit {offset, orbit, series: None}
+    }
+
+    // Fits the series approximation for the points within the probes, the corners and edges
+    // of the view. It is used up to the last iteration at which it matches the deltas of every
+    // probe, which haven't escaped or glitched by then. Orbit traps, averages and atom domains
+    // need every iteration and are left without it.
+    pub fn approximate(&mut self, settings: &RenderSettings, probes: &[Vec2<Real>]) {
+        if settings.tracks_trap() || settings.tracks_average() || settings.tracks_atom_domain() {
+            return;
+        }
+        let julia = settings.fractal.is_julia();
+        let zero = Complex::new(0.0, 0.0);
+        let deltas: Vec<Complex<Real>> = probes.iter().map(|probe| Complex::new(probe.x - self.offset.x, probe.y - self.offset.y)).collect();
+        let radius = deltas.iter().map(|delta| delta.length()).fold(0.0, Real::max);
+        if radius == 0.0 {
+            return;
+        }
+        let limit = settings.bailout.limit(settings.max_length());
+        let tolerance_squared = GLITCH_TOLERANCE*GLITCH_TOLERANCE;
+        // Mandelbrot deltas start at 0 and add dc every iteration, Julia deltas start at d
+        let mut series = Series {skip: 0, radius, coefficients: [zero; SERIES_TERMS]};
+        if julia {
+            series.coefficients[0] = Complex::new(radius, 0.0);
+        }
+        let mut iterated: Vec<Complex<Real>> = if julia { deltas.clone() } else { vec![zero; deltas.len()] };
+        let mut coefficients = series.coefficients;
+        for n in 0..self.orbit.len() - 1 {
+            let reference = self.orbit[n];
+            // d' = 2Zd + d^2 + dc, the coefficient of every power collects the products of
+            // lower powers adding up to it
+            let mut next = [zero; SERIES_TERMS];
+            for k in 0..SERIES_TERMS {
+                let mut value = reference * coefficients[k] * 2.0;
+                for i in 0..k {
+                    value = value + coefficients[i] * coefficients[k - 1 - i];
+                }
+                next[k] = value;
+            }
+            if !julia {
+                next[0] = next[0] + Complex::new(radius, 0.0);
+            }
+            coefficients = next;
+            for (d, delta) in iterated.iter_mut().zip(&deltas) {
+                let dc = if julia { zero } else { *delta };
+                *d = reference * *d * 2.0 + d.squared() + dc;
+            }
+            let candidate = Series {skip: n + 1, radius, coefficients};
+            let next_reference = self.orbit[n + 1];
+            let accurate = coefficients.iter().all(|coefficient| coefficient.r.is_finite() && coefficient.i.is_finite())
+                && iterated.iter().zip(&deltas).all(|(d, delta)| {
+                    let z = next_reference + *d;
+                    !settings.bailout.escaped(&z, limit) && z.length_squared() >= tolerance_squared * next_reference.length_squared()
+                        && (candidate.evaluate(*delta).0 - *d).length() <= SERIES_TOLERANCE * d.length()
+                });
+            if !accurate {
+                break;
+            }
+            series = candidate;
+        }
+        if series.skip > 0 {
+            self.series = Some(series);
+        }
+    }
+
+    // Iterates the delta orbit of the point at offset from the view center.
+    // Returns None when the result can't be trusted and another reference is needed.
+    pub fn escape_time(&self, settings: &RenderSettings, offset: Vec2<Real>) -> Option<EscapeResult> {
+        let max_iterations = settings.max_iterations;
+        let delta = Complex::new(offset.x - self.offset.x, offset.y - self.offset.y);
+        // Mandelbrot perturbs c while Julia perturbs the starting point
+        let (mut d, dc) = if settings.fractal.is_julia() { (delta, Complex::new(0.0, 0.0)) } else { (Complex::new(0.0, 0.0), delta) };
+        let limit = settings.bailout.limit(settings.max_length());
+        let tolerance_squared = GLITCH_TOLERANCE*GLITCH_TOLERANCE;
+        let track_derivative = settings.tracks_derivative();
+        let track_trap = settings.tracks_trap();
+        let track_average = settings.tracks_average();
+        let mut derivative = settings.fractal.derivative_start();
+        let mut start = 0;
+        if let Some(series) = &self.series {
+            (d, derivative) = series.evaluate(delta);
+            start = series.skip;
+        }
+        let trap_image = settings.tracked_trap_image();
+        let track_atom_domain = settings.tracks_atom_domain();
+        let mut trap = Real::INFINITY;
+        let mut trap_point = Complex::new(Real::NAN, Real::NAN);
+        let (mut atom_domain, mut atom_length) = (0, Real::INFINITY);
+        let (mut average_sum, mut average_last) = (0.0, 0.0);
+        // c rounded to f64 is close enough for the orbit average and the shape of interior cycles
+        let c = if settings.fractal.is_julia() {
+            Complex::new(settings.julia_c.x, settings.julia_c.y)
+        }
+        else {
+            Complex::new(settings.center.x.to_real() + offset.x, settings.center.y.to_real() + offset.y)
+        };
+        let mut periodicity = Periodicity::starting_at(settings, self.orbit[start] + d, start as u32);
+        for (n, reference) in self.orbit.iter().enumerate().skip(start) {
+            let z = *reference + d;
+            if track_trap && n > 0 {
+                trap = trap.min(settings.trap_distance(&z));
+            }
+            if let Some(image) = trap_image {
+                if n > 0 && trap_point.r.is_nan() && image.covers(&settings.orbit_trap, &z) {
+                    trap_point = z;
+                }
+            }
+            if track_atom_domain && n > 0 && z.length_squared() < atom_length {
+                atom_length = z.length_squared();
+                atom_domain = n as u32;
+            }
+            if track_average && n > 1 {
+                let term = settings.orbit_average.term(settings.coloring, &z, &c);
+                average_sum += term;
+                average_last = term;
+            }
+            if settings.bailout.escaped(&z, limit) || n as u32 == max_iterations {
+                return Some(EscapeResult {iterations: n as u32, z, derivative, trap, trap_point, atom_domain, period: 0, interior_distance: Real::INFINITY, average_sum, average_last});
+            }
+            if z.length_squared() < tolerance_squared * reference.length_squared() {
+                return None;
+            }
+            if n > start && periodicity.is_periodic(&z, n as u32) {
+                let (period, interior_distance) = periodicity.interior(settings, z, c, n as u32);
+                return Some(EscapeResult {iterations: max_iterations, z, derivative, trap, trap_point, atom_domain, period, interior_distance, average_sum, average_last});
+            }
+            if track_derivative {
+                derivative = settings.fractal.next_derivative(settings, &z, &c, &derivative);
+            }
+            // d' = 2Zd + d^2 + dc
+            d = Complex::new(
+                2.0*(reference.r*d.r - reference.i*d.i) + d.r*d.r - d.i*d.i + dc.r,
+                2.0*(reference.r*d.i + reference.i*d.r) + 2.0*d.r*d.i + dc.i);
+        }
+        // The reference escaped before the point did
+        None
+    }
+
+    // Delta and its derivative with respect to the initial delta of the point at offset at
+    // every iteration from the series skip on, until visit returns false
+    fn delta_orbit<F: FnMut(usize, Complex<Real>, Complex<Real>) -> bool>(&self, settings: &RenderSettings, offset: Vec2<Real>, mut visit: F) {
+        let delta = Complex::new(offset.x - self.offset.x, offset.y - self.offset.y);
+        let julia = settings.fractal.is_julia();
+        let (mut d, dc, mut derivative) = if julia {
+            (delta, Complex::new(0.0, 0.0), Complex::new(1.0, 0.0))
+        }
+        else {
+            (Complex::new(0.0, 0.0), delta, Complex::new(0.0, 0.0))
+        };
+        let mut start = 0;
+        if let Some(series) = &self.series {
+            (d, derivative) = series.evaluate(delta);
+            start = series.skip;
+        }
+        for (n, reference) in self.orbit.iter().enumerate().skip(start) {
+            if !visit(n, d, derivative) {
+                return;
+            }
+            derivative = (*reference + d) * derivative * 2.0;
+            if !julia {
+                derivative = derivative + Complex::new(1.0, 0.0);
+            }
+            d = *reference * d * 2.0 + d.squared() + dc;
+        }
+    }
+
+    // Estimated center of the glitch the point at offset runs into. The orbit glitches when it
+    // passes close to 0, Newton's method on z(c) = 0 at the glitching iteration converges to
+    // the nucleus of the minibrot responsible, where a new reference stays accurate for the
+    // whole glitched blob. None when the point doesn't glitch or the nucleus is off the view.
+    pub fn glitch_center(&self, settings: &RenderSettings, offset: Vec2<Real>) -> Option<Vec2<Real>> {
+        let limit = settings.bailout.limit(settings.max_length());
+        let tolerance_squared = GLITCH_TOLERANCE*GLITCH_TOLERANCE;
+        let mut glitch = None;
+        self.delta_orbit(settings, offset, |n, d, _| {
+            let z = self.orbit[n] + d;
+            if z.length_squared() < tolerance_squared * self.orbit[n].length_squared() {
+                glitch = Some(n);
+            }
+            glitch.is_none() && !settings.bailout.escaped(&z, limit)
+        });
+        let iteration = glitch?;
+        let mut center = offset;
+        for _ in 0..NEWTON_STEPS {
+            let mut step = None;
+            self.delta_orbit(settings, center, |n, d, derivative| {
+                if n == iteration {
+                    step = Some((self.orbit[n] + d) / derivative);
+                }
+                n < iteration
+            });
+            let step = step.filter(|step| step.r.is_finite() && step.i.is_finite())?;
+            center = Vec2::<Real>{x: center.x - step.r, y: center.y - step.i};
+            if step.length() < NEWTON_ACCURACY * settings.pixel_size() {
+                break;
+            }
+        }
+        let distance = Complex::new(center.x - offset.x, center.y - offset.y).length();
+        (distance <= settings.view_size.x.max(settings.view_size.y)).then_some(center)
+    }
+}
+
+// Per tile perturbation state sharing the primary reference between all tiles
+pub struct Perturbation<'a> {
+    primary: &'a ReferenceOrbit,
+    secondary: Vec<ReferenceOrbit>,
+}
+
+impl<'a> Perturbation<'a> {
+    pub fn new(primary: &'a ReferenceOrbit) -> Perturbation<'a> {
+        Perturbation {primary, secondary: Vec::new()}
+    }
+
+    // Tries the known references in order, adding new ones at the center of the glitch and at
+    // the glitched point if allowed.
+    // Returns None when every reference glitched.
+    pub fn escape_time(&mut self, settings: &RenderSettings, offset: Vec2<Real>, limbs: usize) -> Option<EscapeResult> {
+        if let Some(result) = self.primary.escape_time(settings, offset) {
+            return Some(result);
+        }
+        for reference in &self.secondary {
+            if let Some(result) = reference.escape_time(settings, offset) {
+                return Some(result);
+            }
+        }
+        if self.secondary.len() == MAX_SECONDARY_REFERENCES {
+            return None;
+        }
+        // The estimated glitch center first, the point itself if that didn't help
+        let centers = [self.primary.glitch_center(settings, offset), Some(offset)];
+        for &center in centers.iter().flatten() {
+            if self.secondary.len() == MAX_SECONDARY_REFERENCES {
+                break;
+            }
+            let reference = ReferenceOrbit::new(settings, center, limbs);
+            let result = reference.escape_time(settings, offset);
+            self.secondary.push(reference);
+            if result.is_some() {
+                return result;
+            }
+        }
+        None
+    }
+}
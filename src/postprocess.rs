@@ -0,0 +1,150 @@
+use crate::math::Real;
+use crate::color::{Color, Gamma};
+use crate::image::ImageBuffer;
+
+// Effects applied to the rendered colors before they are saved. Exposure, bloom, sharpening
+// and the vignette work in linear light, the contrast curve on the encoded values. Every
+// effect is off at its default.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PostProcess {
+    // Brightness change in stops
+    pub exposure: Real,
+    // Slope of the contrast curve around mid gray, 1 keeps the contrast
+    pub contrast: Real,
+    // Unsharp mask: weight of the difference to the blurred image and blur radius in pixels
+    pub sharpen: Real,
+    pub sharpen_radius: Real,
+    // Glow around bright filaments: weight of the blurred light above the threshold
+    // luminance and its radius in pixels
+    pub bloom: Real,
+    pub bloom_radius: Real,
+    pub bloom_threshold: Real,
+    // Darkening of the corners, 1 makes them black
+    pub vignette: Real,
+}
+
+impl PostProcess {
+    pub fn new() -> PostProcess {
+        PostProcess {
+            exposure: 0.0,
+            contrast: 1.0,
+            sharpen: 0.0,
+            sharpen_radius: 1.0,
+            bloom: 0.0,
+            bloom_radius: 8.0,
+            bloom_threshold: 0.8,
+            vignette: 0.0,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.exposure == 0.0 && self.contrast == 1.0 && self.sharpen == 0.0 && self.bloom == 0.0 && self.vignette == 0.0
+    }
+
+    // Whether an effect needs the pixels around each pixel, those can't be applied band by band
+    pub fn needs_neighbours(&self) -> bool {
+        self.sharpen != 0.0 || self.bloom != 0.0
+    }
+
+    // Applies the effects to image, rows first_row.. of an image of height full_height
+    pub fn apply(&self, image: &mut ImageBuffer, gamma: Gamma, first_row: usize, full_height: usize) {
+        if self.is_identity() {
+            return;
+        }
+        let mut pixels: Vec<Color> = image.pixels.iter().map(|color| gamma.decode_color(*color)).collect();
+        let (width, height) = (image.width, image.height);
+        let scale = (2.0 as Real).powf(self.exposure);
+        if scale != 1.0 {
+            for color in pixels.iter_mut() {
+                *color = Color {r: color.r * scale, g: color.g * scale, b: color.b * scale, a: color.a};
+            }
+        }
+        if self.bloom != 0.0 {
+            // Only the light above the threshold spreads
+            let bright: Vec<Color> = pixels.iter().map(|color| {
+                let luminance = 0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b;
+                let excess = if luminance > 0.0 { (luminance - self.bloom_threshold).max(0.0) / luminance } else { 0.0 };
+                Color {r: color.r * excess, g: color.g * excess, b: color.b * excess, a: 0.0}
+            }).collect();
+            let glow = gaussian_blur(&bright, width, height, self.bloom_radius);
+            for (color, glow) in pixels.iter_mut().zip(&glow) {
+                color.r += self.bloom * glow.r;
+                color.g += self.bloom * glow.g;
+                color.b += self.bloom * glow.b;
+            }
+        }
+        if self.sharpen != 0.0 {
+            let blurred = gaussian_blur(&pixels, width, height, self.sharpen_radius);
+            for (color, blurred) in pixels.iter_mut().zip(&blurred) {
+                color.r += self.sharpen * (color.r - blurred.r);
+                color.g += self.sharpen * (color.g - blurred.g);
+                color.b += self.sharpen * (color.b - blurred.b);
+            }
+        }
+        if self.vignette != 0.0 {
+            // Falls off with the squared distance from the center, relative to the corners
+            let (center_x, center_y) = (width as Real / 2.0, full_height as Real / 2.0);
+            let corner = center_x * center_x + center_y * center_y;
+            for y in 0..height {
+                let dy = (first_row + y) as Real + 0.5 - center_y;
+                for x in 0..width {
+                    let dx = x as Real + 0.5 - center_x;
+                    let factor = (1.0 - self.vignette * (dx * dx + dy * dy) / corner).max(0.0);
+                    let color = &mut pixels[y * width + x];
+                    *color = Color {r: color.r * factor, g: color.g * factor, b: color.b * factor, a: color.a};
+                }
+            }
+        }
+        let curve = |value: Real| if self.contrast == 1.0 { value } else { 0.5 + (value - 0.5) * self.contrast };
+        for (pixel, color) in image.pixels.iter_mut().zip(&pixels) {
+            let color = gamma.encode_color(*color);
+            *pixel = Color {
+                r: curve(color.r).clamp(0.0, 1.0),
+                g: curve(color.g).clamp(0.0, 1.0),
+                b: curve(color.b).clamp(0.0, 1.0),
+                a: color.a,
+            };
+        }
+    }
+}
+
+impl Default for PostProcess {
+    fn default() -> PostProcess {
+        PostProcess::new()
+    }
+}
+
+// Three box blurs approximating a Gaussian of standard deviation sigma, edges are clamped
+fn gaussian_blur(pixels: &[Color], width: usize, height: usize, sigma: Real) -> Vec<Color> {
+    let box_width = (4.0 * sigma * sigma + 1.0).sqrt();
+    let radius = ((box_width - 1.0) / 2.0).round().max(1.0) as usize;
+    let mut blurred = pixels.to_vec();
+    for _ in 0..3 {
+        blurred = box_blur(&blurred, width, height, radius, true);
+        blurred = box_blur(&blurred, width, height, radius, false);
+    }
+    blurred
+}
+
+// Mean over 2 * radius + 1 pixels along the rows or the columns, as a running sum
+fn box_blur(pixels: &[Color], width: usize, height: usize, radius: usize, horizontal: bool) -> Vec<Color> {
+    let (length, lines) = if horizontal { (width, height) } else { (height, width) };
+    let index = |line: usize, position: usize| if horizontal { line * width + position } else { position * width + line };
+    let count = (2 * radius + 1) as Real;
+    let mut blurred = vec![Color::new(); pixels.len()];
+    for line in 0..lines {
+        let at = |position: isize| pixels[index(line, position.clamp(0, length as isize - 1) as usize)];
+        let mut sum = Color::new();
+        for position in -(radius as isize)..=radius as isize {
+            sum.add(at(position));
+        }
+        for position in 0..length {
+            let mut mean = sum;
+            mean.divide(count);
+            blurred[index(line, position)] = mean;
+            let (entering, leaving) = (at(position as isize + radius as isize + 1), at(position as isize - radius as isize));
+            sum.add(Color {r: entering.r - leaving.r, g: entering.g - leaving.g, b: entering.b - leaving.b, a: entering.a - leaving.a});
+        }
+    }
+    blurred
+}
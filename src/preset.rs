@@ -0,0 +1,82 @@
+use crate::math::Real;
+
+// Well known location of the Mandelbrot set
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    // Center as re,im with every digit needed at the zoom
+    pub center: &'static str,
+    // Relative to a view 4 units wide
+    pub zoom: Real,
+    pub iterations: u32,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "whole",
+        description: "The whole set",
+        center: "-0.75,0",
+        zoom: 1.3,
+        iterations: 200,
+    },
+    Preset {
+        name: "seahorse",
+        description: "Seahorse Valley, between the main cardioid and the period 2 bulb",
+        center: "-0.7453,0.1127",
+        zoom: 650.0,
+        iterations: 500,
+    },
+    Preset {
+        name: "seahorse-deep",
+        description: "Spiral deep inside Seahorse Valley, rendered with perturbation",
+        center: "-0.743643887037158704752191506114774,0.131825904205311970493132056385139",
+        zoom: 1e10,
+        iterations: 10000,
+    },
+    Preset {
+        name: "elephant",
+        description: "Elephant Valley, between the main cardioid and the period 1 cusp",
+        center: "0.2925,0.0149",
+        zoom: 250.0,
+        iterations: 500,
+    },
+    Preset {
+        name: "period-3-valley",
+        description: "Valley between the main cardioid and the upper period 3 bulb",
+        center: "-0.1002,0.8383",
+        zoom: 60.0,
+        iterations: 500,
+    },
+    Preset {
+        name: "scepter",
+        description: "Scepter Valley on the real axis left of the period 2 bulb",
+        center: "-1.36,0",
+        zoom: 40.0,
+        iterations: 500,
+    },
+    Preset {
+        name: "mini",
+        description: "Largest mini Mandelbrot on the real axis, at -1.7549",
+        center: "-1.7548776662466927,0",
+        zoom: 80.0,
+        iterations: 500,
+    },
+    Preset {
+        name: "feigenbaum",
+        description: "Feigenbaum point at the end of the period doubling cascade",
+        center: "-1.401155189092051,0",
+        zoom: 2e4,
+        iterations: 5000,
+    },
+    Preset {
+        name: "dendrite",
+        description: "Misiurewicz point at i, where the filaments branch",
+        center: "0,1",
+        zoom: 10.0,
+        iterations: 1000,
+    },
+];
+
+pub fn find_preset(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name == name)
+}
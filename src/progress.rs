@@ -0,0 +1,206 @@
+use std::fmt;
+use std::io::{stdout, Write};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use mandelbrot_set::RenderProgress;
+use crate::dashboard::Dashboard;
+
+// Minimum time between two JSON progress lines
+const JSON_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    // Bar redrawn in place on the console
+    Bar,
+    // One JSON object per line for other programs
+    Json,
+    // Full terminal dashboard with a live preview
+    Tui,
+    // Nothing but errors
+    Quiet,
+}
+
+impl FromStr for ProgressMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bar" => Ok(ProgressMode::Bar),
+            "json" => Ok(ProgressMode::Json),
+            "tui" => Ok(ProgressMode::Tui),
+            "none" => Ok(ProgressMode::Quiet),
+            _ => Err(format!("unknown progress mode '{}', expected bar, json, tui or none", s)),
+        }
+    }
+}
+
+impl fmt::Display for ProgressMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProgressMode::Bar => write!(f, "bar"),
+            ProgressMode::Json => write!(f, "json"),
+            ProgressMode::Tui => write!(f, "tui"),
+            ProgressMode::Quiet => write!(f, "none"),
+        }
+    }
+}
+
+// Formats seconds as h:mm:ss, or m:ss below an hour
+pub fn format_time(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds/3600, (seconds/60)%60, seconds%60)
+    }
+    else {
+        format!("{}:{:02}", seconds/60, seconds%60)
+    }
+}
+
+pub fn format_rate(pixels_per_second: f64) -> String {
+    if pixels_per_second >= 1e6 {
+        format!("{:.1} Mpx/s", pixels_per_second / 1e6)
+    }
+    else {
+        format!("{:.1} kpx/s", pixels_per_second / 1e3)
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Reports the progress of renders and the messages of the commands in the selected mode
+pub struct Reporter {
+    mode: ProgressMode,
+    start_time: Instant,
+    // Pixels that were already finished at the first update, e.g. loaded from a checkpoint
+    initial_pixels: Option<usize>,
+    last_json: Option<Instant>,
+    bar_shown: bool,
+    // Latest state of the current render
+    last_progress: Option<RenderProgress>,
+    // Shown in front of the progress, e.g. the job of a batch
+    label: Option<String>,
+    dashboard: Dashboard,
+}
+
+impl Reporter {
+    pub fn new(mode: ProgressMode) -> Reporter {
+        Reporter {
+            mode,
+            start_time: Instant::now(),
+            initial_pixels: None,
+            last_json: None,
+            bar_shown: false,
+            last_progress: None,
+            label: None,
+            dashboard: Dashboard::new(),
+        }
+    }
+
+    // Restarts the clock for the next render
+    pub fn start(&mut self) {
+        self.start_time = Instant::now();
+        self.initial_pixels = None;
+        self.last_json = None;
+        self.last_progress = None;
+        self.dashboard.reset();
+    }
+
+    pub fn set_label(&mut self, label: Option<String>) {
+        self.label = label;
+    }
+
+    pub fn update(&mut self, progress: &RenderProgress) {
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        let initial = *self.initial_pixels.get_or_insert(progress.finished_pixels);
+        let rendered = progress.finished_pixels.saturating_sub(initial);
+        let rate = if elapsed > 0.0 { rendered as f64 / elapsed } else { 0.0 };
+        let remaining = progress.total_pixels.saturating_sub(progress.finished_pixels);
+        let eta = if rate > 0.0 { Some(remaining as f64 / rate) } else { None };
+        self.last_progress = Some(progress.clone());
+        match self.mode {
+            ProgressMode::Bar => {
+                let percent = progress.percent();
+                let mut progress_bar = String::from("[");
+                for i in 0..50 {
+                    progress_bar.push(if i < percent/2 { '=' } else { ' ' });
+                }
+                progress_bar.push(']');
+                let eta = eta.map(format_time).unwrap_or_else(|| String::from("-:--"));
+                let label = self.label.as_ref().map(|label| format!("{}  ", label)).unwrap_or_default();
+                print!("\r{}{} {}%  {} elapsed, {} left, {}, {}/{} threads busy  ", label, progress_bar, percent,
+                    format_time(elapsed), eta, format_rate(rate), progress.busy_threads(), progress.threads.len());
+                stdout().flush().unwrap();
+                self.bar_shown = true;
+            }
+            ProgressMode::Json => {
+                let now = Instant::now();
+                let due = self.last_json.is_none_or(|last| now.duration_since(last) >= JSON_INTERVAL);
+                if !due && remaining > 0 {
+                    return;
+                }
+                self.last_json = Some(now);
+                let threads: Vec<String> = progress.threads.iter().map(|tile| match tile {
+                    Some(offset) => format!("[{}, {}]", offset.x, offset.y),
+                    None => String::from("null"),
+                }).collect();
+                let eta = eta.map(|eta| format!("{:.3}", eta)).unwrap_or_else(|| String::from("null"));
+                let label = self.label.as_ref().map(|label| format!(", \"label\": {}", json_string(label))).unwrap_or_default();
+                println!("{{\"event\": \"progress\", \"percent\": {}, \"finished_pixels\": {}, \"total_pixels\": {}, \"elapsed\": {:.3}, \"eta\": {}, \"pixels_per_second\": {:.0}, \"threads\": [{}]{}}}",
+                    progress.percent(), progress.finished_pixels, progress.total_pixels, elapsed, eta, rate, threads.join(", "), label);
+            }
+            ProgressMode::Tui => self.dashboard.update(progress, self.label.as_deref(), elapsed, rate, eta),
+            ProgressMode::Quiet => (),
+        }
+    }
+
+    // Ends the current render with its duration
+    pub fn finish(&mut self) {
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        match self.mode {
+            ProgressMode::Bar => {
+                if self.bar_shown {
+                    println!();
+                    self.bar_shown = false;
+                }
+                println!("Finished rendering in {}", format_time(elapsed));
+            }
+            ProgressMode::Tui => {
+                self.dashboard.detach();
+                println!("Finished rendering in {}", format_time(elapsed));
+            }
+            ProgressMode::Json => println!("{{\"event\": \"finished\", \"elapsed\": {:.3}}}", elapsed),
+            ProgressMode::Quiet => (),
+        }
+    }
+
+    // Final state of the last render, None before its first update
+    pub fn last_progress(&self) -> Option<&RenderProgress> {
+        self.last_progress.as_ref()
+    }
+
+    pub fn message(&self, text: &str) {
+        match self.mode {
+            ProgressMode::Bar => println!("{}", text),
+            // The dashboard continues below the message
+            ProgressMode::Tui => {
+                self.dashboard.detach();
+                println!("{}", text);
+            }
+            ProgressMode::Json => println!("{{\"event\": \"message\", \"text\": {}}}", json_string(text)),
+            ProgressMode::Quiet => (),
+        }
+    }
+}
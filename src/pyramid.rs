@@ -0,0 +1,189 @@
+use std::path::Path;
+use crate::math::Real;
+use crate::color::Color;
+use crate::image::{ImageBuffer, ImageSettings, ImageFormat, save_image_with, prepare_output};
+
+// Directory layout of a tile pyramid
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TileLayout {
+    // Deep Zoom (OpenSeadragon): <name>.dzi and <name>_files/<level>/<column>_<row>.<ext>,
+    // levels down to a single pixel, edge tiles cropped to the image
+    DeepZoom,
+    // Leaflet and other slippy maps: <directory>/<zoom>/<x>/<y>.<ext>, zoom 0 is a single
+    // tile, every tile is square with transparent padding outside the image
+    Xyz,
+}
+
+impl TileLayout {
+    // Deep Zoom for .dzi paths, slippy map tiles for directories
+    pub fn for_path(path: &str) -> TileLayout {
+        match Path::new(path).extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("dzi") => TileLayout::DeepZoom,
+            _ => TileLayout::Xyz,
+        }
+    }
+}
+
+// Rows of one level waiting for a whole row of tiles
+struct Level {
+    width: usize,
+    height: usize,
+    // Image row of the first buffered row
+    first_row: usize,
+    pixels: Vec<Color>,
+}
+
+// Writes the tiles of an image and of every halved level below it band by band, so images
+// larger than the memory can be explored in a browser. Every level holds at most one row of
+// tiles at a time.
+pub struct TilePyramid {
+    path: String,
+    layout: TileLayout,
+    tile_size: usize,
+    extension: &'static str,
+    settings: ImageSettings,
+    // Full resolution first
+    levels: Vec<Level>,
+}
+
+impl TilePyramid {
+    pub fn new(path: &str, width: usize, height: usize, tile_size: usize, layout: TileLayout, settings: &ImageSettings) -> Result<TilePyramid, String> {
+        // Halving pairs rows within every row of tiles
+        if tile_size == 0 || !tile_size.is_multiple_of(2) {
+            return Err(String::from("tile size must be positive and even"));
+        }
+        let extension = match settings.format.unwrap_or(ImageFormat::Png) {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Webp => "webp",
+            ImageFormat::Avif => "avif",
+            ImageFormat::Exr => return Err(String::from("tiles can't be written as OpenEXR")),
+        };
+        // Deep Zoom goes down to one pixel, slippy maps to one tile
+        let smallest = match layout {
+            TileLayout::DeepZoom => 1,
+            TileLayout::Xyz => tile_size,
+        };
+        let mut levels = Vec::new();
+        let (mut level_width, mut level_height) = (width, height);
+        loop {
+            levels.push(Level {width: level_width, height: level_height, first_row: 0, pixels: Vec::new()});
+            if level_width.max(level_height) <= smallest {
+                break;
+            }
+            level_width = level_width.div_ceil(2);
+            level_height = level_height.div_ceil(2);
+        }
+        // Tiles don't carry the metadata of the image
+        let settings = ImageSettings {text: Vec::new(), ..settings.clone()};
+        Ok(TilePyramid {path: String::from(path), layout, tile_size, extension, settings, levels})
+    }
+
+    // Number of the deepest level, 0 is the smallest
+    pub fn max_level(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    // Appends the rows of band, which has to be as wide as the image
+    pub fn write_band(&mut self, band: &ImageBuffer) -> Result<(), String> {
+        let top = &self.levels[0];
+        if band.width != top.width || top.first_row + top.pixels.len() / top.width.max(1) + band.height > top.height {
+            return Err(String::from("the band doesn't fit the image"));
+        }
+        self.add_rows(0, &band.pixels)
+    }
+
+    fn add_rows(&mut self, level: usize, pixels: &[Color]) -> Result<(), String> {
+        self.levels[level].pixels.extend_from_slice(pixels);
+        loop {
+            let current = &self.levels[level];
+            let buffered = current.pixels.len() / current.width.max(1);
+            let complete = current.first_row + buffered == current.height;
+            if buffered == 0 || (buffered < self.tile_size && !complete) {
+                return Ok(());
+            }
+            let rows = buffered.min(self.tile_size);
+            let (width, first_row) = (current.width, current.first_row);
+            let chunk: Vec<Color> = self.levels[level].pixels.drain(..rows * width).collect();
+            self.levels[level].first_row += rows;
+            self.write_tiles(level, first_row, &chunk)?;
+            if level + 1 < self.levels.len() {
+                let halved = halve(&chunk, width, rows);
+                self.add_rows(level + 1, &halved)?;
+            }
+        }
+    }
+
+    // Saves a row of tiles starting at first_row of a level
+    fn write_tiles(&self, level: usize, first_row: usize, pixels: &[Color]) -> Result<(), String> {
+        let width = self.levels[level].width;
+        let rows = pixels.len() / width;
+        let number = self.max_level() - level;
+        let row = first_row / self.tile_size;
+        for column in 0..width.div_ceil(self.tile_size) {
+            let left = column * self.tile_size;
+            let tile_width = self.tile_size.min(width - left);
+            let mut tile = match self.layout {
+                TileLayout::DeepZoom => ImageBuffer::new(tile_width, rows),
+                TileLayout::Xyz => ImageBuffer::new(self.tile_size, self.tile_size),
+            };
+            for y in 0..rows {
+                for x in 0..tile_width {
+                    tile.set(x, y, pixels[y * width + left + x]);
+                }
+            }
+            let path = match self.layout {
+                TileLayout::DeepZoom => format!("{}/{}/{}_{}.{}", self.tile_directory(), number, column, row, self.extension),
+                TileLayout::Xyz => format!("{}/{}/{}/{}.{}", self.path, number, column, row, self.extension),
+            };
+            prepare_output(&path)?;
+            save_image_with(&tile, &path, &self.settings)?;
+        }
+        Ok(())
+    }
+
+    // Directory of the Deep Zoom levels next to the descriptor
+    fn tile_directory(&self) -> String {
+        let stem = self.path.strip_suffix(".dzi").or_else(|| self.path.strip_suffix(".DZI")).unwrap_or(&self.path);
+        format!("{}_files", stem)
+    }
+
+    // Writes the Deep Zoom descriptor once all rows were added
+    pub fn finish(self) -> Result<(), String> {
+        let top = &self.levels[0];
+        if top.first_row != top.height {
+            return Err(format!("cannot write {}: {} rows are missing", self.path, top.height - top.first_row));
+        }
+        if self.layout == TileLayout::DeepZoom {
+            let descriptor = format!(concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" TileSize=\"{}\" Overlap=\"0\" Format=\"{}\">\n",
+                "  <Size Width=\"{}\" Height=\"{}\"/>\n",
+                "</Image>\n"), self.tile_size, self.extension, top.width, top.height);
+            prepare_output(&self.path)?;
+            std::fs::write(&self.path, descriptor).map_err(|e| format!("cannot write {}: {}", self.path, e))?;
+        }
+        Ok(())
+    }
+}
+
+// Averages blocks of 2x2 pixels, the last column and row average fewer when the size is odd
+fn halve(pixels: &[Color], width: usize, height: usize) -> Vec<Color> {
+    let (half_width, half_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut halved = Vec::with_capacity(half_width * half_height);
+    for y in 0..half_height {
+        for x in 0..half_width {
+            let mut color = Color::new();
+            let mut count = 0;
+            for source_y in 2 * y..(2 * y + 2).min(height) {
+                for source_x in 2 * x..(2 * x + 2).min(width) {
+                    color.add(pixels[source_y * width + source_x]);
+                    count += 1;
+                }
+            }
+            color.divide(count as Real);
+            halved.push(color);
+        }
+    }
+    halved
+}
@@ -0,0 +1,293 @@
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Neg};
+use std::str::FromStr;
+use crate::math::{Real, Complex, clamp};
+use crate::color::Color;
+use crate::fractal::Fractal;
+use crate::render::{RenderSettings, EscapeResult};
+
+// Steps along a ray before it counts as missing the surface
+const MAX_STEPS: u32 = 512;
+// Distance from the camera beyond which rays miss
+const MAX_DISTANCE: Real = 20.0;
+// Radius beyond which the orbits of the distance estimators escape
+const ESCAPE_RADIUS: Real = 2.0;
+// Half the width of the default view, the image plane spans the field of view at zoom 1
+const HALF_VIEW_WIDTH: Real = 2.0;
+// Samples of the ambient occlusion along the normal, their spacing and the darkening per
+// unit of missing distance
+const OCCLUSION_SAMPLES: u32 = 5;
+const OCCLUSION_SPACING: Real = 0.02;
+const OCCLUSION_STRENGTH: Real = 8.0;
+// Steps of the shadow rays towards the light
+const SHADOW_STEPS: u32 = 128;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Vec3 {
+    pub x: Real,
+    pub y: Real,
+    pub z: Real,
+}
+
+impl Vec3 {
+    pub fn new(x: Real, y: Real, z: Real) -> Vec3 {
+        Vec3 {x, y, z}
+    }
+
+    pub fn dot(&self, other: &Vec3) -> Real {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: &Vec3) -> Vec3 {
+        Vec3::new(self.y * other.z - self.z * other.y, self.z * other.x - self.x * other.z, self.x * other.y - self.y * other.x)
+    }
+
+    pub fn length(&self) -> Real {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalized(&self) -> Vec3 {
+        *self * self.length().recip()
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<Real> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, factor: Real) -> Vec3 {
+        Vec3::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+// Parses x,y,z
+impl FromStr for Vec3 {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Vec3, String> {
+        let invalid = || format!("invalid vector '{}', expected x,y,z", text);
+        let values = text.split(',').map(|value| value.trim().parse::<Real>()).collect::<Result<Vec<Real>, _>>().map_err(|_| invalid())?;
+        match values[..] {
+            [x, y, z] => Ok(Vec3 {x, y, z}),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{}", self.x, self.y, self.z)
+    }
+}
+
+// Pinhole camera of the 3D fractals, y is up. The view of the settings is a window of its
+// image plane, zooming narrows the field of view and panning turns the rays off center.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Camera {
+    pub position: Vec3,
+    pub look_at: Vec3,
+    // Horizontal field of view at zoom 1 in degrees
+    pub fov: Real,
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera {position: Vec3::new(2.0, 1.6, -3.4), look_at: Vec3::new(0.0, 0.0, 0.0), fov: 45.0}
+    }
+
+    // Right, up and forward unit vectors of the camera
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = (self.look_at - self.position).normalized();
+        // Looking straight up or down the roll is arbitrary
+        let world_up = if forward.x == 0.0 && forward.z == 0.0 { Vec3::new(0.0, 0.0, 1.0) } else { Vec3::new(0.0, 1.0, 0.0) };
+        let right = world_up.cross(&forward).normalized();
+        (right, forward.cross(&right), forward)
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera::new()
+    }
+}
+
+// Distance estimate to the surface of the 3D fractal of the settings from point and the
+// smallest length of the orbit of point, its orbit trap
+fn distance_estimate(settings: &RenderSettings, point: Vec3) -> (Real, Real) {
+    match settings.fractal {
+        Fractal::Mandelbulb => mandelbulb(point, settings.power, settings.max_iterations),
+        Fractal::QuaternionJulia => quaternion_julia(point, &settings.quaternion_c, settings.max_iterations),
+        _ => unreachable!("{} isn't a 3D fractal", settings.fractal),
+    }
+}
+
+// Iterates the triplex power z' = z^power + point in spherical coordinates around the y axis,
+// tracking the running derivative for the estimate 0.5*ln(r)*r/dr
+fn mandelbulb(point: Vec3, power: Real, iterations: u32) -> (Real, Real) {
+    let mut z = point;
+    let mut derivative = 1.0;
+    let mut r = z.length();
+    let mut trap = r;
+    for _ in 0..iterations {
+        if r > ESCAPE_RADIUS {
+            break;
+        }
+        let theta = if r > 0.0 { (z.y / r).acos() } else { 0.0 };
+        let phi = z.z.atan2(z.x);
+        derivative = r.powf(power - 1.0) * power * derivative + 1.0;
+        let (sin_theta, cos_theta) = (theta * power).sin_cos();
+        let (sin_phi, cos_phi) = (phi * power).sin_cos();
+        z = Vec3::new(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi) * r.powf(power) + point;
+        r = z.length();
+        trap = trap.min(r);
+    }
+    (0.5 * r.ln() * r / derivative, trap)
+}
+
+// Iterates the quaternion z' = z^2 + c from the point as the real, i and j parts, tracking the
+// derivative dz' = 2z*dz for the estimate 0.5*|z|*ln|z|/|dz|
+fn quaternion_julia(point: Vec3, c: &[Real; 4], iterations: u32) -> (Real, Real) {
+    let mut z = [point.x, point.y, point.z, 0.0];
+    let mut derivative = [1.0, 0.0, 0.0, 0.0];
+    let length = |q: &[Real; 4]| (q[0]*q[0] + q[1]*q[1] + q[2]*q[2] + q[3]*q[3]).sqrt();
+    let mut r = length(&z);
+    let mut trap = r;
+    for _ in 0..iterations {
+        if r > ESCAPE_RADIUS {
+            break;
+        }
+        let product = multiply(&z, &derivative);
+        derivative = [2.0 * product[0], 2.0 * product[1], 2.0 * product[2], 2.0 * product[3]];
+        // The cross terms of a quaternion square cancel
+        z = [z[0]*z[0] - z[1]*z[1] - z[2]*z[2] - z[3]*z[3] + c[0], 2.0*z[0]*z[1] + c[1], 2.0*z[0]*z[2] + c[2], 2.0*z[0]*z[3] + c[3]];
+        r = length(&z);
+        trap = trap.min(r);
+    }
+    (0.5 * r * r.ln() / length(&derivative), trap)
+}
+
+// Hamilton product of the quaternions a and b
+fn multiply(a: &[Real; 4], b: &[Real; 4]) -> [Real; 4] {
+    [
+        a[0]*b[0] - a[1]*b[1] - a[2]*b[2] - a[3]*b[3],
+        a[0]*b[1] + a[1]*b[0] + a[2]*b[3] - a[3]*b[2],
+        a[0]*b[2] - a[1]*b[3] + a[2]*b[0] + a[3]*b[1],
+        a[0]*b[3] + a[1]*b[2] - a[2]*b[1] + a[3]*b[0],
+    ]
+}
+
+// Marches the ray through the point at position of the image plane until the distance estimate
+// falls below half a pixel. Rays that hit get 0 iterations, their lit brightness and
+// highlight in z and the orbit trap of the surface in trap, the others max_iterations.
+pub fn escape_time(settings: &RenderSettings, position: Complex<Real>) -> EscapeResult {
+    let camera = &settings.camera;
+    let (right, up, forward) = camera.basis();
+    let scale = (camera.fov.to_radians() / 2.0).tan() / HALF_VIEW_WIDTH;
+    let direction = (forward + right * (position.r * scale) + up * (position.i * scale)).normalized();
+    let pixel_angle = settings.pixel_size() * scale;
+    let mut result = EscapeResult {iterations: settings.max_iterations, z: Complex::new(0.0, 0.0), derivative: Complex::new(0.0, 0.0), trap: Real::INFINITY, trap_point: Complex::new(Real::NAN, Real::NAN), atom_domain: 0, period: 0, interior_distance: Real::INFINITY, average_sum: 0.0, average_last: 0.0};
+    let mut distance = 0.0;
+    for _ in 0..MAX_STEPS {
+        let point = camera.position + direction * distance;
+        let (estimate, trap) = distance_estimate(settings, point);
+        let epsilon = (0.5 * pixel_angle * distance).max(1e-7);
+        if estimate < epsilon {
+            let normal = surface_normal(settings, point, epsilon);
+            // The lighting is relative to the camera, z pointing back at it
+            let lighting = settings.lighting.unwrap_or_default();
+            let light = lighting.direction();
+            let light = right * light[0] + up * light[1] - forward * light[2];
+            let unshadowed = soft_shadow(settings, point + normal * (2.0 * epsilon), light, epsilon);
+            let (brightness, highlight) = lighting.intensity(&[normal.dot(&right), normal.dot(&up), -normal.dot(&forward)], unshadowed);
+            let occlusion = ambient_occlusion(settings, point, normal);
+            result.iterations = 0;
+            result.z = Complex::new(brightness * occlusion, highlight);
+            result.trap = trap;
+            break;
+        }
+        distance += estimate;
+        if distance > MAX_DISTANCE {
+            break;
+        }
+    }
+    result
+}
+
+// Gradient of the distance estimate by central differences
+fn surface_normal(settings: &RenderSettings, point: Vec3, h: Real) -> Vec3 {
+    let estimate = |offset: Vec3| distance_estimate(settings, point + offset).0 - distance_estimate(settings, point - offset).0;
+    Vec3::new(estimate(Vec3::new(h, 0.0, 0.0)), estimate(Vec3::new(0.0, h, 0.0)), estimate(Vec3::new(0.0, 0.0, h))).normalized()
+}
+
+// Share of the light reaching point from the direction light. The ray towards it takes the
+// smallest ratio of the distance estimate to the distance travelled, which is how far it
+// passes by the surface, the sharpness turning it into a penumbra.
+fn soft_shadow(settings: &RenderSettings, point: Vec3, light: Vec3, epsilon: Real) -> Real {
+    if settings.shadow_sharpness == 0.0 {
+        return 1.0;
+    }
+    let mut share: Real = 1.0;
+    let mut distance = epsilon;
+    for _ in 0..SHADOW_STEPS {
+        let (estimate, _) = distance_estimate(settings, point + light * distance);
+        if estimate < 0.5 * epsilon {
+            return 0.0;
+        }
+        share = share.min(settings.shadow_sharpness * estimate / distance);
+        distance += estimate;
+        if distance > MAX_DISTANCE {
+            break;
+        }
+    }
+    clamp(share, 0.0, 1.0)
+}
+
+// Darkening of creases: points a little off the surface along the normal that are closer to
+// another part of the surface than to their own, weighted less the farther out they are
+fn ambient_occlusion(settings: &RenderSettings, point: Vec3, normal: Vec3) -> Real {
+    let mut missing = 0.0;
+    let mut weight = 1.0;
+    for sample in 1..=OCCLUSION_SAMPLES {
+        let offset = sample as Real * OCCLUSION_SPACING;
+        missing += weight * (offset - distance_estimate(settings, point + normal * offset).0).max(0.0);
+        weight *= 0.5;
+    }
+    clamp(1.0 - OCCLUSION_STRENGTH * missing, 0.0, 1.0)
+}
+
+// Palette color of the orbit trap at the surface, lit, or the interior color for rays that
+// miss it
+pub fn color(settings: &RenderSettings, result: &EscapeResult) -> Color {
+    if result.iterations >= settings.max_iterations {
+        return settings.interior_color;
+    }
+    let palette = &settings.palette;
+    let last = (palette.colors.len() - 1) as Real;
+    let color = palette.interpolate(clamp(result.trap, 0.0, 1.0) * last);
+    let (brightness, highlight) = (result.z.r, result.z.i);
+    let channel = |value: Real| clamp(value * brightness + highlight, 0.0, 1.0);
+    Color {r: channel(color.r), g: channel(color.g), b: channel(color.b), a: color.a}
+}
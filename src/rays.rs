@@ -0,0 +1,180 @@
+use std::fmt;
+use std::str::FromStr;
+use std::collections::HashMap;
+use crate::math::{Real, Complex};
+use crate::color::Color;
+use crate::image::ImageBuffer;
+use crate::render::RenderSettings;
+use crate::overlay::draw_path;
+
+// |z| beyond which the potential is close enough to log |z| for the angle of z to be the angle
+// of the ray through it
+const ESCAPE_RADIUS: Real = 65536.0;
+// Points per iteration level along a ray, the level doubles the angle and squares the radius
+const SHARPNESS: usize = 8;
+const NEWTON_STEPS: usize = 16;
+// Binary digits read from the angle of z at the escape iteration, after the exact ones
+const TAIL_DIGITS: usize = 24;
+// Longest expansion of ExternalAngle::binary before it's cut off
+const MAX_BINARY_DIGITS: usize = 64;
+
+// Rational external angle p/q in turns, rays of odd denominators land on the roots of
+// hyperbolic components, the others on Misiurewicz points
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ExternalAngle {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl ExternalAngle {
+    // Angle of the ray the quadratic map takes this one to
+    pub fn doubled(&self) -> ExternalAngle {
+        let numerator = (2 * self.numerator as u128 % self.denominator as u128) as u64;
+        ExternalAngle {numerator, denominator: self.denominator}
+    }
+
+    pub fn to_real(&self) -> Real {
+        self.numerator as Real / self.denominator as Real
+    }
+
+    // Binary expansion with the periodic part in parentheses, e.g. .(001) for 1/7 and .0(01)
+    // for 1/6, the kneading of the ray under doubling
+    pub fn binary(&self) -> String {
+        let mut text = String::from(".");
+        let mut seen = HashMap::new();
+        let mut angle = *self;
+        for index in 0..MAX_BINARY_DIGITS {
+            if let Some(start) = seen.insert(angle.numerator, index) {
+                text.insert(start + 1, '(');
+                text.push(')');
+                return text;
+            }
+            let doubled = angle.doubled();
+            text.push(if 2 * angle.numerator as u128 >= angle.denominator as u128 { '1' } else { '0' });
+            angle = doubled;
+        }
+        text.push_str("...");
+        text
+    }
+}
+
+impl FromStr for ExternalAngle {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<ExternalAngle, String> {
+        let error = || format!("invalid external angle '{}', expected p/q with 0 <= p < q, e.g. 1/3", text);
+        let (numerator, denominator) = text.split_once('/').ok_or_else(error)?;
+        let numerator: u64 = numerator.trim().parse().map_err(|_| error())?;
+        let denominator: u64 = denominator.trim().parse().map_err(|_| error())?;
+        if numerator >= denominator {
+            return Err(error());
+        }
+        Ok(ExternalAngle {numerator, denominator})
+    }
+}
+
+impl fmt::Display for ExternalAngle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+// z_n(c) of the Mandelbrot set and its derivative with respect to c
+fn iterate(c: Complex<Real>, n: usize) -> (Complex<Real>, Complex<Real>) {
+    let mut z = Complex::new(0.0, 0.0);
+    let mut derivative = Complex::new(0.0, 0.0);
+    for _ in 0..n {
+        derivative = z * derivative * 2.0 + Complex::new(1.0, 0.0);
+        z = z.squared() + c;
+    }
+    (z, derivative)
+}
+
+fn polar(radius: Real, turn: Real) -> Complex<Real> {
+    let (sin, cos) = (turn * 2.0 * std::f64::consts::PI).sin_cos();
+    Complex::new(radius * cos, radius * sin)
+}
+
+fn turn_of(z: Complex<Real>) -> Real {
+    (z.i.atan2(z.r) / (2.0 * std::f64::consts::PI)).rem_euclid(1.0)
+}
+
+// Newton's method on z_n(c) = target from c, None once it diverges
+fn solve(c: Complex<Real>, n: usize, target: Complex<Real>) -> Option<Complex<Real>> {
+    let mut c = c;
+    for _ in 0..NEWTON_STEPS {
+        let (z, derivative) = iterate(c, n);
+        let step = (z - target) / derivative;
+        c = c - step;
+        if !(c.r.is_finite() && c.i.is_finite()) {
+            return None;
+        }
+        if step.length() <= c.length() * 1e-15 {
+            break;
+        }
+    }
+    Some(c)
+}
+
+// Points of the parameter ray of the angle from far outside the set towards its landing point,
+// SHARPNESS per level: at level n the point is where z_n has the angle 2^(n-1) * angle and a
+// radius going from ESCAPE_RADIUS down to its square root. Stops early when Newton's method
+// loses the ray.
+pub fn trace_ray(angle: &ExternalAngle, depth: usize) -> Vec<Complex<Real>> {
+    let mut points = Vec::with_capacity(depth * SHARPNESS);
+    let mut c = polar(ESCAPE_RADIUS, angle.to_real());
+    let mut angle = *angle;
+    for level in 1..=depth {
+        for step in 0..SHARPNESS {
+            let radius = ESCAPE_RADIUS.powf(0.5_f64.powf((step as Real + 0.5) / SHARPNESS as Real));
+            c = match solve(c, level, polar(radius, angle.to_real())) {
+                Some(c) => c,
+                None => return points,
+            };
+            points.push(c);
+        }
+        angle = angle.doubled();
+    }
+    points
+}
+
+// Leading binary digits of the external angle of a point outside the set. The point follows its
+// ray outwards one level at a time: at level n it moves until |z_n| reaches ESCAPE_RADIUS^2,
+// where z_(n-1) escapes, and the angle of z_(n-1) tells which of the two halvings of the angle
+// of z_n it has, the digit n-1 of the angle. The digits from the escape iteration on come from
+// the angle of z there.
+pub fn external_angle(c: Complex<Real>, max_iterations: u32) -> Result<Vec<bool>, String> {
+    let escape = (1..=max_iterations as usize).find(|&n| iterate(c, n).0.length() > ESCAPE_RADIUS)
+        .ok_or_else(|| format!("the point doesn't escape within {} iterations, only points outside the set have an external angle", max_iterations))?;
+    let mut turn = turn_of(iterate(c, escape).0);
+    let mut tail = turn;
+    let mut c = c;
+    let mut digits = Vec::with_capacity(escape - 1 + TAIL_DIGITS);
+    for level in (2..=escape).rev() {
+        let start = iterate(c, level).0.length().ln();
+        let end = 2.0 * ESCAPE_RADIUS.ln();
+        for step in 1..=SHARPNESS {
+            let radius = (start + (end - start) * step as Real / SHARPNESS as Real).exp();
+            c = solve(c, level, polar(radius, turn)).ok_or_else(|| String::from("Newton's method loses the ray of the point"))?;
+        }
+        let measured = turn_of(iterate(c, level - 1).0);
+        let distance = |a: Real, b: Real| { let d = (a - b).rem_euclid(1.0); d.min(1.0 - d) };
+        let digit = distance(measured, (turn + 1.0) / 2.0) < distance(measured, turn / 2.0);
+        turn = (turn + if digit { 1.0 } else { 0.0 }) / 2.0;
+        digits.push(digit);
+    }
+    digits.reverse();
+    for _ in 0..TAIL_DIGITS {
+        tail *= 2.0;
+        digits.push(tail >= 1.0);
+        tail = tail.fract();
+    }
+    Ok(digits)
+}
+
+// Draws the rays as lines of single pixels over a render of the view of settings
+pub fn draw_rays(image: &mut ImageBuffer, settings: &RenderSettings, rays: &[Vec<Complex<Real>>], color: Color) {
+    for ray in rays {
+        draw_path(image, settings, ray, color);
+    }
+}
@@ -0,0 +1,1837 @@
+use std::sync::{Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time;
+use std::fmt;
+use std::str::FromStr;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::math::{Real, Float, Complex, Vec2, clamp, divide_roundup};
+use crate::double_double::DoubleDouble;
+use crate::big_float::BigFloat;
+use crate::perturbation::{ReferenceOrbit, Perturbation};
+use crate::color::{Color, Gamma};
+use crate::palette::Palette;
+use crate::sampler::Sampler;
+use crate::coloring::{Coloring, Transparency, Histogram, SMOOTH_MAX_LENGTH};
+use crate::fractal::{Fractal, HybridPattern};
+use crate::formula::Formula;
+use crate::newton::Polynomial;
+use crate::lyapunov::LyapunovSequence;
+use crate::raymarch::{self, Camera};
+use crate::skew::Skew;
+use crate::orbit_trap::{OrbitTrap, TrapImage};
+use crate::stalks::Stalks;
+use crate::average::OrbitAverage;
+use crate::lighting::Lighting;
+use crate::interior::{self, Interior};
+use crate::image::ImageBuffer;
+use crate::simd;
+use crate::cache::IterationCache;
+use crate::checkpoint::Checkpoint;
+use crate::postprocess::PostProcess;
+use crate::resample::Filter;
+use crate::scheduler::TileQueue;
+
+pub const MAX_LENGTH: Real = 2.0;
+// Step length below which the orbits of the converging formulas count as settled
+const CONVERGENCE_EPSILON: Real = 1e-6;
+// Small enough that the last tiles of a render keep every thread busy
+pub const TILE_WIDTH: usize = 64;
+pub const TILE_HEIGHT: usize = 64;
+// Pixels per side of the averaged blocks of RenderProgress::tiles
+pub const PREVIEW_SCALE: usize = 16;
+
+// Number type used for the escape time iteration
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Precision {
+    // Picks the cheapest type able to resolve the pixel spacing
+    Auto,
+    Single,
+    Double,
+    DoubleDouble,
+    Arbitrary,
+    // f64 deltas around a high precision reference orbit
+    Perturbation,
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Precision, String> {
+        match text {
+            "auto" => Ok(Precision::Auto),
+            "single" => Ok(Precision::Single),
+            "double" => Ok(Precision::Double),
+            "double-double" => Ok(Precision::DoubleDouble),
+            "arbitrary" => Ok(Precision::Arbitrary),
+            "perturbation" => Ok(Precision::Perturbation),
+            _ => Err(format!("unknown precision '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Precision::Auto => "auto",
+            Precision::Single => "single",
+            Precision::Double => "double",
+            Precision::DoubleDouble => "double-double",
+            Precision::Arbitrary => "arbitrary",
+            Precision::Perturbation => "perturbation",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Norm of z compared with the escape radius
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Bailout {
+    // |z|
+    Euclidean,
+    // |Re z| + |Im z|
+    Manhattan,
+    // max(|Re z|, |Im z|)
+    Maximum,
+}
+
+impl FromStr for Bailout {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Bailout, String> {
+        match text {
+            "euclidean" => Ok(Bailout::Euclidean),
+            "manhattan" => Ok(Bailout::Manhattan),
+            "max" => Ok(Bailout::Maximum),
+            _ => Err(format!("unknown bailout norm '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for Bailout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Bailout::Euclidean => "euclidean",
+            Bailout::Manhattan => "manhattan",
+            Bailout::Maximum => "max",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Bailout {
+    // Value escaped() compares with for the escape radius, squared for the euclidean norm
+    // so the iteration never takes a square root
+    pub fn limit<T: Float>(&self, radius: Real) -> T {
+        match self {
+            Bailout::Euclidean => T::from_real(radius * radius),
+            Bailout::Manhattan | Bailout::Maximum => T::from_real(radius),
+        }
+    }
+
+    // Whether z lies outside the escape radius in this norm, limit comes from limit()
+    pub fn escaped<T: Float>(&self, z: &Complex<T>, limit: T) -> bool {
+        match self {
+            Bailout::Euclidean => z.length_squared() > limit,
+            Bailout::Manhattan => z.r.abs() + z.i.abs() > limit,
+            Bailout::Maximum => z.r.abs() > limit || z.i.abs() > limit,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub fractal: Fractal,
+    pub julia_c: Vec2<Real>,
+    // Exponent of Fractal::Multibrot and Fractal::Nova
+    pub power: Real,
+    // Relaxation R of Fractal::Nova
+    pub relaxation: Vec2<Real>,
+    // Factor p of the previous iterate of Fractal::Phoenix
+    pub phoenix_p: Vec2<Real>,
+    // Iteration formula of Fractal::Formula
+    pub formula: Option<Formula>,
+    // Polynomial of Fractal::Newton
+    pub polynomial: Option<Polynomial>,
+    // Colors of the roots of the polynomial in order, roots without one are spread over the palette
+    pub root_colors: Vec<Color>,
+    // Forcing sequence of Fractal::Lyapunov
+    pub lyapunov_sequence: Option<LyapunovSequence>,
+    // Formulas Fractal::Hybrid iterates in turn
+    pub hybrid: Option<HybridPattern>,
+    // Camera of the 3D fractals
+    pub camera: Camera,
+    // Constant a + bi + cj + dk of Fractal::QuaternionJulia
+    pub quaternion_c: [Real; 4],
+    // Penumbra sharpness of the soft shadows of the 3D fractals, 0 turns them off
+    pub shadow_sharpness: Real,
+    pub width: usize,
+    pub height: usize,
+    pub center: Vec2<BigFloat>,
+    pub view_size: Vec2<Real>,
+    // Counterclockwise rotation of the view around its center in degrees
+    pub rotation: Real,
+    // Linear map of the rotated view, None leaves it as it is
+    pub skew: Option<Skew>,
+    pub max_iterations: u32,
+    // Radius beyond which an orbit escapes, None uses the one the coloring needs
+    pub escape_radius: Option<Real>,
+    pub bailout: Bailout,
+    // Derive max_iterations from the zoom depth within these bounds
+    pub auto_iterations: Option<IterationRange>,
+    // Maximum samples per pixel
+    pub sample_count: usize,
+    // Samples every pixel gets before deciding whether it needs the maximum
+    pub min_sample_count: usize,
+    // Color error above which a pixel is refined with more samples
+    pub sample_threshold: Real,
+    // Fill rectangles with a uniform border without sampling their inside (Mariani-Silver)
+    pub subdivision: bool,
+    // Iterate f64 samples four at a time with SIMD instructions when the CPU supports them
+    pub simd: bool,
+    // Skip the first iterations of perturbed samples by approximating their deltas with a
+    // series fitted to the reference orbit
+    pub series_approximation: bool,
+    // Render only one half of views mirrored about the real axis and copy the other
+    pub symmetry: bool,
+    pub sampler: Sampler,
+    pub precision: Precision,
+    pub thread_count: usize,
+    // Render time after which pixels get no more samples than they already have, the rest
+    // of the image is finished with one sample per pixel
+    pub time_limit: Option<time::Duration>,
+    // Set from another thread or a signal handler to stop the render once the tiles in
+    // progress are finished, the other tiles stay transparent
+    pub interrupt: Option<&'static AtomicBool>,
+    pub palette: Palette,
+    pub coloring: Coloring,
+    // Coloring of the samples that never escape
+    pub interior: Interior,
+    pub interior_color: Color,
+    pub transparency: Transparency,
+    pub post: PostProcess,
+    // Renders at supersample times the resolution and reduces the image with the filter,
+    // 1 renders at the output resolution
+    pub supersample: usize,
+    pub filter: Filter,
+    // Samples and palette entries are blended in linear light and the pixels encoded with this
+    pub gamma: Gamma,
+    pub orbit_trap: OrbitTrap,
+    // Picture of the image-trap coloring
+    pub trap_image: Option<TrapImage>,
+    pub stalks: Stalks,
+    pub orbit_average: OrbitAverage,
+    // Slope shading of escaped samples on top of the coloring
+    pub lighting: Option<Lighting>,
+    // Track the derivative of z even when the coloring doesn't need it, for exported distances
+    pub track_derivative: bool,
+    // Seed of the sample positions, renders with the same seed are identical. None picks a random one.
+    pub seed: Option<u64>,
+}
+
+impl RenderSettings {
+    pub fn new() -> RenderSettings {
+        let width = 1366;
+        let height = 768;
+        let view_height = 6.5E-4;
+        RenderSettings {
+            fractal: Fractal::Mandelbrot,
+            julia_c: Vec2::<Real>{x: -0.8, y: 0.156},
+            power: 3.0,
+            relaxation: Vec2::<Real>{x: 1.0, y: 0.0},
+            phoenix_p: Vec2::<Real>{x: -0.5, y: 0.0},
+            formula: None,
+            polynomial: None,
+            root_colors: Vec::new(),
+            lyapunov_sequence: None,
+            hybrid: None,
+            camera: Camera::new(),
+            quaternion_c: [-0.2, 0.6, 0.2, 0.0],
+            shadow_sharpness: 16.0,
+            width,
+            height,
+            center: Vec2::<BigFloat>{x: BigFloat::from_real(-0.7453), y: BigFloat::from_real(0.1127)},
+            view_size: Vec2::<Real>{x: view_height * (width as Real) / (height as Real), y: view_height},
+            rotation: 0.0,
+            skew: None,
+            max_iterations: 250,
+            auto_iterations: None,
+            escape_radius: None,
+            bailout: Bailout::Euclidean,
+            sample_count: 16,
+            min_sample_count: 4,
+            sample_threshold: 0.01,
+            subdivision: true,
+            simd: true,
+            series_approximation: true,
+            symmetry: true,
+            sampler: Sampler::Stratified,
+            precision: Precision::Auto,
+            thread_count: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            time_limit: None,
+            interrupt: None,
+            palette: Palette::default(),
+            coloring: Coloring::Banded,
+            interior: Interior::Palette,
+            interior_color: Color {r: 0.0, g: 0.0, b: 0.0, a: 1.0},
+            transparency: Transparency::Opaque,
+            post: PostProcess::new(),
+            supersample: 1,
+            filter: Filter::Lanczos,
+            gamma: Gamma::Srgb,
+            orbit_trap: OrbitTrap::new(),
+            trap_image: None,
+            stalks: Stalks::new(),
+            orbit_average: OrbitAverage::new(),
+            lighting: None,
+            track_derivative: false,
+            seed: None,
+        }
+    }
+}
+
+// Bounds of the automatic iteration limit
+#[derive(Copy, Clone, PartialEq)]
+pub struct IterationRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl IterationRange {
+    pub fn new() -> IterationRange {
+        IterationRange {min: 100, max: 100_000}
+    }
+}
+
+impl Default for IterationRange {
+    fn default() -> IterationRange {
+        IterationRange::new()
+    }
+}
+
+impl RenderSettings {
+    // Iteration limit for the zoom depth: 100 + 30 * depth^1.5 where depth is the number of
+    // halvings of the view from 4 units, clamped to the range
+    pub fn auto_iteration_count(&self, range: &IterationRange) -> u32 {
+        let depth = (4.0 / self.view_size.x.min(self.view_size.y)).log2().max(0.0);
+        let count = 100.0 + 30.0 * depth.powf(1.5);
+        clamp(count.round(), range.min as Real, range.max.max(range.min) as Real) as u32
+    }
+
+    // Same view at 1/scale of the resolution with one sample per pixel, for quick previews
+    pub fn preview(&self, scale: usize) -> RenderSettings {
+        let mut preview = self.clone();
+        preview.width = self.width.div_ceil(scale);
+        preview.height = self.height.div_ceil(scale);
+        preview.sample_count = 1;
+        preview.min_sample_count = 1;
+        preview.supersample = 1;
+        preview.time_limit = None;
+        preview.update_auto_iterations();
+        preview
+    }
+
+    // Settings of the enlarged image of a supersampled render, effects are applied after
+    // reducing it
+    fn supersampled(&self) -> RenderSettings {
+        let mut large = self.clone();
+        large.width = self.width * self.supersample;
+        large.height = self.height * self.supersample;
+        large.supersample = 1;
+        large.post = PostProcess::new();
+        large
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupt.is_some_and(|interrupt| interrupt.load(Ordering::Relaxed))
+    }
+
+    // Sets max_iterations from the view when automatic iterations are enabled
+    pub fn update_auto_iterations(&mut self) {
+        if let Some(range) = self.auto_iterations {
+            self.max_iterations = self.auto_iteration_count(&range);
+        }
+    }
+
+    // Distance between two neighbouring pixels on the complex plane
+    pub fn pixel_size(&self) -> Real {
+        let size = (self.view_size.x / self.width as Real).min(self.view_size.y / self.height as Real);
+        // The skew squeezes the pixels along one direction
+        size * self.skew.map_or(1.0, |skew| skew.min_stretch())
+    }
+
+    // Formula of the step from iteration n to n + 1, the fractal itself unless it's a hybrid
+    pub fn step_fractal(&self, n: u32) -> Fractal {
+        match (self.fractal, &self.hybrid) {
+            (Fractal::Hybrid, Some(hybrid)) => hybrid.fractal(n),
+            _ => self.fractal,
+        }
+    }
+
+    // Resolves Precision::Auto from the pixel spacing relative to the center magnitude.
+    // Formulas without a perturbation kernel use double-double or arbitrary precision instead.
+    pub fn effective_precision(&self) -> Precision {
+        // User formulas, Newton and Lyapunov fractals and the rational formulas are only evaluated at f64
+        if matches!(self.fractal, Fractal::Formula | Fractal::Newton | Fractal::Lyapunov) || self.fractal.is_rational() || self.fractal.is_3d() {
+            return Precision::Double;
+        }
+        let perturbable = self.fractal.is_quadratic();
+        if self.precision == Precision::Perturbation && !perturbable {
+            return Precision::Arbitrary;
+        }
+        if self.precision != Precision::Auto {
+            return self.precision;
+        }
+        let magnitude = self.center.x.to_real().abs().max(self.center.y.to_real().abs()).max(1.0);
+        let relative_size = self.pixel_size() / magnitude;
+        if relative_size > (2.0 as Real).powi(-40) {
+            Precision::Double
+        }
+        else if !perturbable && relative_size > (2.0 as Real).powi(-90) {
+            Precision::DoubleDouble
+        }
+        else if !perturbable {
+            Precision::Arbitrary
+        }
+        // Deltas below the f64 exponent range can't be perturbed
+        else if self.pixel_size() > 1e-290 {
+            Precision::Perturbation
+        }
+        else {
+            Precision::Arbitrary
+        }
+    }
+
+    // Offset from the view center of a (fractional) pixel position, y pointing down
+    pub fn pixel_to_offset(&self, x: Real, y: Real) -> Vec2<Real> {
+        let norm_x = x / (self.width as Real) * 2.0 - 1.0; // [-1:1]
+        let norm_y = -(y / (self.height as Real) * 2.0 - 1.0); // [-1:1]
+        let mut offset = Vec2::<Real>{x: norm_x * self.view_size.x / 2.0, y: norm_y * self.view_size.y / 2.0};
+        if self.rotation != 0.0 {
+            let (sin, cos) = self.rotation.to_radians().sin_cos();
+            offset = Vec2::<Real>{x: offset.x * cos - offset.y * sin, y: offset.x * sin + offset.y * cos};
+        }
+        match &self.skew {
+            Some(skew) => skew.apply(offset),
+            None => offset,
+        }
+    }
+
+    // Offset of a point of the complex plane from the view center, in full precision so it stays
+    // exact in deep views
+    pub fn offset_of(&self, point: &Vec2<BigFloat>) -> Vec2<Real> {
+        Vec2::<Real>{x: (point.x - self.center.x).to_real(), y: (point.y - self.center.y).to_real()}
+    }
+
+    // Pixel position of an offset from the view center, the inverse of pixel_to_offset
+    pub fn offset_to_pixel(&self, offset: Vec2<Real>) -> Vec2<Real> {
+        let mut offset = match &self.skew {
+            Some(skew) => skew.inverse().apply(offset),
+            None => offset,
+        };
+        if self.rotation != 0.0 {
+            let (sin, cos) = self.rotation.to_radians().sin_cos();
+            offset = Vec2::<Real>{x: offset.x * cos + offset.y * sin, y: -offset.x * sin + offset.y * cos};
+        }
+        let norm_x = offset.x / (self.view_size.x / 2.0);
+        let norm_y = offset.y / (self.view_size.y / 2.0);
+        Vec2::<Real>{x: (norm_x + 1.0) / 2.0 * self.width as Real, y: (1.0 - norm_y) / 2.0 * self.height as Real}
+    }
+
+    // Moves the view center by the given offset on the complex plane
+    pub fn pan(&mut self, offset: Vec2<Real>) {
+        self.center.x = self.center.x + BigFloat::from_real(offset.x);
+        self.center.y = self.center.y + BigFloat::from_real(offset.y);
+    }
+
+    // Scales the view by factor (> 1 zooms in) keeping the point under the pixel in place
+    pub fn zoom_at(&mut self, x: Real, y: Real, factor: Real) {
+        let offset = self.pixel_to_offset(x, y);
+        self.pan(Vec2::<Real>{x: offset.x * (1.0 - 1.0 / factor), y: offset.y * (1.0 - 1.0 / factor)});
+        self.view_size.x /= factor;
+        self.view_size.y /= factor;
+    }
+
+    // Whether the iteration tracks the derivative of z
+    pub fn tracks_derivative(&self) -> bool {
+        self.track_derivative || self.coloring.needs_derivative() || self.lighting.is_some()
+    }
+
+    // Whether the iteration measures the orbit against the orbit trap
+    pub fn tracks_trap(&self) -> bool {
+        self.coloring.needs_trap()
+    }
+
+    // Distance of an orbit value to the trap the coloring measures the orbit against
+    pub fn trap_distance(&self, z: &Complex<Real>) -> Real {
+        if self.coloring == Coloring::Stalks { self.stalks.distance(z) } else { self.orbit_trap.distance(z) }
+    }
+
+    // Image the iteration looks for the first orbit value on, None when the coloring needs none
+    pub fn tracked_trap_image(&self) -> Option<&TrapImage> {
+        self.trap_image.as_ref().filter(|_| self.coloring.needs_trap_image())
+    }
+
+    // Whether the iteration finds the atom domain
+    pub fn tracks_atom_domain(&self) -> bool {
+        self.coloring.needs_atom_domain()
+    }
+
+    // Whether interior samples have to be iterated instead of skipped by the cardioid test
+    pub fn iterates_interior(&self) -> bool {
+        self.interior.needs_orbit() || self.tracks_atom_domain()
+    }
+
+    // Whether the iteration averages a quantity over the orbit
+    pub fn tracks_average(&self) -> bool {
+        self.coloring.needs_average()
+    }
+
+    // Bailout radius of the iteration
+    pub fn max_length(&self) -> Real {
+        if let Some(radius) = self.escape_radius {
+            return radius;
+        }
+        // Normals need the same large radius as the distance estimate
+        let max_length = self.coloring.max_length(MAX_LENGTH);
+        if self.lighting.is_some() { max_length.max(SMOOTH_MAX_LENGTH) } else { max_length }
+    }
+
+    // Whether conjugate points get the same color, so the image is mirrored about the real axis
+    pub fn is_conjugate_symmetric(&self) -> bool {
+        let fractal = match self.fractal {
+            Fractal::Mandelbrot | Fractal::Multibrot | Fractal::Tricorn | Fractal::Celtic => true,
+            Fractal::Julia => self.julia_c.y == 0.0,
+            Fractal::Phoenix => self.julia_c.y == 0.0 && self.phoenix_p.y == 0.0,
+            Fractal::Nova => self.relaxation.y == 0.0,
+            Fractal::MagnetI | Fractal::MagnetII => true,
+            // Conjugate roots get different colors
+            Fractal::BurningShip | Fractal::Formula | Fractal::Newton => false,
+            // The axes are a and b, the 3D fractals are seen from the camera
+            Fractal::Lyapunov | Fractal::Mandelbulb | Fractal::QuaternionJulia => false,
+            Fractal::Hybrid => self.hybrid.as_ref().is_some_and(|hybrid| hybrid.steps().iter().all(|step| *step != Fractal::BurningShip)),
+        };
+        // The angle of z changes sign and the light comes from one side
+        let coloring = !matches!(self.coloring, Coloring::Stripe | Coloring::Binary | Coloring::Angle) && self.lighting.is_none();
+        let trap = match self.coloring {
+            Coloring::OrbitTrap => self.orbit_trap.position.y == 0.0,
+            // Pictures aren't mirrored
+            Coloring::ImageTrap => false,
+            _ => true,
+        };
+        fractal && coloring && trap
+    }
+
+    // Rows to render when the view is mirrored about the real axis onto whole pixel rows, None
+    // when every row has to be rendered
+    fn mirrored_rows(&self) -> Option<MirroredRows> {
+        if !self.symmetry || self.rotation != 0.0 || self.skew.is_some() || !self.is_conjugate_symmetric() {
+            return None;
+        }
+        // Row y shows the conjugate of row mirror - y, the pixel rows only line up when the
+        // center is a whole number of half pixels away from the axis
+        let height = self.height as isize;
+        let shift = 2.0 * self.center.y.to_real() / (self.view_size.y / self.height as Real);
+        if shift.abs() >= (2 * height) as Real || (shift - shift.round()).abs() > 1e-6 {
+            return None;
+        }
+        let mirror = height - 1 + shift.round() as isize;
+        if mirror < 0 || mirror > 2 * (height - 1) {
+            return None;
+        }
+        // Render the half next to the rows without a mirror image, tile aligned for checkpoints
+        let (first, end) = if mirror >= height - 1 {
+            (0, mirror as usize / 2 + 1)
+        }
+        else {
+            ((mirror as usize).div_ceil(2) / TILE_HEIGHT * TILE_HEIGHT, self.height)
+        };
+        if end - first >= self.height {
+            return None;
+        }
+        Some(MirroredRows {first, count: end - first, mirror: mirror as usize})
+    }
+
+    // Copy with the palette and interior color decoded to linear light, colorings of it give
+    // linear colors
+    pub(crate) fn linear_colors(&self) -> RenderSettings {
+        let mut settings = self.clone();
+        for color in settings.palette.colors.iter_mut() {
+            *color = self.gamma.decode_color(*color);
+        }
+        settings.interior_color = self.gamma.decode_color(self.interior_color);
+        for color in settings.root_colors.iter_mut() {
+            *color = self.gamma.decode_color(*color);
+        }
+        settings.stalks.color = self.gamma.decode_color(self.stalks.color);
+        settings.trap_image = self.trap_image.as_ref().map(|image| image.decoded(self.gamma));
+        settings
+    }
+
+    // Number of BigFloat limbs needed to resolve the pixel spacing
+    pub fn arbitrary_limbs(&self) -> usize {
+        let bits = -self.pixel_size().log2();
+        BigFloat::limbs_for_bits(bits.max(0.0).ceil() as usize + 32)
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings::new()
+    }
+}
+
+#[derive(Copy, Clone)]
+struct ThreadDescryptor {
+    offset: Vec2<usize>,
+    thread_size: Vec2<usize>,
+}
+
+// Iteration count at which an orbit escaped (max_iterations if it didn't) and its last value
+#[derive(Copy, Clone)]
+pub struct EscapeResult {
+    pub iterations: u32,
+    pub z: Complex<Real>,
+    // Derivative of z with respect to the pixel, only tracked when the coloring needs it
+    pub derivative: Complex<Real>,
+    // Smallest distance of the orbit to the orbit trap, only tracked when the coloring needs it
+    pub trap: Real,
+    // First orbit value on an opaque pixel of the trap image, NaN when the orbit missed it, only
+    // tracked when the coloring needs it
+    pub trap_point: Complex<Real>,
+    // Iteration at which |z| was smallest, the atom domain of the sample, only tracked when the
+    // coloring needs it
+    pub atom_domain: u32,
+    // Period of the cycle of interior samples, 0 when unknown or not tracked
+    pub period: u32,
+    // Interior distance estimate, only tracked when the interior coloring needs it
+    pub interior_distance: Real,
+    // Sum and last term of the quantity averaged over the orbit by the stripe and triangle
+    // inequality average colorings, only tracked when the coloring needs them
+    pub average_sum: Real,
+    pub average_last: Real,
+}
+
+// Brent's cycle detection: compares the orbit with the value saved at the last power of two
+// iteration, a match within the tolerance means the orbit is periodic and never escapes
+pub struct Periodicity<T> {
+    saved: Complex<T>,
+    saved_iteration: u32,
+    next_save: u32,
+    tolerance_squared: Real,
+}
+
+impl<T: Float> Periodicity<T> {
+    // start is the first value of the orbit
+    pub fn new(settings: &RenderSettings, start: Complex<T>) -> Periodicity<T> {
+        Periodicity::starting_at(settings, start, 0)
+    }
+
+    // Detection for an orbit whose iterations before iteration were skipped, start being its
+    // value at iteration
+    pub fn starting_at(settings: &RenderSettings, start: Complex<T>, iteration: u32) -> Periodicity<T> {
+        let tolerance = settings.pixel_size() * 1e-4;
+        Periodicity {saved: start, saved_iteration: iteration, next_save: (iteration + 1).next_power_of_two(), tolerance_squared: tolerance*tolerance}
+    }
+
+    pub fn is_periodic(&mut self, z: &Complex<T>, iteration: u32) -> bool {
+        let difference = *z - self.saved;
+        if difference.length_squared().to_real() < self.tolerance_squared {
+            return true;
+        }
+        if iteration == self.next_save {
+            self.saved = *z;
+            self.saved_iteration = iteration;
+            self.next_save = self.next_save.saturating_mul(2);
+        }
+        false
+    }
+
+    // Period and interior distance of an orbit found periodic at iteration, z being its last value
+    pub fn interior(&self, settings: &RenderSettings, z: Complex<Real>, c: Complex<Real>, iteration: u32) -> (u32, Real) {
+        if !settings.interior.needs_period() {
+            return (0, Real::INFINITY);
+        }
+        // The distance between the matches is a multiple of the period
+        let candidate = iteration - self.saved_iteration;
+        let period = if settings.fractal == Fractal::Formula {
+            candidate
+        }
+        else {
+            interior::minimal_period(settings, z, c, candidate, self.tolerance_squared.sqrt() * 10.0)
+        };
+        (period, interior::interior_distance(settings, z, c, period))
+    }
+}
+
+// Iterates the formula for the point at position on the complex plane
+pub fn escape_time<T: Float>(settings: &RenderSettings, position: Complex<T>) -> EscapeResult {
+    let limit: T = settings.bailout.limit(settings.max_length());
+    let mut iterations: u32 = 0;
+    let (mut temp, c) = if settings.fractal.is_julia() {
+        (position, Complex::new(T::from_real(settings.julia_c.x), T::from_real(settings.julia_c.y)))
+    }
+    else {
+        let start = settings.fractal.start();
+        (Complex::new(T::from_real(start.r), T::from_real(start.i)), position)
+    };
+    let mut previous = Complex::new(T::from_real(0.0), T::from_real(0.0));
+    let converges = settings.fractal.converges();
+    // A repeated z doesn't close a cycle of the phoenix, whose state includes the previous z,
+    // nor of a hybrid at another position in its pattern
+    let check_period = !matches!(settings.fractal, Fractal::Phoenix | Fractal::Hybrid);
+    let track_derivative = settings.tracks_derivative();
+    let track_trap = settings.tracks_trap();
+    let track_average = settings.tracks_average();
+    let mut derivative = settings.fractal.derivative_start();
+    let trap_image = settings.tracked_trap_image();
+    let track_atom_domain = settings.tracks_atom_domain();
+    let mut trap = Real::INFINITY;
+    let mut trap_point = Complex::new(Real::NAN, Real::NAN);
+    let (mut atom_domain, mut atom_length) = (0, Real::INFINITY);
+    let (mut average_sum, mut average_last) = (0.0, 0.0);
+    let mut periodicity = Periodicity::new(settings, temp);
+    let (mut period, mut interior_distance) = (0, Real::INFINITY);
+    while !settings.bailout.escaped(&temp, limit) && iterations < settings.max_iterations {
+        if track_derivative {
+            derivative = settings.step_fractal(iterations).next_derivative(settings, &temp.to_real(), &c.to_real(), &derivative);
+        }
+        let next = settings.step_fractal(iterations).step(settings, &temp, &previous, &c);
+        previous = temp;
+        temp = next;
+        iterations += 1;
+        if converges {
+            // Orbits settling on a fixed point end like escaping ones. Their z becomes the
+            // inverse of the last step, which shrinks quadratically like an escaping z grows,
+            // so the smooth colorings stay continuous.
+            let step = (temp - previous).to_real();
+            if step.length_squared() < CONVERGENCE_EPSILON * CONVERGENCE_EPSILON {
+                let z = Complex::from_real(1.0) / step;
+                return EscapeResult {iterations, z, derivative, trap, trap_point, atom_domain, period, interior_distance, average_sum, average_last};
+            }
+            // Poles of the rational formulas leave NaN, which neither escapes nor settles
+            if temp.to_real().r.is_nan() || temp.to_real().i.is_nan() {
+                iterations = settings.max_iterations;
+                break;
+            }
+        }
+        if track_trap {
+            trap = trap.min(settings.trap_distance(&temp.to_real()));
+        }
+        if let Some(image) = trap_image {
+            if trap_point.r.is_nan() && image.covers(&settings.orbit_trap, &temp.to_real()) {
+                trap_point = temp.to_real();
+            }
+        }
+        if track_atom_domain && temp.to_real().length_squared() < atom_length {
+            atom_length = temp.to_real().length_squared();
+            atom_domain = iterations;
+        }
+        if track_average && iterations > 1 {
+            let term = settings.orbit_average.term(settings.coloring, &temp.to_real(), &c.to_real());
+            average_sum += term;
+            average_last = term;
+        }
+        if check_period && periodicity.is_periodic(&temp, iterations) {
+            let (cycle_period, distance) = periodicity.interior(settings, temp.to_real(), c.to_real(), iterations);
+            period = cycle_period;
+            interior_distance = distance;
+            iterations = settings.max_iterations;
+            break;
+        }
+    }
+    EscapeResult {iterations, z: temp.to_real(), derivative, trap, trap_point, atom_domain, period, interior_distance, average_sum, average_last}
+}
+
+// Iterates the user formula at f64 precision for the pixel at position
+fn formula_escape_time(settings: &RenderSettings, formula: &Formula, position: Complex<Real>) -> EscapeResult {
+    let limit = settings.bailout.limit(settings.max_length());
+    let track_derivative = settings.tracks_derivative();
+    let track_trap = settings.tracks_trap();
+    let track_average = settings.tracks_average();
+    let mut z = Complex::new(0.0, 0.0);
+    let mut derivative = Complex::new(0.0, 0.0);
+    let trap_image = settings.tracked_trap_image();
+    let track_atom_domain = settings.tracks_atom_domain();
+    let mut trap = Real::INFINITY;
+    let mut trap_point = Complex::new(Real::NAN, Real::NAN);
+    let (mut atom_domain, mut atom_length) = (0, Real::INFINITY);
+    let (mut average_sum, mut average_last) = (0.0, 0.0);
+    let mut iterations = 0;
+    let mut periodicity = Periodicity::new(settings, z);
+    let (mut period, mut interior_distance) = (0, Real::INFINITY);
+    while !settings.bailout.escaped(&z, limit) && iterations < settings.max_iterations {
+        let (next, next_derivative) = if track_derivative {
+            formula.step::<true>(&z, &derivative, &position)
+        }
+        else {
+            formula.step::<false>(&z, &derivative, &position)
+        };
+        z = next;
+        derivative = next_derivative;
+        iterations += 1;
+        if track_trap {
+            trap = trap.min(settings.trap_distance(&z));
+        }
+        if let Some(image) = trap_image {
+            if trap_point.r.is_nan() && image.covers(&settings.orbit_trap, &z) {
+                trap_point = z;
+            }
+        }
+        if track_atom_domain && z.length_squared() < atom_length {
+            atom_length = z.length_squared();
+            atom_domain = iterations;
+        }
+        if track_average && iterations > 1 {
+            let term = settings.orbit_average.term(settings.coloring, &z, &position);
+            average_sum += term;
+            average_last = term;
+        }
+        // NaN never escapes nor cycles
+        if z.r.is_nan() || z.i.is_nan() {
+            iterations = settings.max_iterations;
+            break;
+        }
+        if periodicity.is_periodic(&z, iterations) {
+            let (cycle_period, distance) = periodicity.interior(settings, z, position, iterations);
+            period = cycle_period;
+            interior_distance = distance;
+            iterations = settings.max_iterations;
+            break;
+        }
+    }
+    EscapeResult {iterations, z, derivative, trap, trap_point, atom_domain, period, interior_distance, average_sum, average_last}
+}
+
+// Orbit of the point at the offset from the view center at f64 precision, from the start value to
+// the escape, the fixed point of converging formulas or the iteration limit
+pub fn orbit_at(settings: &RenderSettings, offset: Vec2<Real>) -> Result<Vec<Complex<Real>>, String> {
+    if matches!(settings.fractal, Fractal::Newton | Fractal::Lyapunov) || settings.fractal.is_3d() {
+        return Err(format!("{} fractals have no orbit of z", settings.fractal));
+    }
+    let position: Complex<Real> = position(&settings.center, offset);
+    let limit = settings.bailout.limit(settings.max_length());
+    let formula = settings.formula.as_ref().filter(|_| settings.fractal == Fractal::Formula);
+    let (mut z, c) = if formula.is_some() {
+        (Complex::new(0.0, 0.0), position)
+    }
+    else if settings.fractal.is_julia() {
+        (position, Complex::new(settings.julia_c.x, settings.julia_c.y))
+    }
+    else {
+        (settings.fractal.start(), position)
+    };
+    let mut previous = Complex::new(0.0, 0.0);
+    let mut orbit = vec![z];
+    while !settings.bailout.escaped(&z, limit) && orbit.len() <= settings.max_iterations as usize {
+        let next = match formula {
+            Some(formula) => formula.step::<false>(&z, &z, &c).0,
+            None => settings.step_fractal(orbit.len() as u32 - 1).step(settings, &z, &previous, &c),
+        };
+        previous = z;
+        z = next;
+        if z.r.is_nan() || z.i.is_nan() {
+            break;
+        }
+        orbit.push(z);
+        if settings.fractal.converges() && (z - previous).length_squared() < CONVERGENCE_EPSILON * CONVERGENCE_EPSILON {
+            break;
+        }
+    }
+    Ok(orbit)
+}
+
+// Analytic test for the main cardioid and the period-2 bulb, whose points never escape
+fn in_cardioid_or_bulb(c: &Complex<Real>) -> bool {
+    let x = c.r - 0.25;
+    let y_squared = c.i*c.i;
+    let q = x*x + y_squared;
+    q * (q + x) <= 0.25 * y_squared || (c.r + 1.0)*(c.r + 1.0) + y_squared <= 0.0625
+}
+
+fn position<T: Float>(center: &Vec2<BigFloat>, offset: Vec2<Real>) -> Complex<T> {
+    Complex::new(T::from_big(&center.x) + T::from_real(offset.x), T::from_big(&center.y) + T::from_real(offset.y))
+}
+
+// Maps the offset from the view center to the complex plane using the given precision,
+// limbs is the BigFloat size of the arbitrary precisions
+pub fn escape_time_at(settings: &RenderSettings, precision: Precision, limbs: usize, offset: Vec2<Real>) -> EscapeResult {
+    if let (Fractal::Formula, Some(formula)) = (settings.fractal, &settings.formula) {
+        return formula_escape_time(settings, formula, position(&settings.center, offset));
+    }
+    if let (Fractal::Newton, Some(polynomial)) = (settings.fractal, &settings.polynomial) {
+        return polynomial.escape_time(settings, position(&settings.center, offset));
+    }
+    if let (Fractal::Lyapunov, Some(sequence)) = (settings.fractal, &settings.lyapunov_sequence) {
+        return sequence.escape_time(settings, position(&settings.center, offset));
+    }
+    if settings.fractal.is_3d() {
+        return raymarch::escape_time(settings, position(&settings.center, offset));
+    }
+    match precision {
+        Precision::Single => escape_time(settings, position::<f32>(&settings.center, offset)),
+        Precision::Auto | Precision::Double => escape_time(settings, position::<f64>(&settings.center, offset)),
+        Precision::DoubleDouble => escape_time(settings, position::<DoubleDouble>(&settings.center, offset)),
+        Precision::Arbitrary | Precision::Perturbation => {
+            let c: Complex<BigFloat> = position(&settings.center, offset);
+            escape_time(settings, Complex::new(c.r.with_precision(limbs), c.i.with_precision(limbs)))
+        }
+    }
+}
+
+// State shared by every tile of a render
+struct RenderContext<'a> {
+    settings: &'a RenderSettings,
+    precision: Precision,
+    limbs: usize,
+    reference: Option<ReferenceOrbit>,
+    histogram: Option<Histogram>,
+    // Seeds the per pixel sample positions so later passes can continue the sequence
+    seed: u64,
+    // View center rounded to f64, precise enough for the interior test
+    center: Complex<Real>,
+    // Whether samples go through the vectorized f64 kernel
+    vectorize: bool,
+    // End of the time limit, later pixels are sampled only once
+    deadline: Option<time::Instant>,
+    // Samples of the rendered pixels and pixels that got fewer than requested because of the deadline
+    sample_total: AtomicUsize,
+    reduced_pixels: AtomicUsize,
+    // Stops the render like the interrupt of the settings
+    cancel: Option<&'a AtomicBool>,
+    // Receives every finished tile
+    tiles: Option<&'a mpsc::Sender<RenderedTile>>,
+    // Reduced tiles finished since the last progress report
+    previews: Mutex<Vec<RenderedTile>>,
+    mirrored: Option<MirroredRows>,
+    // Encoded colors of the pixels that are taken as they are instead of sampled, row major
+    // over the whole image
+    guide: Option<&'a [Option<Color>]>,
+    // Settings the samples are colored with
+    linear: RenderSettings,
+}
+
+impl<'a> RenderContext<'a> {
+    fn new(settings: &'a RenderSettings) -> RenderContext<'a> {
+        // WebAssembly has no clock in std, time limits are ignored there
+        let deadline = if cfg!(target_arch = "wasm32") { None } else { settings.time_limit.map(|limit| time::Instant::now() + limit) };
+        let precision = settings.effective_precision();
+        let limbs = settings.arbitrary_limbs();
+        // Supersampled renders evaluate nothing at the output resolution
+        let enlarged = settings.supersample > 1;
+        let reference = match precision {
+            Precision::Perturbation if !enlarged => {
+                let mut reference = ReferenceOrbit::new(settings, Vec2::<Real>::new(), limbs);
+                if settings.series_approximation {
+                    // The corners and edge centers of the view bound the deltas of every sample
+                    let (width, height) = (settings.width as Real, settings.height as Real);
+                    let probes: Vec<Vec2<Real>> = [(0.0, 0.0), (0.5, 0.0), (1.0, 0.0), (0.0, 0.5), (1.0, 0.5), (0.0, 1.0), (0.5, 1.0), (1.0, 1.0)].iter()
+                        .map(|&(x, y)| settings.pixel_to_offset(x * width, y * height))
+                        .collect();
+                    reference.approximate(settings, &probes);
+                }
+                Some(reference)
+            }
+            _ => None,
+        };
+        let seed = settings.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let mut context = RenderContext {settings, precision, limbs, reference, histogram: None, seed,
+            center: Complex::new(settings.center.x.to_real(), settings.center.y.to_real()),
+            vectorize: settings.simd && simd::available() && precision == Precision::Double && settings.fractal.is_quadratic()
+                && settings.bailout == Bailout::Euclidean
+                && !settings.tracks_derivative() && !settings.tracks_trap() && !settings.tracks_average() && !settings.iterates_interior(),
+            deadline, sample_total: AtomicUsize::new(0), reduced_pixels: AtomicUsize::new(0),
+            cancel: None, tiles: None, previews: Mutex::new(Vec::new()), mirrored: settings.mirrored_rows(), guide: None, linear: settings.linear_colors()};
+        if settings.coloring == Coloring::Histogram && !enlarged {
+            context.histogram = Some(context.build_histogram());
+        }
+        context
+    }
+
+    fn cancelled(&self) -> bool {
+        self.settings.is_interrupted() || self.cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    // Sends a finished tile and the rows mirrored from it to the tile receiver and queues
+    // their reduced versions for the next progress report
+    fn send_tile(&self, desc: &ThreadDescryptor, colors: &[Color]) {
+        let (width, height) = (desc.thread_size.x, desc.thread_size.y);
+        let tile = RenderedTile {x: desc.offset.x, y: desc.offset.y, width, height, pixels: colors.to_vec()};
+        let mut tiles = vec![tile];
+        if let Some(rows) = &self.mirrored {
+            // Row y shows row mirror - y, the mirrored rows are consecutive
+            let mut mirror = RenderedTile {x: desc.offset.x, y: 0, width, height: 0, pixels: Vec::new()};
+            for row in (0..height).rev() {
+                let y = match rows.mirror.checked_sub(desc.offset.y + row) {
+                    Some(y) if y < self.settings.height && (y < rows.first || y >= rows.first + rows.count) => y,
+                    _ => continue,
+                };
+                if mirror.height == 0 {
+                    mirror.y = y;
+                }
+                mirror.height += 1;
+                mirror.pixels.extend_from_slice(&colors[row * width..(row + 1) * width]);
+            }
+            if mirror.height > 0 {
+                tiles.push(mirror);
+            }
+        }
+        self.previews.lock().unwrap().extend(tiles.iter().map(|tile| tile.reduced(PREVIEW_SCALE)));
+        if let Some(sender) = self.tiles {
+            for tile in tiles {
+                // The receiver may be gone already
+                let _ = sender.send(tile);
+            }
+        }
+    }
+
+    fn expired(&self) -> bool {
+        self.deadline.is_some_and(|deadline| time::Instant::now() >= deadline)
+    }
+
+    // Estimates the iteration distribution from a coarse grid of samples over the view
+    fn build_histogram(&self) -> Histogram {
+        const GRID_SIZE: usize = 128;
+        let settings = self.settings;
+        let columns = settings.width.min(GRID_SIZE);
+        let rows = settings.height.min(GRID_SIZE);
+        let mut evaluator = Evaluator::new(self);
+        let mut results = Vec::with_capacity(columns * rows);
+        for y in 0..rows {
+            for x in 0..columns {
+                let offset = settings.pixel_to_offset(
+                    (x as Real + 0.5) * settings.width as Real / columns as Real,
+                    (y as Real + 0.5) * settings.height as Real / rows as Real);
+                results.push(evaluator.escape_time(offset));
+            }
+        }
+        Histogram::new(&results, settings.max_iterations)
+    }
+}
+
+// Per thread view of the render context
+struct Evaluator<'a> {
+    context: &'a RenderContext<'a>,
+    perturbation: Option<Perturbation<'a>>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(context: &'a RenderContext<'a>) -> Evaluator<'a> {
+        Evaluator {context, perturbation: context.reference.as_ref().map(Perturbation::new)}
+    }
+
+    fn interior(&self) -> EscapeResult {
+        EscapeResult {iterations: self.context.settings.max_iterations, z: Complex::new(0.0, 0.0), derivative: Complex::new(0.0, 0.0), trap: Real::INFINITY,
+            trap_point: Complex::new(Real::NAN, Real::NAN), atom_domain: 0, period: 0, interior_distance: Real::INFINITY, average_sum: 0.0, average_last: 0.0}
+    }
+
+    fn is_known_interior(&self, offset: Vec2<Real>) -> bool {
+        let context = self.context;
+        // Interior colorings other than flat ones need the orbit
+        context.settings.fractal == Fractal::Mandelbrot && !context.settings.iterates_interior() && in_cardioid_or_bulb(&Complex::new(context.center.r + offset.x, context.center.i + offset.y))
+    }
+
+    fn escape_time(&mut self, offset: Vec2<Real>) -> EscapeResult {
+        let context = self.context;
+        if self.is_known_interior(offset) {
+            return self.interior();
+        }
+        let perturbed = self.perturbation.as_mut().and_then(|p| p.escape_time(context.settings, offset, context.limbs));
+        match perturbed {
+            Some(result) => result,
+            None => escape_time_at(context.settings, context.precision, context.limbs, offset),
+        }
+    }
+
+    // Evaluates every offset, four at a time through the vectorized kernel when possible
+    fn escape_time_batch(&mut self, offsets: &[Vec2<Real>], results: &mut Vec<EscapeResult>) {
+        results.clear();
+        if !self.context.vectorize {
+            for offset in offsets {
+                results.push(self.escape_time(*offset));
+            }
+            return;
+        }
+        results.resize(offsets.len(), self.interior());
+        let center = self.context.center;
+        let mut lanes = [0usize; 4];
+        let mut positions = [Complex::new(0.0, 0.0); 4];
+        let mut count = 0;
+        for (index, offset) in offsets.iter().enumerate() {
+            if self.is_known_interior(*offset) {
+                continue;
+            }
+            lanes[count] = index;
+            positions[count] = Complex::new(center.r + offset.x, center.i + offset.y);
+            count += 1;
+            if count == 4 {
+                let lane_results = simd::escape_time_x4(self.context.settings, &positions);
+                for (lane, result) in lanes.iter().zip(lane_results.iter()) {
+                    results[*lane] = *result;
+                }
+                count = 0;
+            }
+        }
+        for lane in &lanes[..count] {
+            results[*lane] = self.escape_time(offsets[*lane]);
+        }
+    }
+
+    fn color(&self, result: &EscapeResult) -> Color {
+        let settings = &self.context.linear;
+        settings.coloring.color(settings, result, self.context.histogram.as_ref())
+    }
+}
+
+// Running color statistics of the samples of a pixel
+#[derive(Copy, Clone, Default)]
+struct PixelSamples {
+    sum: Color,
+    sum_squares: Color,
+    count: usize,
+    // Iteration count of the first sample and whether any other sample differed from it
+    iterations: u32,
+    mixed: bool,
+}
+
+impl PixelSamples {
+    fn add(&mut self, color: Color, iterations: u32) {
+        if self.count == 0 {
+            self.iterations = iterations;
+        }
+        else if iterations != self.iterations {
+            self.mixed = true;
+        }
+        self.sum.add(color);
+        self.sum_squares.add(Color {r: color.r*color.r, g: color.g*color.g, b: color.b*color.b, a: color.a*color.a});
+        self.count += 1;
+    }
+
+    fn mean(&self) -> Color {
+        let mut mean = self.sum;
+        mean.divide(self.count as Real);
+        mean
+    }
+
+    // Largest standard error of the mean over the color channels
+    fn error(&self) -> Real {
+        let n = self.count as Real;
+        let mean = self.mean();
+        let variance = |sum_squares: Real, mean: Real| (sum_squares / n - mean*mean).max(0.0);
+        let largest = variance(self.sum_squares.r, mean.r)
+            .max(variance(self.sum_squares.g, mean.g))
+            .max(variance(self.sum_squares.b, mean.b));
+        (largest / n).sqrt()
+    }
+
+    // Iteration count of a pixel whose color is determined by it alone
+    fn uniform_iterations(&self, settings: &RenderSettings) -> Option<u32> {
+        let flat = if self.iterations >= settings.max_iterations {
+            !settings.iterates_interior()
+        }
+        else {
+            // Newton, Lyapunov and 3D fractals ignore the coloring
+            settings.coloring == Coloring::Banded && settings.lighting.is_none() && !matches!(settings.fractal, Fractal::Newton | Fractal::Lyapunov) && !settings.fractal.is_3d()
+        };
+        if self.mixed || !flat { None } else { Some(self.iterations) }
+    }
+}
+
+fn color_difference(a: &Color, b: &Color) -> Real {
+    (a.r - b.r).abs().max((a.g - b.g).abs()).max((a.b - b.b).abs())
+}
+
+// Rectangles at or below this size are sampled completely instead of subdivided
+const MIN_SUBDIVISION_SIZE: usize = 4;
+
+// Samples the pixels of a single tile
+struct TileRenderer<'a> {
+    context: &'a RenderContext<'a>,
+    desc: &'a ThreadDescryptor,
+    evaluator: Evaluator<'a>,
+    points: Vec<Vec2<Real>>,
+    offsets: Vec<Vec2<Real>>,
+    results: Vec<EscapeResult>,
+    samples: Vec<PixelSamples>,
+    // Escape results of every sample per pixel, only kept for an iteration cache
+    records: Option<Vec<Vec<EscapeResult>>>,
+    min_samples: usize,
+    // Colors the pixels take from the guide of the context instead of being sampled
+    guided: Vec<Option<Color>>,
+}
+
+impl<'a> TileRenderer<'a> {
+    fn new(context: &'a RenderContext<'a>, desc: &'a ThreadDescryptor, keep_records: bool) -> TileRenderer<'a> {
+        let settings = context.settings;
+        let max_samples = settings.sample_count.max(1);
+        let (width, height) = (desc.thread_size.x, desc.thread_size.y);
+        let guided = match context.guide {
+            Some(guide) => (0..width * height).map(|index| guide[(index / width + desc.offset.y) * settings.width + index % width + desc.offset.x]).collect(),
+            None => vec![None; width * height],
+        };
+        TileRenderer {
+            context,
+            desc,
+            evaluator: Evaluator::new(context),
+            points: Vec::with_capacity(max_samples),
+            offsets: Vec::with_capacity(max_samples),
+            results: Vec::with_capacity(max_samples),
+            samples: vec![PixelSamples::default(); desc.thread_size.x * desc.thread_size.y],
+            records: if keep_records { Some(vec![Vec::new(); desc.thread_size.x * desc.thread_size.y]) } else { None },
+            min_samples: settings.min_sample_count.clamp(1, max_samples),
+            guided,
+        }
+    }
+
+    // Takes the samples [first:last) of the pixel's sequence
+    fn sample(&mut self, x: usize, y: usize, first: usize, last: usize) {
+        let settings = self.context.settings;
+        let (image_x, image_y) = (x + self.desc.offset.x, y + self.desc.offset.y);
+        let mut rng = StdRng::seed_from_u64(pixel_seed(self.context.seed, image_x, image_y));
+        settings.sampler.fill(&mut rng, settings.sample_count.max(1), &mut self.points);
+        self.offsets.clear();
+        for point in &self.points[first..last] {
+            self.offsets.push(settings.pixel_to_offset(image_x as Real + point.x, image_y as Real + point.y));
+        }
+        self.evaluator.escape_time_batch(&self.offsets, &mut self.results);
+        let index = y * self.desc.thread_size.x + x;
+        let pixel = &mut self.samples[index];
+        for result in &self.results {
+            pixel.add(self.evaluator.color(result).premultiplied(), result.iterations);
+        }
+        if let Some(records) = self.records.as_mut() {
+            records[index].extend_from_slice(&self.results);
+        }
+    }
+
+    // Gives an unsampled pixel the minimum sample count, or a single sample past the deadline
+    fn sample_once(&mut self, x: usize, y: usize) {
+        let index = y * self.desc.thread_size.x + x;
+        if self.samples[index].count == 0 && self.guided[index].is_none() {
+            let count = if self.context.expired() { 1 } else { self.min_samples };
+            self.sample(x, y, 0, count);
+        }
+    }
+
+    // Mariani-Silver: a rectangle whose border has a single uniform iteration count is
+    // filled without sampling its inside, otherwise it is split in two and retried.
+    // The range [x0:x1)x[y0:y1) includes the border.
+    fn subdivide(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        let width = self.desc.thread_size.x;
+        for x in x0..x1 {
+            self.sample_once(x, y0);
+            self.sample_once(x, y1 - 1);
+        }
+        for y in y0..y1 {
+            self.sample_once(x0, y);
+            self.sample_once(x1 - 1, y);
+        }
+        if x1 - x0 <= 2 || y1 - y0 <= 2 {
+            return;
+        }
+
+        let settings = self.context.settings;
+        let border = self.samples[y0 * width + x0];
+        if let Some(iterations) = border.uniform_iterations(settings) {
+            // Guided pixels have no iteration counts
+            let same = |x: usize, y: usize| self.guided[y * width + x].is_none() && self.samples[y * width + x].uniform_iterations(settings) == Some(iterations);
+            let uniform = (x0..x1).all(|x| same(x, y0) && same(x, y1 - 1))
+                && (y0..y1).all(|y| same(x0, y) && same(x1 - 1, y));
+            if uniform {
+                for y in y0+1..y1-1 {
+                    for x in x0+1..x1-1 {
+                        self.samples[y * width + x] = border;
+                        if let Some(records) = self.records.as_mut() {
+                            records[y * width + x] = records[y0 * width + x0].clone();
+                        }
+                    }
+                }
+                return;
+            }
+        }
+
+        if x1 - x0 <= MIN_SUBDIVISION_SIZE || y1 - y0 <= MIN_SUBDIVISION_SIZE {
+            for y in y0+1..y1-1 {
+                for x in x0+1..x1-1 {
+                    self.sample_once(x, y);
+                }
+            }
+        }
+        // The halves share the dividing line
+        else if x1 - x0 >= y1 - y0 {
+            let middle = (x0 + x1) / 2;
+            self.subdivide(x0, y0, middle + 1, y1);
+            self.subdivide(middle, y0, x1, y1);
+        }
+        else {
+            let middle = (y0 + y1) / 2;
+            self.subdivide(x0, y0, x1, middle + 1);
+            self.subdivide(x0, middle, x1, y1);
+        }
+    }
+
+    // Renders the tile, counting every finished pixel in finished_pixels.
+    // Every pixel gets the minimum sample count first, pixels that are noisy or differ from
+    // their neighbours are then refined up to the maximum sample count. Past the deadline
+    // pixels keep the samples they have.
+    fn render(mut self, finished_pixels: &AtomicUsize) -> (Vec<Color>, Option<Vec<Vec<EscapeResult>>>) {
+        let settings = self.context.settings;
+        let (width, height) = (self.desc.thread_size.x, self.desc.thread_size.y);
+        let max_samples = settings.sample_count.max(1);
+
+        if settings.subdivision {
+            self.subdivide(0, 0, width, height);
+        }
+        else {
+            for y in 0..height {
+                for x in 0..width {
+                    self.sample_once(x, y);
+                }
+            }
+        }
+        let context = self.context;
+        let guided = std::mem::take(&mut self.guided);
+        if self.min_samples == max_samples {
+            context.sample_total.fetch_add(self.samples.iter().map(|pixel| pixel.count).sum(), Ordering::Relaxed);
+            context.reduced_pixels.fetch_add(self.samples.iter().zip(&guided).filter(|(pixel, color)| color.is_none() && pixel.count < max_samples).count(), Ordering::Relaxed);
+            finished_pixels.fetch_add(width * height, Ordering::Release);
+        }
+        else {
+            let means: Vec<Color> = self.samples.iter().map(|pixel| pixel.mean()).collect();
+            let threshold = settings.sample_threshold;
+            for y in 0..height {
+                let (mut row_samples, mut reduced) = (0, 0);
+                for x in 0..width {
+                    if guided[y * width + x].is_some() {
+                        continue;
+                    }
+                    let mean = &means[y * width + x];
+                    let noisy = self.samples[y * width + x].error() > threshold;
+                    // Guided colors are interpolated, they would make every sampled pixel next to
+                    // them look like an edge
+                    let edge = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)].iter()
+                        .filter(|&&(nx, ny)| nx < width && ny < height && guided[ny * width + nx].is_none())
+                        .any(|&(nx, ny)| color_difference(mean, &means[ny * width + nx]) > threshold);
+                    let target = if noisy || edge { max_samples } else { self.min_samples };
+                    if target > self.min_samples && !context.expired() {
+                        self.sample(x, y, self.min_samples, max_samples);
+                    }
+                    let count = self.samples[y * width + x].count;
+                    row_samples += count;
+                    if count < target {
+                        reduced += 1;
+                    }
+                }
+                context.sample_total.fetch_add(row_samples, Ordering::Relaxed);
+                context.reduced_pixels.fetch_add(reduced, Ordering::Relaxed);
+                // Release makes the sample counts visible along with the finished pixels
+                finished_pixels.fetch_add(width, Ordering::Release);
+            }
+        }
+
+        let gamma = settings.gamma;
+        let colors = self.samples.iter().zip(&guided).map(|(pixel, color)| match color {
+            Some(color) => *color,
+            None => gamma.encode_color(pixel.mean().unpremultiplied()),
+        }).collect();
+        (colors, self.records)
+    }
+}
+
+// Rows [first_row:first_row + image.height) and columns [first_column:first_column + image.width)
+// of the image being rendered and, when requested, the escape results of their samples
+struct RenderTarget {
+    first_row: usize,
+    first_column: usize,
+    image: ImageBuffer,
+    records: Option<Vec<Vec<EscapeResult>>>,
+    // First error saving a tile to the checkpoint
+    error: Option<String>,
+    // Indices of the tiles rendered or loaded so far
+    finished_tiles: Vec<usize>,
+}
+
+// Seed of the sample positions of a pixel, only depends on the pixel so the tile order doesn't matter.
+// SplitMix64 finalizer, neighbouring pixels get unrelated seeds.
+fn pixel_seed(seed: u64, x: usize, y: usize) -> u64 {
+    let mut z = seed ^ ((y as u64) << 32 | x as u64).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// Finished pixels of a render, x and y of the top left corner in the image
+#[derive(Clone)]
+pub struct RenderedTile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    // Rows from the top
+    pub pixels: Vec<Color>,
+}
+
+impl RenderedTile {
+    // Averages blocks of scale x scale pixels, position and size are divided by scale too
+    pub fn reduced(&self, scale: usize) -> RenderedTile {
+        let (width, height) = (self.width.div_ceil(scale), self.height.div_ceil(scale));
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut color = Color::new();
+                let mut count = 0;
+                for source_y in y * scale..((y + 1) * scale).min(self.height) {
+                    for source_x in x * scale..((x + 1) * scale).min(self.width) {
+                        color.add(self.pixels[source_y * self.width + source_x]);
+                        count += 1;
+                    }
+                }
+                color.divide(count as Real);
+                pixels.push(color);
+            }
+        }
+        RenderedTile {x: self.x / scale, y: self.y / scale, width, height, pixels}
+    }
+}
+
+// State of a running render passed to progress callbacks
+#[derive(Clone)]
+pub struct RenderProgress {
+    pub finished_pixels: usize,
+    pub total_pixels: usize,
+    // Size of the image
+    pub width: usize,
+    pub height: usize,
+    // Offset of the tile every worker thread is rendering, None when it is idle
+    pub threads: Vec<Option<Vec2<usize>>>,
+    // Samples taken for the rendered pixels, not counting tiles loaded from a checkpoint
+    pub samples: usize,
+    // Rendered pixels that got fewer samples than requested because of the time limit
+    pub reduced_pixels: usize,
+    // Tiles finished since the previous report reduced by PREVIEW_SCALE, for live previews
+    pub tiles: Vec<RenderedTile>,
+}
+
+impl RenderProgress {
+    // Finished percentage [0:100]
+    pub fn percent(&self) -> u32 {
+        (self.finished_pixels * 100 / self.total_pixels.max(1)) as u32
+    }
+
+    pub fn busy_threads(&self) -> usize {
+        self.threads.iter().filter(|tile| tile.is_some()).count()
+    }
+
+    // Mean samples per finished pixel
+    pub fn samples_per_pixel(&self) -> Real {
+        self.samples as Real / self.finished_pixels.max(1) as Real
+    }
+}
+
+// Takes tiles from the queue as worker thread number thread until all of them are rendered.
+// current_tile holds the index + 1 of the tile being rendered, 0 when idle.
+#[allow(clippy::too_many_arguments)]
+fn thread_worker(context: &RenderContext, target: &Mutex<RenderTarget>, checkpoint: Option<&Checkpoint>, descryptors: &[ThreadDescryptor], queue: &TileQueue, thread: usize, finished_pixels: &AtomicUsize, current_tile: &AtomicUsize) {
+    let keep_records = target.lock().unwrap().records.is_some();
+    // Deferred checkpoints are written at the end of an interrupted render
+    let checkpoint = checkpoint.filter(|checkpoint| !checkpoint.is_deferred());
+    loop {
+        let tile = match queue.next(thread) {
+            Some(tile) if !context.cancelled() => tile,
+            _ => {
+                current_tile.store(0, Ordering::Release);
+                break;
+            }
+        };
+        current_tile.store(tile + 1, Ordering::Relaxed);
+        let desc = &descryptors[tile];
+        let pixel_count = desc.thread_size.x * desc.thread_size.y;
+        let (temp_color_buffer, mut temp_records, saved) = match checkpoint.and_then(|c| c.load_tile(desc.offset.x, desc.offset.y, pixel_count)) {
+            Some(colors) => {
+                finished_pixels.fetch_add(pixel_count, Ordering::Relaxed);
+                (colors, None, Ok(()))
+            }
+            None => {
+                let (colors, records) = TileRenderer::new(context, desc, keep_records).render(finished_pixels);
+                let saved = checkpoint.map_or(Ok(()), |c| c.save_tile(desc.offset.x, desc.offset.y, &colors));
+                (colors, records, saved)
+            }
+        };
+        context.send_tile(desc, &temp_color_buffer);
+
+        // copy the temporary color buffer after locking the image mutex
+        let mut target = target.lock().unwrap();
+        if let Err(message) = saved {
+            target.error.get_or_insert(message);
+        }
+        target.finished_tiles.push(tile);
+        let RenderTarget {first_row, first_column, image, records, ..} = &mut *target;
+        for y in 0..desc.thread_size.y {
+            let row = y + desc.offset.y - *first_row;
+            for x in 0..desc.thread_size.x {
+                let column = x + desc.offset.x - *first_column;
+                image.set(column, row, temp_color_buffer[y * desc.thread_size.x + x]);
+                if let (Some(records), Some(temp_records)) = (records.as_mut(), temp_records.as_mut()) {
+                    records[row * image.width + column] = std::mem::take(&mut temp_records[y * desc.thread_size.x + x]);
+                }
+            }
+        }
+    }
+}
+
+pub fn render(settings: &RenderSettings) -> ImageBuffer {
+    render_with_progress(settings, |_| {})
+}
+
+// Renders into an RGBA buffer of width * height * 4 bytes without touching the file system,
+// the entry point of the WebAssembly build
+pub fn render_into(buffer: &mut [u8], settings: &RenderSettings) -> Result<(), String> {
+    let size = settings.width * settings.height * 4;
+    if buffer.len() != size {
+        return Err(format!("the buffer has {} bytes, a {}x{} image needs {}", buffer.len(), settings.width, settings.height, size));
+    }
+    buffer.copy_from_slice(&render(settings).to_rgba8());
+    Ok(())
+}
+
+// Renders the image, calling progress about ten times a second from the calling thread
+pub fn render_with_progress<F: FnMut(&RenderProgress)>(settings: &RenderSettings, progress: F) -> ImageBuffer {
+    render_target(&RenderContext::new(settings), false, None, progress).image
+}
+
+// Renders until cancel is set, sending every finished tile to tiles. The image of a cancelled
+// render misses the tiles that weren't started.
+pub(crate) fn render_cancellable<F: FnMut(&RenderProgress)>(settings: &RenderSettings, cancel: &AtomicBool, tiles: &mpsc::Sender<RenderedTile>, progress: F) -> ImageBuffer {
+    let mut context = RenderContext::new(settings);
+    context.cancel = Some(cancel);
+    context.tiles = Some(tiles);
+    render_target(&context, false, None, progress).image
+}
+
+// Renders the image, taking the tiles finished by an earlier run from the checkpoint and
+// saving the new ones to it. Deferred checkpoints get the finished tiles of interrupted renders.
+pub fn render_with_checkpoint<F: FnMut(&RenderProgress)>(settings: &RenderSettings, checkpoint: &Checkpoint, progress: F) -> Result<ImageBuffer, String> {
+    let target = render_target(&RenderContext::new(settings), false, Some(checkpoint), progress);
+    match target.error {
+        Some(message) => Err(message),
+        None => Ok(target.image),
+    }
+}
+
+// Renders the image and keeps the escape results of every sample so it can be recolored.
+// The cache holds the samples of the output pixels, supersampling is skipped.
+pub fn render_with_cache<F: FnMut(&RenderProgress)>(settings: &RenderSettings, progress: F) -> (ImageBuffer, IterationCache) {
+    let settings = &RenderSettings {supersample: 1, ..settings.clone()};
+    let target = render_target(&RenderContext::new(settings), true, None, progress);
+    let cache = IterationCache {
+        width: settings.width,
+        height: settings.height,
+        derivative: settings.tracks_derivative(),
+        trap: settings.tracks_trap(),
+        trap_point: settings.tracked_trap_image().is_some(),
+        atom_domain: settings.tracks_atom_domain(),
+        interior: settings.interior.needs_orbit(),
+        average: settings.tracks_average(),
+        samples: target.records.unwrap_or_default(),
+    };
+    (target.image, cache)
+}
+
+// Renders the image in bands of at most band_height rows (rounded up to whole tiles) and
+// hands every finished band to write_band from top to bottom, so only one band is held in
+// memory at a time
+pub fn render_in_bands<F, W>(settings: &RenderSettings, band_height: usize, checkpoint: Option<&Checkpoint>, mut progress: F, mut write_band: W) -> Result<(), String>
+    where F: FnMut(&RenderProgress), W: FnMut(&ImageBuffer) -> Result<(), String> {
+    if settings.post.needs_neighbours() {
+        return Err(String::from("sharpening and bloom need the whole image, they can't be applied band by band"));
+    }
+    if settings.supersample > 1 {
+        return Err(String::from("supersampled images can't be rendered band by band"));
+    }
+    let context = RenderContext::new(settings);
+    let band_height = divide_roundup(band_height.max(1), TILE_HEIGHT) * TILE_HEIGHT;
+    let mut first_row = 0;
+    while first_row < settings.height {
+        let rows = band_height.min(settings.height - first_row);
+        let mut target = render_rows(&context, first_row, rows, false, checkpoint, &mut progress);
+        if let Some(message) = target.error {
+            return Err(message);
+        }
+        settings.post.apply(&mut target.image, settings.gamma, first_row, settings.height);
+        write_band(&target.image)?;
+        first_row += rows;
+    }
+    Ok(())
+}
+
+// Renders the pixels [x:x + width) x [y:y + height) of the image of the settings, so regions
+// rendered by separate processes can be put together. x and y have to be multiples of
+// TILE_WIDTH and TILE_HEIGHT and the settings need a seed for the regions to get the samples
+// of the whole image. Symmetry isn't used and post-processing is left to the caller.
+pub fn render_region(settings: &RenderSettings, x: usize, y: usize, width: usize, height: usize) -> Result<ImageBuffer, String> {
+    if !x.is_multiple_of(TILE_WIDTH) || !y.is_multiple_of(TILE_HEIGHT) || width == 0 || height == 0 || x + width > settings.width || y + height > settings.height {
+        return Err(format!("region {}x{} at {},{} isn't made of whole tiles of the {}x{} image", width, height, x, y, settings.width, settings.height));
+    }
+    if settings.seed.is_none() {
+        return Err(String::from("rendering a region needs a seed"));
+    }
+    if settings.supersample > 1 {
+        return Err(String::from("supersampled images can't be rendered by region"));
+    }
+    Ok(render_areas(settings, &[(x, y, width, height)], |_| {}).remove(0))
+}
+
+// Renders the rectangles (x, y, width, height) of the image of the settings with one context,
+// the progress counts the pixels of all of them. Symmetry isn't used and post-processing is
+// left to the caller.
+pub(crate) fn render_areas<F: FnMut(&RenderProgress)>(settings: &RenderSettings, areas: &[(usize, usize, usize, usize)], mut progress: F) -> Vec<ImageBuffer> {
+    let mut context = RenderContext::new(settings);
+    context.mirrored = None;
+    let total_pixels = areas.iter().map(|(_, _, width, height)| width * height).sum();
+    let mut finished_pixels = 0;
+    let mut images = Vec::with_capacity(areas.len());
+    for &(x, y, width, height) in areas {
+        let mut area_progress = |state: &RenderProgress| {
+            progress(&RenderProgress {finished_pixels: finished_pixels + state.finished_pixels, total_pixels, ..state.clone()});
+        };
+        images.push(render_area(&context, x, width, y, height, false, None, &mut area_progress).image);
+        finished_pixels += width * height;
+    }
+    images
+}
+
+// Renders the image of the settings, pixels with a guide color take it as it is and only the
+// others are sampled. Symmetry isn't used and post-processing is left to the caller.
+pub(crate) fn render_guided<F: FnMut(&RenderProgress)>(settings: &RenderSettings, guide: &[Option<Color>], mut progress: F) -> ImageBuffer {
+    let mut context = RenderContext::new(settings);
+    context.mirrored = None;
+    context.guide = Some(guide);
+    render_rows(&context, 0, settings.height, false, None, &mut progress).image
+}
+
+fn render_target<F: FnMut(&RenderProgress)>(context: &RenderContext, keep_records: bool, checkpoint: Option<&Checkpoint>, mut progress: F) -> RenderTarget {
+    let settings = context.settings;
+    if settings.supersample > 1 {
+        return render_supersampled(context, checkpoint, progress);
+    }
+    let mut target = match &context.mirrored {
+        Some(rows) => {
+            // Report the progress of the rendered rows only
+            let offset = rows.first * settings.width;
+            let mut half_progress = |state: &RenderProgress| {
+                progress(&RenderProgress {finished_pixels: state.finished_pixels - offset, total_pixels: rows.count * settings.width, ..state.clone()});
+            };
+            let half = render_rows(context, rows.first, rows.count, keep_records, checkpoint, &mut half_progress);
+            rows.mirror(half, settings.height)
+        }
+        None => render_rows(context, 0, settings.height, keep_records, checkpoint, &mut progress),
+    };
+    settings.post.apply(&mut target.image, settings.gamma, 0, settings.height);
+    target
+}
+
+// Renders the enlarged image and reduces it. The tiles of the enlarged image don't match the
+// output so none are sent, and no escape results are kept.
+fn render_supersampled<F: FnMut(&RenderProgress)>(context: &RenderContext, checkpoint: Option<&Checkpoint>, mut progress: F) -> RenderTarget {
+    let settings = context.settings;
+    let factor = settings.supersample;
+    let mut large = settings.supersampled();
+    large.seed = Some(context.seed);
+    let mut large_context = RenderContext::new(&large);
+    large_context.cancel = context.cancel;
+    // Positions and previews are reported at the output resolution
+    let mut reduced_progress = |state: &RenderProgress| {
+        progress(&RenderProgress {
+            width: settings.width,
+            height: settings.height,
+            threads: state.threads.iter().map(|offset| offset.map(|offset| Vec2::<usize>{x: offset.x / factor, y: offset.y / factor})).collect(),
+            tiles: state.tiles.iter().map(|tile| tile.reduced(factor)).collect(),
+            ..state.clone()
+        });
+    };
+    // Dynamic so the instantiations don't nest
+    let target = render_target(&large_context, false, checkpoint, &mut reduced_progress as &mut dyn FnMut(&RenderProgress));
+    let mut image = settings.filter.downsample(&target.image, settings.supersample, settings.gamma);
+    settings.post.apply(&mut image, settings.gamma, 0, settings.height);
+    RenderTarget {first_row: 0, first_column: 0, image, records: None, error: target.error, finished_tiles: Vec::new()}
+}
+
+// Rows [first:first + count) of a view mirrored about the real axis, every other row y
+// is the mirror image of row mirror - y
+struct MirroredRows {
+    first: usize,
+    count: usize,
+    mirror: usize,
+}
+
+impl MirroredRows {
+    // Completes the rendered rows to the full image, the mirrored samples get conjugate orbits
+    fn mirror(&self, half: RenderTarget, height: usize) -> RenderTarget {
+        let width = half.image.width;
+        let mut image = ImageBuffer::new(width, height);
+        let mut records = half.records.as_ref().map(|_| vec![Vec::new(); width * height]);
+        for y in 0..height {
+            let source = if y >= self.first && y < self.first + self.count { y } else { self.mirror - y };
+            let source_row = source - self.first;
+            for x in 0..width {
+                image.set(x, y, half.image.get(x, source_row));
+            }
+            if let (Some(records), Some(half_records)) = (records.as_mut(), half.records.as_ref()) {
+                for x in 0..width {
+                    let samples = &half_records[source_row * width + x];
+                    records[y * width + x] = if source == y {
+                        samples.clone()
+                    }
+                    else {
+                        samples.iter().map(|result| EscapeResult {z: result.z.conj(), derivative: result.derivative.conj(), trap_point: result.trap_point.conj(), ..*result}).collect()
+                    };
+                }
+            }
+        }
+        RenderTarget {first_row: 0, first_column: 0, image, records, error: half.error, finished_tiles: half.finished_tiles}
+    }
+}
+
+// Renders rows [first_row:first_row + rows), first_row has to be a multiple of TILE_HEIGHT
+fn render_rows<F: FnMut(&RenderProgress)>(context: &RenderContext, first_row: usize, rows: usize, keep_records: bool, checkpoint: Option<&Checkpoint>, progress: &mut F) -> RenderTarget {
+    let settings = context.settings;
+    // The rows above count as finished
+    let mut image_progress = |state: &RenderProgress| {
+        progress(&RenderProgress {finished_pixels: first_row * settings.width + state.finished_pixels, total_pixels: settings.width * settings.height, ..state.clone()});
+    };
+    render_area(context, 0, settings.width, first_row, rows, keep_records, checkpoint, &mut image_progress)
+}
+
+// Counts a worker of render_area as live until it returns or panics, and wakes the progress loop
+#[cfg(not(target_arch = "wasm32"))]
+struct LiveWorker<'a> {
+    count: &'a AtomicUsize,
+    main_thread: &'a thread::Thread,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for LiveWorker<'_> {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::Release);
+        self.main_thread.unpark();
+    }
+}
+
+// Renders columns [first_column:first_column + columns) of rows [first_row:first_row + rows),
+// the progress counts the pixels of the area. Tiles match those of the whole image, and can
+// be loaded from checkpoints, when the first column and row are multiples of TILE_WIDTH and
+// TILE_HEIGHT.
+#[allow(clippy::too_many_arguments)]
+fn render_area<F: FnMut(&RenderProgress)>(context: &RenderContext, first_column: usize, columns: usize, first_row: usize, rows: usize, keep_records: bool, checkpoint: Option<&Checkpoint>, progress: &mut F) -> RenderTarget {
+    let settings = context.settings;
+    let target = Mutex::new(RenderTarget {
+        first_row,
+        first_column,
+        image: ImageBuffer::new(columns, rows),
+        records: if keep_records { Some(vec![Vec::new(); columns * rows]) } else { None },
+        error: None,
+        finished_tiles: Vec::new(),
+    });
+
+    // Fill threads descryptors
+    let mut threads_descryptors = Vec::new();
+    for y in 0..divide_roundup(rows, TILE_HEIGHT) {
+        for x in 0..divide_roundup(columns, TILE_WIDTH) {
+            let max_width = columns - x*TILE_WIDTH;
+            let max_height = rows - y*TILE_HEIGHT;
+            threads_descryptors.push(ThreadDescryptor {
+                offset: Vec2::<usize>{x: first_column + x * TILE_WIDTH, y: first_row + y * TILE_HEIGHT},
+                thread_size: Vec2::<usize>{x: clamp(TILE_WIDTH, 0, max_width), y: clamp(TILE_HEIGHT, 0, max_height)},
+            });
+        }
+    }
+
+    // Render the tiles on a pool of worker threads
+    let finished_pixels = AtomicUsize::new(0);
+    let current_tiles: Vec<AtomicUsize> = (0..settings.thread_count.max(1)).map(|_| AtomicUsize::new(0)).collect();
+    let queue = TileQueue::new(threads_descryptors.len(), current_tiles.len());
+    let band_pixels = columns * rows;
+    // WebAssembly has no threads, the tiles are rendered on the calling thread
+    #[cfg(target_arch = "wasm32")]
+    {
+        thread_worker(context, &target, checkpoint, &threads_descryptors, &queue, 0, &finished_pixels, &current_tiles[0]);
+        progress(&RenderProgress {
+            finished_pixels: finished_pixels.load(Ordering::Acquire),
+            total_pixels: band_pixels,
+            width: settings.width,
+            height: settings.height,
+            threads: vec![None],
+            samples: context.sample_total.load(Ordering::Relaxed),
+            reduced_pixels: context.reduced_pixels.load(Ordering::Relaxed),
+            tiles: std::mem::take(&mut *context.previews.lock().unwrap()),
+        });
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        // Workers wake the progress loop when they stop so short renders don't wait for its timeout
+        let main_thread = thread::current();
+        let live_workers = AtomicUsize::new(current_tiles.len());
+        thread::scope(|scope| {
+            for (thread, current_tile) in current_tiles.iter().enumerate() {
+                let (target, threads_descryptors, queue, finished_pixels) = (&target, &threads_descryptors, &queue, &finished_pixels);
+                let live = LiveWorker {count: &live_workers, main_thread: &main_thread};
+                scope.spawn(move || {
+                    let _live = live;
+                    thread_worker(context, target, checkpoint, threads_descryptors, queue, thread, finished_pixels, current_tile);
+                });
+            }
+            loop {
+                // Workers only exit early by panicking, which the scope passes on once the loop stops
+                let stopped = live_workers.load(Ordering::Acquire) == 0;
+                // Idle workers have queued all their tiles, the last report gets them
+                let idle = current_tiles.iter().all(|tile| tile.load(Ordering::Acquire) == 0);
+                let finished = finished_pixels.load(Ordering::Acquire);
+                progress(&RenderProgress {
+                    finished_pixels: finished,
+                    total_pixels: band_pixels,
+                    width: settings.width,
+                    height: settings.height,
+                    threads: current_tiles.iter().map(|tile| match tile.load(Ordering::Relaxed) {
+                        0 => None,
+                        index => Some(threads_descryptors[index - 1].offset),
+                    }).collect(),
+                    samples: context.sample_total.load(Ordering::Relaxed),
+                    reduced_pixels: context.reduced_pixels.load(Ordering::Relaxed),
+                    tiles: std::mem::take(&mut *context.previews.lock().unwrap()),
+                });
+                if stopped || (idle && (finished == band_pixels || context.cancelled())) {
+                    break;
+                }
+                thread::park_timeout(time::Duration::from_millis(100));
+            }
+        });
+    }
+
+    let mut target = target.into_inner().unwrap();
+    if let Some(checkpoint) = checkpoint.filter(|checkpoint| checkpoint.is_deferred() && context.cancelled()) {
+        if let Err(message) = save_finished_tiles(checkpoint, &target, &threads_descryptors) {
+            target.error.get_or_insert(message);
+        }
+    }
+    target
+}
+
+// Writes the finished tiles of an interrupted render to a deferred checkpoint
+fn save_finished_tiles(checkpoint: &Checkpoint, target: &RenderTarget, descryptors: &[ThreadDescryptor]) -> Result<(), String> {
+    if target.finished_tiles.is_empty() {
+        return Ok(());
+    }
+    checkpoint.start()?;
+    for &tile in &target.finished_tiles {
+        let desc = &descryptors[tile];
+        let mut colors = Vec::with_capacity(desc.thread_size.x * desc.thread_size.y);
+        for y in 0..desc.thread_size.y {
+            for x in 0..desc.thread_size.x {
+                colors.push(target.image.get(x + desc.offset.x - target.first_column, y + desc.offset.y - target.first_row));
+            }
+        }
+        checkpoint.save_tile(desc.offset.x, desc.offset.y, &colors)?;
+    }
+    Ok(())
+}
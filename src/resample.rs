@@ -0,0 +1,130 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::Real;
+use crate::color::{Color, Gamma};
+use crate::image::ImageBuffer;
+
+// Reconstruction filter of supersampled renders
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Filter {
+    // Plain average of every block of source pixels
+    Box,
+    // Windowed sinc with three lobes, the sharpest, may ring slightly at hard edges
+    Lanczos,
+    // Mitchell-Netravali cubic with B = C = 1/3, a balance of sharpness and ringing
+    Mitchell,
+}
+
+impl Filter {
+    // Half width of the kernel in output pixels
+    fn radius(&self) -> Real {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Lanczos => 3.0,
+            Filter::Mitchell => 2.0,
+        }
+    }
+
+    // Kernel at distance x from the output pixel center, in output pixels
+    fn weight(&self, x: Real) -> Real {
+        let x = x.abs();
+        match self {
+            Filter::Box => if x < 0.5 { 1.0 } else { 0.0 },
+            Filter::Lanczos if x < 1e-8 => 1.0,
+            Filter::Lanczos if x < 3.0 => {
+                let pi_x = std::f64::consts::PI as Real * x;
+                3.0 * pi_x.sin() * (pi_x / 3.0).sin() / (pi_x * pi_x)
+            }
+            Filter::Lanczos => 0.0,
+            Filter::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let value = if x < 1.0 {
+                    (12.0 - 9.0*b - 6.0*c) * x*x*x + (-18.0 + 12.0*b + 6.0*c) * x*x + (6.0 - 2.0*b)
+                }
+                else if x < 2.0 {
+                    (-b - 6.0*c) * x*x*x + (6.0*b + 30.0*c) * x*x + (-12.0*b - 48.0*c) * x + (8.0*b + 24.0*c)
+                }
+                else {
+                    0.0
+                };
+                value / 6.0
+            }
+        }
+    }
+
+    // First source index and normalized weights of every output pixel of a row or column
+    fn weights(&self, source_length: usize, factor: usize) -> Vec<(usize, Vec<Real>)> {
+        let output_length = source_length / factor;
+        let scale = factor as Real;
+        let reach = self.radius() * scale;
+        (0..output_length).map(|i| {
+            let center = (i as Real + 0.5) * scale;
+            let first = (center - reach).floor().max(0.0) as usize;
+            let last = ((center + reach).ceil() as usize).min(source_length);
+            let mut weights: Vec<Real> = (first..last).map(|j| self.weight((j as Real + 0.5 - center) / scale)).collect();
+            let total: Real = weights.iter().sum();
+            for weight in weights.iter_mut() {
+                *weight /= total;
+            }
+            (first, weights)
+        }).collect()
+    }
+
+    // Reduces an image rendered at factor times the resolution, blending in linear light with
+    // premultiplied alpha so transparent pixels don't darken the edges
+    pub fn downsample(&self, image: &ImageBuffer, factor: usize, gamma: Gamma) -> ImageBuffer {
+        let (width, height) = (image.width / factor, image.height / factor);
+        let pixels: Vec<Color> = image.pixels.iter().map(|color| gamma.decode_color(*color).premultiplied()).collect();
+        let blend = |colors: &mut dyn Iterator<Item = (Color, Real)>| {
+            let mut sum = Color::new();
+            for (color, weight) in colors {
+                sum.add(Color {r: color.r * weight, g: color.g * weight, b: color.b * weight, a: color.a * weight});
+            }
+            sum
+        };
+        // Rows first, then columns
+        let columns = self.weights(image.width, factor);
+        let mut narrow = Vec::with_capacity(width * image.height);
+        for y in 0..image.height {
+            let row = &pixels[y * image.width..(y + 1) * image.width];
+            for (first, weights) in &columns {
+                narrow.push(blend(&mut weights.iter().enumerate().map(|(i, weight)| (row[first + i], *weight))));
+            }
+        }
+        let rows = self.weights(image.height, factor);
+        let mut output = ImageBuffer::new(width, height);
+        for (y, (first, weights)) in rows.iter().enumerate() {
+            for x in 0..width {
+                let color = blend(&mut weights.iter().enumerate().map(|(i, weight)| (narrow[(first + i) * width + x], *weight)));
+                // Negative lobes may overshoot
+                let alpha = color.a.clamp(0.0, 1.0);
+                let color = Color {r: color.r.max(0.0), g: color.g.max(0.0), b: color.b.max(0.0), a: alpha};
+                output.set(x, y, gamma.encode_color(color.unpremultiplied()));
+            }
+        }
+        output
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Filter, String> {
+        match text {
+            "box" => Ok(Filter::Box),
+            "lanczos" => Ok(Filter::Lanczos),
+            "mitchell" => Ok(Filter::Mitchell),
+            _ => Err(format!("unknown filter '{}', expected box, lanczos or mitchell", text)),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::Box => write!(f, "box"),
+            Filter::Lanczos => write!(f, "lanczos"),
+            Filter::Mitchell => write!(f, "mitchell"),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::str::FromStr;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use crate::math::{Real, Vec2, divide_roundup};
+
+// Distribution of the anti-aliasing samples inside a pixel
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Sampler {
+    // Independent uniform positions
+    Random,
+    // One jittered position per cell of a grid covering the pixel
+    Stratified,
+    // Scrambled 2D Sobol sequence, every prefix is well distributed
+    Sobol,
+}
+
+impl FromStr for Sampler {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Sampler, String> {
+        match text {
+            "random" => Ok(Sampler::Random),
+            "stratified" => Ok(Sampler::Stratified),
+            "sobol" => Ok(Sampler::Sobol),
+            _ => Err(format!("unknown sampler '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for Sampler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Sampler::Random => "random",
+            Sampler::Stratified => "stratified",
+            Sampler::Sobol => "sobol",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Sampler {
+    // Replaces points with count sample positions inside the pixel, in [0:1) on both axes
+    pub fn fill<R: Rng>(&self, rng: &mut R, count: usize, points: &mut Vec<Vec2<Real>>) {
+        points.clear();
+        match self {
+            Sampler::Random => {
+                for _ in 0..count {
+                    points.push(Vec2::<Real>{x: rng.gen::<Real>(), y: rng.gen::<Real>()});
+                }
+            }
+            Sampler::Stratified => {
+                let columns = ((count as Real).sqrt().ceil() as usize).max(1);
+                let rows = divide_roundup(count, columns);
+                for i in 0..count {
+                    let (column, row) = (i % columns, i / columns);
+                    // The last row may be incomplete, stretch its cells over the pixel width
+                    let row_columns = if row == rows - 1 { count - row * columns } else { columns };
+                    points.push(Vec2::<Real>{
+                        x: (column as Real + rng.gen::<Real>()) / row_columns as Real,
+                        y: (row as Real + rng.gen::<Real>()) / rows as Real,
+                    });
+                }
+                // Random order keeps prefixes of the grid unbiased
+                points.shuffle(rng);
+            }
+            Sampler::Sobol => {
+                // Random digital shifts decorrelate neighbouring pixels
+                let (shift_x, shift_y) = (rng.gen::<u32>(), rng.gen::<u32>());
+                for i in 0..count {
+                    points.push(Vec2::<Real>{
+                        x: to_unit((i as u32).reverse_bits() ^ shift_x),
+                        y: to_unit(sobol_second_dimension(i as u32) ^ shift_y),
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn to_unit(bits: u32) -> Real {
+    bits as Real / 4294967296.0
+}
+
+// Second dimension of the Sobol sequence, the first one is the bit reversed index
+fn sobol_second_dimension(index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    let mut i = index;
+    while i != 0 {
+        if i & 1 != 0 {
+            result ^= direction;
+        }
+        i >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
@@ -0,0 +1,43 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+// Work stealing queue of tile indices. Every worker thread starts with its own contiguous run
+// of tiles and takes them from the front; a thread that runs out takes the back half of the
+// longest run left, so neighbouring tiles stay on one thread and no thread idles while tiles
+// remain, even when the cheap interior tiles all end up on some of them.
+pub struct TileQueue {
+    queues: Vec<Mutex<VecDeque<usize>>>,
+}
+
+impl TileQueue {
+    pub fn new(tile_count: usize, thread_count: usize) -> TileQueue {
+        let thread_count = thread_count.max(1);
+        let queues = (0..thread_count).map(|thread| {
+            Mutex::new((tile_count * thread / thread_count..tile_count * (thread + 1) / thread_count).collect())
+        }).collect();
+        TileQueue {queues}
+    }
+
+    // Next tile of the thread, None once every tile has been taken
+    pub fn next(&self, thread: usize) -> Option<usize> {
+        if let Some(tile) = self.queues[thread].lock().unwrap().pop_front() {
+            return Some(tile);
+        }
+        loop {
+            let victim = (0..self.queues.len()).filter(|&other| other != thread)
+                .map(|other| (self.queues[other].lock().unwrap().len(), other))
+                .max()
+                .filter(|&(length, _)| length > 0)?.1;
+            let mut stolen = {
+                let mut queue = self.queues[victim].lock().unwrap();
+                let length = queue.len();
+                queue.split_off(length - length.div_ceil(2))
+            };
+            // Another thread may have emptied the victim in the meantime
+            if let Some(tile) = stolen.pop_front() {
+                self.queues[thread].lock().unwrap().extend(stolen);
+                return Some(tile);
+            }
+        }
+    }
+}
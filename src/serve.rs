@@ -0,0 +1,157 @@
+// Slippy map tile server: renders /z/x/y.png tiles on demand and keeps them in a disk cache.
+// Tile 0/0/0 is the view of the render options, every zoom level halves the tile side, x grows
+// to the right and y downward. Connections are handled one after the other, every tile is
+// rendered with all threads.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::time::Instant;
+use mandelbrot_set::{Real, Float, Vec2, BigFloat, RenderSettings, PostProcess, ImageSettings, render, save_image_with};
+use crate::progress::Reporter;
+
+// Deepest zoom level served, a zoom of about 3e13. Leaflet computes pixel positions in
+// doubles, tile indices stay exact up to here.
+const MAX_ZOOM: u32 = 45;
+
+// Map page using Leaflet with a flat coordinate system, zoom 0 shows tile 0/0/0
+const INDEX_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Mandelbrot set</title>
+<link rel="stylesheet" href="https://unpkg.com/leaflet@1.9.4/dist/leaflet.css">
+<script src="https://unpkg.com/leaflet@1.9.4/dist/leaflet.js"></script>
+<style>html, body, #map { height: 100%; margin: 0; background: #000; }</style>
+</head>
+<body>
+<div id="map"></div>
+<script>
+var size = TILE_SIZE;
+var map = L.map('map', {crs: L.CRS.Simple, minZoom: 0, maxZoom: MAX_ZOOM});
+L.tileLayer('/{z}/{x}/{y}.png', {tileSize: size, maxZoom: MAX_ZOOM, noWrap: true}).addTo(map);
+map.setView([-size / 2, size / 2], 1);
+</script>
+</body>
+</html>
+"#;
+
+pub struct TileServer {
+    settings: RenderSettings,
+    image_settings: ImageSettings,
+    // Directory of the cached tiles of these settings
+    cache: String,
+}
+
+impl TileServer {
+    // The tiles are squares of the width of settings, cached tiles are kept in a directory
+    // named after the scene so other settings don't reuse them
+    pub fn new(settings: &RenderSettings, image_settings: &ImageSettings, cache_directory: &str, scene: &str) -> TileServer {
+        let mut settings = settings.clone();
+        settings.height = settings.width;
+        // Effects reaching across tile borders would show seams, only the per pixel ones are kept
+        settings.post = PostProcess {exposure: settings.post.exposure, contrast: settings.post.contrast, ..PostProcess::new()};
+        let image_settings = ImageSettings {text: Vec::new(), ..image_settings.clone()};
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(scene.as_bytes());
+        let cache = format!("{}/{:08x}", cache_directory.trim_end_matches('/'), hasher.finalize());
+        TileServer {settings, image_settings, cache}
+    }
+
+    pub fn run(&self, address: &str, reporter: &Reporter) -> Result<(), String> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("cannot listen on {}: {}", address, e))?;
+        reporter.message(&format!("Serving tiles at http://{}/, cached in {}", address, self.cache));
+        for stream in listener.incoming() {
+            // A failed connection only concerns its client
+            let handled = stream.map_err(|e| e.to_string()).and_then(|stream| self.handle(stream, reporter));
+            if let Err(message) = handled {
+                reporter.message(&format!("error: {}", message));
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&self, mut stream: TcpStream, reporter: &Reporter) -> Result<(), String> {
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request).map_err(|e| format!("cannot read the request: {}", e))?;
+        let mut parts = request.split_whitespace();
+        let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let reply = if method != "GET" && method != "HEAD" {
+            response(405, "text/plain", b"only GET is supported\n")
+        }
+        else if target == "/" || target == "/index.html" {
+            let page = INDEX_PAGE.replace("TILE_SIZE", &self.settings.width.to_string()).replace("MAX_ZOOM", &MAX_ZOOM.to_string());
+            response(200, "text/html; charset=utf-8", page.as_bytes())
+        }
+        else {
+            match parse_tile(target) {
+                Some(tile) => match self.tile(tile, reporter) {
+                    Ok(png) => response(200, "image/png", &png),
+                    Err(message) => response(500, "text/plain", format!("{}\n", message).as_bytes()),
+                },
+                None => response(404, "text/plain", b"not found, tiles are at /z/x/y.png\n"),
+            }
+        };
+        let end = if method == "HEAD" { head_length(&reply) } else { reply.len() };
+        stream.write_all(&reply[..end]).map_err(|e| format!("cannot send the response: {}", e))
+    }
+
+    // PNG data of a tile, rendered unless it is cached
+    fn tile(&self, (zoom, x, y): (u32, i64, i64), reporter: &Reporter) -> Result<Vec<u8>, String> {
+        let path = format!("{}/{}/{}/{}.png", self.cache, zoom, x, y);
+        if !Path::new(&path).is_file() {
+            let start_time = Instant::now();
+            let settings = self.tile_settings(zoom, x, y);
+            save_image_with(&render(&settings), &path, &self.image_settings)?;
+            reporter.message(&format!("Rendered tile {}/{}/{} with {} iterations in {} ms", zoom, x, y, settings.max_iterations, start_time.elapsed().as_millis()));
+        }
+        std::fs::read(&path).map_err(|e| format!("cannot read {}: {}", path, e))
+    }
+
+    fn tile_settings(&self, zoom: u32, x: i64, y: i64) -> RenderSettings {
+        let mut settings = self.settings.clone();
+        let base_width = self.settings.view_size.x;
+        let side = base_width / (2.0 as Real).powi(zoom as i32);
+        // Offsets of deep tiles need more digits than f64 has, the tile side is a power of two
+        // fraction of the base width so the products are exact
+        let offset = |index: i64| BigFloat::from_real(index as Real + 0.5) * BigFloat::from_real(side) - BigFloat::from_real(base_width / 2.0);
+        settings.center = Vec2::<BigFloat>{
+            x: self.settings.center.x + offset(x),
+            y: self.settings.center.y - offset(y),
+        };
+        settings.view_size = Vec2::<Real>{x: side, y: side};
+        settings.update_auto_iterations();
+        settings
+    }
+}
+
+// Zoom, x and y of a /z/x/y.png path
+fn parse_tile(target: &str) -> Option<(u32, i64, i64)> {
+    let path = target.split('?').next()?.strip_prefix('/')?.strip_suffix(".png")?;
+    let mut parts = path.split('/');
+    let zoom: u32 = parts.next()?.parse().ok()?;
+    let x: i64 = parts.next()?.parse().ok()?;
+    let y: i64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || zoom > MAX_ZOOM {
+        return None;
+    }
+    Some((zoom, x, y))
+}
+
+fn response(status: u32, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let reason = match status {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let mut response = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, reason, content_type, body.len()).into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+// Length of the status line and headers of a response
+fn head_length(response: &[u8]) -> usize {
+    response.windows(4).position(|window| window == b"\r\n\r\n").map_or(response.len(), |i| i + 4)
+}
@@ -0,0 +1,135 @@
+use crate::math::{Real, Complex};
+use crate::render::{RenderSettings, EscapeResult};
+
+// Whether the vectorized kernel can run on this CPU
+pub fn available() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+// Iterates four points of z^2 + c at once at f64 precision, the same way the scalar loop
+// does without tracking the derivative. Only call this when available() returns true.
+pub fn escape_time_x4(settings: &RenderSettings, positions: &[Complex<Real>; 4]) -> [EscapeResult; 4] {
+    #[cfg(target_arch = "x86_64")]
+    {
+        assert!(available(), "the AVX kernel is not supported by this CPU");
+        unsafe { avx::escape_time_x4(settings, positions) }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        let _ = (settings, positions);
+        panic!("the vectorized kernel is only available on x86_64");
+    }
+}
+
+// Starting value and constant of every lane
+fn lanes(settings: &RenderSettings, positions: &[Complex<Real>; 4]) -> ([Real; 4], [Real; 4], [Real; 4], [Real; 4]) {
+    let mut start = ([0.0; 4], [0.0; 4]);
+    let mut c = ([0.0; 4], [0.0; 4]);
+    for (lane, position) in positions.iter().enumerate() {
+        let (z, constant) = if settings.fractal.is_julia() {
+            (*position, Complex::new(settings.julia_c.x, settings.julia_c.y))
+        }
+        else {
+            (Complex::new(0.0, 0.0), *position)
+        };
+        start.0[lane] = z.r;
+        start.1[lane] = z.i;
+        c.0[lane] = constant.r;
+        c.1[lane] = constant.i;
+    }
+    (start.0, start.1, c.0, c.1)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx {
+    use std::arch::x86_64::*;
+    use crate::math::{Real, Complex};
+    use crate::render::{RenderSettings, EscapeResult};
+
+    const ALL_LANES: i32 = 0b1111;
+
+    #[target_feature(enable = "avx")]
+    pub unsafe fn escape_time_x4(settings: &RenderSettings, positions: &[Complex<Real>; 4]) -> [EscapeResult; 4] {
+        let (start_r, start_i, c_r, c_i) = super::lanes(settings, positions);
+        let max_iterations = settings.max_iterations;
+        let max_length = settings.max_length();
+        let tolerance = settings.pixel_size() * 1e-4;
+        let derivative = settings.fractal.derivative_start();
+
+        let bailout = _mm256_set1_pd(max_length * max_length);
+        let tolerance_squared = _mm256_set1_pd(tolerance * tolerance);
+        let cr = _mm256_loadu_pd(c_r.as_ptr());
+        let ci = _mm256_loadu_pd(c_i.as_ptr());
+        let mut zr = _mm256_loadu_pd(start_r.as_ptr());
+        let mut zi = _mm256_loadu_pd(start_i.as_ptr());
+        // Brent's cycle detection, see Periodicity
+        let mut saved_r = zr;
+        let mut saved_i = zi;
+        let mut next_save = 1u32;
+
+        let mut results = [EscapeResult {iterations: max_iterations, z: Complex::new(0.0, 0.0), derivative, trap: Real::INFINITY,
+            trap_point: Complex::new(Real::NAN, Real::NAN), atom_domain: 0, period: 0, interior_distance: Real::INFINITY, average_sum: 0.0, average_last: 0.0}; 4];
+        let mut done = 0;
+        let mut iterations = 0u32;
+        // Records z and the iteration count of the lanes in mask
+        let finish = |results: &mut [EscapeResult; 4], mask: i32, zr: __m256d, zi: __m256d, iterations: u32| {
+            let mut r = [0.0; 4];
+            let mut i = [0.0; 4];
+            _mm256_storeu_pd(r.as_mut_ptr(), zr);
+            _mm256_storeu_pd(i.as_mut_ptr(), zi);
+            for lane in 0..4 {
+                if mask & (1 << lane) != 0 {
+                    results[lane].iterations = iterations;
+                    results[lane].z = Complex::new(r[lane], i[lane]);
+                }
+            }
+        };
+        loop {
+            let zr2 = _mm256_mul_pd(zr, zr);
+            let zi2 = _mm256_mul_pd(zi, zi);
+            let escaped = _mm256_movemask_pd(_mm256_cmp_pd(_mm256_add_pd(zr2, zi2), bailout, _CMP_GT_OQ)) & !done;
+            if escaped != 0 {
+                finish(&mut results, escaped, zr, zi, iterations);
+                done |= escaped;
+            }
+            if iterations == max_iterations {
+                finish(&mut results, ALL_LANES & !done, zr, zi, max_iterations);
+                break;
+            }
+            if done == ALL_LANES {
+                break;
+            }
+
+            // z = z^2 + c
+            let zri = _mm256_mul_pd(zr, zi);
+            zi = _mm256_add_pd(_mm256_add_pd(zri, zri), ci);
+            zr = _mm256_add_pd(_mm256_sub_pd(zr2, zi2), cr);
+            iterations += 1;
+
+            let dr = _mm256_sub_pd(zr, saved_r);
+            let di = _mm256_sub_pd(zi, saved_i);
+            let distance = _mm256_add_pd(_mm256_mul_pd(dr, dr), _mm256_mul_pd(di, di));
+            let periodic = _mm256_movemask_pd(_mm256_cmp_pd(distance, tolerance_squared, _CMP_LT_OQ)) & !done;
+            if periodic != 0 {
+                finish(&mut results, periodic, zr, zi, max_iterations);
+                done |= periodic;
+                if done == ALL_LANES {
+                    break;
+                }
+            }
+            if iterations == next_save {
+                saved_r = zr;
+                saved_i = zi;
+                next_save = next_save.saturating_mul(2);
+            }
+        }
+        results
+    }
+}
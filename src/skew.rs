@@ -0,0 +1,197 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, Float, Complex, Vec2};
+use crate::big_float::BigFloat;
+use crate::fractal::Fractal;
+use crate::render::RenderSettings;
+
+// Linear map of the view applied to the offsets from its center after the rotation, turning
+// (x, y) into (xx*x + xy*y, yx*x + yy*y). Minibrots and embedded Julia sets of the folding
+// formulas are stretched along some direction, the inverse stretch makes them round again.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Skew {
+    pub xx: Real,
+    pub xy: Real,
+    pub yx: Real,
+    pub yy: Real,
+}
+
+impl Skew {
+    pub fn new() -> Skew {
+        Skew {xx: 1.0, xy: 0.0, yx: 0.0, yy: 1.0}
+    }
+
+    pub fn apply(&self, offset: Vec2<Real>) -> Vec2<Real> {
+        Vec2::<Real>{x: self.xx * offset.x + self.xy * offset.y, y: self.yx * offset.x + self.yy * offset.y}
+    }
+
+    pub fn determinant(&self) -> Real {
+        self.xx * self.yy - self.xy * self.yx
+    }
+
+    pub fn inverse(&self) -> Skew {
+        let determinant = self.determinant();
+        Skew {xx: self.yy / determinant, xy: -self.xy / determinant, yx: -self.yx / determinant, yy: self.xx / determinant}
+    }
+
+    // Shortest length a unit offset is mapped to, the smallest singular value
+    pub fn min_stretch(&self) -> Real {
+        // Eigenvalues of the symmetric transpose(skew) * skew
+        let p = self.xx * self.xx + self.yx * self.yx;
+        let q = self.xx * self.xy + self.yx * self.yy;
+        let r = self.xy * self.xy + self.yy * self.yy;
+        let half_difference = (p - r) / 2.0;
+        ((p + r) / 2.0 - (half_difference * half_difference + q * q).sqrt()).max(0.0).sqrt()
+    }
+
+    fn multiply(&self, other: &Skew) -> Skew {
+        Skew {
+            xx: self.xx * other.xx + self.xy * other.yx,
+            xy: self.xx * other.xy + self.xy * other.yy,
+            yx: self.yx * other.xx + self.yy * other.yx,
+            yy: self.yx * other.xy + self.yy * other.yy,
+        }
+    }
+
+    fn add(&self, other: &Skew) -> Skew {
+        Skew {xx: self.xx + other.xx, xy: self.xy + other.xy, yx: self.yx + other.yx, yy: self.yy + other.yy}
+    }
+
+    // Skew that undoes the stretch of the view center's orbit. The derivative of z with
+    // respect to the pixel is taken at the iteration where the orbit comes closest to 0, the
+    // period of the nearest minibrot, which maps the minibrot onto a round shape. The result
+    // is the symmetric part of its inverse scaled to keep the area, so the view neither
+    // rotates nor zooms.
+    pub fn estimate(settings: &RenderSettings) -> Result<Skew, String> {
+        // The phoenix depends on the previous iterate too, the others have no orbit of z and c
+        if matches!(settings.fractal, Fractal::Newton | Fractal::Phoenix | Fractal::Lyapunov) || settings.fractal.is_3d() {
+            return Err(format!("the skew of {} fractals can't be estimated", settings.fractal));
+        }
+        let mut settings = settings.clone();
+        settings.skew = None;
+        let jacobian = if settings.fractal == Fractal::Formula {
+            let c = Complex::new(settings.center.x.to_real(), settings.center.y.to_real());
+            closest_jacobian(&settings, Complex::new(0.0, 0.0), c)
+        }
+        else {
+            let limbs = settings.arbitrary_limbs();
+            let position = Complex::new(settings.center.x.with_precision(limbs), settings.center.y.with_precision(limbs));
+            if settings.fractal.is_julia() {
+                closest_jacobian(&settings, position, Complex::new(BigFloat::from_real(settings.julia_c.x), BigFloat::from_real(settings.julia_c.y)))
+            }
+            else {
+                let start = settings.fractal.start();
+                closest_jacobian(&settings, Complex::new(BigFloat::from_real(start.r).with_precision(limbs), BigFloat::from_real(start.i).with_precision(limbs)), position)
+            }
+        };
+        let error = || String::from("the skew can't be estimated at this view center");
+        let jacobian = jacobian.ok_or_else(error)?;
+        // Only the shape matters, scaling keeps the products in range
+        let scale = jacobian.xx.abs().max(jacobian.xy.abs()).max(jacobian.yx.abs()).max(jacobian.yy.abs());
+        if !(scale > 0.0 && scale.is_finite()) {
+            return Err(error());
+        }
+        let jacobian = Skew {xx: jacobian.xx / scale, xy: jacobian.xy / scale, yx: jacobian.yx / scale, yy: jacobian.yy / scale};
+        // inverse(jacobian) * transpose(inverse(jacobian)) is inverse(transpose(jacobian) * jacobian),
+        // proportional to the adjugate of the symmetric [[p, q], [q, r]]
+        let p = jacobian.xx * jacobian.xx + jacobian.yx * jacobian.yx;
+        let q = jacobian.xx * jacobian.xy + jacobian.yx * jacobian.yy;
+        let r = jacobian.xy * jacobian.xy + jacobian.yy * jacobian.yy;
+        let (a, b, d) = (r, -q, p);
+        // Square root of the symmetric positive matrix [[a, b], [b, d]], scaled to determinant 1
+        let root_determinant = (a * d - b * b).sqrt();
+        if root_determinant.is_nan() || root_determinant <= 0.0 {
+            return Err(error());
+        }
+        let norm = (a + d + 2.0 * root_determinant).sqrt() * root_determinant.sqrt();
+        Ok(Skew {xx: (a + root_determinant) / norm, xy: b / norm, yx: b / norm, yy: (d + root_determinant) / norm})
+    }
+}
+
+impl Default for Skew {
+    fn default() -> Skew {
+        Skew::new()
+    }
+}
+
+// Parses xx,xy,yx,yy
+impl FromStr for Skew {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Skew, String> {
+        let invalid = || format!("invalid skew '{}', expected xx,xy,yx,yy", text);
+        let values = text.split(',').map(|value| value.trim().parse::<Real>()).collect::<Result<Vec<Real>, _>>().map_err(|_| invalid())?;
+        let skew = match values[..] {
+            [xx, xy, yx, yy] => Skew {xx, xy, yx, yy},
+            _ => return Err(invalid()),
+        };
+        if !skew.determinant().is_normal() {
+            return Err(format!("skew '{}' is singular", text));
+        }
+        Ok(skew)
+    }
+}
+
+impl fmt::Display for Skew {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.xx, self.xy, self.yx, self.yy)
+    }
+}
+
+// Applies the formula of the settings to iterate n at f64 precision. The phoenix, the only
+// formula using the previous iterate, has no estimate.
+fn step(settings: &RenderSettings, n: u32, z: Complex<Real>, c: Complex<Real>) -> Complex<Real> {
+    match (settings.fractal, &settings.formula) {
+        (Fractal::Formula, Some(formula)) => formula.step::<false>(&z, &Complex::new(0.0, 0.0), &c).0,
+        _ => settings.step_fractal(n).step(settings, &z, &z, &c),
+    }
+}
+
+// Derivatives of one step with respect to z and to c as real 2x2 matrices, by forward
+// differences so the folding formulas and user formulas need no formula of their own. On the
+// folds, like the real axis of the burning ship, they take the side the orbit continues on.
+fn step_jacobians(settings: &RenderSettings, n: u32, z: Complex<Real>, c: Complex<Real>) -> (Skew, Skew) {
+    let difference = |f: &dyn Fn(Complex<Real>) -> Complex<Real>, at: Complex<Real>| {
+        let h = 1e-7 * at.length().max(1e-300);
+        let value = f(at);
+        let dx = (f(at + Complex::new(h, 0.0)) - value) * (1.0 / h);
+        let dy = (f(at + Complex::new(0.0, h)) - value) * (1.0 / h);
+        Skew {xx: dx.r, xy: dy.r, yx: dx.i, yy: dy.i}
+    };
+    (difference(&|z| step(settings, n, z, c), z), difference(&|c| step(settings, n, z, c), c))
+}
+
+// Derivative of the orbit with respect to the pixel at the iteration closest to 0. The orbit
+// is iterated at the precision of T and its derivative at f64.
+fn closest_jacobian<T: Float>(settings: &RenderSettings, mut z: Complex<T>, c: Complex<T>) -> Option<Skew> {
+    let limit = settings.bailout.limit(settings.max_length());
+    let julia = settings.fractal.is_julia();
+    let (c_real, zero) = (c.to_real(), Skew {xx: 0.0, xy: 0.0, yx: 0.0, yy: 0.0});
+    let mut jacobian = if julia { Skew::new() } else { zero };
+    let mut closest: Option<(Real, Skew)> = None;
+    for n in 0..settings.max_iterations {
+        let z_real = z.to_real();
+        let (by_z, by_c) = step_jacobians(settings, n, z_real, c_real);
+        jacobian = by_z.multiply(&jacobian);
+        if !julia {
+            jacobian = jacobian.add(&by_c);
+        }
+        z = match (settings.fractal, &settings.formula) {
+            (Fractal::Formula, Some(_)) => {
+                let next = step(settings, n, z_real, c_real);
+                Complex::new(T::from_real(next.r), T::from_real(next.i))
+            }
+            _ => settings.step_fractal(n).step(settings, &z, &z, &c),
+        };
+        let next = z.to_real();
+        if settings.bailout.escaped(&next, limit) || !next.r.is_finite() || !next.i.is_finite() {
+            break;
+        }
+        // Later passes near 0 only repeat the period with a larger derivative
+        let length = next.length();
+        if closest.is_none_or(|(closest_length, _)| length < 0.5 * closest_length) {
+            closest = Some((length, jacobian));
+        }
+    }
+    closest.map(|(_, jacobian)| jacobian)
+}
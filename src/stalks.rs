@@ -0,0 +1,40 @@
+use crate::math::{Real, Complex, clamp};
+use crate::color::Color;
+use crate::coloring::smooth_iterations;
+use crate::render::{RenderSettings, EscapeResult};
+
+// Parameters of the Pickover stalk coloring, which draws stalks where the orbit passes close to
+// the real or the imaginary axis over the smooth coloring
+#[derive(Copy, Clone, PartialEq)]
+pub struct Stalks {
+    // Distance from the axes within which an orbit value draws a stalk
+    pub thickness: Real,
+    // Exponent of the fade from the middle of a stalk to its edge, larger ones narrow the stalks
+    pub falloff: Real,
+    pub color: Color,
+}
+
+impl Stalks {
+    pub fn new() -> Stalks {
+        Stalks {thickness: 0.05, falloff: 1.0, color: Color {r: 1.0, g: 1.0, b: 1.0, a: 1.0}}
+    }
+
+    // Distance of an orbit value to the nearest axis
+    pub fn distance(&self, z: &Complex<Real>) -> Real {
+        z.r.abs().min(z.i.abs())
+    }
+
+    // Stalk color over the smooth coloring, weighted by how close the orbit came to an axis
+    pub fn color(&self, settings: &RenderSettings, result: &EscapeResult) -> Color {
+        let smooth = settings.palette.interpolate(smooth_iterations(result, settings.fractal.degree(settings.power)));
+        let weight = clamp(1.0 - result.trap / self.thickness, 0.0, 1.0).powf(self.falloff);
+        let channel = |stalk: Real, base: Real| base + (stalk - base) * weight;
+        Color {r: channel(self.color.r, smooth.r), g: channel(self.color.g, smooth.g), b: channel(self.color.b, smooth.b), a: channel(self.color.a, smooth.a)}
+    }
+}
+
+impl Default for Stalks {
+    fn default() -> Stalks {
+        Stalks::new()
+    }
+}
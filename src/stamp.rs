@@ -0,0 +1,107 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::Real;
+use crate::color::Color;
+use crate::image::ImageBuffer;
+use crate::font::{GLYPH_HEIGHT, draw_text, text_width};
+use crate::overlay::set_pixel;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Corner, String> {
+        match text {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(format!("unknown corner '{}', expected top-left, top-right, bottom-left or bottom-right", text)),
+        }
+    }
+}
+
+impl fmt::Display for Corner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Corner::TopLeft => "top-left",
+            Corner::TopRight => "top-right",
+            Corner::BottomLeft => "bottom-left",
+            Corner::BottomRight => "bottom-right",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Caption and watermark image stamped into a corner of the output, the watermark closest to it
+pub struct Stamp {
+    // Lines of text separated by newlines
+    pub caption: Option<String>,
+    pub watermark: Option<ImageBuffer>,
+    pub corner: Corner,
+    // Opacity of both, multiplied with the alpha of the watermark
+    pub opacity: Real,
+    pub color: Color,
+}
+
+impl Stamp {
+    pub fn new() -> Stamp {
+        Stamp {caption: None, watermark: None, corner: Corner::BottomLeft, opacity: 0.8, color: Color {r: 1.0, g: 1.0, b: 1.0, a: 1.0}}
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.caption.is_none() && self.watermark.is_none()
+    }
+
+    pub fn draw(&self, image: &mut ImageBuffer) {
+        // Larger text and margins on larger images
+        let scale = (image.width.min(image.height) / 500).max(1);
+        let margin = 10 * scale;
+        let caption = self.caption.as_ref().map(|caption| caption_block(caption, scale, self.color));
+        let left = matches!(self.corner, Corner::TopLeft | Corner::BottomLeft);
+        let top = matches!(self.corner, Corner::TopLeft | Corner::TopRight);
+        // Distance of the next block from the top or bottom edge
+        let mut distance = margin as i64;
+        for block in self.watermark.iter().chain(caption.iter()) {
+            let x = if left { margin as i64 } else { image.width as i64 - margin as i64 - block.width as i64 };
+            let y = if top { distance } else { image.height as i64 - distance - block.height as i64 };
+            for by in 0..block.height {
+                for bx in 0..block.width {
+                    let color = block.get(bx, by);
+                    let (px, py) = (x + bx as i64, y + by as i64);
+                    if color.a > 0.0 && px >= 0 && py >= 0 {
+                        set_pixel(image, px as Real, py as Real, Color {a: color.a * self.opacity, ..color});
+                    }
+                }
+            }
+            distance += (block.height + margin / 2) as i64;
+        }
+    }
+}
+
+impl Default for Stamp {
+    fn default() -> Stamp {
+        Stamp::new()
+    }
+}
+
+// Transparent image of the caption lines with a dark shadow one glyph pixel to the bottom right
+fn caption_block(caption: &str, scale: usize, color: Color) -> ImageBuffer {
+    let lines: Vec<&str> = caption.lines().collect();
+    let line_height = (GLYPH_HEIGHT + 3) * scale;
+    let width = lines.iter().map(|line| text_width(line, scale)).max().unwrap_or(0);
+    let mut block = ImageBuffer::new(width + scale, lines.len() * line_height);
+    let shadow = Color {r: 0.0, g: 0.0, b: 0.0, a: 1.0};
+    for (index, line) in lines.iter().enumerate() {
+        draw_text(&mut block, scale, index * line_height + scale, line, scale, shadow);
+        draw_text(&mut block, 0, index * line_height, line, scale, color);
+    }
+    block
+}
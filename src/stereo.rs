@@ -0,0 +1,116 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::math::{Real, clamp};
+use crate::color::Color;
+use crate::export::pixel_heights;
+use crate::image::ImageBuffer;
+use crate::render::{RenderSettings, RenderProgress, render_with_progress, render_with_cache};
+
+// How the views of both eyes are combined into one image
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StereoMode {
+    // Red channel of the left eye, green and blue of the right one, for red-cyan glasses
+    Anaglyph,
+    // Left eye view on the left, right eye view on the right, twice as wide as the image
+    SideBySide,
+}
+
+impl FromStr for StereoMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<StereoMode, String> {
+        match text {
+            "anaglyph" => Ok(StereoMode::Anaglyph),
+            "side-by-side" => Ok(StereoMode::SideBySide),
+            _ => Err(format!("unknown stereo mode '{}', expected anaglyph or side-by-side", text)),
+        }
+    }
+}
+
+impl fmt::Display for StereoMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            StereoMode::Anaglyph => "anaglyph",
+            StereoMode::SideBySide => "side-by-side",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Stereo {
+    pub mode: StereoMode,
+    // Distance between the cameras of the eyes of 3D fractals, None for a thirtieth of the
+    // distance from the camera to the point it looks at
+    pub eye_separation: Option<Real>,
+    // Largest horizontal shift between the eyes in pixels for 2D fractals, whose depth is the
+    // logarithm of the iteration count, None for a fortieth of the image width
+    pub depth: Option<Real>,
+}
+
+impl Stereo {
+    pub fn new(mode: StereoMode) -> Stereo {
+        Stereo {mode, eye_separation: None, depth: None}
+    }
+}
+
+// Renders the views of the left and the right eye and combines them. 3D fractals are rendered
+// from two cameras side by side looking the same way, 2D fractals once, their pixels shifted
+// apart the more the higher their iteration count so the boundary stands out of the image.
+pub fn render_stereo<F: FnMut(&RenderProgress)>(settings: &RenderSettings, stereo: &Stereo, mut progress: F) -> ImageBuffer {
+    let (left, right) = if settings.fractal.is_3d() {
+        let camera = &settings.camera;
+        let separation = stereo.eye_separation.unwrap_or((camera.look_at - camera.position).length() / 30.0);
+        let (right, _, _) = camera.basis();
+        let eye = |side: Real| {
+            let mut eye = settings.clone();
+            let shift = right * (side * separation / 2.0);
+            eye.camera.position = camera.position + shift;
+            eye.camera.look_at = camera.look_at + shift;
+            eye
+        };
+        (render_with_progress(&eye(-1.0), &mut progress), render_with_progress(&eye(1.0), &mut progress))
+    }
+    else {
+        let (image, cache) = render_with_cache(settings, progress);
+        let depth = stereo.depth.unwrap_or(settings.width as Real / 40.0);
+        let heights = pixel_heights(settings, &cache);
+        (shift_view(&image, &heights, depth / 2.0), shift_view(&image, &heights, -depth / 2.0))
+    };
+    match stereo.mode {
+        StereoMode::Anaglyph => {
+            let mut image = ImageBuffer::new(left.width, left.height);
+            for (pixel, (l, r)) in image.pixels.iter_mut().zip(left.pixels.iter().zip(right.pixels.iter())) {
+                *pixel = Color {r: l.r, g: r.g, b: r.b, a: l.a.max(r.a)};
+            }
+            image
+        }
+        StereoMode::SideBySide => {
+            let mut image = ImageBuffer::new(2 * left.width, left.height);
+            for y in 0..left.height {
+                for x in 0..left.width {
+                    image.set(x, y, left.get(x, y));
+                    image.set(left.width + x, y, right.get(x, y));
+                }
+            }
+            image
+        }
+    }
+}
+
+// View of one eye of a 2D image: every pixel takes the color shift * (height - 0.5) pixels to
+// its left, interpolated, so middle heights stay in the plane of the screen
+fn shift_view(image: &ImageBuffer, heights: &[Real], shift: Real) -> ImageBuffer {
+    let mut view = ImageBuffer::new(image.width, image.height);
+    let last = (image.width - 1) as Real;
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let source = clamp(x as Real - shift * (heights[y * image.width + x] - 0.5), 0.0, last);
+            let (left, fraction) = (source.floor() as usize, source.fract());
+            let (a, b) = (image.get(left, y), image.get((left + 1).min(image.width - 1), y));
+            let mix = |a: Real, b: Real| a + (b - a) * fraction;
+            view.set(x, y, Color {r: mix(a.r, b.r), g: mix(a.g, b.g), b: mix(a.b, b.b), a: mix(a.a, b.a)});
+        }
+    }
+    view
+}
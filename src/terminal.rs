@@ -0,0 +1,209 @@
+// Prints images on the terminal, for checking the framing over SSH without copying files
+
+use std::env;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use mandelbrot_set::{Real, Color, ImageBuffer, RenderSettings};
+
+// Assumed pixels of a character cell for the graphics protocols
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 16;
+// Base64 characters per kitty escape sequence
+const KITTY_CHUNK: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TerminalGraphics {
+    // Upper half block characters with 24 bit foreground and background colors, two pixels
+    // per cell, works in most terminals
+    Blocks,
+    // DEC sixel graphics with a 6x6x6 color cube (xterm -ti vt340, mlterm, foot, WezTerm)
+    Sixel,
+    // Kitty graphics protocol with RGB pixels (kitty, WezTerm, Konsole)
+    Kitty,
+}
+
+impl TerminalGraphics {
+    // Preview settings fitting the terminal, whose size is read from COLUMNS and LINES
+    pub fn preview_settings(&self, settings: &RenderSettings) -> RenderSettings {
+        // One line is kept for the prompt
+        let (columns, lines) = terminal_size();
+        let lines = lines - 1;
+        let (max_width, max_height) = match self {
+            TerminalGraphics::Blocks => (columns, 2 * lines),
+            TerminalGraphics::Sixel | TerminalGraphics::Kitty => (columns * CELL_WIDTH, lines * CELL_HEIGHT),
+        };
+        let scale = (max_width as Real / settings.width as Real).min(max_height as Real / settings.height as Real);
+        let mut preview = settings.preview(1);
+        preview.width = ((settings.width as Real * scale).round() as usize).max(1);
+        preview.height = ((settings.height as Real * scale).round() as usize).max(1);
+        preview
+    }
+
+    pub fn print(&self, image: &ImageBuffer, out: &mut dyn Write) -> io::Result<()> {
+        match self {
+            TerminalGraphics::Blocks => print_blocks(image, out),
+            TerminalGraphics::Sixel => print_sixel(image, out),
+            TerminalGraphics::Kitty => print_kitty(image, out),
+        }
+    }
+}
+
+impl FromStr for TerminalGraphics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blocks" => Ok(TerminalGraphics::Blocks),
+            "sixel" => Ok(TerminalGraphics::Sixel),
+            "kitty" => Ok(TerminalGraphics::Kitty),
+            _ => Err(format!("unknown terminal graphics '{}', expected blocks, sixel or kitty", s)),
+        }
+    }
+}
+
+impl fmt::Display for TerminalGraphics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TerminalGraphics::Blocks => write!(f, "blocks"),
+            TerminalGraphics::Sixel => write!(f, "sixel"),
+            TerminalGraphics::Kitty => write!(f, "kitty"),
+        }
+    }
+}
+
+// Columns and lines of the terminal from COLUMNS and LINES, 80x24 when they aren't exported
+pub fn terminal_size() -> (usize, usize) {
+    let size = |name: &str, default: usize| env::var(name).ok().and_then(|value| value.parse().ok()).filter(|&value: &usize| value > 1).unwrap_or(default);
+    (size("COLUMNS", 80), size("LINES", 24))
+}
+
+// 8 bit RGB of a color over a black background
+pub fn color_rgb(color: Color) -> [u8; 3] {
+    let channel = |value: Real| ((value * color.a).clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(color.r), channel(color.g), channel(color.b)]
+}
+
+fn rgb(image: &ImageBuffer, x: usize, y: usize) -> [u8; 3] {
+    color_rgb(image.get(x, y))
+}
+
+fn print_blocks(image: &ImageBuffer, out: &mut dyn Write) -> io::Result<()> {
+    for y in (0..image.height).step_by(2) {
+        for x in 0..image.width {
+            let top = rgb(image, x, y);
+            // The last row of odd heights is left on the terminal background
+            if y + 1 < image.height {
+                let bottom = rgb(image, x, y + 1);
+                write!(out, "\x1b[38;2;{};{};{};48;2;{};{};{}m\u{2580}", top[0], top[1], top[2], bottom[0], bottom[1], bottom[2])?;
+            }
+            else {
+                write!(out, "\x1b[0;38;2;{};{};{}m\u{2580}", top[0], top[1], top[2])?;
+            }
+        }
+        writeln!(out, "\x1b[0m")?;
+    }
+    out.flush()
+}
+
+fn print_sixel(image: &ImageBuffer, out: &mut dyn Write) -> io::Result<()> {
+    // Index into the 6x6x6 color cube
+    let index = |x: usize, y: usize| {
+        let [r, g, b] = rgb(image, x, y);
+        let level = |value: u8| (value as usize * 5 + 127) / 255;
+        level(r) * 36 + level(g) * 6 + level(b)
+    };
+    let mut data = String::from("\x1bP0;1;0q");
+    data.push_str(&format!("\"1;1;{};{}", image.width, image.height));
+    for color in 0..216 {
+        let percent = |level: usize| level * 100 / 5;
+        data.push_str(&format!("#{};2;{};{};{}", color, percent(color / 36), percent(color / 6 % 6), percent(color % 6)));
+    }
+    let mut indices = vec![0; image.width * 6];
+    for band in (0..image.height).step_by(6) {
+        let rows = (image.height - band).min(6);
+        let mut used = [false; 216];
+        for row in 0..rows {
+            for x in 0..image.width {
+                indices[row * image.width + x] = index(x, band + row);
+                used[indices[row * image.width + x]] = true;
+            }
+        }
+        // One pass over the band per color, carriage returns in between
+        let mut first = true;
+        for color in (0..216).filter(|&color| used[color]) {
+            if !first {
+                data.push('$');
+            }
+            first = false;
+            data.push_str(&format!("#{}", color));
+            let mut run = (0, 0);
+            for x in 0..image.width {
+                let bits = (0..rows).filter(|&row| indices[row * image.width + x] == color).fold(0, |bits, row| bits | 1 << row);
+                if bits != run.0 && run.1 > 0 {
+                    push_sixels(&mut data, run.0, run.1);
+                    run.1 = 0;
+                }
+                run = (bits, run.1 + 1);
+            }
+            push_sixels(&mut data, run.0, run.1);
+        }
+        data.push('-');
+    }
+    data.push_str("\x1b\\");
+    out.write_all(data.as_bytes())?;
+    writeln!(out)?;
+    out.flush()
+}
+
+// Appends count repetitions of a sixel, as a repeat introducer from four on
+fn push_sixels(data: &mut String, bits: u8, count: usize) {
+    let sixel = (63 + bits) as char;
+    if count >= 4 {
+        data.push_str(&format!("!{}{}", count, sixel));
+    }
+    else {
+        data.extend(std::iter::repeat_n(sixel, count));
+    }
+}
+
+fn print_kitty(image: &ImageBuffer, out: &mut dyn Write) -> io::Result<()> {
+    let mut pixels = Vec::with_capacity(image.width * image.height * 3);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            pixels.extend_from_slice(&rgb(image, x, y));
+        }
+    }
+    let encoded = base64(&pixels);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        if i == 0 {
+            write!(out, "\x1b_Ga=T,f=24,s={},v={},m={};", image.width, image.height, more)?;
+        }
+        else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+    writeln!(out)?;
+    out.flush()
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for group in data.chunks(3) {
+        let value = group.iter().enumerate().fold(0u32, |value, (i, &byte)| value | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(ALPHABET[(value >> (18 - 6 * i) & 63) as usize] as char);
+            }
+            else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
@@ -0,0 +1,90 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use crate::image::{ImageBuffer, prepare_output};
+
+pub struct VideoSettings {
+    pub fps: u32,
+    // Constant rate factor, lower is better quality
+    pub crf: u32,
+    // Name or path of the ffmpeg executable
+    pub ffmpeg: String,
+    // Milliseconds each frame of a GIF or APNG is shown, None takes it from fps
+    pub delay: Option<u32>,
+    // Times a GIF or APNG plays, 0 loops forever
+    pub loop_count: u32,
+}
+
+impl VideoSettings {
+    pub fn new() -> VideoSettings {
+        VideoSettings {fps: 30, crf: 18, ffmpeg: String::from("ffmpeg"), delay: None, loop_count: 0}
+    }
+
+    // Milliseconds each frame is shown
+    pub fn frame_delay(&self) -> u32 {
+        self.delay.unwrap_or_else(|| (1000.0 / self.fps.max(1) as f64).round() as u32)
+    }
+}
+
+impl Default for VideoSettings {
+    fn default() -> VideoSettings {
+        VideoSettings::new()
+    }
+}
+
+pub fn is_video_path(path: &str) -> bool {
+    matches!(extension(path).as_str(), "mp4" | "webm" | "mkv" | "mov")
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+// Encodes frames by piping raw RGBA data into an ffmpeg child process
+pub struct VideoEncoder {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    width: usize,
+    height: usize,
+}
+
+impl VideoEncoder {
+    pub fn new(path: &str, width: usize, height: usize, settings: &VideoSettings) -> Result<VideoEncoder, String> {
+        let codec: &[&str] = match extension(path).as_str() {
+            "webm" => &["-c:v", "libvpx-vp9", "-b:v", "0"],
+            _ => &["-c:v", "libx264", "-preset", "slow"],
+        };
+        prepare_output(path)?;
+        let size = format!("{}x{}", width, height);
+        let fps = settings.fps.to_string();
+        let crf = settings.crf.to_string();
+        let mut child = Command::new(&settings.ffmpeg)
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba", "-s", &size, "-r", &fps, "-i", "-"])
+            .args(codec)
+            // yuv420p needs even dimensions
+            .args(["-crf", &crf, "-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-pix_fmt", "yuv420p", path])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("cannot start {}: {}", settings.ffmpeg, e))?;
+        let stdin = child.stdin.take();
+        Ok(VideoEncoder {child, stdin, width, height})
+    }
+
+    pub fn write_frame(&mut self, image: &ImageBuffer) -> Result<(), String> {
+        if image.width != self.width || image.height != self.height {
+            return Err(String::from("frame size doesn't match the video size"));
+        }
+        let stdin = self.stdin.as_mut().ok_or_else(|| String::from("the video is already finished"))?;
+        stdin.write_all(&image.to_rgba8()).map_err(|e| format!("cannot write the frame to ffmpeg: {}", e))
+    }
+
+    // Closes the pipe and waits for ffmpeg to write the file
+    pub fn finish(mut self) -> Result<(), String> {
+        drop(self.stdin.take());
+        let status = self.child.wait().map_err(|e| format!("ffmpeg failed: {}", e))?;
+        if !status.success() {
+            return Err(format!("ffmpeg exited with {}", status));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+// WebAssembly exports for wasm32-unknown-unknown. The page allocates the pixel buffer in the
+// module memory with mandelbrot_alloc, renders into it and reads width * height * 4 RGBA bytes
+// from the returned pointer. Plain exports keep the build free of JavaScript glue crates, see
+// web/index.html for the loader.
+
+use std::{ptr, slice};
+use crate::math::{Real, Float, Vec2};
+use crate::big_float::BigFloat;
+use crate::render::{RenderSettings, render_into};
+
+#[no_mangle]
+pub extern "C" fn mandelbrot_alloc(length: usize) -> *mut u8 {
+    let mut buffer = vec![0u8; length].into_boxed_slice();
+    let pointer = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    pointer
+}
+
+// Safety: pointer and length have to come from mandelbrot_alloc
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_free(pointer: *mut u8, length: usize) {
+    if !pointer.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(pointer, length)));
+    }
+}
+
+// Renders the view of view_width around the center into a buffer of mandelbrot_alloc with
+// at least width * height * 4 bytes, returns 0 on success and -1 for an empty image
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_render(pointer: *mut u8, width: usize, height: usize, center_x: Real, center_y: Real, view_width: Real, iterations: u32, samples: usize) -> i32 {
+    if pointer.is_null() || width == 0 || height == 0 {
+        return -1;
+    }
+    let mut settings = RenderSettings::new();
+    settings.width = width;
+    settings.height = height;
+    settings.center = Vec2::<BigFloat>{x: BigFloat::from_real(center_x), y: BigFloat::from_real(center_y)};
+    settings.view_size = Vec2::<Real>{x: view_width, y: view_width * height as Real / width as Real};
+    settings.max_iterations = iterations;
+    settings.sample_count = samples.max(1);
+    settings.min_sample_count = settings.min_sample_count.min(settings.sample_count);
+    settings.thread_count = 1;
+    // There is no entropy source without JavaScript glue
+    settings.seed = Some(0);
+    let buffer = slice::from_raw_parts_mut(pointer, width * height * 4);
+    match render_into(buffer, &settings) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
@@ -0,0 +1,53 @@
+// Command line tests: the options are parsed by the binary and the effective view is read back
+// from --dump-config, which writes the settings without rendering.
+
+use std::process::Command;
+
+// View width and height of the settings the options produce on a 200x100 image
+fn view_size(options: &[&str]) -> (f64, f64) {
+    let output = Command::new(env!("CARGO_BIN_EXE_mandelbrot_set"))
+        .args(options)
+        .args(["--width", "200", "--height", "100", "--dump-config", "-"])
+        .output().unwrap();
+    assert!(output.status.success(), "{:?} failed: {}", options, String::from_utf8_lossy(&output.stderr));
+    let text = String::from_utf8(output.stdout).unwrap();
+    let value = |key: &str| -> f64 {
+        text.lines().find_map(|line| line.strip_prefix(key)?.trim_start().strip_prefix('=')?.trim().parse().ok())
+            .unwrap_or_else(|| panic!("no {} in the dumped config:\n{}", key, text))
+    };
+    (value("view-width"), value("view-height"))
+}
+
+fn assert_view(options: &[&str], expected: (f64, f64)) {
+    let (width, height) = view_size(options);
+    let close = |a: f64, b: f64| (a - b).abs() <= b * 1e-9;
+    assert!(close(width, expected.0) && close(height, expected.1), "{:?} gave a {} x {} view, expected {} x {}", options, width, height, expected.0, expected.1);
+}
+
+#[test]
+fn zoom_after_magnification() {
+    assert_view(&["--magnification", "10", "--zoom", "100"], (0.04, 0.02));
+    assert_view(&["--magnification", "10", "--view-width", "0.04"], (0.04, 0.02));
+}
+
+#[test]
+fn magnification_after_zoom() {
+    assert_view(&["--zoom", "100", "--magnification", "10"], (0.8, 0.4));
+    assert_view(&["--view-width", "0.04", "--magnification", "10"], (0.8, 0.4));
+}
+
+#[test]
+fn corners_fit_the_aspect_ratio() {
+    assert_view(&["--corners", "-2,-1,1,1"], (4.0, 2.0));
+    assert_view(&["--corners", "-2,-1,1,1", "--magnification", "10"], (0.8, 0.4));
+}
+
+#[test]
+fn deeply_nested_formula_is_an_error() {
+    let formula = format!("{}z{}", "(".repeat(60000), ")".repeat(60000));
+    let output = Command::new(env!("CARGO_BIN_EXE_mandelbrot_set"))
+        .args(["--formula", &formula, "--dump-config", "-"])
+        .output().unwrap();
+    assert_eq!(output.status.code(), Some(2), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("nested too deeply"));
+}
@@ -0,0 +1,117 @@
+// Golden image regression tests: small canonical scenes rendered deterministically (fixed
+// seed and precision, scalar kernel) and compared with the references in tests/golden.
+// After an intended change of the output, regenerate them with
+//     UPDATE_GOLDEN=1 cargo test --test golden
+// and check the new images before committing them. Mismatching renders are written to
+// target/golden for inspection.
+
+use std::fs::File;
+use mandelbrot_set::{Real, Vec2, BigFloat, RenderSettings, Precision, Fractal, Coloring, ImageBuffer, Palette, render, save_image};
+
+const WIDTH: usize = 96;
+const HEIGHT: usize = 64;
+// Channel differences up to this many levels of 255 are rounding noise between platforms
+const CHANNEL_TOLERANCE: u8 = 2;
+// Fraction of the channels allowed to differ by more than the tolerance
+const OUTLIER_FRACTION: Real = 0.002;
+
+fn scene(center: (&str, &str), view_width: Real, iterations: u32) -> RenderSettings {
+    let mut settings = RenderSettings::new();
+    settings.width = WIDTH;
+    settings.height = HEIGHT;
+    settings.center = Vec2::<BigFloat>{x: center.0.parse().unwrap(), y: center.1.parse().unwrap()};
+    settings.view_size = Vec2::<Real>{x: view_width, y: view_width * HEIGHT as Real / WIDTH as Real};
+    settings.max_iterations = iterations;
+    settings.sample_count = 4;
+    settings.min_sample_count = 4;
+    settings.precision = Precision::Double;
+    settings.simd = false;
+    settings.seed = Some(1);
+    settings
+}
+
+// RGBA pixels of a PNG written by save_image
+fn read_png(path: &str) -> Option<(usize, usize, Vec<u8>)> {
+    let decoder = png::Decoder::new(File::open(path).ok()?);
+    let (info, mut reader) = decoder.read_info().ok()?;
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data).ok()?;
+    let rgba = match info.color_type {
+        png::ColorType::RGBA => data,
+        png::ColorType::RGB => data.chunks(3).flat_map(|pixel| vec![pixel[0], pixel[1], pixel[2], 255]).collect(),
+        _ => return None,
+    };
+    Some((info.width as usize, info.height as usize, rgba))
+}
+
+fn check(name: &str, settings: &RenderSettings) {
+    let image: ImageBuffer = render(settings);
+    let path = format!("tests/golden/{}.png", name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        save_image(&image, &path).unwrap();
+        return;
+    }
+    let (width, height, expected) = read_png(&path).unwrap_or_else(|| panic!("cannot read {}, run with UPDATE_GOLDEN=1 to create it", path));
+    assert_eq!((width, height), (image.width, image.height), "{} changed size", name);
+    let actual = image.to_rgba8();
+    let outliers = actual.iter().zip(&expected).filter(|(a, e)| (**a as i32 - **e as i32).abs() > CHANNEL_TOLERANCE as i32).count();
+    if outliers as Real > OUTLIER_FRACTION * actual.len() as Real {
+        let failed = format!("target/golden/{}.png", name);
+        std::fs::create_dir_all("target/golden").unwrap();
+        save_image(&image, &failed).unwrap();
+        panic!("{}: {} of {} channels differ from {}, the render is in {}", name, outliers, actual.len(), path, failed);
+    }
+}
+
+#[test]
+fn whole_set_banded() {
+    check("whole_set_banded", &scene(("-0.75", "0"), 3.5, 200));
+}
+
+#[test]
+fn seahorse_smooth() {
+    let mut settings = scene(("-0.7453", "0.1127"), 6.5e-3, 500);
+    settings.coloring = Coloring::Smooth;
+    check("seahorse_smooth", &settings);
+}
+
+#[test]
+fn julia_distance() {
+    let mut settings = scene(("0", "0"), 3.2, 300);
+    settings.fractal = Fractal::Julia;
+    settings.coloring = Coloring::Distance;
+    check("julia_distance", &settings);
+}
+
+#[test]
+fn burning_ship_histogram() {
+    let mut settings = scene(("-1.76", "-0.03"), 0.12, 300);
+    settings.fractal = Fractal::BurningShip;
+    settings.coloring = Coloring::Histogram;
+    check("burning_ship_histogram", &settings);
+}
+
+#[test]
+fn generated_palette() {
+    let mut settings = scene(("-0.1002", "0.8383"), 0.07, 500);
+    settings.coloring = Coloring::Smooth;
+    settings.palette = Palette::generate("cubehelix").unwrap();
+    check("generated_palette", &settings);
+}
+
+#[test]
+fn deep_zoom_perturbation() {
+    let mut settings = scene(("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139"), 4e-10, 10000);
+    settings.precision = Precision::Perturbation;
+    settings.series_approximation = false;
+    settings.coloring = Coloring::Smooth;
+    check("deep_zoom_perturbation", &settings);
+}
+
+#[test]
+fn deep_zoom_series_approximation() {
+    let mut settings = scene(("-0.743643887037158704752191506114774", "0.131825904205311970493132056385139"), 4e-10, 10000);
+    settings.precision = Precision::Perturbation;
+    settings.coloring = Coloring::Smooth;
+    check("deep_zoom_series_approximation", &settings);
+}